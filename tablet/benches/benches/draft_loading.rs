@@ -0,0 +1,28 @@
+//! Draft loading, run against the draft files under fixtures/draft
+use std::{hint::black_box, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use raft::{Draft, Drafts};
+
+fn fixture_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/draft")
+}
+
+fn draft_new(c: &mut Criterion) {
+    let file = std::fs::read_to_string(fixture_dir().join("koreader.draft")).unwrap();
+
+    c.bench_function("Draft::new", |b| {
+        b.iter(|| Draft::new(black_box(&file)).unwrap())
+    });
+}
+
+fn drafts_load(c: &mut Criterion) {
+    let dir = fixture_dir();
+
+    c.bench_function("Drafts::load", |b| {
+        b.iter(|| Drafts::load(&dir).unwrap().len())
+    });
+}
+
+criterion_group!(benches, draft_new, drafts_load);
+criterion_main!(benches);
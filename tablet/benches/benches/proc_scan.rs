@@ -0,0 +1,54 @@
+//! Process scanning, run against the proc crate's fixture tree so results compare
+//! between machines, and against the live /proc for a sense of real cost
+use std::{hint::black_box, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use proc::{proc_fs, ProcFsRoot, Stat};
+
+/// PIDs a tray would have recorded for its launched drafts
+const DRAFT_PIDS: [usize; 3] = [212, 480, 733];
+
+fn fixture_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../crates/proc/tests/fixtures/proc")
+}
+
+fn stat_from_str(c: &mut Criterion) {
+    let simple = std::fs::read_to_string(fixture_root().join("481/stat")).unwrap();
+    let spaced = std::fs::read_to_string(fixture_root().join("1024/stat")).unwrap();
+
+    c.bench_function("Stat::from_str", |b| {
+        b.iter(|| black_box(&simple).parse::<Stat>().unwrap())
+    });
+    c.bench_function("Stat::from_str spaced name", |b| {
+        b.iter(|| black_box(&spaced).parse::<Stat>().unwrap())
+    });
+}
+
+fn scan(c: &mut Criterion) {
+    let root = ProcFsRoot::new(fixture_root());
+
+    c.bench_function("proc_fs fixture", |b| {
+        b.iter(|| root.processes().unwrap().flatten().count())
+    });
+    c.bench_function("proc_fs live", |b| {
+        b.iter(|| proc_fs().unwrap().flatten().count())
+    });
+
+    // What the tray's draft_procs does, one scan per recorded PID
+    c.bench_function("draft_procs fixture", |b| {
+        b.iter(|| {
+            DRAFT_PIDS
+                .iter()
+                .filter_map(|pid| {
+                    root.processes()
+                        .unwrap()
+                        .flatten()
+                        .find(|(_, proc)| proc.stat.process_id == *pid)
+                })
+                .count()
+        })
+    });
+}
+
+criterion_group!(benches, stat_from_str, scan);
+criterion_main!(benches);
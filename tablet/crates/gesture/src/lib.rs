@@ -1,9 +1,180 @@
-use libremarkable::{cgmath, cgmath::InnerSpace, input::multitouch::Finger};
+use cgmath::InnerSpace;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
     ops::{Deref, DerefMut},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
+/// Source of event timestamps, injectable so timing-based gestures can be tested
+pub trait Clock: Send + Sync {
+    /// Time elapsed since an arbitrary fixed epoch
+    fn now(&self) -> Duration;
+}
+
+/// Clock backed by the system monotonic clock
+pub struct SystemClock(Instant);
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock(Instant::now())
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.0.elapsed()
+    }
+}
+
+/// Manually-advanced clock for deterministic tests
+#[derive(Debug, Default, Clone)]
+pub struct MockClock(Arc<Mutex<Duration>>);
+
+impl MockClock {
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Duration {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// A finger on the screen, independent of the device or simulator reporting it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchPoint {
+    /// Identifies the finger from press to release
+    pub id: i32,
+    pub pos: cgmath::Point2<u16>,
+    /// Contact pressure, 0 if the source doesn't report it
+    pub pressure: u16,
+    /// Contact size along its major axis, None if the source doesn't report it
+    pub size: Option<u16>,
+    /// When the source saw the event, on the recognizer's clock.
+    /// Stamped with the recognizer's clock on arrival if None.
+    pub timestamp: Option<Duration>,
+}
+
+impl TouchPoint {
+    pub fn new(id: i32, pos: cgmath::Point2<u16>) -> Self {
+        TouchPoint {
+            id,
+            pos,
+            pressure: 0,
+            size: None,
+            timestamp: None,
+        }
+    }
+}
+
+impl Default for TouchPoint {
+    fn default() -> Self {
+        TouchPoint::new(0, cgmath::Point2::new(0, 0))
+    }
+}
+
+#[cfg(feature = "libremarkable")]
+impl From<libremarkable::input::multitouch::Finger> for TouchPoint {
+    fn from(finger: libremarkable::input::multitouch::Finger) -> Self {
+        TouchPoint::new(finger.tracking_id, finger.pos)
+    }
+}
+
+/// Affine map from input coordinates to display coordinates, as the coefficients
+/// [a, b, c, d, e, f] of x' = a*x + b*y + c and y' = d*x + e*y + f
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TouchTransform(pub [f32; 6]);
+
+impl Default for TouchTransform {
+    fn default() -> Self {
+        TouchTransform::IDENTITY
+    }
+}
+
+impl TouchTransform {
+    pub const IDENTITY: TouchTransform = TouchTransform([1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+    /// The transform mapping three input points onto three display points,
+    /// None if the input points are too close to a line to tell the mapping apart
+    pub fn from_points(
+        input: [cgmath::Point2<f32>; 3],
+        display: [cgmath::Point2<f32>; 3],
+    ) -> Option<Self> {
+        let [p0, p1, p2] = input;
+        let det = (p1.x - p0.x) * (p2.y - p0.y) - (p2.x - p0.x) * (p1.y - p0.y);
+        if det.abs() < 1.0 {
+            return None;
+        }
+
+        // Solve for one output axis at a time, by Cramer's rule relative to the first point
+        let solve = |v0: f32, v1: f32, v2: f32| {
+            let a = ((v1 - v0) * (p2.y - p0.y) - (v2 - v0) * (p1.y - p0.y)) / det;
+            let b = ((p1.x - p0.x) * (v2 - v0) - (p2.x - p0.x) * (v1 - v0)) / det;
+            (a, b, v0 - a * p0.x - b * p0.y)
+        };
+        let (a, b, c) = solve(display[0].x, display[1].x, display[2].x);
+        let (d, e, f) = solve(display[0].y, display[1].y, display[2].y);
+        Some(TouchTransform([a, b, c, d, e, f]))
+    }
+
+    /// This transform followed by another
+    pub fn then(&self, next: &TouchTransform) -> TouchTransform {
+        let [a, b, c, d, e, f] = self.0;
+        let [na, nb, nc, nd, ne, nf] = next.0;
+        TouchTransform([
+            na * a + nb * d,
+            na * b + nb * e,
+            na * c + nb * f + nc,
+            nd * a + ne * d,
+            nd * b + ne * e,
+            nd * c + ne * f + nf,
+        ])
+    }
+
+    pub fn apply_point(&self, pos: cgmath::Point2<f32>) -> cgmath::Point2<f32> {
+        let [a, b, c, d, e, f] = self.0;
+        cgmath::Point2::new(a * pos.x + b * pos.y + c, d * pos.x + e * pos.y + f)
+    }
+
+    /// Move a touch into display coordinates, clamped to the coordinate range
+    pub fn apply(&self, touch: TouchPoint) -> TouchPoint {
+        let pos = self.apply_point(cgmath::Point2::new(touch.pos.x as f32, touch.pos.y as f32));
+        let clamp = |v: f32| v.round().clamp(0.0, u16::MAX as f32) as u16;
+        TouchPoint {
+            pos: cgmath::Point2::new(clamp(pos.x), clamp(pos.y)),
+            ..touch
+        }
+    }
+}
+
+impl FromStr for TouchTransform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid touch transform {s:?}: {e:}"))?;
+        let coefficients = values
+            .try_into()
+            .map_err(|_| format!("Touch transform {s:?} needs six values"))?;
+        Ok(TouchTransform(coefficients))
+    }
+}
+
+impl Display for TouchTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = self.0.map(|value| value.to_string());
+        f.write_str(&values.join(","))
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum EventType {
     Press,
@@ -12,10 +183,10 @@ pub enum EventType {
 }
 
 #[derive(Debug, Default)]
-pub struct FingerHistory(Vec<(EventType, Finger)>);
+pub struct FingerHistory(Vec<(EventType, TouchPoint, Duration)>);
 
 impl Deref for FingerHistory {
-    type Target = Vec<(EventType, Finger)>;
+    type Target = Vec<(EventType, TouchPoint, Duration)>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -28,8 +199,8 @@ impl DerefMut for FingerHistory {
     }
 }
 
-impl From<Vec<(EventType, Finger)>> for FingerHistory {
-    fn from(finger_history: Vec<(EventType, Finger)>) -> Self {
+impl From<Vec<(EventType, TouchPoint, Duration)>> for FingerHistory {
+    fn from(finger_history: Vec<(EventType, TouchPoint, Duration)>) -> Self {
         FingerHistory(finger_history)
     }
 }
@@ -44,57 +215,522 @@ impl FingerHistory {
                 - cgmath::Point2::<f32>::new(last_pos.x as f32, last_pos.y as f32),
         )
     }
+
+    /// Time elapsed between the first and last recorded events
+    pub fn duration(&self) -> Option<Duration> {
+        Some(self.last()?.2.saturating_sub(self.first()?.2))
+    }
+
+    /// Highest pressure recorded, 0 if the source doesn't report it
+    pub fn peak_pressure(&self) -> u16 {
+        self.iter()
+            .map(|(_, touch, _)| touch.pressure)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Limits past which a contact is taken for a resting palm rather than a finger.
+/// Either is skipped if None, or if the source doesn't report it.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct PalmRejection {
+    pub max_pressure: Option<u16>,
+    pub max_size: Option<u16>,
+}
+
+impl PalmRejection {
+    pub fn is_palm(&self, touch: &TouchPoint) -> bool {
+        let pressed_hard = matches!(self.max_pressure, Some(max) if touch.pressure > max);
+        let too_large =
+            matches!((self.max_size, touch.size), (Some(max), Some(size)) if size > max);
+        pressed_hard || too_large
+    }
+}
+
+/// Identifier for a callback that can later be replaced or removed
+pub type CallbackId = String;
+
+type BoxedCallback = Box<dyn GestureCallback + Send + Sync>;
+
+/// Callback for a tap made with several fingers at once
+struct MultiTap {
+    fingers: usize,
+    hysteresis: f32,
+    callback: Box<dyn FnMut() + Send + Sync>,
+}
+
+/// Rectangular screen region, as a position and size
+pub type Zone = (cgmath::Point2<u16>, cgmath::Vector2<u16>);
+
+fn zone_contains((position, size): &Zone, point: cgmath::Point2<u16>) -> bool {
+    point.x >= position.x
+        && point.x <= position.x + size.x
+        && point.y >= position.y
+        && point.y < position.y + size.y
+}
+
+/// Drops touches likely to be accidental before they reach a recognizer, for contacts made
+/// while holding the device
+///
+/// Touches beginning within the edge margin are ignored until they lift, unless they start in
+/// an edge zone that hosts a gesture. Presses are held back until they've lasted the minimum
+/// contact, then passed on with their original timestamp, and those lifting sooner are dropped.
+pub struct TouchFilter {
+    bounds: Zone,
+    edge_margin: u16,
+    edge_zones: Vec<Zone>,
+    min_contact: Duration,
+    clock: Arc<dyn Clock>,
+    /// Presses yet to last the minimum contact
+    pending: BTreeMap<i32, TouchPoint>,
+    /// Touches ignored until they lift
+    rejected: BTreeSet<i32>,
+}
+
+impl TouchFilter {
+    /// A filter passing everything through, for touches within bounds
+    pub fn new(bounds: Zone) -> Self {
+        TouchFilter {
+            bounds,
+            edge_margin: 0,
+            edge_zones: vec![],
+            min_contact: Duration::ZERO,
+            clock: Arc::new(SystemClock::default()),
+            pending: Default::default(),
+            rejected: Default::default(),
+        }
+    }
+
+    /// Share a clock with the recognizer fed by this filter, so held presses keep their timing
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_edge_margin(mut self, edge_margin: u16) -> Self {
+        self.edge_margin = edge_margin;
+        self
+    }
+
+    /// Let touches beginning in a zone through regardless of the edge margin
+    pub fn with_edge_zone(mut self, zone: Zone) -> Self {
+        self.edge_zones.push(zone);
+        self
+    }
+
+    pub fn with_min_contact(mut self, min_contact: Duration) -> Self {
+        self.min_contact = min_contact;
+        self
+    }
+
+    /// Forget touches in progress, as when another process has been handling input
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.rejected.clear();
+    }
+
+    fn near_edge(&self, pos: cgmath::Point2<u16>) -> bool {
+        let (position, size) = self.bounds;
+        let margin = self.edge_margin;
+        let inner = (
+            cgmath::Point2::new(position.x + margin, position.y + margin),
+            cgmath::Vector2::new(
+                size.x.saturating_sub(margin * 2),
+                size.y.saturating_sub(margin * 2),
+            ),
+        );
+        margin > 0
+            && !zone_contains(&inner, pos)
+            && !self.edge_zones.iter().any(|zone| zone_contains(zone, pos))
+    }
+
+    /// Pass a touch event through, returning the events a recognizer should see in its place
+    pub fn filter(
+        &mut self,
+        event_type: EventType,
+        touch: TouchPoint,
+    ) -> Vec<(EventType, TouchPoint)> {
+        let touch = TouchPoint {
+            timestamp: Some(touch.timestamp.unwrap_or_else(|| self.clock.now())),
+            ..touch
+        };
+        let lasted = |press: &TouchPoint| {
+            touch.timestamp.unwrap_or_default() - press.timestamp.unwrap_or_default()
+                >= self.min_contact
+        };
+
+        match event_type {
+            EventType::Press => {
+                self.pending.remove(&touch.id);
+                self.rejected.remove(&touch.id);
+                if self.near_edge(touch.pos) {
+                    self.rejected.insert(touch.id);
+                    vec![]
+                } else if self.min_contact > Duration::ZERO {
+                    self.pending.insert(touch.id, touch);
+                    vec![]
+                } else {
+                    vec![(EventType::Press, touch)]
+                }
+            }
+            EventType::Move => {
+                if self.rejected.contains(&touch.id) {
+                    return vec![];
+                }
+                match self.pending.get(&touch.id) {
+                    Some(press) if lasted(press) => {
+                        let press = self.pending.remove(&touch.id).unwrap();
+                        vec![(EventType::Press, press), (EventType::Move, touch)]
+                    }
+                    Some(_) => vec![],
+                    None => vec![(EventType::Move, touch)],
+                }
+            }
+            EventType::Release => {
+                if self.rejected.remove(&touch.id) {
+                    return vec![];
+                }
+                match self.pending.remove(&touch.id) {
+                    Some(press) if lasted(&press) => {
+                        vec![(EventType::Press, press), (EventType::Release, touch)]
+                    }
+                    Some(_) => vec![],
+                    None => vec![(EventType::Release, touch)],
+                }
+            }
+        }
+    }
+}
+
+/// How far a pan has got, as the average travel of its fingers since they pressed
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pan {
+    /// The fingers are still down
+    Moved(cgmath::Vector2<f32>),
+    /// The first of them lifted, ending the pan
+    Released(cgmath::Vector2<f32>),
+}
+
+/// Callback for several fingers dragged together from within a zone
+struct PanTracker {
+    zone: Zone,
+    fingers: usize,
+    hysteresis: f32,
+    callback: Box<dyn FnMut(Pan) + Send + Sync>,
+}
+
+/// Callback for fingers held in several zones at once
+struct Chord {
+    zones: Vec<Zone>,
+    duration: Duration,
+    callback: Box<dyn FnMut() + Send + Sync>,
 }
 
-#[derive(Default)]
 pub struct GestureRecognizer {
     active_fingers: BTreeMap<i32, FingerHistory>,
-    callbacks: Vec<Box<dyn GestureCallback + Send + Sync>>,
+    callbacks: Vec<(Option<CallbackId>, BoxedCallback)>,
+    multi_taps: Vec<MultiTap>,
+    pans: Vec<PanTracker>,
+    chords: Vec<Chord>,
+    /// Most fingers held down at once since the screen was last clear
+    touch_peak: usize,
+    /// Furthest any finger has travelled since the screen was last clear
+    touch_travel: f32,
+    palm_rejection: Option<PalmRejection>,
+    /// Contacts rejected as palms, ignored until they lift
+    palms: BTreeSet<i32>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        GestureRecognizer {
+            active_fingers: Default::default(),
+            callbacks: Default::default(),
+            multi_taps: Default::default(),
+            pans: Default::default(),
+            chords: Default::default(),
+            touch_peak: 0,
+            touch_travel: 0.0,
+            palm_rejection: None,
+            palms: Default::default(),
+            clock: Arc::new(SystemClock::default()),
+        }
+    }
 }
 
 pub trait GestureCallback: FnMut(&FingerHistory) -> Option<()> {}
 impl<F> GestureCallback for F where F: FnMut(&FingerHistory) -> Option<()> {}
 
 impl GestureRecognizer {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Ignore contacts that look like a resting palm, from when they're spotted until they lift
+    pub fn with_palm_rejection(mut self, palm_rejection: PalmRejection) -> Self {
+        self.palm_rejection = Some(palm_rejection);
+        self
+    }
+
     pub fn with_callback<F>(mut self, f: F) -> Self
     where
         F: GestureCallback + Send + Sync + 'static,
     {
-        self.callbacks.push(Box::new(f));
+        self.callbacks.push((None, Box::new(f)));
+        self
+    }
+
+    /// Register a callback under an id, replacing any existing callback with the same id
+    pub fn with_keyed_callback<F>(mut self, id: impl Into<CallbackId>, f: F) -> Self
+    where
+        F: GestureCallback + Send + Sync + 'static,
+    {
+        self.insert_keyed_callback(id, f);
+        self
+    }
+
+    /// Register or replace a keyed callback in place.
+    /// Replacements keep the priority of the callback they replace.
+    pub fn insert_keyed_callback<F>(&mut self, id: impl Into<CallbackId>, f: F)
+    where
+        F: GestureCallback + Send + Sync + 'static,
+    {
+        self.insert_boxed(Some(id.into()), Box::new(f));
+    }
+
+    /// Remove a keyed callback, returning whether it was present
+    pub fn remove(&mut self, id: &str) -> bool {
+        let len = self.callbacks.len();
+        self.callbacks
+            .retain(|(candidate, _)| candidate.as_deref() != Some(id));
+        self.callbacks.len() != len
+    }
+
+    /// Register a callback for a tap made with the given number of fingers,
+    /// fired once all of them have lifted without moving beyond the hysteresis
+    pub fn with_multi_tap<F>(mut self, fingers: usize, hysteresis: f32, callback: F) -> Self
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.multi_taps.push(MultiTap {
+            fingers,
+            hysteresis,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Register a callback for a pan made with the given number of fingers, all pressed
+    /// within the zone. It's called as they move once any has travelled beyond the
+    /// hysteresis, and a last time when the first of them lifts.
+    pub fn with_pan<F>(mut self, zone: Zone, fingers: usize, hysteresis: f32, callback: F) -> Self
+    where
+        F: FnMut(Pan) + Send + Sync + 'static,
+    {
+        self.pans.push(PanTracker {
+            zone,
+            fingers,
+            hysteresis,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Register a callback for fingers pressed and held in every zone for at least duration,
+    /// fired when the first of them lifts
+    pub fn with_chord<F>(mut self, zones: Vec<Zone>, duration: Duration, callback: F) -> Self
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.chords.push(Chord {
+            zones,
+            duration,
+            callback: Box::new(callback),
+        });
         self
     }
 
     pub fn with_recognizer(mut self, gesture_recognizer: Self) -> Self {
-        self.callbacks.extend(gesture_recognizer.callbacks);
+        for (id, callback) in gesture_recognizer.callbacks {
+            self.insert_boxed(id, callback);
+        }
+        self.multi_taps.extend(gesture_recognizer.multi_taps);
+        self.pans.extend(gesture_recognizer.pans);
+        self.chords.extend(gesture_recognizer.chords);
         self
     }
 
-    pub fn finger_press(&mut self, finger: Finger) -> Vec<i32> {
+    /// Continue tracking fingers still down on a recognizer this one replaces,
+    /// so a gesture in progress survives the UI being rebuilt under it
+    pub fn with_touches_from(mut self, previous: Self) -> Self {
+        self.active_fingers = previous.active_fingers;
+        self.touch_peak = previous.touch_peak;
+        self.touch_travel = previous.touch_travel;
+        self.palms = previous.palms;
+        self
+    }
+
+    fn insert_boxed(&mut self, id: Option<CallbackId>, callback: BoxedCallback) {
+        let existing = id.as_ref().and_then(|id| {
+            self.callbacks
+                .iter_mut()
+                .find(|(candidate, _)| candidate.as_ref() == Some(id))
+        });
+
+        match existing {
+            Some((_, existing)) => *existing = callback,
+            None => self.callbacks.push((id, callback)),
+        }
+    }
+
+    /// Stamp an event with the recognizer's clock, unless its source already did
+    fn stamp(&self, touch: &TouchPoint) -> Duration {
+        touch.timestamp.unwrap_or_else(|| self.clock.now())
+    }
+
+    /// Whether a contact is, or has just turned out to be, a palm, dropping its history if so
+    fn reject_palm(&mut self, touch: &TouchPoint) -> bool {
+        let is_palm = self
+            .palm_rejection
+            .is_some_and(|palm_rejection| palm_rejection.is_palm(touch));
+        if is_palm {
+            self.active_fingers.remove(&touch.id);
+            self.palms.insert(touch.id);
+        }
+        is_palm || self.palms.contains(&touch.id)
+    }
+
+    /// Dispatch a touch event, as from a TouchFilter
+    pub fn finger_event(&mut self, event_type: EventType, touch: TouchPoint) -> Vec<i32> {
+        match event_type {
+            EventType::Press => self.finger_press(touch),
+            EventType::Move => self.finger_move(touch),
+            EventType::Release => self.finger_release(touch),
+        }
+    }
+
+    pub fn finger_press(&mut self, touch: impl Into<TouchPoint>) -> Vec<i32> {
+        let touch = touch.into();
+        // A fresh press reusing a palm's id is a new contact
+        self.palms.remove(&touch.id);
+        if self.reject_palm(&touch) {
+            return vec![];
+        }
+        let now = self.stamp(&touch);
         self.active_fingers
-            .insert(finger.tracking_id, vec![(EventType::Press, finger)].into());
+            .insert(touch.id, vec![(EventType::Press, touch, now)].into());
+        self.touch_peak = self.touch_peak.max(self.active_fingers.len());
         self.check_gesture()
     }
 
-    pub fn finger_release(&mut self, finger: Finger) -> Vec<i32> {
-        let finger_history = self.active_fingers.entry(finger.tracking_id).or_default();
-        finger_history.push((EventType::Release, finger));
+    pub fn finger_release(&mut self, touch: impl Into<TouchPoint>) -> Vec<i32> {
+        let touch = touch.into();
+        if self.palms.remove(&touch.id) {
+            return vec![];
+        }
+        let now = self.stamp(&touch);
+        let finger_history = self.active_fingers.entry(touch.id).or_default();
+        finger_history.push((EventType::Release, touch, now));
+        self.check_chords(now);
+        self.check_pans(Pan::Released);
         let res = self.check_gesture();
-        self.active_fingers.remove(&finger.tracking_id);
+        self.active_fingers.remove(&touch.id);
+
+        if self.active_fingers.is_empty() {
+            self.check_multi_tap();
+        }
+
         res
     }
 
-    pub fn finger_move(&mut self, finger: Finger) -> Vec<i32> {
-        let finger_history = self.active_fingers.entry(finger.tracking_id).or_default();
-        finger_history.push((EventType::Move, finger));
+    pub fn finger_move(&mut self, touch: impl Into<TouchPoint>) -> Vec<i32> {
+        let touch = touch.into();
+        if self.reject_palm(&touch) {
+            return vec![];
+        }
+        let now = self.stamp(&touch);
+        let finger_history = self.active_fingers.entry(touch.id).or_default();
+        finger_history.push((EventType::Move, touch, now));
+        if let Some(delta) = finger_history.finger_delta() {
+            self.touch_travel = self.touch_travel.max(delta.magnitude());
+        }
+        self.check_pans(Pan::Moved);
         self.check_gesture()
     }
 
+    fn check_multi_tap(&mut self) {
+        for multi_tap in &mut self.multi_taps {
+            if multi_tap.fingers == self.touch_peak && self.touch_travel < multi_tap.hysteresis {
+                (multi_tap.callback)();
+            }
+        }
+
+        self.touch_peak = 0;
+        self.touch_travel = 0.0;
+    }
+
+    /// Average travel of the fingers down, if they're exactly those of a pan
+    fn pan_travel(&self, pan: &PanTracker) -> Option<cgmath::Vector2<f32>> {
+        if self.touch_peak != pan.fingers
+            || self.active_fingers.len() != pan.fingers
+            || self.touch_travel < pan.hysteresis
+        {
+            return None;
+        }
+
+        let mut travel = cgmath::Vector2::new(0.0, 0.0);
+        for history in self.active_fingers.values() {
+            let (EventType::Press, first, _) = history.first()? else {
+                return None;
+            };
+            if !zone_contains(&pan.zone, first.pos) {
+                return None;
+            }
+            travel -= history.finger_delta()?;
+        }
+        Some(travel / pan.fingers as f32)
+    }
+
+    fn check_pans(&mut self, event: fn(cgmath::Vector2<f32>) -> Pan) {
+        let mut pans = std::mem::take(&mut self.pans);
+        for pan in &mut pans {
+            if let Some(travel) = self.pan_travel(pan) {
+                (pan.callback)(event(travel));
+            }
+        }
+        self.pans = pans;
+    }
+
+    fn check_chords(&mut self, now: Duration) {
+        let active_fingers = &self.active_fingers;
+        for chord in &mut self.chords {
+            let held = chord.zones.iter().all(|zone| {
+                active_fingers
+                    .values()
+                    .any(|history| match (history.first(), history.last()) {
+                        (Some((EventType::Press, first, pressed)), Some((_, last, _))) => {
+                            zone_contains(zone, first.pos)
+                                && zone_contains(zone, last.pos)
+                                && now.saturating_sub(*pressed) >= chord.duration
+                        }
+                        _ => false,
+                    })
+            });
+
+            if held {
+                (chord.callback)();
+            }
+        }
+    }
+
     fn check_gesture(&mut self) -> Vec<i32> {
         let finished_gestures = self
             .active_fingers
             .iter()
             .flat_map(|(finger_id, finger_history)| {
-                for callback in &mut self.callbacks {
+                for (_, callback) in &mut self.callbacks {
                     if callback(finger_history).is_some() {
                         return Some(*finger_id);
                     }
@@ -124,11 +760,7 @@ pub fn recognize_starting_zone(
 ) -> impl GestureCallback + Send + Sync {
     move |finger_history: &FingerHistory| {
         let start = finger_history.first()?.1.pos;
-        if start.x >= position.x
-            && start.x <= position.x + size.x
-            && start.y >= position.y
-            && start.y < position.y + size.y
-        {
+        if zone_contains(&(position, size), start) {
             next(finger_history)
         } else {
             None
@@ -145,13 +777,13 @@ pub fn recognize_tap(
             return None;
         }
 
-        if let Some((EventType::Press, _)) = finger_history.first() {
+        if let Some((EventType::Press, _, _)) = finger_history.first() {
             ()
         } else {
             return None;
         }
 
-        let finger = if let Some((EventType::Release, last)) = finger_history.last() {
+        let finger = if let Some((EventType::Release, last, _)) = finger_history.last() {
             last
         } else {
             return None;
@@ -166,12 +798,75 @@ pub fn recognize_tap(
     }
 }
 
+pub fn recognize_long_press(
+    duration: Duration,
+    hysteresis: f32,
+    mut callback: impl FnMut(cgmath::Point2<u16>) + Clone,
+) -> impl GestureCallback + Clone {
+    move |finger_history: &FingerHistory| {
+        if !matches!(finger_history.first(), Some((EventType::Press, _, _))) {
+            return None;
+        }
+
+        let finger = if let Some((EventType::Release, last, _)) = finger_history.last() {
+            last
+        } else {
+            return None;
+        };
+
+        if finger_history.duration()? >= duration
+            && finger_history.finger_delta()?.magnitude() < hysteresis
+        {
+            callback(finger.pos);
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+/// Press within hysteresis, firmly enough to reach a pressure, and release.
+/// An alternative to a long press on sources that report pressure.
+pub fn recognize_firm_press(
+    pressure: u16,
+    hysteresis: f32,
+    mut callback: impl FnMut(cgmath::Point2<u16>) + Clone,
+) -> impl GestureCallback + Clone {
+    move |finger_history: &FingerHistory| {
+        if !matches!(finger_history.first(), Some((EventType::Press, _, _))) {
+            return None;
+        }
+
+        let finger = match finger_history.last() {
+            Some((EventType::Release, last, _)) => last,
+            _ => return None,
+        };
+
+        if finger_history.peak_pressure() >= pressure
+            && finger_history.finger_delta()?.magnitude() < hysteresis
+        {
+            callback(finger.pos);
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+/// Recognize either of two gestures, checking the first before the second
+pub fn recognize_either(
+    mut first: impl GestureCallback + Clone,
+    mut second: impl GestureCallback + Clone,
+) -> impl GestureCallback + Clone {
+    move |finger_history: &FingerHistory| first(finger_history).or_else(|| second(finger_history))
+}
+
 pub fn recognize_press(
     mut callback: impl FnMut(cgmath::Point2<u16>) + Clone,
 ) -> impl GestureCallback + Clone {
     move |finger_history: &FingerHistory| {
         let pos = if finger_history.len() == 1 {
-            let (event_type, finger) = finger_history[0];
+            let (event_type, finger, _) = finger_history[0];
             if matches!(event_type, EventType::Press) {
                 Some(finger.pos)
             } else {
@@ -190,7 +885,7 @@ pub fn recognize_release(
     mut callback: impl FnMut(cgmath::Point2<u16>) + Clone,
 ) -> impl GestureCallback + Clone {
     move |finger_history: &FingerHistory| {
-        let pos = if let Some((event_type, finger)) = finger_history.last() {
+        let pos = if let Some((event_type, finger, _)) = finger_history.last() {
             if matches!(event_type, EventType::Release) {
                 Some(finger.pos)
             } else {
@@ -217,3 +912,543 @@ pub fn recognize_drag(
         }
     }
 }
+
+/// Like a drag, but only decided once the finger lifts, with its total travel
+pub fn recognize_drag_release(
+    mut callback: impl FnMut(cgmath::Vector2<f32>) -> bool + Clone,
+) -> impl GestureCallback + Clone {
+    move |finger_history: &FingerHistory| {
+        if !matches!(finger_history.last(), Some((EventType::Release, _, _))) {
+            return None;
+        }
+
+        let finger_delta = finger_history.finger_delta()?;
+        if callback(finger_delta) {
+            Some(())
+        } else {
+            None
+        }
+    }
+}
+
+/// Which of a ring of equal slices an offset from its center points into,
+/// with the first slice centered straight up and the rest following clockwise.
+/// None within the dead zone, or if there are no slices.
+pub fn slice_at(offset: cgmath::Vector2<f32>, slices: usize, dead_zone: f32) -> Option<usize> {
+    if slices == 0 || offset.magnitude() < dead_zone {
+        return None;
+    }
+
+    let tau = std::f32::consts::TAU;
+    let width = tau / slices as f32;
+    let angle = offset.x.atan2(-offset.y).rem_euclid(tau);
+    Some(((angle + width / 2.0) / width) as usize % slices)
+}
+
+/// Press and hold within hysteresis, then drag out and release over a slice of a radial menu.
+///
+/// Once held, hover is called on each move with the press position and the slice under the
+/// finger, and select with that slice on release. Releasing in the dead zone selects None.
+pub fn recognize_radial(
+    hold: Duration,
+    hysteresis: f32,
+    slices: usize,
+    dead_zone: f32,
+    mut hover: impl FnMut(cgmath::Point2<u16>, Option<usize>) + Clone,
+    mut select: impl FnMut(Option<usize>) + Clone,
+) -> impl GestureCallback + Clone {
+    move |finger_history: &FingerHistory| {
+        let (first, pressed) = match finger_history.first() {
+            Some((EventType::Press, first, pressed)) => (first.pos, *pressed),
+            _ => return None,
+        };
+        let (event_type, last, now) = finger_history.last()?;
+        if now.saturating_sub(pressed) < hold {
+            return None;
+        }
+
+        let offset = |pos: cgmath::Point2<u16>| {
+            cgmath::Vector2::new(pos.x as f32 - first.x as f32, pos.y as f32 - first.y as f32)
+        };
+        let held = finger_history
+            .iter()
+            .take_while(|(_, _, time)| time.saturating_sub(pressed) < hold)
+            .all(|(_, finger, _)| offset(finger.pos).magnitude() < hysteresis);
+        if !held {
+            return None;
+        }
+
+        let slice = slice_at(offset(last.pos), slices, dead_zone);
+        match event_type {
+            EventType::Release => {
+                select(slice);
+                Some(())
+            }
+            _ => {
+                hover(first, slice);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn finger(id: i32, x: u16, y: u16) -> TouchPoint {
+        TouchPoint::new(id, cgmath::Point2::new(x, y))
+    }
+
+    fn counter() -> (Arc<AtomicUsize>, impl FnMut(cgmath::Point2<u16>) + Clone) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let callback = {
+            let count = count.clone();
+            move |_| {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        (count, callback)
+    }
+
+    #[test]
+    fn tap_within_hysteresis() {
+        let (count, callback) = counter();
+        let mut recognizer =
+            GestureRecognizer::default().with_callback(recognize_tap(8.0, callback));
+
+        recognizer.finger_press(finger(1, 100, 100));
+        recognizer.finger_move(finger(1, 103, 102));
+        assert_eq!(recognizer.finger_release(finger(1, 104, 102)), vec![1]);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn drag_is_not_a_tap() {
+        let (count, callback) = counter();
+        let mut recognizer =
+            GestureRecognizer::default().with_callback(recognize_tap(8.0, callback));
+
+        recognizer.finger_press(finger(1, 100, 100));
+        recognizer.finger_move(finger(1, 150, 100));
+        assert!(recognizer.finger_release(finger(1, 150, 100)).is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn long_press_uses_injected_clock() {
+        let clock = MockClock::default();
+        let (count, callback) = counter();
+        let mut recognizer = GestureRecognizer::default()
+            .with_clock(Arc::new(clock.clone()))
+            .with_callback(recognize_long_press(
+                Duration::from_millis(500),
+                8.0,
+                callback,
+            ));
+
+        // Released too early
+        recognizer.finger_press(finger(1, 100, 100));
+        clock.advance(Duration::from_millis(499));
+        assert!(recognizer.finger_release(finger(1, 100, 100)).is_empty());
+
+        // Held long enough
+        recognizer.finger_press(finger(2, 100, 100));
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(recognizer.finger_release(finger(2, 100, 100)), vec![2]);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn long_drag_takes_priority_over_release() {
+        let (long, long_callback) = counter();
+        let (short, short_callback) = counter();
+        let mut recognizer = GestureRecognizer::default()
+            .with_callback(recognize_drag({
+                let mut long_callback = long_callback.clone();
+                move |delta| {
+                    let done = delta.y > 100.0;
+                    if done {
+                        long_callback(cgmath::Point2::new(0, 0));
+                    }
+                    done
+                }
+            }))
+            .with_callback(recognize_drag_release({
+                let mut short_callback = short_callback.clone();
+                move |delta| {
+                    short_callback(cgmath::Point2::new(0, 0));
+                    delta.y > 8.0
+                }
+            }));
+
+        // Short swipe up, only decided on release
+        recognizer.finger_press(finger(1, 100, 500));
+        assert!(recognizer.finger_move(finger(1, 100, 450)).is_empty());
+        assert_eq!(recognizer.finger_release(finger(1, 100, 450)), vec![1]);
+
+        // Long swipe up, decided mid-drag
+        recognizer.finger_press(finger(2, 100, 500));
+        assert_eq!(recognizer.finger_move(finger(2, 100, 350)), vec![2]);
+
+        assert_eq!(long.load(Ordering::SeqCst), 1);
+        assert_eq!(short.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn radial_menu_selects_slice_after_hold() {
+        use std::sync::atomic::AtomicI32;
+
+        assert_eq!(slice_at(cgmath::Vector2::new(0.0, -50.0), 4, 10.0), Some(0));
+        assert_eq!(slice_at(cgmath::Vector2::new(50.0, 0.0), 4, 10.0), Some(1));
+        assert_eq!(
+            slice_at(cgmath::Vector2::new(-50.0, -45.0), 4, 10.0),
+            Some(3)
+        );
+        assert_eq!(slice_at(cgmath::Vector2::new(5.0, 0.0), 4, 10.0), None);
+
+        let clock = MockClock::default();
+        let selected = Arc::new(AtomicI32::new(-1));
+        let recognizer = || {
+            let selected = selected.clone();
+            GestureRecognizer::default()
+                .with_clock(Arc::new(clock.clone()))
+                .with_callback(recognize_radial(
+                    Duration::from_millis(500),
+                    8.0,
+                    4,
+                    10.0,
+                    |_, _| (),
+                    move |slice| {
+                        selected.store(slice.map_or(-2, |slice| slice as i32), Ordering::SeqCst)
+                    },
+                ))
+        };
+
+        // Dragged away before the hold, so not a menu
+        let mut first = recognizer();
+        first.finger_press(finger(1, 100, 100));
+        first.finger_move(finger(1, 100, 50));
+        clock.advance(Duration::from_millis(500));
+        assert!(first.finger_release(finger(1, 100, 50)).is_empty());
+        assert_eq!(selected.load(Ordering::SeqCst), -1);
+
+        // Held, then carried over to a rebuilt recognizer before dragging right
+        let mut first = recognizer();
+        first.finger_press(finger(2, 100, 100));
+        clock.advance(Duration::from_millis(500));
+        first.finger_move(finger(2, 101, 100));
+        let mut second = recognizer().with_touches_from(first);
+        second.finger_move(finger(2, 150, 100));
+        assert_eq!(second.finger_release(finger(2, 150, 100)), vec![2]);
+        assert_eq!(selected.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn finger_id_reuse_starts_fresh_history() {
+        let (count, callback) = counter();
+        let mut recognizer =
+            GestureRecognizer::default().with_callback(recognize_tap(8.0, callback));
+
+        // A drag that doesn't resolve to a tap
+        recognizer.finger_press(finger(1, 100, 100));
+        recognizer.finger_move(finger(1, 300, 100));
+        recognizer.finger_release(finger(1, 300, 100));
+
+        // The same tracking ID reused elsewhere must not inherit the old history
+        recognizer.finger_press(finger(1, 500, 500));
+        assert_eq!(recognizer.finger_release(finger(1, 500, 500)), vec![1]);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn release_without_press_is_ignored() {
+        let (count, callback) = counter();
+        let mut recognizer =
+            GestureRecognizer::default().with_callback(recognize_tap(8.0, callback));
+
+        recognizer.finger_move(finger(3, 100, 100));
+        assert!(recognizer.finger_release(finger(3, 100, 100)).is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn completed_gesture_consumes_history() {
+        let (count, callback) = counter();
+        let mut recognizer = GestureRecognizer::default().with_callback(recognize_press(callback));
+
+        assert_eq!(recognizer.finger_press(finger(1, 10, 10)), vec![1]);
+
+        // Subsequent events for the same finger form a new, press-less history
+        assert!(recognizer.finger_move(finger(1, 12, 10)).is_empty());
+        assert!(recognizer.finger_release(finger(1, 12, 10)).is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn keyed_callback_is_replaced_in_place() {
+        let (first, first_callback) = counter();
+        let (second, second_callback) = counter();
+        let mut recognizer = GestureRecognizer::default()
+            .with_keyed_callback("icon", recognize_press(first_callback))
+            .with_keyed_callback("icon", recognize_press(second_callback));
+
+        assert_eq!(recognizer.finger_press(finger(1, 10, 10)), vec![1]);
+        assert_eq!(first.load(Ordering::SeqCst), 0);
+        assert_eq!(second.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn removed_callback_no_longer_fires() {
+        let (count, callback) = counter();
+        let mut recognizer =
+            GestureRecognizer::default().with_keyed_callback("icon", recognize_press(callback));
+
+        assert!(recognizer.remove("icon"));
+        assert!(!recognizer.remove("icon"));
+        assert!(recognizer.finger_press(finger(1, 10, 10)).is_empty());
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn multi_tap_requires_all_fingers() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut recognizer = GestureRecognizer::default().with_multi_tap(2, 32.0, {
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        // One finger isn't enough
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_release(finger(1, 10, 10));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        // Two fingers fire once both have lifted
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_press(finger(2, 100, 10));
+        recognizer.finger_release(finger(1, 10, 10));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        recognizer.finger_release(finger(2, 100, 10));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // Moving too far cancels it
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_press(finger(2, 100, 10));
+        recognizer.finger_move(finger(2, 200, 10));
+        recognizer.finger_release(finger(1, 10, 10));
+        recognizer.finger_release(finger(2, 200, 10));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn pan_follows_all_fingers_until_one_lifts() {
+        let pans = Arc::new(Mutex::new(vec![]));
+        let zone = (cgmath::Point2::new(0, 100), cgmath::Vector2::new(500, 100));
+        let mut recognizer = GestureRecognizer::default().with_pan(zone, 2, 8.0, {
+            let pans = pans.clone();
+            move |pan| pans.lock().unwrap().push(pan)
+        });
+
+        // A single finger isn't a two-finger pan
+        recognizer.finger_press(finger(1, 10, 110));
+        recognizer.finger_move(finger(1, 10, 160));
+        recognizer.finger_release(finger(1, 10, 160));
+        assert!(pans.lock().unwrap().is_empty());
+
+        // Nor are two fingers that haven't moved past the hysteresis
+        recognizer.finger_press(finger(1, 10, 110));
+        recognizer.finger_press(finger(2, 100, 110));
+        recognizer.finger_move(finger(1, 10, 114));
+        assert!(pans.lock().unwrap().is_empty());
+
+        recognizer.finger_move(finger(2, 100, 150));
+        recognizer.finger_release(finger(1, 10, 130));
+        recognizer.finger_release(finger(2, 100, 150));
+        assert_eq!(
+            *pans.lock().unwrap(),
+            [
+                Pan::Moved(cgmath::Vector2::new(0.0, 22.0)),
+                Pan::Released(cgmath::Vector2::new(0.0, 30.0)),
+            ]
+        );
+
+        // Fingers starting outside the zone don't pan
+        pans.lock().unwrap().clear();
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_press(finger(2, 100, 110));
+        recognizer.finger_move(finger(2, 100, 150));
+        recognizer.finger_release(finger(1, 10, 10));
+        assert!(pans.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn chord_requires_every_zone_held() {
+        let clock = MockClock::default();
+        let count = Arc::new(AtomicUsize::new(0));
+        let zones = vec![
+            (cgmath::Point2::new(0, 0), cgmath::Vector2::new(50, 50)),
+            (cgmath::Point2::new(500, 500), cgmath::Vector2::new(50, 50)),
+        ];
+        let mut recognizer = GestureRecognizer::default()
+            .with_clock(Arc::new(clock.clone()))
+            .with_chord(zones, Duration::from_secs(2), {
+                let count = count.clone();
+                move || {
+                    count.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+
+        // One corner alone does nothing
+        recognizer.finger_press(finger(1, 10, 10));
+        clock.advance(Duration::from_secs(2));
+        recognizer.finger_release(finger(1, 10, 10));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        // Both corners, but not for long enough
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_press(finger(2, 510, 510));
+        clock.advance(Duration::from_secs(1));
+        recognizer.finger_release(finger(1, 10, 10));
+        recognizer.finger_release(finger(2, 510, 510));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        // Both corners held
+        recognizer.finger_press(finger(1, 10, 10));
+        recognizer.finger_press(finger(2, 510, 510));
+        clock.advance(Duration::from_secs(2));
+        recognizer.finger_release(finger(1, 10, 10));
+        recognizer.finger_release(finger(2, 510, 510));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn firm_press_stands_in_for_long_press() {
+        let (count, callback) = counter();
+        let mut recognizer = GestureRecognizer::default()
+            .with_clock(Arc::new(MockClock::default()))
+            .with_callback(recognize_either(
+                recognize_long_press(Duration::from_millis(500), 8.0, callback.clone()),
+                recognize_firm_press(200, 8.0, callback),
+            ));
+
+        // A light, quick press is neither
+        recognizer.finger_press(finger(1, 100, 100));
+        assert!(recognizer.finger_release(finger(1, 100, 100)).is_empty());
+
+        // Pressing firmly stands in for holding
+        recognizer.finger_press(finger(2, 100, 100));
+        recognizer.finger_move(TouchPoint {
+            pressure: 250,
+            ..finger(2, 100, 100)
+        });
+        assert_eq!(recognizer.finger_release(finger(2, 100, 100)), vec![2]);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn palm_is_ignored_until_lifted() {
+        let (count, callback) = counter();
+        let mut recognizer = GestureRecognizer::default()
+            .with_palm_rejection(PalmRejection {
+                max_pressure: None,
+                max_size: Some(40),
+            })
+            .with_callback(recognize_release(callback));
+
+        // Spreads out past the limit after landing
+        recognizer.finger_press(finger(1, 100, 100));
+        recognizer.finger_move(TouchPoint {
+            size: Some(60),
+            ..finger(1, 100, 100)
+        });
+        recognizer.finger_move(finger(1, 100, 100));
+        assert!(recognizer.finger_release(finger(1, 100, 100)).is_empty());
+
+        // The id is free for a finger once the palm lifts
+        recognizer.finger_press(finger(1, 100, 100));
+        assert_eq!(recognizer.finger_release(finger(1, 100, 100)), vec![1]);
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn transform_maps_calibration_points() {
+        let input = [(100.0, 100.0), (900.0, 150.0), (400.0, 1700.0)]
+            .map(|(x, y)| cgmath::Point2::new(x, y));
+        let expected = TouchTransform([-1.0, 0.0, 1404.0, 0.0, 1.02, -8.0]);
+        let display = input.map(|pos| expected.apply_point(pos));
+
+        let transform = TouchTransform::from_points(input, display).unwrap();
+        for (have, want) in transform.0.iter().zip(expected.0) {
+            assert!((have - want).abs() < 0.001, "{transform:?}");
+        }
+
+        // Following the identity changes nothing
+        assert_eq!(
+            TouchTransform::IDENTITY
+                .then(&transform)
+                .apply(finger(1, 100, 100)),
+            finger(1, 1304, 94)
+        );
+        assert_eq!(transform.to_string().parse(), Ok(transform));
+
+        // Collinear points can't pin down a mapping
+        let line = [(0.0, 0.0), (10.0, 10.0), (20.0, 20.0)].map(|(x, y)| cgmath::Point2::new(x, y));
+        assert_eq!(TouchTransform::from_points(line, display), None);
+    }
+
+    #[test]
+    fn filter_drops_brief_and_edge_touches() {
+        let clock = MockClock::default();
+        let bounds = (cgmath::Point2::new(0, 0), cgmath::Vector2::new(1000, 1000));
+        let mut filter = TouchFilter::new(bounds)
+            .with_clock(Arc::new(clock.clone()))
+            .with_edge_margin(20)
+            .with_edge_zone((cgmath::Point2::new(0, 900), cgmath::Vector2::new(1000, 100)))
+            .with_min_contact(Duration::from_millis(20));
+
+        // A brief contact never reaches the recognizer
+        assert!(filter
+            .filter(EventType::Press, finger(1, 500, 500))
+            .is_empty());
+        clock.advance(Duration::from_millis(10));
+        assert!(filter
+            .filter(EventType::Release, finger(1, 500, 500))
+            .is_empty());
+
+        // A held one arrives on release, keeping its press time
+        assert!(filter
+            .filter(EventType::Press, finger(2, 500, 500))
+            .is_empty());
+        clock.advance(Duration::from_millis(30));
+        let events = filter.filter(EventType::Release, finger(2, 500, 500));
+        assert!(matches!(
+            events[..],
+            [(EventType::Press, press), (EventType::Release, release)]
+                if release.timestamp.unwrap() - press.timestamp.unwrap() == Duration::from_millis(30)
+        ));
+
+        // Edge touches are dropped until they lift, outside of edge zones
+        assert!(filter
+            .filter(EventType::Press, finger(3, 5, 500))
+            .is_empty());
+        clock.advance(Duration::from_millis(30));
+        assert!(filter
+            .filter(EventType::Move, finger(3, 500, 500))
+            .is_empty());
+        assert!(filter
+            .filter(EventType::Release, finger(3, 500, 500))
+            .is_empty());
+
+        assert!(filter
+            .filter(EventType::Press, finger(4, 500, 995))
+            .is_empty());
+        clock.advance(Duration::from_millis(30));
+        assert_eq!(filter.filter(EventType::Move, finger(4, 500, 800)).len(), 2);
+    }
+}
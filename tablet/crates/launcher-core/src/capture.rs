@@ -0,0 +1,189 @@
+//! Screenshot capture worker
+//!
+//! The render thread only copies region data out of the framebuffer, compression
+//! and file IO happen here so drawing isn't held up behind them.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Condvar, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use libremarkable::image::{ColorType, ImageBuffer, Rgb};
+use shared::screenshot::save_screenshot;
+
+/// How captured region data is written out
+#[derive(Debug, Copy, Clone)]
+pub enum CaptureFormat {
+    /// Compressed raw dump, for restoring to the framebuffer
+    Dump,
+    /// rgb565 converted to an RGB PNG of the given size, for the user
+    Png { width: u32, height: u32 },
+}
+
+struct CaptureJob {
+    path: PathBuf,
+    data: Vec<u8>,
+    format: CaptureFormat,
+}
+
+/// Where screenshots taken by the user are saved
+pub const SCREENSHOT_DIR: &str = "/home/root/screenshots";
+
+/// Path for a screenshot taken by the user now, creating the directory if needed
+pub fn screenshot_path() -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::create_dir_all(SCREENSHOT_DIR).ok();
+    PathBuf::from(SCREENSHOT_DIR).join(format!("screenshot-{timestamp:}.png"))
+}
+
+/// Writes queued or in progress, counted by path as one may be queued again before
+/// an earlier write to it finishes
+type Pending = Arc<(Mutex<BTreeMap<PathBuf, usize>>, Condvar)>;
+
+/// Count a write to path as done, waking anything waiting on it
+fn finish(pending: &Pending, path: &Path) {
+    let (pending, written) = &**pending;
+    let mut pending = pending.lock().unwrap();
+    if let Some(count) = pending.get_mut(path) {
+        *count -= 1;
+        if *count == 0 {
+            pending.remove(path);
+        }
+    }
+    written.notify_all();
+}
+
+/// Handle for queueing writes to the capture worker thread
+#[derive(Clone)]
+pub struct CaptureWorker {
+    tx: Sender<CaptureJob>,
+    pending: Pending,
+}
+
+impl CaptureWorker {
+    /// Queue raw region data to be written to path, dropping it if the worker has stopped
+    pub fn submit(&self, path: PathBuf, data: Vec<u8>, format: CaptureFormat) {
+        {
+            let mut pending = self.pending.0.lock().unwrap();
+            *pending.entry(path.clone()).or_default() += 1;
+        }
+        if let Err(e) = self.tx.send(CaptureJob { path, data, format }) {
+            println!("Capture worker stopped, not saving {:?}", e.0.path);
+            finish(&self.pending, &e.0.path);
+        }
+    }
+
+    /// Block until every queued write to path has finished
+    pub fn wait(&self, path: &Path) {
+        let (pending, written) = &*self.pending;
+        let _pending = written
+            .wait_while(pending.lock().unwrap(), |pending| {
+                pending.contains_key(path)
+            })
+            .unwrap();
+    }
+
+    /// Block until every queued write has finished
+    pub fn flush(&self) {
+        let (pending, written) = &*self.pending;
+        let _pending = written
+            .wait_while(pending.lock().unwrap(), |pending| !pending.is_empty())
+            .unwrap();
+    }
+}
+
+fn save_png(path: &Path, data: &[u8], width: u32, height: u32) -> Result<(), String> {
+    let pixels = data
+        .chunks_exact(2)
+        .flat_map(|pixel| {
+            let pixel = u16::from_le_bytes([pixel[0], pixel[1]]);
+            let r = ((pixel >> 11) & 0x1f) as u8;
+            let g = ((pixel >> 5) & 0x3f) as u8;
+            let b = (pixel & 0x1f) as u8;
+            [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2]
+        })
+        .collect::<Vec<_>>();
+
+    let image = ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, pixels)
+        .ok_or("Region data doesn't match its size")?;
+
+    libremarkable::image::save_buffer(path, &image, width, height, ColorType::Rgb8)
+        .map_err(|e| format!("{e:}"))
+}
+
+/// Spawn the capture worker, which calls on_saved with each path written and whether it
+/// succeeded, stopping once it returns false
+pub fn capture_worker(on_saved: impl Fn(PathBuf, bool) -> bool + Send + 'static) -> CaptureWorker {
+    let (tx, rx) = channel::<CaptureJob>();
+    let pending: Pending = Default::default();
+
+    {
+        let pending = pending.clone();
+        std::thread::spawn(move || {
+            while let Ok(CaptureJob { path, data, format }) = rx.recv() {
+                println!("Saving screenshot {path:?}...");
+                let result = match format {
+                    CaptureFormat::Dump => {
+                        save_screenshot(&path, &data).map_err(|e| format!("{e:}"))
+                    }
+                    CaptureFormat::Png { width, height } => save_png(&path, &data, width, height),
+                };
+                let saved = match result {
+                    Ok(()) => true,
+                    Err(e) => {
+                        println!("Failed to save screenshot {path:?}: {e:}");
+                        false
+                    }
+                };
+
+                finish(&pending, &path);
+                if !on_saved(path, saved) {
+                    break;
+                }
+            }
+
+            // Nothing queued will be written now, so don't leave anyone waiting on it
+            drop(rx);
+            let (pending_paths, written) = &*pending;
+            pending_paths.lock().unwrap().clear();
+            written.notify_all();
+        });
+    }
+
+    CaptureWorker { tx, pending }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stopped_worker_releases_waiters() {
+        let dir = std::env::temp_dir().join(format!("capture-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("twice.png");
+        let png = CaptureFormat::Png {
+            width: 1,
+            height: 1,
+        };
+
+        // Stops after the first write, leaving the second queued
+        let worker = capture_worker(|_, _| false);
+        worker.submit(path.clone(), vec![0; 2], png);
+        worker.submit(path.clone(), vec![0; 2], png);
+        worker.wait(&path);
+        worker.flush();
+
+        worker.submit(path.clone(), vec![0; 2], png);
+        worker.flush();
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
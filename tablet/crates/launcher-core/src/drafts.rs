@@ -0,0 +1,462 @@
+//! Draft management and process control
+//!
+//! [`DraftPrograms`] indexes the drafts found on disk, tracks their processes through the
+//! pidfiles written on launch, and suspends, resumes, launches and closes them.
+use std::{collections::BTreeMap, error::Error, sync::Arc, time::Duration};
+
+use libremarkable::image::{ColorType, ImageBuffer, Rgba};
+use proc::{Proc, State};
+use raft::{Draft, DraftId, Drafts};
+use shared::{
+    cont_recursive,
+    health::tree_memory,
+    hooks::{run_hooks, HookEvent},
+    kill_recursive, launch_draft,
+    metrics::{count, Counter},
+    path_temp_icon,
+    pidfile::{read_pids, remove_pid},
+    renice_recursive, report_launch_failure, run_resume_hook, stop_recursive, terminate_recursive,
+    ProcessTree, SUSPENDED_NICE,
+};
+use std::sync::{Mutex, MutexGuard};
+
+/// How often launched drafts are checked for having exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a killed draft is given to release the framebuffer and input devices
+pub const KILL_SLEEP_DURATION: Duration = Duration::from_millis(100);
+
+/// How long a draft closed with SIGTERM gets to exit before it's killed
+pub const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A draft's icon, scaled to the launcher's icon size
+pub type DraftIcon = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+/// What run_draft_program did with a draft
+#[derive(Debug, Copy, Clone)]
+pub enum RunType {
+    Continue,
+    Launch,
+    Failed,
+}
+
+/// Process state of a started draft
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DraftState {
+    Running,
+    Suspended,
+}
+
+/// The drafts found on disk and the processes launched for them
+#[derive(Debug, Default)]
+pub struct DraftPrograms {
+    drafts: BTreeMap<DraftId, Draft>,
+    /// First draft found with each name, for looking up drafts recorded by name
+    names: BTreeMap<String, DraftId>,
+    icons: Mutex<BTreeMap<DraftId, DraftIcon>>,
+    procs: Mutex<BTreeMap<DraftId, Proc>>,
+    /// Drafts launched by this process, by PID, for it to notice when they exit
+    children: Mutex<BTreeMap<usize, DraftId>>,
+    /// Names of the only drafts the active user profile shows and launches, all if None
+    allowed: Mutex<Option<Vec<String>>>,
+}
+
+impl DraftPrograms {
+    pub fn new(drafts: Drafts) -> Self {
+        let mut by_id = BTreeMap::<DraftId, Draft>::new();
+        let mut names = BTreeMap::<String, DraftId>::new();
+        for draft in drafts.take() {
+            let id = draft.id();
+            if let Some(existing) = by_id.get(&id) {
+                println!(
+                    "Warning: Drafts {:?} and {:?} both have the id {id:?}, ignoring the latter",
+                    existing.path, draft.path
+                );
+                continue;
+            }
+
+            match names.get(&draft.name) {
+                Some(other) => println!(
+                    "Warning: Drafts {other:?} and {id:?} share the name {:?}, it will refer to the former",
+                    draft.name
+                ),
+                None => {
+                    names.insert(draft.name.clone(), id.clone());
+                }
+            }
+            by_id.insert(id, draft);
+        }
+
+        DraftPrograms {
+            drafts: by_id,
+            names,
+            icons: Default::default(),
+            procs: Default::default(),
+            children: Default::default(),
+            allowed: Default::default(),
+        }
+    }
+
+    pub fn drafts(&self) -> &BTreeMap<DraftId, Draft> {
+        &self.drafts
+    }
+
+    pub fn set_allowed(&self, allowed: Option<Vec<String>>) {
+        *self.allowed.lock().unwrap() = allowed;
+    }
+
+    /// Whether the active user profile shows and launches a draft
+    pub fn is_allowed(&self, draft: &Draft) -> bool {
+        self.allowed
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&draft.name))
+    }
+
+    /// Drafts the active user profile shows, by id
+    pub fn visible_drafts(&self) -> impl Iterator<Item = (&DraftId, &Draft)> {
+        self.drafts
+            .iter()
+            .filter(|(_, draft)| self.is_allowed(draft))
+    }
+
+    /// Draft recorded by name, as recent drafts and quick bar pins are
+    pub fn draft_named(&self, name: &str) -> Option<&Draft> {
+        self.drafts.get(self.names.get(name)?)
+    }
+
+    pub fn draft_icons(&self) -> MutexGuard<'_, BTreeMap<String, DraftIcon>> {
+        self.icons.lock().unwrap()
+    }
+
+    pub fn set_icon(&self, key: DraftId, icon: DraftIcon) {
+        self.draft_icons().insert(key, icon);
+    }
+
+    pub fn draft_procs<'a>(&'a self) -> Result<Vec<(&'a Draft, Proc)>, std::io::Error> {
+        self.draft_procs_in(&ProcessTree::scan())
+    }
+
+    /// Processes of started drafts among those provided, forgetting pidfiles of any gone
+    pub fn draft_procs_in<'a>(
+        &'a self,
+        procs: &ProcessTree,
+    ) -> Result<Vec<(&'a Draft, Proc)>, std::io::Error> {
+        Ok(read_pids()
+            .into_iter()
+            .filter_map(|pidfile| {
+                let draft = match self.drafts().get(&pidfile.id) {
+                    Some(draft) => draft,
+                    None => {
+                        println!(
+                            "Warning: No draft with the id {:?}, ignoring its PID",
+                            pidfile.id
+                        );
+                        return None;
+                    }
+                };
+
+                if let Some(proc) = procs.find(pidfile.pid) {
+                    Some((draft, proc.clone()))
+                } else {
+                    println!(
+                        "Warning: PID {} present in temp dir but not running, deleting record",
+                        pidfile.pid
+                    );
+                    remove_pid(&pidfile.id, pidfile.pid);
+                    None
+                }
+            })
+            .collect::<Vec<_>>())
+    }
+
+    /// A draft's process, if it's been started and is still around
+    pub fn draft_proc(&self, draft: &Draft) -> Option<(&Draft, Proc)> {
+        self.draft_procs()
+            .unwrap_or_default()
+            .into_iter()
+            .find(|(candidate, _)| candidate.id() == draft.id())
+    }
+
+    /// Re-scan draft processes and cache the result, called once per frame
+    pub fn refresh_procs(&self) -> BTreeMap<DraftId, Proc> {
+        let procs = self
+            .draft_procs()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(draft, proc)| (draft.id(), proc))
+            .collect::<BTreeMap<_, _>>();
+
+        *self.cached_procs() = procs.clone();
+        procs
+    }
+
+    /// Draft processes as of the last call to refresh_procs
+    pub fn cached_procs(&self) -> MutexGuard<'_, BTreeMap<DraftId, Proc>> {
+        self.procs.lock().unwrap()
+    }
+
+    /// Cached process state of each started draft, drafts that aren't running are absent
+    pub fn draft_states(&self) -> BTreeMap<DraftId, DraftState> {
+        self.cached_procs()
+            .iter()
+            .filter_map(|(key, proc)| {
+                let state = match proc.stat.state {
+                    State::Running | State::Sleeping | State::Delay => DraftState::Running,
+                    State::Traced => DraftState::Suspended,
+                    _ => return None,
+                };
+                Some((key.clone(), state))
+            })
+            .collect()
+    }
+
+    pub fn stop_draft_programs(&self) -> Vec<Draft> {
+        let procs = ProcessTree::scan();
+        let running_draft_procs = self
+            .draft_procs_in(&procs)
+            .unwrap()
+            .into_iter()
+            .filter(|(_, proc)| match proc.stat.state {
+                State::Running | State::Sleeping | State::Delay => true,
+                _ => false,
+            })
+            .collect::<Vec<_>>();
+
+        if running_draft_procs.len() > 1 {
+            println!("Warning: More than one draft application is running");
+        }
+
+        for (_, process) in &running_draft_procs {
+            stop_recursive(&procs, process);
+            // Stopped processes don't run, but lowering their priority keeps any
+            // work they resume with from competing with the foreground
+            renice_recursive(&procs, process, SUSPENDED_NICE);
+        }
+
+        running_draft_procs
+            .into_iter()
+            .map(|(draft, _)| draft.clone())
+            .collect::<Vec<_>>()
+    }
+
+    /// Find a draft by id whose process is currently stopped
+    pub fn stopped_draft(&self, id: &str) -> Option<Draft> {
+        let stopped = stopped_ids(&self.draft_procs().ok()?);
+        stopped
+            .iter()
+            .any(|stopped| stopped == id)
+            .then(|| self.drafts.get(id).cloned())?
+    }
+
+    /// Ids of all drafts whose processes are currently stopped
+    pub fn stopped_draft_ids(&self) -> Vec<DraftId> {
+        stopped_ids(&self.draft_procs().unwrap_or_default())
+    }
+
+    /// Reconcile drafts a previous tray recorded as stopped, returning the ids of those still
+    /// stopped and able to be continued. Processes left stopped under an id no draft has any
+    /// more, such as after its file was removed, could never be continued, so they're killed.
+    pub fn recover_stopped(&self, ids: &[DraftId]) -> Vec<DraftId> {
+        let procs = ProcessTree::scan();
+        let mut recovered = vec![];
+        for pidfile in read_pids()
+            .into_iter()
+            .filter(|pidfile| ids.contains(&pidfile.id))
+        {
+            let Some(proc) = procs
+                .find(pidfile.pid)
+                .filter(|proc| matches!(proc.stat.state, State::Traced))
+            else {
+                continue;
+            };
+
+            if self.drafts.contains_key(&pidfile.id) {
+                recovered.push(pidfile.id);
+            } else {
+                println!(
+                    "Killing stopped process {} of missing draft {:?}",
+                    pidfile.pid, pidfile.id
+                );
+                cont_recursive(&procs, proc);
+                kill_recursive(&procs, proc);
+                remove_pid(&pidfile.id, pidfile.pid);
+            }
+        }
+        recovered
+    }
+
+    /// Ids of launched drafts that have exited since last checked. Drafts are detached on
+    /// launch, so parchment reaps them and reports any that fail to start.
+    pub fn exited_children(&self) -> Vec<DraftId> {
+        let procs = ProcessTree::scan();
+        let mut children = self.children.lock().unwrap();
+        let exited = children
+            .keys()
+            .filter(|pid| {
+                !procs
+                    .find(**pid)
+                    .is_some_and(|proc| proc.stat.state != State::Zombie)
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        exited
+            .into_iter()
+            .filter_map(|pid| children.remove(&pid))
+            .collect()
+    }
+
+    pub fn run_draft_program(&self, draft: &Draft) -> RunType {
+        let procs = ProcessTree::scan();
+        if let Some((candidate, proc)) = self
+            .draft_procs_in(&procs)
+            .unwrap()
+            .into_iter()
+            .filter(|(_, proc)| match proc.stat.state {
+                State::Traced => true,
+                _ => false,
+            })
+            .find(|(candidate, _)| candidate.id() == draft.id())
+        {
+            // If the process still exists and is sleeping, restore its priority and continue it
+            renice_recursive(&procs, &proc, candidate.nice.unwrap_or(0));
+            cont_recursive(&procs, &proc);
+            run_resume_hook(candidate, &proc);
+            RunType::Continue
+        } else {
+            // If the process isn't running, launch it and add its PID to the temp directory
+            if !self.is_allowed(draft) {
+                println!(
+                    "Not launching {:?}, the user profile doesn't allow it",
+                    draft.name
+                );
+                return RunType::Failed;
+            }
+            match launch_draft(draft) {
+                Ok(pid) => {
+                    self.children.lock().unwrap().insert(pid, draft.id());
+                    RunType::Launch
+                }
+                Err(e) => {
+                    report_launch_failure(draft, &e.to_string());
+                    RunType::Failed
+                }
+            }
+        }
+    }
+}
+
+/// Ids of the drafts whose processes are stopped
+fn stopped_ids(draft_procs: &[(&Draft, Proc)]) -> Vec<DraftId> {
+    draft_procs
+        .iter()
+        .filter(|(_, proc)| matches!(proc.stat.state, State::Traced))
+        .map(|(draft, _)| draft.id())
+        .collect()
+}
+
+/// Kill a draft's process tree, or with graceful give it TERMINATE_TIMEOUT to exit after
+/// SIGTERM first, then run its AppKill hooks. Blocks until the draft is gone.
+pub fn close_process(draft: &Draft, proc: &Proc, graceful: bool) {
+    let procs = ProcessTree::scan();
+    count(Counter::MemoryReclaimed, tree_memory(&procs, proc) as u64);
+    if graceful {
+        terminate_recursive(&procs, proc, TERMINATE_TIMEOUT);
+    } else {
+        kill_recursive(&procs, proc);
+        std::thread::sleep(KILL_SLEEP_DURATION);
+    }
+    run_hooks(HookEvent::AppKill {
+        draft: draft.name.clone(),
+    });
+}
+
+/// Watch drafts this process launched for exiting. on_exit is called with each one that
+/// has, and the watcher stops once it returns false.
+pub fn exit_watcher(
+    drafts: Arc<DraftPrograms>,
+    on_exit: impl Fn(DraftId) -> bool + Send + 'static,
+) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(EXIT_POLL_INTERVAL);
+        for id in drafts.exited_children() {
+            if !on_exit(id) {
+                return;
+            }
+        }
+    });
+}
+
+/// A draft's icon scaled to icon_size pixels square, cached in the temp directory
+pub fn get_draft_icon(
+    draft: &Draft,
+    icon_size: u32,
+) -> Result<DraftIcon, Box<dyn Error + Send + Sync + 'static>> {
+    let mut cache_path = path_temp_icon(draft.id());
+    cache_path.set_extension(format!("{icon_size:}.rgba.png"));
+
+    let image = if cache_path.exists() {
+        println!("Loading cached icon {cache_path:?}");
+        libremarkable::image::open(cache_path)?.to_rgba8()
+    } else {
+        let icon = draft.icon.as_ref().ok_or("Draft has no icon")?;
+        let image = libremarkable::image::open(icon)?;
+        let image = image.resize(
+            icon_size,
+            icon_size,
+            libremarkable::image::imageops::FilterType::Lanczos3,
+        );
+        // Alpha is kept so the icon can be blended over whatever background it's drawn on
+        let image = image.into_rgba8();
+
+        println!("Saving icon to {cache_path:?}");
+        // The cache only saves rescaling next time, so the icon is still usable without it
+        if let Err(e) = libremarkable::image::save_buffer(
+            &cache_path,
+            &image,
+            image.width(),
+            image.height(),
+            ColorType::Rgba8,
+        ) {
+            println!("Failed to cache icon to {cache_path:?}: {e:}");
+        }
+
+        image
+    };
+
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proc(pid: usize, state: char) -> Proc {
+        let stat = format!(
+            "{pid:} (test) {state:} 1 {pid:} {pid:} {}",
+            ["0"; 46].join(" ")
+        );
+        Proc {
+            stat: stat.parse().unwrap(),
+            cmdline: String::new(),
+        }
+    }
+
+    #[test]
+    fn tells_stopped_drafts_with_one_name_apart() {
+        let draft = |file: &str| Draft {
+            name: "yaft".to_string(),
+            path: Some(format!("/opt/etc/draft/{file:}.draft").into()),
+            ..Default::default()
+        };
+        let (stopped, running) = (draft("yaft"), draft("yaft-dev"));
+        assert_ne!(stopped.id(), running.id());
+
+        let draft_procs = [
+            (&stopped, proc(4194401, 'T')),
+            (&running, proc(4194402, 'S')),
+        ];
+        assert_eq!(stopped_ids(&draft_procs), [stopped.id()]);
+    }
+}
@@ -0,0 +1,28 @@
+//! Launcher state shared by the tray and any other frontend, with no UI of its own
+//!
+//! A frontend loads the drafts on disk into [`drafts::DraftPrograms`], then uses it to launch,
+//! suspend, resume and close them:
+//!
+//! ```no_run
+//! use launcher_core::drafts::{DraftPrograms, RunType};
+//! use raft::Drafts;
+//!
+//! let drafts = DraftPrograms::new(Drafts::new().unwrap());
+//! // Stop whatever's in the foreground, then bring up the first draft in its place
+//! drafts.stop_draft_programs();
+//! let first = drafts.visible_drafts().next().map(|(_, draft)| draft.clone());
+//! if let Some(draft) = first {
+//!     if let RunType::Failed = drafts.run_draft_program(&draft) {
+//!         println!("{:?} didn't start", draft.name);
+//!     }
+//! }
+//! ```
+//!
+//! Launched drafts should be watched for exiting with [`drafts::exit_watcher`], and a frontend drawing
+//! over drafts saves what it covers with [`capture::capture_worker`]. The pidfiles
+//! tracking draft processes and the session persisted across launcher restarts live
+//! in `shared`, and are re-exported here as [`pidfile`] and [`session`].
+pub mod capture;
+pub mod drafts;
+
+pub use shared::{pidfile, session};
@@ -0,0 +1,182 @@
+//! Network status read from /proc, for showing how to reach the device
+//!
+//! The kernel doesn't list addresses by interface anywhere in /proc, so local
+//! addresses are taken from the routing trie and matched to an interface by the
+//! routes covering them.
+use std::net::Ipv4Addr;
+
+pub mod wpa;
+
+pub const SSH_PORT: u16 = 22;
+
+const HOSTNAME_PATH: &str = "/proc/sys/kernel/hostname";
+const FIB_TRIE_PATH: &str = "/proc/net/fib_trie";
+const ROUTE_PATH: &str = "/proc/net/route";
+const TCP_TABLES: [&str; 2] = ["/proc/net/tcp", "/proc/net/tcp6"];
+const TCP_LISTEN: u8 = 0x0A;
+
+/// Route to a subnet, from /proc/net/route
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub interface: String,
+    pub destination: Ipv4Addr,
+    pub mask: Ipv4Addr,
+}
+
+impl Route {
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & u32::from(self.mask) == u32::from(self.destination)
+    }
+}
+
+pub fn hostname() -> Option<String> {
+    Some(
+        std::fs::read_to_string(HOSTNAME_PATH)
+            .ok()?
+            .trim()
+            .to_string(),
+    )
+}
+
+/// Addresses the kernel holds as local, from the routing trie
+pub fn parse_fib_trie(input: &str) -> Vec<Ipv4Addr> {
+    let mut addresses = vec![];
+    let mut last = None;
+    for line in input.lines() {
+        let line = line.trim_start_matches([' ', '|', '+', '-']);
+        if let Ok(address) = line.trim().parse::<Ipv4Addr>() {
+            last = Some(address);
+        } else if line.contains("host LOCAL") {
+            if let Some(address) = last.take() {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+    }
+    addresses
+}
+
+/// Parse /proc/net/route, whose addresses are hex in host byte order
+pub fn parse_routes(input: &str) -> Vec<Route> {
+    let address = |hex: &str| {
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .map(|bits| Ipv4Addr::from(u32::from_be(bits)))
+    };
+    input
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            Some(Route {
+                interface: fields.first()?.to_string(),
+                destination: address(fields.get(1)?)?,
+                mask: address(fields.get(7)?)?,
+            })
+        })
+        .collect()
+}
+
+/// Pair each non-loopback address with the interface of the narrowest route covering it
+pub fn match_interfaces(addresses: &[Ipv4Addr], routes: &[Route]) -> Vec<(String, Ipv4Addr)> {
+    addresses
+        .iter()
+        .filter(|address| !address.is_loopback())
+        .filter_map(|address| {
+            let route = routes
+                .iter()
+                .filter(|route| !route.mask.is_unspecified() && route.contains(*address))
+                .max_by_key(|route| u32::from(route.mask))?;
+            Some((route.interface.clone(), *address))
+        })
+        .collect()
+}
+
+/// IPv4 addresses of each interface that's up, by interface name
+pub fn interface_addresses() -> Vec<(String, Ipv4Addr)> {
+    let trie = std::fs::read_to_string(FIB_TRIE_PATH).unwrap_or_default();
+    let routes = std::fs::read_to_string(ROUTE_PATH).unwrap_or_default();
+    match_interfaces(&parse_fib_trie(&trie), &parse_routes(&routes))
+}
+
+/// Local port and connection state from a /proc/net/tcp line
+fn parse_tcp_line(line: &str) -> Option<(u16, u8)> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    let (_, local_port) = fields.get(1)?.rsplit_once(':')?;
+    let local_port = u16::from_str_radix(local_port, 16).ok()?;
+    let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+    Some((local_port, state))
+}
+
+/// Whether anything is accepting TCP connections on a port
+pub fn tcp_listening(port: u16) -> bool {
+    TCP_TABLES
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(table).ok())
+        .any(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(parse_tcp_line)
+                .any(|line| line == (port, TCP_LISTEN))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_match_interfaces() {
+        let trie = "Main:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 0.0.0.0
+        /0 universe UNICAST
+     +-- 10.11.99.0/29 2 0 2
+        |-- 10.11.99.0
+           /29 link UNICAST
+        |-- 10.11.99.1
+           /32 host LOCAL
+     |-- 192.168.1.0
+        /24 link UNICAST
+     |-- 192.168.1.20
+        /32 host LOCAL
+Local:
+  +-- 127.0.0.0/8 2 0 2
+     |-- 127.0.0.1
+        /32 host LOCAL
+     |-- 10.11.99.1
+        /32 host LOCAL
+";
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0
+wlan0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+usb0\t00630B0A\t00000000\t0001\t0\t0\t0\tF8FFFFFF\t0\t0\t0
+";
+
+        let addresses = parse_fib_trie(trie);
+        assert_eq!(
+            addresses,
+            vec![
+                Ipv4Addr::new(10, 11, 99, 1),
+                Ipv4Addr::new(192, 168, 1, 20),
+                Ipv4Addr::new(127, 0, 0, 1)
+            ]
+        );
+        assert_eq!(
+            match_interfaces(&addresses, &parse_routes(routes)),
+            vec![
+                ("usb0".to_string(), Ipv4Addr::new(10, 11, 99, 1)),
+                ("wlan0".to_string(), Ipv4Addr::new(192, 168, 1, 20)),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_listening_socket() {
+        let line = "   0: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 1234 1 00000000 100 0 0 10 0";
+        assert_eq!(parse_tcp_line(line), Some((SSH_PORT, TCP_LISTEN)));
+    }
+}
@@ -0,0 +1,241 @@
+//! Client for wpa_supplicant's control socket
+//!
+//! Each request is a datagram sent from a socket bound to our own path, which
+//! wpa_supplicant replies to.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Error,
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+pub const WPA_CTRL_DIR: &str = "/var/run/wpa_supplicant";
+pub const WIFI_INTERFACE: &str = "wlan0";
+
+/// wpa_state once associated and authenticated
+pub const WPA_COMPLETED: &str = "COMPLETED";
+
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLY_SIZE: usize = 16 * 1024;
+
+/// Distinguishes the sockets of clients open at once within a process
+static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// A network seen by the last scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+    pub bssid: String,
+    pub frequency: u32,
+    /// Signal level in dBm
+    pub signal: i32,
+    pub flags: String,
+    pub ssid: String,
+}
+
+impl ScanResult {
+    /// Whether joining needs a passphrase
+    pub fn secured(&self) -> bool {
+        ["WPA", "RSN", "WEP"]
+            .iter()
+            .any(|flag| self.flags.contains(flag))
+    }
+
+    /// Signal strength as a rough percentage, from -100 dBm at 0 to -50 dBm at 100
+    pub fn signal_percent(&self) -> u8 {
+        ((self.signal + 100) * 2).clamp(0, 100) as u8
+    }
+}
+
+/// Parse a SCAN_RESULTS reply, skipping its header
+pub fn parse_scan_results(reply: &str) -> Vec<ScanResult> {
+    reply
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(ScanResult {
+                bssid: fields.next()?.to_string(),
+                frequency: fields.next()?.parse().ok()?,
+                signal: fields.next()?.parse().ok()?,
+                flags: fields.next()?.to_string(),
+                ssid: fields.next().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Networks worth offering, strongest first: one entry per SSID, without hidden ones
+pub fn visible_networks(mut results: Vec<ScanResult>) -> Vec<ScanResult> {
+    results.sort_by_key(|result| std::cmp::Reverse(result.signal));
+    let mut seen = BTreeSet::new();
+    results.retain(|result| !result.ssid.is_empty() && seen.insert(result.ssid.clone()));
+    results
+}
+
+/// Parse a STATUS reply's key=value lines
+pub fn parse_status(reply: &str) -> BTreeMap<String, String> {
+    reply
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Parse a LIST_NETWORKS reply into network ids by SSID
+pub fn parse_networks(reply: &str) -> BTreeMap<String, String> {
+    reply
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let id = fields.next()?;
+            let ssid = fields.next()?;
+            Some((ssid.to_string(), id.to_string()))
+        })
+        .collect()
+}
+
+/// SSIDs are sent hex encoded, so they need no quoting or escaping
+fn hex(s: &str) -> String {
+    s.bytes().map(|byte| format!("{byte:02x}")).collect()
+}
+
+pub struct WpaControl {
+    socket: UnixDatagram,
+    local: PathBuf,
+}
+
+impl WpaControl {
+    pub fn open(interface: &str) -> std::io::Result<Self> {
+        let local = PathBuf::from(format!(
+            "/tmp/wpa_ctrl_{}-{}",
+            std::process::id(),
+            CLIENTS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::remove_file(&local).ok();
+
+        let socket = UnixDatagram::bind(&local)?;
+        let control = WpaControl { socket, local };
+        control
+            .socket
+            .connect(Path::new(WPA_CTRL_DIR).join(interface))?;
+        control.socket.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        Ok(control)
+    }
+
+    pub fn request(&self, request: &str) -> std::io::Result<String> {
+        self.socket.send(request.as_bytes())?;
+        let mut reply = vec![0; REPLY_SIZE];
+        let len = self.socket.recv(&mut reply)?;
+        Ok(String::from_utf8_lossy(&reply[..len]).into_owned())
+    }
+
+    /// Send a request that's answered with OK on success
+    fn command(&self, command: &str) -> std::io::Result<()> {
+        let reply = self.request(command)?;
+        match reply.trim() {
+            "OK" => Ok(()),
+            reply => Err(Error::other(format!(
+                "{:} failed: {reply:}",
+                command.split(' ').next().unwrap()
+            ))),
+        }
+    }
+
+    /// Start a scan, carrying on if one is already running
+    pub fn scan(&self) -> std::io::Result<()> {
+        match self.request("SCAN")?.trim() {
+            "OK" | "FAIL-BUSY" => Ok(()),
+            reply => Err(Error::other(format!("SCAN failed: {reply:}"))),
+        }
+    }
+
+    pub fn scan_results(&self) -> std::io::Result<Vec<ScanResult>> {
+        Ok(parse_scan_results(&self.request("SCAN_RESULTS")?))
+    }
+
+    pub fn status(&self) -> std::io::Result<BTreeMap<String, String>> {
+        Ok(parse_status(&self.request("STATUS")?))
+    }
+
+    /// SSIDs of the networks wpa_supplicant already has configured
+    pub fn known_networks(&self) -> std::io::Result<BTreeSet<String>> {
+        Ok(parse_networks(&self.request("LIST_NETWORKS")?)
+            .into_keys()
+            .collect())
+    }
+
+    /// Join a network, adding it if it isn't configured yet.
+    /// A passphrase replaces any saved one, and a new network without one is joined as open.
+    pub fn connect(&self, ssid: &str, passphrase: Option<&str>) -> std::io::Result<()> {
+        let known = parse_networks(&self.request("LIST_NETWORKS")?);
+        let id = match known.get(ssid) {
+            Some(id) => id.clone(),
+            None => {
+                let id = self.request("ADD_NETWORK")?.trim().to_string();
+                self.command(&format!("SET_NETWORK {id:} ssid {}", hex(ssid)))?;
+                if passphrase.is_none() {
+                    self.command(&format!("SET_NETWORK {id:} key_mgmt NONE"))?;
+                }
+                id
+            }
+        };
+
+        if let Some(passphrase) = passphrase {
+            self.command(&format!("SET_NETWORK {id:} psk \"{passphrase:}\""))?;
+        }
+        self.command(&format!("SELECT_NETWORK {id:}"))?;
+
+        // Only possible when the config allows updates, joining works either way
+        if let Err(e) = self.command("SAVE_CONFIG") {
+            println!("Not saving network {ssid:?}: {e:}");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WpaControl {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.local).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_replies() {
+        let results = parse_scan_results(
+            "bssid / frequency / signal level / flags / ssid\n\
+             aa:bb:cc:dd:ee:ff\t2437\t-48\t[WPA2-PSK-CCMP][ESS]\tHome\n\
+             11:22:33:44:55:66\t5180\t-81\t[ESS]\tCafe\n",
+        );
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].ssid, "Home");
+        assert!(results[0].secured());
+        assert_eq!(results[0].signal_percent(), 100);
+        assert!(!results[1].secured());
+        assert_eq!(results[1].signal_percent(), 38);
+
+        let mut repeated = results.clone();
+        repeated.push(ScanResult {
+            signal: -30,
+            ..results[1].clone()
+        });
+        let visible = visible_networks(repeated);
+        assert_eq!(visible.len(), 2);
+        assert_eq!((visible[0].ssid.as_str(), visible[0].signal), ("Cafe", -30));
+
+        let status = parse_status("wpa_state=COMPLETED\nssid=Home\nip_address=192.168.1.20\n");
+        assert_eq!(status["wpa_state"], WPA_COMPLETED);
+        assert_eq!(status["ip_address"], "192.168.1.20");
+
+        let networks =
+            parse_networks("network id / ssid / bssid / flags\n0\tHome\tany\t[CURRENT]\n");
+        assert_eq!(networks["Home"], "0");
+        assert_eq!(hex("Hi!"), "486921");
+    }
+}
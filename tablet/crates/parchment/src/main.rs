@@ -1,45 +1,224 @@
+use nix::{
+    sys::wait::{waitpid, WaitPidFlag},
+    unistd::Pid,
+};
+use proc::{ProcFsRoot, State};
+use raft::{
+    templates::{template, TEMPLATES},
+    Drafts, DRAFT_PATH,
+};
 use shared::{
-    cont_recursive, kill_recursive, path_temp_icons, path_temp_pids, path_temp_screenshots,
-    processes, system_xochitl_process, TEMP_DIR,
+    audit::tail_audit_log,
+    cgroup::clear_cgroups,
+    config::Config,
+    cont_recursive, describe_exit, kill_recursive, launch_draft,
+    locale::locale_init,
+    metrics::metrics_init,
+    path_temp_icons, path_temp_logs, path_temp_pids, path_temp_screenshots,
+    pidfile::{lock_pids, read_pids},
+    reap_draft, report_launch_failure, system_xochitl_process, ProcessTree, LAUNCH_FAILURE_WINDOW,
+    TEMP_DIR,
 };
-use std::process::Command;
+use std::{path::PathBuf, process::Command, time::Duration};
 
-fn main() {
-    println!("parchment startup");
+/// Subcommand that writes a draft for a well-known app instead of starting the launcher
+const GENERATE_DRAFT: &str = "generate-draft";
+
+/// Replace an existing draft for the same app
+const FORCE_ARG: &str = "--force";
+
+/// Subcommand that prints the most recent entries in the audit log
+const AUDIT_LOG: &str = "audit-log";
+
+/// Entries the audit log subcommand prints when not given a count
+const AUDIT_LOG_DEFAULT_COUNT: usize = 20;
+
+/// How often processes left to parchment are checked for having exited
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reap exited processes left to parchment as the subreaper, such as drafts, which are
+/// detached from their launcher, reporting drafts that fail to start. wave is left out,
+/// as it's waited on directly.
+fn orphan_reaper(wave: usize) {
+    let parchment = std::process::id() as usize;
+    std::thread::spawn(move || loop {
+        std::thread::sleep(REAP_INTERVAL);
+        let procs = ProcessTree::scan();
+        let uptime = ProcFsRoot::system().uptime().ok();
+        let pidfiles = read_pids();
+        for zombie in procs.iter().filter(|proc| {
+            proc.stat.parent_process_id == parchment
+                && proc.stat.state == State::Zombie
+                && proc.stat.process_id != wave
+        }) {
+            let pid = zombie.stat.process_id;
+            let Some(pidfile) = pidfiles.iter().find(|pidfile| pidfile.pid == pid) else {
+                // Something a draft started and left behind
+                waitpid(Pid::from_raw(pid as i32), Some(WaitPidFlag::WNOHANG)).ok();
+                continue;
+            };
 
-    // Kill any leftover processes
-    if let Ok(dir) = std::fs::read_dir(path_temp_pids()) {
-        for result in dir {
-            let result = result.unwrap();
-            let file_type = result.file_type().unwrap();
-            if !file_type.is_file() {
+            let Some(exit) = reap_draft(&pidfile.id, pid, false) else {
                 continue;
+            };
+            let young = uptime
+                .as_ref()
+                .is_some_and(|uptime| zombie.stat.age(uptime) < LAUNCH_FAILURE_WINDOW);
+            if exit.failed() && young {
+                let draft = Drafts::new()
+                    .map(Drafts::take)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(|draft| draft.id() == pidfile.id);
+                if let Some(draft) = draft {
+                    report_launch_failure(&draft, &describe_exit(exit));
+                }
             }
+        }
+    });
+}
+
+/// Write a draft for the app named in args to the draft directory
+fn generate_draft(args: &[String]) -> Result<PathBuf, String> {
+    let ids = TEMPLATES
+        .iter()
+        .map(|template| template.id)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let app = args
+        .iter()
+        .find(|arg| !arg.starts_with("--"))
+        .ok_or_else(|| {
+            format!(
+                "Usage: parchment {GENERATE_DRAFT:} [{FORCE_ARG:}] APP, where APP is one of {ids:}"
+            )
+        })?;
+    let template =
+        template(app).ok_or_else(|| format!("No template for {app:?}, try one of {ids:}"))?;
+
+    let mut draft = template.generate().ok_or_else(|| {
+        format!(
+            "{} isn't installed, looked for {}",
+            template.name,
+            template.calls.join(", ")
+        )
+    })?;
+
+    let existing = Drafts::new()
+        .map(Drafts::take)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|existing| existing.name == draft.name || existing.call == draft.call);
+    if let Some(existing) = existing {
+        if !args.iter().any(|arg| arg == FORCE_ARG) {
+            return Err(format!(
+                "{:?} already has a draft at {:?}, pass {FORCE_ARG:} to replace it",
+                existing.name, existing.path
+            ));
+        }
+        draft.path = existing.path;
+    }
+
+    draft
+        .save(DRAFT_PATH)
+        .map_err(|e| format!("Failed to write draft: {e:}"))
+}
 
-            let file_name = result.file_name();
-            let pid = std::fs::read_to_string(result.path())
-                .unwrap()
-                .parse::<usize>()
-                .unwrap();
-
-            if let Some(proc) = processes()
-                .filter(|proc| Some(proc) != system_xochitl_process().as_ref())
-                .find(|proc| proc.stat.process_id == pid)
-            {
-                println!("Killing leftover {:?} process with PID {}", file_name, pid);
-                cont_recursive(&proc);
-                kill_recursive(&proc);
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some(GENERATE_DRAFT) {
+        match generate_draft(&args[1..]) {
+            Ok(path) => println!("Wrote {path:?}"),
+            Err(e) => {
+                println!("{e:}");
+                std::process::exit(1);
             }
         }
+        return;
+    }
+    if args.first().map(String::as_str) == Some(AUDIT_LOG) {
+        let count = match args.get(1).map(|count| count.parse::<usize>()) {
+            None => AUDIT_LOG_DEFAULT_COUNT,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                println!("Usage: parchment {AUDIT_LOG:} [COUNT]");
+                std::process::exit(1);
+            }
+        };
+        for line in tail_audit_log(count) {
+            println!("{line:}");
+        }
+        return;
+    }
+
+    println!("parchment startup");
+
+    // Take in drafts as they're detached from the launcher, so they're reaped and reported on
+    let subreaper =
+        unsafe { nix::libc::prctl(nix::libc::PR_SET_CHILD_SUBREAPER, 1 as nix::libc::c_ulong) };
+    if subreaper != 0 {
+        println!("Failed to become a subreaper, exited drafts may be left unreaped");
     }
 
+    // Kill any leftover processes, holding the pid directory so a restarting tray
+    // can't record a launch that's about to be cleared
+    let pid_lock = lock_pids().unwrap();
+    let procs = ProcessTree::scan();
+    let system_xochitl = system_xochitl_process(&procs);
+    for pidfile in read_pids() {
+        if let Some(proc) = procs
+            .iter()
+            .filter(|proc| Some(*proc) != system_xochitl)
+            .find(|proc| proc.stat.process_id == pidfile.pid)
+        {
+            println!(
+                "Killing leftover {:?} process with PID {}",
+                pidfile.id, pidfile.pid
+            );
+            cont_recursive(&procs, proc);
+            kill_recursive(&procs, proc);
+        }
+    }
+
+    clear_cgroups();
+
     // Clear temporary directory and recreate it
     std::fs::remove_dir_all(TEMP_DIR).ok();
     std::fs::create_dir_all(TEMP_DIR).unwrap();
     std::fs::create_dir_all(path_temp_screenshots()).unwrap();
     std::fs::create_dir_all(path_temp_icons()).unwrap();
     std::fs::create_dir_all(path_temp_pids()).unwrap();
+    std::fs::create_dir_all(path_temp_logs()).unwrap();
+    drop(pid_lock);
+
+    let config = Config::load();
+    metrics_init(&config);
+    locale_init(config.locale.as_deref());
+
+    // Launch the autostart draft, if one is marked
+    match Drafts::new() {
+        Ok(drafts) => {
+            let mut autostart = drafts.iter().filter(|draft| draft.auto_launch);
+            if let Some(draft) = autostart.next() {
+                println!("Autostarting {:?}", draft.name);
+                // Detached like any other launch, so the orphan reaper takes care of it
+                if let Err(e) = launch_draft(draft) {
+                    println!("Failed to autostart {:?}: {e:}", draft.name);
+                }
+            }
+
+            for draft in autostart {
+                println!(
+                    "Warning: Ignoring additional autostart draft {:?}",
+                    draft.name
+                );
+            }
+        }
+        Err(e) => println!("Failed to load drafts for autostart: {e:}"),
+    }
 
     // Start wave
-    Command::new("./wave").spawn().unwrap().wait().unwrap();
+    let mut wave = Command::new("./wave").spawn().unwrap();
+    orphan_reaper(wave.id() as usize);
+    wave.wait().unwrap();
 }
@@ -1,4 +1,48 @@
-use std::{collections::BTreeMap, error::Error, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    path::{Path, PathBuf},
+    str::{FromStr, SplitWhitespace},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use nix::unistd::{sysconf, SysconfVar};
+
+/// Clock ticks per second assumed if the kernel can't be asked, USER_HZ on most builds
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// Bytes per page assumed if the kernel can't be asked
+pub const PAGE_SIZE: usize = 4096;
+
+fn sysconf_or(var: SysconfVar, default: u64) -> u64 {
+    match sysconf(var) {
+        Ok(Some(value)) if value > 0 => value as u64,
+        _ => default,
+    }
+}
+
+/// Clock ticks per second that CPU and start times in stat are counted in
+pub fn clock_ticks_per_sec() -> u64 {
+    static TICKS: OnceLock<u64> = OnceLock::new();
+    *TICKS.get_or_init(|| sysconf_or(SysconfVar::CLK_TCK, CLOCK_TICKS_PER_SEC))
+}
+
+/// Bytes per page that resident set sizes in stat are counted in
+pub fn page_size() -> usize {
+    static PAGE: OnceLock<u64> = OnceLock::new();
+    *PAGE.get_or_init(|| sysconf_or(SysconfVar::PAGE_SIZE, PAGE_SIZE as u64)) as usize
+}
+
+/// Length of a count of clock ticks
+pub fn ticks_duration(ticks: usize) -> Duration {
+    let hz = clock_ticks_per_sec();
+    let ticks = ticks as u64;
+    Duration::from_secs(ticks / hz) + Duration::from_nanos((ticks % hz) * 1_000_000_000 / hz)
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum State {
@@ -81,6 +125,69 @@ pub struct Stat {
     pub exit_code: usize,
 }
 
+impl Stat {
+    /// CPU time the process has spent in user mode
+    pub fn utime_duration(&self) -> Duration {
+        ticks_duration(self.user_time)
+    }
+
+    /// CPU time the process has spent in the kernel
+    pub fn stime_duration(&self) -> Duration {
+        ticks_duration(self.kernel_time)
+    }
+
+    /// CPU time the process itself has used, user and kernel
+    pub fn cpu_time(&self) -> Duration {
+        ticks_duration(self.user_time + self.kernel_time)
+    }
+
+    /// When the process started, measured from boot
+    pub fn start_time_since_boot(&self) -> Duration {
+        ticks_duration(self.start_time)
+    }
+
+    /// How long the process has been running
+    pub fn age(&self, uptime: &Uptime) -> Duration {
+        uptime.up.saturating_sub(self.start_time_since_boot())
+    }
+
+    /// Memory the process holds resident, in bytes
+    pub fn rss_bytes(&self) -> usize {
+        self.resident_set_memory_size * page_size()
+    }
+}
+
+/// Share of one CPU used over an interval, as a percentage, from the CPU time spent in it
+pub fn cpu_percent(cpu_time: Duration, elapsed: Duration) -> f32 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    cpu_time.as_secs_f32() * 100.0 / elapsed.as_secs_f32()
+}
+
+/// Time since boot and time spent idle, summed over every CPU, from /proc/uptime
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Uptime {
+    pub up: Duration,
+    pub idle: Duration,
+}
+
+impl FromStr for Uptime {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let mut seconds = || -> Result<Duration, Box<dyn Error>> {
+            let seconds = parts.next().ok_or(StatError::MissingField)?;
+            Ok(Duration::try_from_secs_f64(seconds.parse()?)?)
+        };
+        Ok(Uptime {
+            up: seconds()?,
+            idle: seconds()?,
+        })
+    }
+}
+
 impl PartialEq for Stat {
     fn eq(&self, other: &Self) -> bool {
         self.process_id.eq(&other.process_id)
@@ -93,89 +200,106 @@ impl PartialOrd for Stat {
     }
 }
 
+/// Ways a stat line can be cut short or malformed
+#[derive(Debug)]
+enum StatError {
+    MissingFilename,
+    MissingField,
+}
+
+impl std::fmt::Display for StatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatError::MissingFilename => f.write_str("No parenthesized filename"),
+            StatError::MissingField => f.write_str("Too few fields"),
+        }
+    }
+}
+
+impl Error for StatError {}
+
+/// Parse the next field of a stat line
+fn field<T>(parts: &mut SplitWhitespace) -> Result<T, Box<dyn Error>>
+where
+    T: FromStr,
+    T::Err: Into<Box<dyn Error>>,
+{
+    parts
+        .next()
+        .ok_or(StatError::MissingField)?
+        .parse()
+        .map_err(Into::into)
+}
+
+/// Step over a field of a stat line that isn't kept
+fn skip_field(parts: &mut SplitWhitespace) -> Result<(), StatError> {
+    parts.next().map(drop).ok_or(StatError::MissingField)
+}
+
 impl FromStr for Stat {
     type Err = Box<dyn Error>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split_whitespace();
-
-        let pid = parts.next().unwrap().parse()?;
-
-        let mut tcomm = parts.next().unwrap().to_owned();
-        if tcomm.starts_with("(") && !tcomm.ends_with(")") {
-            loop {
-                let next = parts.next().unwrap();
-                tcomm += " ";
-                tcomm += next;
-                if next.ends_with(")") || next.ends_with("]") {
-                    break;
-                }
-            }
-        }
-        tcomm.remove(0);
-        tcomm.pop().unwrap();
+        // The filename is wrapped in parentheses and may itself hold spaces and parentheses,
+        // so it runs from the first opening to the last closing one
+        let (pid, rest) = s.split_once(" (").ok_or(StatError::MissingFilename)?;
+        let (tcomm, rest) = rest.rsplit_once(") ").ok_or(StatError::MissingFilename)?;
+        let pid = pid.trim().parse()?;
+        let mut parts = rest.split_whitespace();
 
         Ok(Stat {
             process_id: pid,
-            filename: tcomm,
-            state: parts.next().unwrap().parse().unwrap(),
-            parent_process_id: parts.next().unwrap().parse().unwrap(),
-            process_group: parts.next().unwrap().parse().unwrap(),
-            session_id: parts.next().unwrap().parse().unwrap(),
-            tty_number: parts.next().unwrap().parse().unwrap(),
-            tty_process_group: parts.next().unwrap().parse().unwrap(),
-            flags: parts.next().unwrap().parse().unwrap(),
-            minor_faults: parts.next().unwrap().parse().unwrap(),
-            minor_faults_children: parts.next().unwrap().parse().unwrap(),
-            major_faults: parts.next().unwrap().parse().unwrap(),
-            major_faults_children: parts.next().unwrap().parse().unwrap(),
-            user_time: parts.next().unwrap().parse().unwrap(),
-            user_time_children: parts.next().unwrap().parse().unwrap(),
-            kernel_time: parts.next().unwrap().parse().unwrap(),
-            kernel_time_children: parts.next().unwrap().parse().unwrap(),
-            priority: parts.next().unwrap().parse().unwrap(),
-            nice: parts.next().unwrap().parse().unwrap(),
-            num_threads: parts.next().unwrap().parse().unwrap(),
-            it_real_value: {
-                parts.next().unwrap();
-            },
-            start_time: parts.next().unwrap().parse().unwrap(),
-            virtual_memory_size: parts.next().unwrap().parse().unwrap(),
-            resident_set_memory_size: parts.next().unwrap().parse().unwrap(),
-            resident_set_memory_limit: parts.next().unwrap().parse().unwrap(),
-            start_code: parts.next().unwrap().parse().unwrap(),
-            end_code: parts.next().unwrap().parse().unwrap(),
-            start_stack: parts.next().unwrap().parse().unwrap(),
-            esp: parts.next().unwrap().parse().unwrap(),
-            eip: parts.next().unwrap().parse().unwrap(),
-            pending_signals: parts.next().unwrap().parse().unwrap(),
-            blocked_signals: parts.next().unwrap().parse().unwrap(),
-            ignored_signals: parts.next().unwrap().parse().unwrap(),
-            caught_signals: parts.next().unwrap().parse().unwrap(),
-            placeholder_0: {
-                parts.next().unwrap();
-            },
-            placeholder_1: {
-                parts.next().unwrap();
-            },
-            placeholder_2: {
-                parts.next().unwrap();
-            },
-            exit_signal: parts.next().unwrap().parse().unwrap(),
-            task_cpu: parts.next().unwrap().parse().unwrap(),
-            realtime_priority: parts.next().unwrap().parse().unwrap(),
-            scheduling_policy: parts.next().unwrap().parse().unwrap(),
-            block_io_ticks: parts.next().unwrap().parse().unwrap(),
-            guest_time: parts.next().unwrap().parse().unwrap(),
-            guest_time_children: parts.next().unwrap().parse().unwrap(),
-            start_data: parts.next().unwrap().parse().unwrap(),
-            end_data: parts.next().unwrap().parse().unwrap(),
-            start_brk: parts.next().unwrap().parse().unwrap(),
-            arg_start: parts.next().unwrap().parse().unwrap(),
-            arg_end: parts.next().unwrap().parse().unwrap(),
-            env_start: parts.next().unwrap().parse().unwrap(),
-            env_end: parts.next().unwrap().parse().unwrap(),
-            exit_code: parts.next().unwrap().parse().unwrap(),
+            filename: tcomm.to_string(),
+            state: field(&mut parts)?,
+            parent_process_id: field(&mut parts)?,
+            process_group: field(&mut parts)?,
+            session_id: field(&mut parts)?,
+            tty_number: field(&mut parts)?,
+            tty_process_group: field(&mut parts)?,
+            flags: field(&mut parts)?,
+            minor_faults: field(&mut parts)?,
+            minor_faults_children: field(&mut parts)?,
+            major_faults: field(&mut parts)?,
+            major_faults_children: field(&mut parts)?,
+            user_time: field(&mut parts)?,
+            kernel_time: field(&mut parts)?,
+            user_time_children: field(&mut parts)?,
+            kernel_time_children: field(&mut parts)?,
+            priority: field(&mut parts)?,
+            nice: field(&mut parts)?,
+            num_threads: field(&mut parts)?,
+            it_real_value: skip_field(&mut parts)?,
+            start_time: field(&mut parts)?,
+            virtual_memory_size: field(&mut parts)?,
+            resident_set_memory_size: field(&mut parts)?,
+            resident_set_memory_limit: field(&mut parts)?,
+            start_code: field(&mut parts)?,
+            end_code: field(&mut parts)?,
+            start_stack: field(&mut parts)?,
+            esp: field(&mut parts)?,
+            eip: field(&mut parts)?,
+            pending_signals: field(&mut parts)?,
+            blocked_signals: field(&mut parts)?,
+            ignored_signals: field(&mut parts)?,
+            caught_signals: field(&mut parts)?,
+            placeholder_0: skip_field(&mut parts)?,
+            placeholder_1: skip_field(&mut parts)?,
+            placeholder_2: skip_field(&mut parts)?,
+            exit_signal: field(&mut parts)?,
+            task_cpu: field(&mut parts)?,
+            realtime_priority: field(&mut parts)?,
+            scheduling_policy: field(&mut parts)?,
+            block_io_ticks: field(&mut parts)?,
+            guest_time: field(&mut parts)?,
+            guest_time_children: field(&mut parts)?,
+            start_data: field(&mut parts)?,
+            end_data: field(&mut parts)?,
+            start_brk: field(&mut parts)?,
+            arg_start: field(&mut parts)?,
+            arg_end: field(&mut parts)?,
+            env_start: field(&mut parts)?,
+            env_end: field(&mut parts)?,
+            exit_code: field(&mut parts)?,
         })
     }
 }
@@ -198,9 +322,124 @@ impl PartialOrd for Proc {
     }
 }
 
+/// I/O counters from /proc/<pid>/io
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Io {
+    /// Bytes read through any read call, including from pipes and sockets
+    pub read_chars: u64,
+    /// Bytes written through any write call, including to pipes and sockets
+    pub write_chars: u64,
+    pub read_syscalls: u64,
+    pub write_syscalls: u64,
+    /// Bytes fetched from storage
+    pub read_bytes: u64,
+    /// Bytes sent, or due to be sent, to storage
+    pub write_bytes: u64,
+    /// Bytes that were due to be written but were truncated away first
+    pub cancelled_write_bytes: u64,
+}
+
+impl Io {
+    /// Whether any storage writes were made between an earlier sample and this one
+    pub fn writing_since(&self, earlier: &Io) -> bool {
+        self.write_bytes > earlier.write_bytes
+    }
+}
+
+impl FromStr for Io {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut io = Io::default();
+        for line in s.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some(pair) => pair,
+                None => continue,
+            };
+            let value = value.trim().parse()?;
+            match key.trim() {
+                "rchar" => io.read_chars = value,
+                "wchar" => io.write_chars = value,
+                "syscr" => io.read_syscalls = value,
+                "syscw" => io.write_syscalls = value,
+                "read_bytes" => io.read_bytes = value,
+                "write_bytes" => io.write_bytes = value,
+                "cancelled_write_bytes" => io.cancelled_write_bytes = value,
+                _ => (),
+            }
+        }
+        Ok(io)
+    }
+}
+
 pub type Pid = usize;
+
+/// What an open file descriptor refers to, from its /proc/<pid>/fd link
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FdTarget {
+    File(PathBuf),
+    Socket(u64),
+    Pipe(u64),
+    /// Kernel objects without a file, such as eventfd or inotify
+    AnonInode(String),
+    Other(String),
+}
+
+impl FdTarget {
+    pub fn from_link(link: &Path) -> Self {
+        let link_str = match link.to_str() {
+            Some(link_str) => link_str,
+            None => return FdTarget::File(link.to_path_buf()),
+        };
+
+        let inode = |prefix: &str| {
+            link_str
+                .strip_prefix(prefix)?
+                .strip_suffix(']')?
+                .parse::<u64>()
+                .ok()
+        };
+
+        if let Some(inode) = inode("socket:[") {
+            FdTarget::Socket(inode)
+        } else if let Some(inode) = inode("pipe:[") {
+            FdTarget::Pipe(inode)
+        } else if let Some(kind) = link_str.strip_prefix("anon_inode:") {
+            FdTarget::AnonInode(kind.to_string())
+        } else if link.is_absolute() {
+            FdTarget::File(link.to_path_buf())
+        } else {
+            FdTarget::Other(link_str.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fd {
+    pub fd: u32,
+    pub target: FdTarget,
+}
+
+/// A process' open file descriptors, which requires the same permissions as ptrace
+pub fn proc_fds(pid: Pid) -> Result<Vec<Fd>, Box<dyn Error>> {
+    ProcFsRoot::system().fds(pid)
+}
+
+/// PIDs of every readable process with the provided file, or a file beneath it, open
+pub fn open_by<P: AsRef<Path>>(path: P) -> Result<Vec<Pid>, std::io::Error> {
+    ProcFsRoot::system().open_by(path)
+}
+
+/// Read a process' I/O counters, which requires the same permissions as ptrace
+pub fn proc_io(pid: Pid) -> Result<Io, Box<dyn Error>> {
+    ProcFsRoot::system().io(pid)
+}
+
 pub type ProcFs = BTreeMap<Pid, Proc>;
 
+/// A process read from procfs, or why its directory couldn't be read as one
+pub type ProcFsEntry = Result<(Pid, Proc), Box<dyn Error>>;
+
 #[derive(Debug)]
 enum ProcFsError {
     NotADirectory,
@@ -218,47 +457,279 @@ impl std::fmt::Display for ProcFsError {
 
 impl Error for ProcFsError {}
 
-pub fn proc_fs() -> Result<impl Iterator<Item = Result<(Pid, Proc), Box<dyn Error>>>, std::io::Error>
-{
-    Ok(
-        std::fs::read_dir("/proc")?.map::<Result<_, Box<dyn Error>>, _>(|result| {
-            let result = result?;
+/// errno for a process that no longer exists, which some procfs reads fail with in place
+/// of ENOENT while a process is exiting
+const ESRCH: i32 = 3;
 
-            let file_type = result.file_type()?;
-            if !file_type.is_dir() {
-                return Err(Box::new(ProcFsError::NotADirectory));
-            }
+/// Processes skipped by scans since startup for exiting while being read
+static VANISHED: AtomicUsize = AtomicUsize::new(0);
 
-            let file_name = result.file_name();
-            let file_name = file_name.to_str().unwrap();
-            if !file_name.chars().all(char::is_numeric) {
-                return Err(Box::new(ProcFsError::NotAPidDirectory));
-            }
+/// Number of processes skipped by scans since startup for exiting while being read
+pub fn vanished_processes() -> usize {
+    VANISHED.load(Ordering::Relaxed)
+}
 
-            let pid: Pid = file_name.parse()?;
-            let mut path = result.path();
-            path.push("stat");
+/// Whether an error reading a process' files came from it having exited
+fn vanished(e: &(dyn Error + 'static)) -> bool {
+    e.downcast_ref::<std::io::Error>().is_some_and(|e| {
+        e.kind() == std::io::ErrorKind::NotFound || e.raw_os_error() == Some(ESRCH)
+    })
+}
 
-            let stat = std::fs::read_to_string(path)?;
-            let stat = stat.parse::<Stat>()?;
+/// Where procfs is mounted on the device
+pub const PROC_ROOT: &str = "/proc";
 
-            let mut path = result.path();
-            path.push("cmdline");
+/// Environment variable overriding PROC_ROOT, for running inside a container or chroot
+/// whose processes are mounted elsewhere
+pub const PROC_ROOT_VAR: &str = "PROC_ROOT";
 
-            let cmdline = std::fs::read_to_string(path)?
-                .replace("\0", " ")
-                .trim()
-                .to_string();
+/// A procfs tree to read processes from, the system's own or a copy of one such as
+/// the fixtures under tests/fixtures/proc
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcFsRoot(pub PathBuf);
 
-            Ok((pid, Proc { stat, cmdline }))
-        }),
-    )
+impl Default for ProcFsRoot {
+    fn default() -> Self {
+        ProcFsRoot::system()
+    }
+}
+
+impl ProcFsRoot {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        ProcFsRoot(path.into())
+    }
+
+    /// The procfs named by PROC_ROOT_VAR, or PROC_ROOT if it isn't set
+    pub fn system() -> Self {
+        match std::env::var_os(PROC_ROOT_VAR) {
+            Some(path) if !path.is_empty() => ProcFsRoot::new(path),
+            _ => ProcFsRoot::new(PROC_ROOT),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of a file beneath the root, such as "mounts" or "net/tcp"
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Path of a file in a process' directory, such as "stat" or "fd"
+    pub fn pid_path(&self, pid: Pid, file: &str) -> PathBuf {
+        self.0.join(pid.to_string()).join(file)
+    }
+
+    /// Processes under the root
+    ///
+    /// Those that exit part way through being read are skipped rather than reported as
+    /// errors, and counted towards vanished_processes.
+    pub fn processes(&self) -> Result<impl Iterator<Item = ProcFsEntry>, std::io::Error> {
+        Ok(std::fs::read_dir(&self.0)?
+            .map::<Result<_, Box<dyn Error>>, _>(|result| {
+                let result = result?;
+
+                let file_type = result.file_type()?;
+                if !file_type.is_dir() {
+                    return Err(Box::new(ProcFsError::NotADirectory));
+                }
+
+                let file_name = result.file_name();
+                let file_name = file_name.to_str().ok_or(ProcFsError::NotAPidDirectory)?;
+                if !file_name.chars().all(char::is_numeric) {
+                    return Err(Box::new(ProcFsError::NotAPidDirectory));
+                }
+
+                let pid: Pid = file_name.parse()?;
+                let mut path = result.path();
+                path.push("stat");
+
+                let stat = std::fs::read_to_string(path)?;
+                let stat = stat.parse::<Stat>()?;
+
+                let mut path = result.path();
+                path.push("cmdline");
+
+                let cmdline = std::fs::read_to_string(path)?
+                    .replace("\0", " ")
+                    .trim()
+                    .to_string();
+
+                Ok((pid, Proc { stat, cmdline }))
+            })
+            .filter(|entry| match entry {
+                Err(e) if vanished(e.as_ref()) => {
+                    VANISHED.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+                _ => true,
+            }))
+    }
+
+    /// A process' open file descriptors
+    ///
+    /// Descriptors closed while the directory is being read are skipped.
+    pub fn fds(&self, pid: Pid) -> Result<Vec<Fd>, Box<dyn Error>> {
+        let mut fds = std::fs::read_dir(self.pid_path(pid, "fd"))?
+            .flatten()
+            .filter_map(|entry| {
+                let fd = entry.file_name().to_str()?.parse().ok()?;
+                let link = std::fs::read_link(entry.path()).ok()?;
+                Some(Fd {
+                    fd,
+                    target: FdTarget::from_link(&link),
+                })
+            })
+            .collect::<Vec<_>>();
+        fds.sort_by_key(|fd| fd.fd);
+        Ok(fds)
+    }
+
+    /// PIDs of every readable process with the provided file, or a file beneath it, open
+    pub fn open_by<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Pid>, std::io::Error> {
+        let path = path.as_ref();
+        Ok(std::fs::read_dir(&self.0)?
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<Pid>().ok())
+            .filter(|pid| {
+                self.fds(*pid).is_ok_and(|fds| {
+                    fds.iter().any(|fd| match &fd.target {
+                        FdTarget::File(file) => file.starts_with(path),
+                        _ => false,
+                    })
+                })
+            })
+            .collect())
+    }
+
+    /// Time since boot
+    pub fn uptime(&self) -> Result<Uptime, Box<dyn Error>> {
+        std::fs::read_to_string(self.join("uptime"))?.parse()
+    }
+
+    /// A single process' status, without scanning the others
+    pub fn stat(&self, pid: Pid) -> Result<Stat, Box<dyn Error>> {
+        std::fs::read_to_string(self.pid_path(pid, "stat"))?.parse()
+    }
+
+    /// A process' I/O counters
+    pub fn io(&self, pid: Pid) -> Result<Io, Box<dyn Error>> {
+        std::fs::read_to_string(self.pid_path(pid, "io"))?.parse()
+    }
+}
+
+/// Processes under the system's procfs
+pub fn proc_fs() -> Result<impl Iterator<Item = ProcFsEntry>, std::io::Error> {
+    ProcFsRoot::system().processes()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Inputs kept from fuzzing the stat parser, which must parse or fail without panicking
+    #[test]
+    fn replays_fuzz_corpus() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fuzz/corpus/stat");
+        for entry in std::fs::read_dir(corpus).unwrap().flatten() {
+            let input = std::fs::read_to_string(entry.path()).unwrap();
+            let _ = input.parse::<Stat>();
+        }
+
+        let stat = "481 (a) b) c) R 480 480 480 0 -1 4194560 20281 600520 69 2004 695 1697 98680 18212 20 0 6 0 7 25395200 2553 18446744073709551615 1 1 0 0 0 0 0 4096 1088 0 0 0 17 0 0 0 0 0 0 0 0 0 0 0 0 0 0"
+            .parse::<Stat>()
+            .unwrap();
+        assert_eq!(stat.filename, "a) b) c");
+        assert_eq!(stat.state, State::Running);
+        assert!("481 (luajit) R 480 480".parse::<Stat>().is_err());
+    }
+
+    #[test]
+    fn parses_io() {
+        let io = "rchar: 323934931\nwchar: 323929600\nsyscr: 632687\nsyscw: 632675\nread_bytes: 4096\nwrite_bytes: 323932160\ncancelled_write_bytes: 0\n"
+            .parse::<Io>()
+            .unwrap();
+        assert_eq!(io.read_bytes, 4096);
+        assert_eq!(io.write_bytes, 323932160);
+        assert!(io.writing_since(&Io::default()));
+    }
+
+    #[test]
+    fn cpu_percent_of_interval() {
+        let secs = Duration::from_secs_f32;
+        assert_eq!(cpu_percent(secs(0.5), secs(1.0)), 50.0);
+        assert_eq!(cpu_percent(secs(10.0), secs(10.0)), 100.0);
+        assert_eq!(cpu_percent(secs(0.1), Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn converts_ticks_and_pages() {
+        let hz = clock_ticks_per_sec() as usize;
+        assert_eq!(ticks_duration(hz * 3 + hz / 2), Duration::from_millis(3500));
+
+        let root =
+            ProcFsRoot::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proc"));
+        let uptime = root.uptime().unwrap();
+        assert_eq!(uptime.up, Duration::from_millis(11731640));
+        assert!("11731.64".parse::<Uptime>().is_err());
+
+        let stat = std::fs::read_to_string(root.pid_path(481, "stat"))
+            .unwrap()
+            .parse::<Stat>()
+            .unwrap();
+        assert_eq!((stat.user_time, stat.kernel_time), (695, 1697));
+        assert_eq!(stat.cpu_time(), ticks_duration(695 + 1697));
+        assert_eq!(stat.rss_bytes(), 2553 * page_size());
+        assert_eq!(stat.age(&uptime), uptime.up - ticks_duration(7));
+    }
+
+    #[test]
+    fn parses_fd_targets() {
+        assert_eq!(
+            FdTarget::from_link(Path::new("socket:[48213]")),
+            FdTarget::Socket(48213)
+        );
+        assert_eq!(
+            FdTarget::from_link(Path::new("anon_inode:[eventfd]")),
+            FdTarget::AnonInode("[eventfd]".to_string())
+        );
+        assert_eq!(
+            FdTarget::from_link(Path::new("/home/root/notes.pdf")),
+            FdTarget::File("/home/root/notes.pdf".into())
+        );
+    }
+
+    #[test]
+    fn reads_fixture_root() {
+        let root =
+            ProcFsRoot::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/proc"));
+        let procs = root.processes().unwrap().flatten().collect::<ProcFs>();
+        assert_eq!(procs.len(), 8);
+        assert_eq!(procs[&1024].stat.process_id, 1024);
+        assert!(root.fds(1024).is_err());
+        assert_eq!(root.pid_path(481, "io"), root.path().join("481/io"));
+    }
+
+    #[test]
+    fn skips_vanished_processes() {
+        let root = std::env::temp_dir().join(format!("proc-vanished-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("4242")).unwrap();
+        std::fs::write(root.join("uptime"), "").unwrap();
+
+        let before = vanished_processes();
+        let entries = ProcFsRoot::new(&root)
+            .processes()
+            .unwrap()
+            .collect::<Vec<_>>();
+        std::fs::remove_dir_all(&root).ok();
+
+        // The stray file is still an error, the PID directory without a stat is skipped
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].is_err());
+        assert!(vanished_processes() > before);
+    }
+
     #[test]
     fn test() {
         let proc_fs = proc_fs().unwrap().collect::<Vec<_>>();
@@ -1,13 +1,100 @@
 //! Parser for draft application files
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
     ffi::OsStr,
+    fmt::{Display, Write},
     ops::{Deref, DerefMut},
-    path::PathBuf, error::Error,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
 };
 
+pub mod templates;
+
 pub const DRAFT_PATH: &'static str = "/opt/etc/draft";
 pub const ICONS_DIR: &'static str = "icons";
 
+/// Key naming another file to read in its place, relative to the file including it
+pub const INCLUDE_KEY: &str = "include";
+
+/// Unique key for a draft, see Draft::id
+pub type DraftId = String;
+
+/// How a draft is closed from the tray
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SafeKill {
+    /// Kill it outright
+    #[default]
+    Kill,
+    /// Ask it to exit with SIGTERM, only killing it if it doesn't in time
+    Term,
+    /// Ask the user first, then close as with Term
+    Confirm,
+}
+
+impl FromStr for SafeKill {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "kill" => SafeKill::Kill,
+            "term" => SafeKill::Term,
+            "confirm" => SafeKill::Confirm,
+            _ => return Err("Draft has an invalid safeKill policy"),
+        })
+    }
+}
+
+impl Display for SafeKill {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SafeKill::Kill => "kill",
+            SafeKill::Term => "term",
+            SafeKill::Confirm => "confirm",
+        })
+    }
+}
+
+/// What happens to a draft when another is switched to from the tray
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OnSwitch {
+    /// Leave it stopped, to be continued later
+    #[default]
+    Suspend,
+    /// Kill it, freeing its memory
+    Kill,
+}
+
+impl FromStr for OnSwitch {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "suspend" => OnSwitch::Suspend,
+            "kill" => OnSwitch::Kill,
+            _ => return Err("Draft has an invalid onSwitch policy"),
+        })
+    }
+}
+
+impl Display for OnSwitch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OnSwitch::Suspend => "suspend",
+            OnSwitch::Kill => "kill",
+        })
+    }
+}
+
+/// Named set of arguments to launch a draft with, from profile.<name>.callArgs lines
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LaunchProfile {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Draft {
     pub name: String,
@@ -16,42 +103,331 @@ pub struct Draft {
     pub which: Option<String>,
     pub term: Option<String>,
     pub icon: Option<String>,
+    pub auto_launch: bool,
+    /// Launcher gestures to disable while this draft is in the foreground,
+    /// such as swipe for the bottom edge or a tapN binding
+    pub gesture_mask: Vec<String>,
+    /// Scheduling priority to launch with, from -20 (highest) to 19 (lowest)
+    pub nice: Option<i32>,
+    /// CPUs this draft may run on, or all of them if empty
+    pub cpu_affinity: Vec<usize>,
+    /// Memory budget in MiB, enforced through the draft's cgroup
+    pub memory_limit: Option<u64>,
+    /// Extra environment variables to launch with, from env=NAME=value lines
+    pub env: BTreeMap<String, String>,
+    /// How the tray's close button ends this draft
+    pub safe_kill: SafeKill,
+    /// What happens to this draft when another is switched to, the launcher's setting if unset
+    pub on_switch: Option<OnSwitch>,
+    /// Shell command run periodically while this draft is in the foreground,
+    /// exiting unsuccessfully when the draft has hung
+    pub health_check: Option<String>,
+    /// Shell command that makes this draft redraw, run if it's left the screen unchanged
+    /// a moment after being continued. Drafts without one aren't checked.
+    pub repaint: Option<String>,
+    /// Run each time this draft is continued, either a signal name such as SIGUSR1 to send
+    /// to its process, or a shell command
+    pub resume_hook: Option<String>,
+    /// Alternative ways to launch, in the order they're declared
+    pub profiles: Vec<LaunchProfile>,
+    /// Arguments for this launch, set by choosing a profile
+    pub args: Vec<String>,
+    /// File this draft was loaded from, reused when saving
+    pub path: Option<PathBuf>,
+    /// Files this draft's own file includes, as written, kept when saving
+    pub includes: Vec<String>,
+    /// Lines the included files provide, left out when saving so they stay in those files
+    pub included: BTreeSet<String>,
+    /// Keys this parser doesn't recognize, such as launcher-specific extensions
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Name of the launch profile a profile.<name>.callArgs key sets
+fn profile_name(key: &str) -> Option<&str> {
+    key.strip_prefix("profile.")?.strip_suffix(".callArgs")
+}
+
+/// Split a draft line into its key and value, None for blank lines, comments and
+/// [section] headers, which are read over as if every key were at the top level
+fn parse_line(line: &str) -> Option<Result<(&str, Cow<'_, str>), &'static str>> {
+    let line = line.trim();
+    if line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with(';')
+        || (line.starts_with('[') && line.ends_with(']'))
+    {
+        return None;
+    }
+
+    Some(
+        line.split_once('=')
+            .map(|(key, value)| (key.trim(), parse_value(value)))
+            .ok_or("Draft line is not a key=value pair"),
+    )
+}
+
+/// A value without its surrounding quotes, or cut short at a # comment following whitespace.
+/// Only # starts a trailing comment, as ; often appears in the shell commands drafts run.
+fn parse_value(value: &str) -> Cow<'_, str> {
+    let value = value.trim();
+    if let Some(unquoted) = unquote(value) {
+        return Cow::Owned(unquoted);
+    }
+
+    let end = value
+        .match_indices('#')
+        .map(|(i, _)| i)
+        .find(|i| value[..*i].ends_with(char::is_whitespace))
+        .unwrap_or(value.len());
+    Cow::Borrowed(value[..end].trim_end())
+}
+
+/// What's inside a quoted value with escaped backslashes and quotes undone, None if the
+/// value isn't quoted or something other than a comment follows the closing quote
+fn unquote(value: &str) -> Option<String> {
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut unquoted = String::new();
+    let mut chars = value[1..].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.clone().next() {
+                Some(next) if next == quote || next == '\\' => {
+                    unquoted.push(next);
+                    chars.next();
+                }
+                _ => unquoted.push(c),
+            },
+            c if c == quote => {
+                let rest = chars.as_str().trim_start();
+                return (rest.is_empty() || rest.starts_with('#')).then_some(unquoted);
+            }
+            c => unquoted.push(c),
+        }
+    }
+    None
+}
+
+/// A value as written to a draft file, quoted if reading it back would otherwise change it
+fn quote_value(value: &str) -> Cow<'_, str> {
+    if parse_value(value) == value {
+        Cow::Borrowed(value)
+    } else {
+        Cow::Owned(format!(
+            "\"{}\"",
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        ))
+    }
+}
+
+/// Split a profile's callArgs at whitespace, keeping quoted runs together as sh would.
+/// Only within double quotes do backslashes escape, and only quotes and backslashes.
+fn split_args(value: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (Some(open), c) if c == open => quote = None,
+            (Some('"'), '\\') if matches!(chars.peek(), Some('"' | '\\')) => {
+                arg.get_or_insert_with(String::new)
+                    .push(chars.next().unwrap());
+            }
+            (_, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+/// Arguments as written to a callArgs value, quoted where split_args would otherwise change them
+fn join_args(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            if !arg.is_empty()
+                && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+            {
+                Cow::Borrowed(arg.as_str())
+            } else {
+                Cow::Owned(format!(
+                    "\"{}\"",
+                    arg.replace('\\', "\\\\").replace('"', "\\\"")
+                ))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A draft file with the files it includes read in their place, failing on an include cycle
+fn expand_includes(path: &Path, stack: &mut Vec<PathBuf>) -> Result<String, Box<dyn Error>> {
+    let canonical = path.canonicalize()?;
+    if stack.contains(&canonical) {
+        return Err(format!("Draft include cycle through {path:?}").into());
+    }
+    let file = std::fs::read_to_string(path)?;
+
+    stack.push(canonical);
+    let mut expanded = String::new();
+    for line in file.lines() {
+        match parse_line(line) {
+            Some(Ok((INCLUDE_KEY, value))) => {
+                let included = path.parent().unwrap_or(Path::new(".")).join(&*value);
+                expanded += &expand_includes(&included, stack)?;
+            }
+            _ => {
+                expanded += line;
+                expanded.push('\n');
+            }
+        }
+    }
+    stack.pop();
+
+    Ok(expanded)
 }
 
 impl Draft {
+    /// Parse a draft from the flat key=value format, also accepting INI-style sections,
+    /// quoted values and trailing comments
     pub fn new(input: &str) -> Result<Self, &'static str> {
+        let draft = Draft::parse(input)?;
+
+        if draft.name.is_empty() {
+            return Err("Draft has no name");
+        }
+
+        if draft.desc.is_empty() {
+            return Err("Draft has no description");
+        }
+
+        if !draft.call.exists() {
+            return Err("Draft launch target does not exist");
+        }
+
+        Ok(draft)
+    }
+
+    /// Parse draft lines without checking that they make up a whole draft
+    fn parse(input: &str) -> Result<Self, &'static str> {
         let mut draft = Draft::default();
 
-        for line in input
-            .lines()
-            .filter(|line| !line.starts_with("#") && !line.is_empty())
-        {
-            let (key, value) = line.split_once("=").unwrap();
+        // Editors on other platforms may leave a byte order mark at the start
+        for line in input.trim_start_matches('\u{feff}').lines() {
+            let Some(entry) = parse_line(line) else {
+                continue;
+            };
+            let (key, value) = entry?;
+            let value: &str = &value;
             match key {
+                // Read in by Draft::load, which knows where the including file is
+                INCLUDE_KEY => (),
                 "name" => draft.name = value.to_string(),
                 "desc" => draft.desc = value.to_string(),
                 "call" => draft.call = value.into(),
                 "which" => draft.which = Some(value.to_string()),
                 "term" => draft.term = Some(value.to_string()),
+                "autoLaunch" => draft.auto_launch = value == "true",
+                "gestureMask" => {
+                    draft.gesture_mask = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|gesture| !gesture.is_empty())
+                        .map(ToString::to_string)
+                        .collect();
+                }
+                "nice" => {
+                    let nice = value
+                        .trim()
+                        .parse::<i32>()
+                        .map_err(|_| "Draft has an invalid nice value")?;
+                    if !(-20..=19).contains(&nice) {
+                        return Err("Draft nice value is out of range");
+                    }
+                    draft.nice = Some(nice);
+                }
+                "cpuAffinity" => {
+                    draft.cpu_affinity = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|cpu| !cpu.is_empty())
+                        .map(|cpu| cpu.parse::<usize>())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| "Draft has an invalid CPU affinity")?;
+                }
+                // Optional policies that fall back to a default, so a bad value only loses the key
+                "memoryLimit" => match value.trim().parse() {
+                    Ok(memory_limit) => draft.memory_limit = Some(memory_limit),
+                    Err(_) => println!("Ignoring invalid draft memoryLimit {value:?}"),
+                },
+                "safeKill" => match value.trim().parse() {
+                    Ok(safe_kill) => draft.safe_kill = safe_kill,
+                    Err(e) => println!("Ignoring {value:?}: {e:}"),
+                },
+                "onSwitch" => match value.trim().parse() {
+                    Ok(on_switch) => draft.on_switch = Some(on_switch),
+                    Err(e) => println!("Ignoring {value:?}: {e:}"),
+                },
+                "healthCheck" => draft.health_check = Some(value.to_string()),
+                "repaint" => draft.repaint = Some(value.to_string()),
+                "resumeHook" => draft.resume_hook = Some(value.to_string()),
+                "env" => {
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or("Draft env entry is not a NAME=value pair")?;
+                    draft
+                        .env
+                        .insert(name.trim().to_string(), value.trim().to_string());
+                }
+                key if profile_name(key).is_some() => {
+                    let name = profile_name(key).unwrap();
+                    if name.is_empty() {
+                        return Err("Draft has a profile without a name");
+                    }
+                    draft.profiles.push(LaunchProfile {
+                        name: name.to_string(),
+                        args: split_args(value),
+                    });
+                }
                 "imgFile" => {
                     draft.icon =
                         Some(DRAFT_PATH.to_owned() + "/" + ICONS_DIR + "/" + value + ".png");
                 }
-                _ => (),
+                key => {
+                    draft.extra.insert(key.to_string(), value.to_string());
+                }
             }
         }
 
-        if draft.name.is_empty() {
-            return Err("Draft has no name");
-        }
+        Ok(draft)
+    }
 
-        if draft.desc.is_empty() {
-            return Err("Draft has no description");
+    /// Parse a draft file, reading in the files it includes
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut draft = Draft::new(&expand_includes(path, &mut vec![])?)?;
+        draft.path = Some(path.to_path_buf());
+
+        for line in std::fs::read_to_string(path)?.lines() {
+            if let Some(Ok((INCLUDE_KEY, value))) = parse_line(line) {
+                draft.includes.push(value.into_owned());
+            }
         }
 
-        if !draft.call.exists() {
-            return Err("Draft launch target does not exist");
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut included = String::new();
+        for include in &draft.includes {
+            included += &expand_includes(&dir.join(include), &mut vec![path.canonicalize()?])?;
         }
+        draft.included = Draft::parse(&included)?
+            .to_string()
+            .lines()
+            .map(ToString::to_string)
+            .collect();
 
         Ok(draft)
     }
@@ -59,6 +435,248 @@ impl Draft {
     pub fn file_name(&self) -> Option<&OsStr> {
         self.call.file_name()
     }
+
+    /// Identifier that stays unique when two drafts share a name,
+    /// used to key pidfiles, cached icons and anything else kept per draft
+    pub fn id(&self) -> DraftId {
+        match self.path.as_ref().and_then(|path| path.file_stem()) {
+            Some(stem) => format!("{}-{}", slug(&self.name), slug(&stem.to_string_lossy())),
+            None => slug(&self.name),
+        }
+    }
+
+    /// Fill in whatever this draft leaves unset from another
+    pub fn merge_missing(&mut self, other: &Draft) {
+        if self.which.is_none() {
+            self.which = other.which.clone();
+        }
+        if self.term.is_none() {
+            self.term = other.term.clone();
+        }
+        if self.icon.is_none() {
+            self.icon = other.icon.clone();
+        }
+        self.auto_launch |= other.auto_launch;
+        if self.gesture_mask.is_empty() {
+            self.gesture_mask = other.gesture_mask.clone();
+        }
+        if self.nice.is_none() {
+            self.nice = other.nice;
+        }
+        if self.cpu_affinity.is_empty() {
+            self.cpu_affinity = other.cpu_affinity.clone();
+        }
+        if self.memory_limit.is_none() {
+            self.memory_limit = other.memory_limit;
+        }
+        if self.safe_kill == SafeKill::default() {
+            self.safe_kill = other.safe_kill;
+        }
+        if self.on_switch.is_none() {
+            self.on_switch = other.on_switch;
+        }
+        if self.health_check.is_none() {
+            self.health_check = other.health_check.clone();
+        }
+        if self.repaint.is_none() {
+            self.repaint = other.repaint.clone();
+        }
+        if self.resume_hook.is_none() {
+            self.resume_hook = other.resume_hook.clone();
+        }
+        for (name, value) in &other.env {
+            self.env
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        for profile in &other.profiles {
+            if !self.profiles.iter().any(|own| own.name == profile.name) {
+                self.profiles.push(profile.clone());
+            }
+        }
+        for (key, value) in &other.extra {
+            self.extra
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    /// This draft set to launch with one of its profiles
+    pub fn with_profile(&self, name: &str) -> Option<Draft> {
+        let profile = self.profiles.iter().find(|profile| profile.name == name)?;
+        Some(Draft {
+            args: profile.args.clone(),
+            ..self.clone()
+        })
+    }
+
+    /// Write this draft to the provided directory, keeping its original file name if it has one
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<PathBuf> {
+        let file_name = match self.path.as_ref().and_then(|path| path.file_name()) {
+            Some(file_name) => PathBuf::from(file_name),
+            None => {
+                let mut file_name = PathBuf::from(slug(&self.name));
+                file_name.set_extension("draft");
+                file_name
+            }
+        };
+
+        // Includes are relative to the including file, so follow it if it's moving
+        let origin = self.path.as_ref().and_then(|path| path.parent());
+        let mut contents = String::new();
+        for include in &self.includes {
+            let include = match origin {
+                Some(origin) if origin != dir.as_ref() => origin.join(include),
+                _ => PathBuf::from(include),
+            };
+            writeln!(
+                contents,
+                "{INCLUDE_KEY:}={}",
+                quote_value(&include.to_string_lossy())
+            )
+            .unwrap();
+        }
+        for line in self.to_string().lines() {
+            if !self.included.contains(line) {
+                contents += line;
+                contents.push('\n');
+            }
+        }
+
+        let path = dir.as_ref().join(file_name);
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+impl Display for Draft {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "name={}", quote_value(&self.name))?;
+        writeln!(f, "desc={}", quote_value(&self.desc))?;
+        writeln!(f, "call={}", quote_value(&self.call.to_string_lossy()))?;
+
+        if let Some(which) = &self.which {
+            writeln!(f, "which={}", quote_value(which))?;
+        }
+
+        if let Some(term) = &self.term {
+            writeln!(f, "term={}", quote_value(term))?;
+        }
+
+        if let Some(icon) = &self.icon {
+            // Icons are stored as full paths, but drafts reference them by name
+            let icon = Path::new(icon);
+            let name = icon.file_stem().unwrap_or(icon.as_os_str());
+            writeln!(f, "imgFile={}", quote_value(&name.to_string_lossy()))?;
+        }
+
+        if self.auto_launch {
+            writeln!(f, "autoLaunch=true")?;
+        }
+
+        if !self.gesture_mask.is_empty() {
+            writeln!(f, "gestureMask={}", self.gesture_mask.join(","))?;
+        }
+
+        if let Some(nice) = self.nice {
+            writeln!(f, "nice={nice:}")?;
+        }
+
+        if !self.cpu_affinity.is_empty() {
+            let cpus = self
+                .cpu_affinity
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            writeln!(f, "cpuAffinity={}", cpus.join(","))?;
+        }
+
+        if let Some(memory_limit) = self.memory_limit {
+            writeln!(f, "memoryLimit={memory_limit:}")?;
+        }
+
+        if self.safe_kill != SafeKill::default() {
+            writeln!(f, "safeKill={}", self.safe_kill)?;
+        }
+
+        if let Some(on_switch) = self.on_switch {
+            writeln!(f, "onSwitch={on_switch:}")?;
+        }
+
+        if let Some(health_check) = &self.health_check {
+            writeln!(f, "healthCheck={}", quote_value(health_check))?;
+        }
+
+        if let Some(repaint) = &self.repaint {
+            writeln!(f, "repaint={}", quote_value(repaint))?;
+        }
+
+        if let Some(resume_hook) = &self.resume_hook {
+            writeln!(f, "resumeHook={}", quote_value(resume_hook))?;
+        }
+
+        for (name, value) in &self.env {
+            writeln!(f, "env={}", quote_value(&format!("{name:}={value:}")))?;
+        }
+
+        for profile in &self.profiles {
+            writeln!(
+                f,
+                "profile.{}.callArgs={}",
+                profile.name,
+                quote_value(&join_args(&profile.args))
+            )?;
+        }
+
+        for (key, value) in &self.extra {
+            writeln!(f, "{key:}={}", quote_value(value))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowercase alphanumerics with everything else replaced by hyphens, safe to use in file names
+fn slug(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+/// Collapse drafts launching the same program, as reinstalls tend to leave, into the most
+/// recently modified one, filling in anything it leaves unset from the others
+fn dedup_drafts(drafts: Vec<(Draft, SystemTime)>) -> Vec<Draft> {
+    let mut by_call = BTreeMap::<PathBuf, Vec<(Draft, SystemTime)>>::new();
+    for (draft, modified) in drafts {
+        let call = draft
+            .call
+            .canonicalize()
+            .unwrap_or_else(|_| draft.call.clone());
+        by_call.entry(call).or_default().push((draft, modified));
+    }
+
+    by_call
+        .into_iter()
+        .map(|(call, mut duplicates)| {
+            duplicates.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+            let mut duplicates = duplicates.into_iter().map(|(draft, _)| draft);
+            let mut draft = duplicates.next().unwrap();
+            for duplicate in duplicates {
+                println!(
+                    "Merging duplicate draft {:?} ({:?}) into {:?} ({:?}), both launch {call:?}",
+                    duplicate.name, duplicate.path, draft.name, draft.path
+                );
+                draft.merge_missing(&duplicate);
+            }
+            draft
+        })
+        .collect()
 }
 
 #[derive(Debug, Default, Clone)]
@@ -80,18 +698,34 @@ impl DerefMut for Drafts {
 
 impl Drafts {
     pub fn new() -> Result<Self, Box<dyn Error>> {
+        Self::load(DRAFT_PATH)
+    }
+
+    /// Parse every draft file in a directory
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Self, Box<dyn Error>> {
         Ok(Drafts({
-            let draft_paths = std::fs::read_dir("/opt/etc/draft")?
+            let draft_paths = std::fs::read_dir(dir)?
                 .flatten()
                 .map(|entry| entry.path())
                 .filter(|path| path.extension() == Some(OsStr::new("draft")));
 
             let mut drafts = vec![];
             for path in draft_paths {
-                let file = std::fs::read_to_string(path)?;
-                drafts.push(Draft::new(&file)?);
+                // One broken file shouldn't hide every other draft
+                let draft = match Draft::load(&path) {
+                    Ok(draft) => draft,
+                    Err(e) => {
+                        println!("Skipping draft {path:?}: {e:}");
+                        continue;
+                    }
+                };
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                drafts.push((draft, modified));
             }
 
+            let mut drafts = dedup_drafts(drafts);
             drafts.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
 
             drafts
@@ -101,4 +735,187 @@ impl Drafts {
     pub fn take(self) -> Vec<Draft> {
         self.0
     }
+
+    /// Write every draft to the provided directory
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        for draft in self.iter() {
+            draft.save(&dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Inputs kept from fuzzing the draft parser, which must parse or fail without panicking
+    #[test]
+    fn replays_fuzz_corpus() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fuzz/corpus/draft");
+        for entry in std::fs::read_dir(corpus).unwrap().flatten() {
+            let input = std::fs::read_to_string(entry.path()).unwrap();
+            let _ = Draft::new(&input);
+        }
+
+        assert!(Draft::new("name=yaft\ndesc=Terminal\ncall=/bin/sh\nno equals\n").is_err());
+        let draft = Draft::new("name=yaft\ndesc=Terminal\ncall=/bin/sh\nprofile.callArgs=-x\n");
+        assert!(draft.unwrap().profiles.is_empty());
+    }
+
+    #[test]
+    fn reads_ini_style_drafts() {
+        let draft = Draft::new(
+            "; Shared by the KOReader packages\n[draft]\nname = \"KOReader\" # shown under the icon\ndesc='Ebook reader'\ncall=/bin/sh\n\n[launch]\nnice=5\nrepaint=kill -USR1 1#2\n",
+        );
+        let draft = draft.unwrap();
+        assert_eq!(draft.name, "KOReader");
+        assert_eq!(draft.desc, "Ebook reader");
+        assert_eq!(draft.repaint.as_deref(), Some("kill -USR1 1#2"));
+        assert!(Draft::new("name=KOReader\ndesc=Reader\ncall=/bin/sh\nnice=5 ; x\n").is_err());
+    }
+
+    /// Draft files as found in the wild, each with a line expected in how it's written back out
+    const DRAFT_FILES: [(&str, &str); 11] = [
+        (
+            "name=KOReader\ndesc=Ebook reader supporting PDF, DjVu, EPUB, FB2 and much more\ncall=/bin/sh\nterm=:\n",
+            "desc=Ebook reader supporting PDF, DjVu, EPUB, FB2 and much more",
+        ),
+        (
+            "name=Puzzles\ndesc=Simon Tatham's Portable Puzzle Collection\ncall=/bin/sh\nterm=:\n",
+            "desc=Simon Tatham's Portable Puzzle Collection",
+        ),
+        ("name = yaft\ndesc = Terminal\ncall = /bin/sh\n", "name=yaft"),
+        ("name=yaft\r\ndesc=Terminal\r\ncall=/bin/sh\r\n", "desc=Terminal"),
+        ("\u{feff}name=yaft\ndesc=Terminal\ncall=/bin/sh\n", "name=yaft"),
+        ("\tname=yaft  \ndesc=Terminal\ncall=/bin/sh\n", "name=yaft"),
+        (
+            "name=yaft\ndesc=Terminal\ncall=/bin/sh\nhealthCheck=test \"$(cat /sys/power/state)\" = mem\n",
+            "healthCheck=test \"$(cat /sys/power/state)\" = mem",
+        ),
+        (
+            "name=Plato\ndesc=Reader\ncall=/bin/sh\nenv = PLATO_DIR = /home/root/plato\n",
+            "env=PLATO_DIR=/home/root/plato",
+        ),
+        (
+            "name=Notes\ndesc=\"Notes # and more\"\ncall=/bin/sh\n",
+            "desc=\"Notes # and more\"",
+        ),
+        (
+            "name=Quotes\ndesc=\"Says \\\"hi\\\" from C:\\\\\"\ncall=/bin/sh\n",
+            "desc=Says \"hi\" from C:\\",
+        ),
+        ("name=yaft\ndesc=Terminal\ncall=/bin/sh\nterm='  :'\n", "term=\"  :\""),
+    ];
+
+    #[test]
+    fn parses_real_world_drafts() {
+        for (file, line) in DRAFT_FILES {
+            let draft = Draft::new(file).unwrap_or_else(|e| panic!("{file:?}: {e:}"));
+            let written = draft.to_string();
+            assert!(
+                written.lines().any(|l| l == line),
+                "{file:?} wrote {written:?}"
+            );
+            assert_eq!(Draft::new(&written).unwrap().to_string(), written);
+        }
+
+        for file in [
+            "name=yaft\ndesc=Terminal\ncall=/bin/sh\nterm\n",
+            "name=\"\"\ndesc=Terminal\ncall=/bin/sh\n",
+            "name=yaft\ndesc=Terminal\ncall=/nonexistent\n",
+        ] {
+            assert!(Draft::new(file).is_err(), "{file:?}");
+        }
+    }
+
+    #[test]
+    fn reads_includes_and_rejects_cycles() {
+        let dir = std::env::temp_dir().join(format!("raft-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("common.inc"), "desc=Terminal\ncall=/bin/sh\n").unwrap();
+        std::fs::write(dir.join("yaft.draft"), "name=yaft\ninclude=common.inc\n").unwrap();
+        std::fs::write(dir.join("loop.inc"), "include=loop.draft\n").unwrap();
+        std::fs::write(dir.join("loop.draft"), "name=loop\ninclude=loop.inc\n").unwrap();
+
+        let mut draft = Draft::load(dir.join("yaft.draft")).unwrap();
+        assert_eq!(draft.desc, "Terminal");
+        assert_eq!(draft.path, Some(dir.join("yaft.draft")));
+        assert!(Draft::load(dir.join("loop.draft")).is_err());
+
+        // Saving keeps the include rather than copying in what it provides
+        draft.nice = Some(5);
+        draft.save(&dir).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("yaft.draft")).unwrap(),
+            "include=common.inc\nname=yaft\nnice=5\n"
+        );
+        std::fs::write(dir.join("common.inc"), "desc=Shell\ncall=/bin/sh\n").unwrap();
+        assert_eq!(Draft::load(dir.join("yaft.draft")).unwrap().desc, "Shell");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn skips_drafts_that_fail_to_parse() {
+        let dir = std::env::temp_dir().join(format!("raft-skip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("yaft.draft"),
+            "name=yaft\ndesc=Terminal\ncall=/bin/sh\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("typo.draft"),
+            "name=Typo\ndesc=Terminal\ncall=/bin/sh\nnice=high\n",
+        )
+        .unwrap();
+
+        let drafts = Drafts::load(&dir).unwrap();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].name, "yaft");
+
+        let draft = Draft::new(
+            "name=yaft\ndesc=Terminal\ncall=/bin/sh\nmemoryLimit=lots\nsafeKill=nope\nonSwitch=maybe\n",
+        )
+        .unwrap();
+        assert_eq!(draft.memory_limit, None);
+        assert_eq!(draft.safe_kill, SafeKill::default());
+        assert_eq!(draft.on_switch, None);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn round_trips_values_with_spaces() {
+        let dir = std::env::temp_dir().join(format!("raft-spaces-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let call = dir.join("my app");
+        std::fs::write(&call, "").unwrap();
+
+        let args = ["--title", "Two words", "", "say \"hi\" \\o/", "it's"]
+            .map(ToString::to_string)
+            .to_vec();
+        let mut draft = Draft::new(&format!(
+            "name=Spaces\ndesc=Test\ncall=\"{}\"\n",
+            call.display()
+        ))
+        .unwrap();
+        draft.profiles.push(LaunchProfile {
+            name: "quoted".into(),
+            args: args.clone(),
+        });
+
+        let loaded = Draft::load(draft.save(&dir).unwrap()).unwrap();
+        assert_eq!(loaded.call, call);
+        assert_eq!(loaded.profiles[0].args, args);
+
+        assert_eq!(
+            split_args("--flag value  'single quoted' \"a \\\"b\\\"\" C:\\dir"),
+            ["--flag", "value", "single quoted", "a \"b\"", "C:\\dir"]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
 }
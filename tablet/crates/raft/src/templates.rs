@@ -0,0 +1,88 @@
+//! Ready-made drafts for well-known apps
+//!
+//! Each template lists the places its app is commonly installed, by toltec or by hand,
+//! and a draft is generated for the first one found.
+use std::path::{Path, PathBuf};
+
+use crate::{Draft, SafeKill, DRAFT_PATH, ICONS_DIR};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DraftTemplate {
+    /// Short name the template is picked by
+    pub id: &'static str,
+    pub name: &'static str,
+    pub desc: &'static str,
+    /// Launch targets to look for, most likely first
+    pub calls: &'static [&'static str],
+    /// Icon name under the draft icon directory, used if it's installed
+    pub icon: &'static str,
+    pub safe_kill: SafeKill,
+}
+
+pub const TEMPLATES: [DraftTemplate; 4] = [
+    DraftTemplate {
+        id: "koreader",
+        name: "KOReader",
+        desc: "Ebook reader",
+        calls: &["/opt/bin/koreader", "/home/root/koreader/koreader.sh"],
+        icon: "koreader",
+        // Asked to exit, it saves the open book's position
+        safe_kill: SafeKill::Term,
+    },
+    DraftTemplate {
+        id: "yaft",
+        name: "yaft",
+        desc: "Terminal emulator",
+        calls: &["/opt/bin/yaft", "/home/root/yaft/yaft"],
+        icon: "yaft",
+        safe_kill: SafeKill::Kill,
+    },
+    DraftTemplate {
+        id: "netsurf",
+        name: "NetSurf",
+        desc: "Web browser",
+        calls: &["/opt/bin/netsurf", "/home/root/netsurf/nsfb"],
+        icon: "netsurf",
+        safe_kill: SafeKill::Term,
+    },
+    DraftTemplate {
+        id: "tilem",
+        name: "TilEm",
+        desc: "TI calculator emulator",
+        calls: &["/opt/bin/tilem", "/home/root/tilem/tilem"],
+        icon: "tilem",
+        safe_kill: SafeKill::Term,
+    },
+];
+
+/// Template by id, ignoring case
+pub fn template(id: &str) -> Option<&'static DraftTemplate> {
+    TEMPLATES
+        .iter()
+        .find(|template| template.id.eq_ignore_ascii_case(id))
+}
+
+impl DraftTemplate {
+    /// Where the app is installed on this device, if it is
+    pub fn detect(&self) -> Option<PathBuf> {
+        self.calls
+            .iter()
+            .map(PathBuf::from)
+            .find(|call| call.is_file())
+    }
+
+    /// A draft launching the installed app, or None if it isn't installed
+    pub fn generate(&self) -> Option<Draft> {
+        let icon = Path::new(DRAFT_PATH)
+            .join(ICONS_DIR)
+            .join(format!("{}.png", self.icon));
+        Some(Draft {
+            name: self.name.to_string(),
+            desc: self.desc.to_string(),
+            call: self.detect()?,
+            icon: icon.exists().then(|| icon.to_string_lossy().into_owned()),
+            safe_kill: self.safe_kill,
+            ..Default::default()
+        })
+    }
+}
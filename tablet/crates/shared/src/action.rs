@@ -0,0 +1,131 @@
+//! Launcher actions, bound to gestures through the config file
+//!
+//! wave recognizes gestures and hands the bound action to the tray on its command line.
+//! Gestures made in the open tray are named with a tray. or icon. prefix, and are bound to
+//! the actions that act on the tray itself.
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+/// Command line flag naming the action the tray should perform on startup
+pub const ACTION_ARG: &str = "--action";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Open the tray as normal
+    OpenTray,
+    /// Switch back to the draft that was in the foreground before the current one
+    LastApp,
+    /// Save the screen to a PNG
+    Screenshot,
+    /// Grab and discard touch input until unlocked
+    LockInput,
+    /// Show the idle screen until the user swipes to open the tray
+    Idle,
+    /// Show the slim bar of pinned drafts along the bottom edge
+    QuickBar,
+    /// Briefly show a banner for the notification passed alongside, leaving drafts running
+    Notify,
+    /// Switch to the home app, set with homeApp
+    Home,
+    /// Open the command palette, to search for a draft or action by typing
+    Palette,
+    /// Close the tray, returning to the draft it was opened over
+    Dismiss,
+    /// Switch to the draft whose icon the gesture was made on
+    Launch,
+    /// Show the launch menu for the draft whose icon the gesture was made on
+    LaunchMenu,
+    /// Switch between drawing black-on-white and white-on-black
+    ToggleNightMode,
+}
+
+impl Action {
+    /// Whether the action acts on the open tray, so can only be bound to gestures made in it
+    pub fn in_tray(&self) -> bool {
+        matches!(
+            self,
+            Action::Dismiss | Action::Launch | Action::LaunchMenu | Action::ToggleNightMode
+        )
+    }
+
+    /// The action passed to this process, if any
+    pub fn from_args() -> Option<Action> {
+        let mut args = std::env::args().skip_while(|arg| arg != ACTION_ARG).skip(1);
+        let action = args.next()?;
+        match action.parse() {
+            Ok(action) => Some(action),
+            Err(e) => {
+                println!("{e:}");
+                None
+            }
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "openTray" => Action::OpenTray,
+            "lastApp" => Action::LastApp,
+            "screenshot" => Action::Screenshot,
+            "lockInput" => Action::LockInput,
+            "idle" => Action::Idle,
+            "quickBar" => Action::QuickBar,
+            "notify" => Action::Notify,
+            "home" => Action::Home,
+            "palette" => Action::Palette,
+            "dismiss" => Action::Dismiss,
+            "launch" => Action::Launch,
+            "launchMenu" => Action::LaunchMenu,
+            "toggleNightMode" => Action::ToggleNightMode,
+            _ => return Err(format!("Unknown action {s:?}")),
+        })
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Action::OpenTray => "openTray",
+            Action::LastApp => "lastApp",
+            Action::Screenshot => "screenshot",
+            Action::LockInput => "lockInput",
+            Action::Idle => "idle",
+            Action::QuickBar => "quickBar",
+            Action::Notify => "notify",
+            Action::Home => "home",
+            Action::Palette => "palette",
+            Action::Dismiss => "dismiss",
+            Action::Launch => "launch",
+            Action::LaunchMenu => "launchMenu",
+            Action::ToggleNightMode => "toggleNightMode",
+        })
+    }
+}
+
+/// Gesture bindings used when the config doesn't override them.
+///
+/// * swipe is a swipe up from the bottom edge, swipeShort one released before travelling
+///   swipeDistance, unbound by default as that distance leaves it no room
+/// * tapN is a tap made with N fingers at once, in the tray as tray.tapN
+/// * tray.swipe is a swipe down on the drafts panel, tray.swipeN one made with N fingers,
+///   and tray.tapOutside a tap above it
+/// * icon.tap, icon.hold and icon.firmPress are made on a draft's icon
+pub fn default_gestures() -> BTreeMap<String, Action> {
+    [
+        ("swipe", Action::OpenTray),
+        ("tap2", Action::LastApp),
+        ("tap3", Action::Screenshot),
+        ("tap4", Action::LockInput),
+        ("tray.swipe", Action::Dismiss),
+        ("tray.tapOutside", Action::Dismiss),
+        ("tray.tap4", Action::ToggleNightMode),
+        ("icon.tap", Action::Launch),
+        ("icon.hold", Action::LaunchMenu),
+        ("icon.firmPress", Action::LaunchMenu),
+    ]
+    .into_iter()
+    .map(|(gesture, action)| (gesture.to_string(), action))
+    .collect()
+}
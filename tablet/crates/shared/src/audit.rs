@@ -0,0 +1,79 @@
+//! Audit log of launcher actions, for looking back over what the launcher did and when
+//!
+//! Every hook event is appended to one JSON lines file under the temp dir, stamped with
+//! the time it happened in seconds since the epoch. Once the log grows past
+//! AUDIT_ROTATE_SIZE it's moved aside, replacing the previous one, so at most two are kept.
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{draft_log::tail, hooks::HookEvent, path_temp_audit_log, TEMP_DIR};
+
+/// Size the log may reach before it's rotated
+pub const AUDIT_ROTATE_SIZE: u64 = 128 * 1024;
+
+fn path_rotated_audit_log() -> PathBuf {
+    let mut path = path_temp_audit_log();
+    path.set_extension("log.1");
+    path
+}
+
+/// Log line for an event that happened at time
+fn audit_line(event: &HookEvent, time: SystemTime) -> String {
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let json = event.to_json();
+    format!(
+        "{{\"time\":{}.{:03},{}",
+        time.as_secs(),
+        time.subsec_millis(),
+        &json[1..]
+    )
+}
+
+/// Append an event to the audit log, rotating it first if it's grown too large
+pub fn audit(event: &HookEvent) {
+    let path = path_temp_audit_log();
+    let result = std::fs::create_dir_all(TEMP_DIR).and_then(|_| {
+        if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() > AUDIT_ROTATE_SIZE) {
+            std::fs::rename(&path, path_rotated_audit_log())?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        // One write per line, so lines from the tray and wave don't interleave
+        file.write_all(format!("{}\n", audit_line(event, SystemTime::now())).as_bytes())
+    });
+    if let Err(e) = result {
+        println!("Failed to write audit log: {e:}");
+    }
+}
+
+/// Up to the last count events logged, oldest first, reaching into the rotated log if needed
+pub fn tail_audit_log(count: usize) -> Vec<String> {
+    let read = |path: PathBuf| {
+        std::fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default()
+    };
+    let text = read(path_rotated_audit_log()) + &read(path_temp_audit_log());
+    tail(&text, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn line_is_stamped() {
+        let event = HookEvent::AppKill {
+            draft: "Notes".to_string(),
+        };
+        assert_eq!(
+            audit_line(&event, UNIX_EPOCH + Duration::from_millis(1_500_042)),
+            r#"{"time":1500.042,"event":"app-kill","draft":"Notes"}"#
+        );
+    }
+}
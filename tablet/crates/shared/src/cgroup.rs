@@ -0,0 +1,158 @@
+//! Per-draft cgroups for process tracking and resource limits
+//!
+//! Each launched draft is moved into its own group under the parchment parent in
+//! every supported hierarchy that's mounted, so its processes can be listed directly
+//! instead of walking parent PIDs, and its memory can be capped. Both the unified
+//! hierarchy and v1 per-controller mounts are supported.
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use proc::ProcFsRoot;
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+pub const CGROUP_PARENT: &str = "parchment";
+
+const CONTROLLERS: [&str; 2] = ["memory", "cpu"];
+
+fn is_unified() -> bool {
+    Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+}
+
+/// Mounted hierarchies that draft groups are created in
+fn hierarchies() -> Vec<PathBuf> {
+    let root = PathBuf::from(CGROUP_ROOT);
+    if is_unified() {
+        vec![root]
+    } else {
+        CONTROLLERS
+            .iter()
+            .map(|controller| root.join(controller))
+            .filter(|path| path.join("cgroup.procs").exists())
+            .collect()
+    }
+}
+
+/// Directory name for a draft's group, since draft names may contain any character
+fn group_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct DraftCgroup {
+    dirs: Vec<PathBuf>,
+}
+
+impl DraftCgroup {
+    /// Create a draft's group in each available hierarchy, or None if the kernel has none
+    pub fn create(name: &str) -> Option<Self> {
+        let mut dirs = vec![];
+        for hierarchy in hierarchies() {
+            let parent = hierarchy.join(CGROUP_PARENT);
+            if let Err(e) = std::fs::create_dir_all(&parent) {
+                println!("Failed to create cgroup {parent:?}: {e:}");
+                continue;
+            }
+
+            if is_unified() {
+                // Controllers have to be enabled by every ancestor before a group can use them
+                for dir in [&hierarchy, &parent] {
+                    for controller in CONTROLLERS {
+                        std::fs::write(
+                            dir.join("cgroup.subtree_control"),
+                            format!("+{controller:}"),
+                        )
+                        .ok();
+                    }
+                }
+            }
+
+            let dir = parent.join(group_name(name));
+            match std::fs::create_dir_all(&dir) {
+                Ok(()) => dirs.push(dir),
+                Err(e) => println!("Failed to create cgroup {dir:?}: {e:}"),
+            }
+        }
+
+        (!dirs.is_empty()).then_some(DraftCgroup { dirs })
+    }
+
+    /// Move a process into this group
+    ///
+    /// Children it has already spawned stay where they are, so this should be
+    /// called as soon as possible after launch.
+    pub fn add(&self, pid: usize) -> std::io::Result<()> {
+        for dir in &self.dirs {
+            std::fs::write(dir.join("cgroup.procs"), pid.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Cap the memory available to every process in this group
+    pub fn set_memory_limit(&self, bytes: u64) -> std::io::Result<()> {
+        let file = if is_unified() {
+            "memory.max"
+        } else {
+            "memory.limit_in_bytes"
+        };
+
+        let dir = self
+            .dirs
+            .iter()
+            .find(|dir| dir.join(file).exists())
+            .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, "No memory controller"))?;
+
+        std::fs::write(dir.join(file), bytes.to_string())
+    }
+}
+
+/// PIDs sharing a draft group with the provided process, or None if it isn't in one
+pub fn cgroup_pids(pid: usize) -> Option<Vec<usize>> {
+    let cgroups = std::fs::read_to_string(ProcFsRoot::system().pid_path(pid, "cgroup")).ok()?;
+    let prefix = format!("/{CGROUP_PARENT:}/");
+
+    // Lines take the form hierarchy-id:controllers:path, with no controllers for the unified hierarchy
+    let (controllers, path) = cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        fields.next()?;
+        let controllers = fields.next()?;
+        let path = fields.next()?;
+        path.starts_with(&prefix).then_some((controllers, path))
+    })?;
+
+    let mut dir = PathBuf::from(CGROUP_ROOT);
+    if !controllers.is_empty() {
+        dir.push(controllers);
+    }
+    dir.push(path.trim_start_matches('/'));
+
+    let procs = std::fs::read_to_string(dir.join("cgroup.procs")).ok()?;
+    Some(
+        procs
+            .lines()
+            .filter_map(|line| line.trim().parse().ok())
+            .collect(),
+    )
+}
+
+/// Remove draft groups left behind by a previous session
+///
+/// Groups that still contain processes can't be removed and are skipped.
+pub fn clear_cgroups() {
+    for hierarchy in hierarchies() {
+        if let Ok(dir) = std::fs::read_dir(hierarchy.join(CGROUP_PARENT)) {
+            for entry in dir.flatten() {
+                std::fs::remove_dir(entry.path()).ok();
+            }
+        }
+    }
+}
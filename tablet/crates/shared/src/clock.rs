@@ -0,0 +1,159 @@
+//! Time zone and network time
+//!
+//! The device clock runs in UTC. The zone is recorded by name in /etc/timezone, with
+//! /etc/localtime linked to its zoneinfo file and TZ set for drafts launched afterwards.
+//! Network time comes from systemd-timesyncd, which marks the clock synchronized each
+//! time it reaches a server.
+use std::{
+    path::Path,
+    process::Command,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::usb::run;
+
+pub const TIMEZONE_PATH: &str = "/etc/timezone";
+pub const LOCALTIME_PATH: &str = "/etc/localtime";
+pub const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+pub const TIMESYNC_SYNCHRONIZED: &str = "/run/systemd/timesync/synchronized";
+pub const TIMESYNCD: &str = "systemd-timesyncd";
+
+const SYNC_POLL: Duration = Duration::from_millis(500);
+const SYNC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Configured zone name, such as Europe/London
+pub fn timezone() -> Option<String> {
+    std::fs::read_to_string(TIMEZONE_PATH)
+        .ok()
+        .map(|zone| zone.trim().to_string())
+        .filter(|zone| !zone.is_empty())
+}
+
+/// Zone names under a zoneinfo directory, skipping its alternate trees and data files,
+/// which aren't capitalized
+fn zone_names(dir: &Path, prefix: &str, zones: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !name.starts_with(|c: char| c.is_ascii_uppercase()) {
+            continue;
+        }
+        let zone = format!("{prefix:}{name:}");
+        if entry.path().is_dir() {
+            zone_names(&entry.path(), &format!("{zone:}/"), zones);
+        } else {
+            zones.push(zone);
+        }
+    }
+}
+
+/// Every zone with zoneinfo installed, sorted by name
+pub fn timezones() -> Vec<String> {
+    let mut zones = Vec::new();
+    zone_names(Path::new(ZONEINFO_DIR), "", &mut zones);
+    zones.sort();
+    zones
+}
+
+/// Zones containing a query, ignoring case and with spaces standing in for underscores,
+/// an exact match first
+pub fn match_timezones<'a>(zones: &'a [String], query: &str) -> Vec<&'a String> {
+    let query = query.trim().to_lowercase().replace(' ', "_");
+    let mut matches = zones
+        .iter()
+        .filter(|zone| zone.to_lowercase().contains(&query))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|zone| zone.to_lowercase() != query);
+    matches
+}
+
+/// Record the zone, point localtime at it and use it for drafts launched from now on
+pub fn set_timezone(zone: &str) -> Result<(), String> {
+    let zoneinfo = Path::new(ZONEINFO_DIR).join(zone);
+    if !zoneinfo.is_file() {
+        return Err(format!("Unknown time zone {zone:?}"));
+    }
+    println!("Setting time zone to {zone:}");
+
+    std::fs::write(TIMEZONE_PATH, format!("{zone:}\n"))
+        .map_err(|e| format!("Failed to write {TIMEZONE_PATH:}: {e:}"))?;
+    std::fs::remove_file(LOCALTIME_PATH).ok();
+    std::os::unix::fs::symlink(&zoneinfo, LOCALTIME_PATH)
+        .map_err(|e| format!("Failed to link {LOCALTIME_PATH:}: {e:}"))?;
+    std::env::set_var("TZ", zone);
+    Ok(())
+}
+
+/// Parse date's +HHMM / -HHMM offset into seconds
+pub fn parse_utc_offset(offset: &str) -> Option<i64> {
+    let offset = offset.trim();
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours = offset.get(1..3)?.parse::<i64>().ok()?;
+    let minutes = offset.get(3..5)?.parse::<i64>().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Seconds the configured zone is currently ahead of UTC
+pub fn utc_offset() -> i64 {
+    let mut date = Command::new("date");
+    date.arg("+%z");
+    if let Some(zone) = timezone() {
+        date.env("TZ", zone);
+    }
+    date.output()
+        .ok()
+        .and_then(|output| parse_utc_offset(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+/// When timesyncd last reached a time server
+fn last_synchronized() -> Option<SystemTime> {
+    std::fs::metadata(TIMESYNC_SYNCHRONIZED)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Whether the clock has been set from a time server since boot
+pub fn clock_synchronized() -> bool {
+    last_synchronized().is_some()
+}
+
+/// Turn on network time and have timesyncd reach a server now, waiting until it does
+pub fn sync_clock() -> Result<(), String> {
+    let before = last_synchronized();
+    let start = Instant::now();
+
+    run("timedatectl", &["set-ntp", "true"])?;
+    run("systemctl", &["restart", TIMESYNCD])?;
+
+    while start.elapsed() < SYNC_TIMEOUT {
+        std::thread::sleep(SYNC_POLL);
+        let synchronized = last_synchronized();
+        if synchronized.is_some() && synchronized != before {
+            return Ok(());
+        }
+    }
+    Err("No time server reached".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_offsets_and_matches_zones() {
+        assert_eq!(parse_utc_offset("+0100\n"), Some(3600));
+        assert_eq!(parse_utc_offset("-0930"), Some(-(9 * 3600 + 30 * 60)));
+        assert_eq!(parse_utc_offset("UTC"), None);
+
+        let zones = ["America/New_York", "Europe/London", "London"].map(String::from);
+        assert_eq!(match_timezones(&zones, "new york"), [&zones[0]]);
+        assert_eq!(match_timezones(&zones, "LONDON"), [&zones[2], &zones[1]]);
+    }
+}
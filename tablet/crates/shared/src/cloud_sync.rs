@@ -0,0 +1,85 @@
+//! Detection of xochitl's cloud sync
+//!
+//! xochitl doesn't report sync progress anywhere we can read, but it only holds
+//! HTTPS connections open while talking to the cloud, so an established connection
+//! to port 443 owned by its process is taken to mean a sync is in progress.
+use std::collections::BTreeSet;
+
+use proc::{FdTarget, ProcFsRoot};
+
+use crate::ProcessTree;
+
+pub const HTTPS_PORT: u16 = 443;
+pub const XOCHITL_PROCESS: &str = "xochitl";
+
+/// Connection tables, relative to the procfs root
+const TCP_TABLES: [&str; 2] = ["net/tcp", "net/tcp6"];
+const TCP_ESTABLISHED: u8 = 0x01;
+
+/// Remote port, connection state and socket inode from a /proc/net/tcp line
+fn parse_tcp_line(line: &str) -> Option<(u16, u8, u64)> {
+    let fields = line.split_whitespace().collect::<Vec<_>>();
+    let (_, remote_port) = fields.get(2)?.rsplit_once(':')?;
+    let remote_port = u16::from_str_radix(remote_port, 16).ok()?;
+    let state = u8::from_str_radix(fields.get(3)?, 16).ok()?;
+    let inode = fields.get(9)?.parse().ok()?;
+    Some((remote_port, state, inode))
+}
+
+/// Inodes of every established connection to an HTTPS server
+fn https_socket_inodes() -> BTreeSet<u64> {
+    TCP_TABLES
+        .iter()
+        .filter_map(|table| std::fs::read_to_string(ProcFsRoot::system().join(table)).ok())
+        .flat_map(|table| {
+            table
+                .lines()
+                .skip(1)
+                .filter_map(parse_tcp_line)
+                .filter(|(port, state, _)| *port == HTTPS_PORT && *state == TCP_ESTABLISHED)
+                .map(|(_, _, inode)| inode)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Inodes of the sockets a process has open
+fn socket_inodes(pid: usize) -> BTreeSet<u64> {
+    ProcFsRoot::system()
+        .fds(pid)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|fd| match fd.target {
+            FdTarget::Socket(inode) => Some(inode),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a process has a connection open to an HTTPS server
+pub fn process_syncing(pid: usize) -> bool {
+    let sockets = socket_inodes(pid);
+    !sockets.is_empty() && !sockets.is_disjoint(&https_socket_inodes())
+}
+
+/// Whether any xochitl process is syncing
+pub fn xochitl_syncing(procs: &ProcessTree) -> bool {
+    procs
+        .iter()
+        .filter(|proc| proc.stat.filename == XOCHITL_PROCESS)
+        .any(|proc| process_syncing(proc.stat.process_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tcp_line() {
+        let line = "   3: 0B01A8C0:C7A2 22D8B85D:01BB 01 00000000:00000000 02:000A7B2C 00000000     0        0 48213 2 00000000 20 4 30 10 -1";
+        assert_eq!(
+            parse_tcp_line(line),
+            Some((HTTPS_PORT, TCP_ESTABLISHED, 48213))
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! User configuration, read from a key=value file at startup
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+use gesture::TouchTransform;
+use libremarkable::framebuffer::common::mxcfb_rect;
+use raft::OnSwitch;
+
+use crate::{
+    action::{default_gestures, Action},
+    oom::{DRAFT_OOM_SCORE_ADJ, LAUNCHER_OOM_SCORE_ADJ},
+    usb::UsbStorage,
+    user_profile::{name_list, UserProfile},
+    TAP_HYSTERESIS,
+};
+
+pub const CONFIG_PATH: &str = "/opt/etc/parchment/config";
+
+/// Where the tray sends its drawing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DisplayBackend {
+    /// rm2fb on reMarkable 2, mxcfb otherwise
+    #[default]
+    Auto,
+    /// The mxcfb framebuffer device
+    Mxcfb,
+    /// The rm2fb server's shared buffer
+    Rm2fb,
+    /// An in-memory buffer that never reaches the panel
+    Simulator,
+}
+
+impl FromStr for DisplayBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "auto" => DisplayBackend::Auto,
+            "mxcfb" => DisplayBackend::Mxcfb,
+            "rm2fb" => DisplayBackend::Rm2fb,
+            "simulator" => DisplayBackend::Simulator,
+            _ => return Err(format!("Unknown display backend {s:?}")),
+        })
+    }
+}
+
+impl Display for DisplayBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DisplayBackend::Auto => "auto",
+            DisplayBackend::Mxcfb => "mxcfb",
+            DisplayBackend::Rm2fb => "rm2fb",
+            DisplayBackend::Simulator => "simulator",
+        })
+    }
+}
+
+pub const DEFAULT_WIDGET_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Panel widget backed by a shell command, declared with widget.<name>.<field> keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct WidgetConfig {
+    /// Run with sh -c, its output is shown as text, or as an image if it's the path to a PNG
+    pub command: String,
+    /// Screen rect to draw in, as left,top,width,height
+    pub rect: mxcfb_rect,
+    /// Time between runs of the command
+    pub interval: Duration,
+    /// Run with sh -c when the widget is tapped
+    pub tap: Option<String>,
+}
+
+impl Default for WidgetConfig {
+    fn default() -> Self {
+        WidgetConfig {
+            command: String::new(),
+            rect: mxcfb_rect::invalid(),
+            interval: DEFAULT_WIDGET_INTERVAL,
+            tap: None,
+        }
+    }
+}
+
+impl WidgetConfig {
+    fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        match field {
+            "command" => self.command = value.to_string(),
+            "tap" => self.tap = Some(value.to_string()),
+            "interval" => {
+                let secs = value
+                    .parse::<u64>()
+                    .map_err(|e| format!("Invalid widget interval {value:?}: {e:}"))?;
+                self.interval = Duration::from_secs(secs.max(1));
+            }
+            "rect" => {
+                let fields = value
+                    .split(',')
+                    .map(|field| field.trim().parse::<u32>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| format!("Invalid widget rect {value:?}: {e:}"))?;
+                let [left, top, width, height] = fields[..] else {
+                    return Err(format!("Widget rect {value:?} needs four values"));
+                };
+                self.rect = mxcfb_rect {
+                    top,
+                    left,
+                    width,
+                    height,
+                };
+            }
+            _ => return Err(format!("Unknown widget field {field:?}")),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Multiplier applied to icon, font and touch target sizes
+    pub ui_scale: f32,
+    /// Locale code used to pick a translation file, English if unset
+    pub locale: Option<String>,
+    /// Draw white-on-black
+    pub invert: bool,
+    /// Gray level of the tray panel background, from 0 (black) to 255 (white)
+    pub panel_background: u8,
+    /// Nine-patch image drawn as the tray panel instead of a plain border
+    pub panel_skin: Option<PathBuf>,
+    /// Backend the tray draws through
+    pub display_backend: DisplayBackend,
+    /// Overlay frame timing metrics
+    pub perf_hud: bool,
+    /// Frames per second for UI animations, 0 to disable them
+    pub animation_fps: u32,
+    /// Time without touch input before wave shows the idle screen, None when disabled
+    pub idle_timeout: Option<Duration>,
+    /// Action wave runs when the foreground draft exits on its own, None to leave the screen be
+    pub app_exit_action: Option<Action>,
+    /// Correction from reported touch positions to display positions, set by calibration
+    pub touch_transform: TouchTransform,
+    /// Width of the screen border where touches are ignored, except in edge gesture zones
+    pub touch_edge_margin: u16,
+    /// Contacts lifting sooner than this are ignored, set in milliseconds
+    pub touch_min_contact: Duration,
+    /// How far a swipe up from the bottom edge travels before it runs the swipe action,
+    /// scaled by uiScale. Raise it to leave room for a shorter swipeShort.
+    pub swipe_distance: f32,
+    /// Action bound to each gesture in wave and the tray, set with gesture.<name>=<action> or none
+    /// to unbind. Gesture names are listed with default_gestures
+    pub gestures: BTreeMap<String, Action>,
+    /// Frontlight brightness percentage to restore when each draft is brought forward, by draft id
+    pub draft_brightness: BTreeMap<String, u8>,
+    /// Drafts pinned to the quick bar and pie menu, in order, set as a comma-separated list of names
+    pub quick_bar_apps: Vec<String>,
+    /// Summon a radial menu of pinned drafts by pressing and holding on the tray
+    pub pie_menu: bool,
+    /// Image exposed by USB mass storage mode, set with usbStorageImage and usbStorageMount
+    pub usb_storage: UsbStorage,
+    /// Command-driven widgets drawn over the tray, by name
+    pub widgets: BTreeMap<String, WidgetConfig>,
+    /// Drafts that need the launch PIN to be switched to, by name
+    pub protected_apps: Vec<String>,
+    /// Digits entered to switch to a protected draft, protection is off without one
+    pub launch_pin: Option<String>,
+    /// User profiles, by name
+    pub profiles: BTreeMap<String, UserProfile>,
+    /// Name of the user profile in use, if any
+    pub active_profile: Option<String>,
+    /// OOM score adjustment for wave and tray, from -1000 (never killed) to 1000
+    pub launcher_oom_score_adj: i32,
+    /// OOM score adjustment for launched drafts
+    pub draft_oom_score_adj: i32,
+    /// Draft the home button and home action switch to, by name
+    pub home_app: Option<String>,
+    /// What happens to drafts when another is switched to, unless they set onSwitch themselves
+    pub on_switch: OnSwitch,
+    /// File to export counters to in Prometheus text format, metrics are off without one
+    pub metrics_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ui_scale: 1.0,
+            locale: None,
+            invert: false,
+            panel_background: u8::MAX,
+            panel_skin: None,
+            display_backend: DisplayBackend::Auto,
+            perf_hud: false,
+            animation_fps: 4,
+            idle_timeout: Some(Duration::from_secs(300)),
+            app_exit_action: Some(Action::LastApp),
+            touch_transform: TouchTransform::IDENTITY,
+            touch_edge_margin: 16,
+            touch_min_contact: Duration::from_millis(20),
+            swipe_distance: TAP_HYSTERESIS,
+            gestures: default_gestures(),
+            draft_brightness: Default::default(),
+            quick_bar_apps: Default::default(),
+            pie_menu: false,
+            usb_storage: Default::default(),
+            widgets: Default::default(),
+            protected_apps: Default::default(),
+            launch_pin: None,
+            profiles: Default::default(),
+            active_profile: None,
+            launcher_oom_score_adj: LAUNCHER_OOM_SCORE_ADJ,
+            draft_oom_score_adj: DRAFT_OOM_SCORE_ADJ,
+            home_app: None,
+            on_switch: OnSwitch::default(),
+            metrics_file: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config file, falling back to defaults if it's missing or malformed
+    pub fn load() -> Self {
+        let path = PathBuf::from(CONFIG_PATH);
+        let input = match std::fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(_) => return Config::default(),
+        };
+
+        match input.parse() {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Failed to parse config {path:?}: {e:}");
+                Config::default()
+            }
+        }
+    }
+
+    /// The active user profile, None if none is chosen or it isn't declared
+    pub fn profile(&self) -> Option<&UserProfile> {
+        self.profiles.get(self.active_profile.as_ref()?)
+    }
+
+    /// Drafts pinned to the quick bar and pie menu under the active profile
+    pub fn pinned_apps(&self) -> &[String] {
+        self.profile()
+            .and_then(|profile| profile.quick_bar_apps.as_deref())
+            .unwrap_or(&self.quick_bar_apps)
+    }
+
+    /// Whether to draw white-on-black under the active profile
+    pub fn inverted(&self) -> bool {
+        self.profile()
+            .and_then(|profile| profile.invert)
+            .unwrap_or(self.invert)
+    }
+
+    /// Gray level of the tray panel background under the active profile
+    pub fn panel_level(&self) -> u8 {
+        self.profile()
+            .and_then(|profile| profile.panel_background)
+            .unwrap_or(self.panel_background)
+    }
+}
+
+/// Set a single key in the config file, leaving the rest of it untouched
+pub fn update_config(key: &str, value: &str) -> std::io::Result<()> {
+    let path = PathBuf::from(CONFIG_PATH);
+    let input = std::fs::read_to_string(&path).unwrap_or_default();
+
+    let mut found = false;
+    let mut lines = input
+        .lines()
+        .map(|line| match line.split_once('=') {
+            Some((candidate, _)) if candidate.trim() == key => {
+                found = true;
+                format!("{key:}={value:}")
+            }
+            _ => line.to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    if !found {
+        lines.push(format!("{key:}={value:}"));
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, lines.join("\n") + "\n")
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+
+        for line in s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#') && !line.is_empty())
+        {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("Config line {line:?} is not a key=value pair"))?;
+
+            match key.trim() {
+                "uiScale" => {
+                    config.ui_scale = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid uiScale {value:?}: {e:}"))?
+                }
+                "locale" => config.locale = Some(value.trim().to_string()),
+                "quickBarApps" => config.quick_bar_apps = name_list(value),
+                "protectedApps" => config.protected_apps = name_list(value),
+                "launchPin" => {
+                    let pin = value.trim();
+                    if pin.is_empty() || !pin.chars().all(|c| c.is_ascii_digit()) {
+                        return Err(format!("launchPin {value:?} must be digits"));
+                    }
+                    config.launch_pin = Some(pin.to_string());
+                }
+                "activeProfile" => {
+                    config.active_profile = Some(value.trim())
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                }
+                "pieMenu" => config.pie_menu = value.trim() == "true",
+                "usbStorageImage" => config.usb_storage.image = PathBuf::from(value.trim()),
+                "usbStorageMount" => config.usb_storage.mount = PathBuf::from(value.trim()),
+                "invert" => config.invert = value.trim() == "true",
+                "panelBackground" => {
+                    config.panel_background = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid panelBackground {value:?}: {e:}"))?
+                }
+                "homeApp" => {
+                    config.home_app = Some(value.trim())
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                }
+                "onSwitch" => config.on_switch = value.trim().parse()?,
+                "metricsFile" => {
+                    config.metrics_file = Some(value.trim())
+                        .filter(|path| !path.is_empty())
+                        .map(PathBuf::from)
+                }
+                "panelSkin" => config.panel_skin = Some(PathBuf::from(value.trim())),
+                "displayBackend" => config.display_backend = value.trim().parse()?,
+                "perfHud" => config.perf_hud = value.trim() == "true",
+                "animationFps" => {
+                    config.animation_fps = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid animationFps {value:?}: {e:}"))?
+                }
+                "appExitAction" => {
+                    config.app_exit_action = match value.trim() {
+                        "none" => None,
+                        action => Some(action.parse()?),
+                    }
+                }
+                "idleTimeout" => {
+                    let secs = value
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|e| format!("Invalid idle timeout {value:?}: {e:}"))?;
+                    config.idle_timeout = (secs > 0).then_some(Duration::from_secs(secs));
+                }
+                "touchTransform" => config.touch_transform = value.trim().parse()?,
+                "touchEdgeMargin" => {
+                    config.touch_edge_margin = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid touchEdgeMargin {value:?}: {e:}"))?
+                }
+                "swipeDistance" => {
+                    config.swipe_distance = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid swipeDistance {value:?}: {e:}"))?
+                }
+                "touchMinContact" => {
+                    let millis = value
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|e| format!("Invalid touchMinContact {value:?}: {e:}"))?;
+                    config.touch_min_contact = Duration::from_millis(millis);
+                }
+                "launcherOomScoreAdj" => {
+                    config.launcher_oom_score_adj = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid launcherOomScoreAdj {value:?}: {e:}"))?
+                }
+                "draftOomScoreAdj" => {
+                    config.draft_oom_score_adj = value
+                        .trim()
+                        .parse()
+                        .map_err(|e| format!("Invalid draftOomScoreAdj {value:?}: {e:}"))?
+                }
+                key => {
+                    if let Some(gesture) = key.strip_prefix("gesture.") {
+                        match value.trim() {
+                            "none" => {
+                                config.gestures.remove(gesture);
+                            }
+                            action => {
+                                config.gestures.insert(gesture.to_string(), action.parse()?);
+                            }
+                        }
+                    } else if let Some(draft) = key.strip_prefix("brightness.") {
+                        let brightness = value
+                            .trim()
+                            .parse()
+                            .map_err(|e| format!("Invalid brightness {value:?}: {e:}"))?;
+                        config
+                            .draft_brightness
+                            .insert(draft.to_string(), brightness);
+                    } else if let Some(profile) = key.strip_prefix("profile.") {
+                        let (name, field) = profile
+                            .split_once('.')
+                            .ok_or_else(|| format!("Profile key {key:?} has no field"))?;
+                        config
+                            .profiles
+                            .entry(name.to_string())
+                            .or_default()
+                            .set(field, value.trim())?;
+                    } else if let Some(widget) = key.strip_prefix("widget.") {
+                        let (name, field) = widget
+                            .split_once('.')
+                            .ok_or_else(|| format!("Widget key {key:?} has no field"))?;
+                        config
+                            .widgets
+                            .entry(name.to_string())
+                            .or_default()
+                            .set(field, value.trim())?;
+                    } else {
+                        println!("Ignoring unknown config key {key:?}");
+                    }
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,68 @@
+//! Output of launched drafts, kept per draft under the temp dir for debugging failed launches
+//!
+//! Each draft's stdout and stderr go to one log. A log that has grown past
+//! LOG_ROTATE_SIZE is moved aside when its draft is next launched, replacing the
+//! previous one, so at most two are kept per draft.
+use std::{
+    fs::{File, OpenOptions},
+    path::PathBuf,
+};
+
+use crate::{path_temp_log, path_temp_logs};
+
+/// Size a log may reach before it's rotated on the next launch
+pub const LOG_ROTATE_SIZE: u64 = 256 * 1024;
+
+fn path_rotated_log(id: &str) -> PathBuf {
+    let mut path = path_temp_log(id);
+    path.set_extension("log.1");
+    path
+}
+
+/// Open a draft's log for its launched process to append to, rotating it first if it's grown too large
+pub fn open_draft_log(id: &str) -> std::io::Result<File> {
+    std::fs::create_dir_all(path_temp_logs())?;
+    let path = path_temp_log(id);
+    if std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() > LOG_ROTATE_SIZE) {
+        std::fs::rename(&path, path_rotated_log(id))?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Whether a draft has logged anything, in either its current or rotated log
+pub fn has_draft_log(id: &str) -> bool {
+    [path_temp_log(id), path_rotated_log(id)]
+        .iter()
+        .any(|path| std::fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0))
+}
+
+/// Up to the last count lines a draft logged, oldest first, reaching into the rotated log if needed
+pub fn tail_draft_log(id: &str, count: usize) -> Vec<String> {
+    let read = |path: PathBuf| {
+        std::fs::read(path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+            .unwrap_or_default()
+    };
+    let text = read(path_rotated_log(id)) + &read(path_temp_log(id));
+    tail(&text, count)
+}
+
+pub(crate) fn tail(text: &str, count: usize) -> Vec<String> {
+    let lines = text.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_keeps_last_lines() {
+        assert_eq!(tail("a\nb\nc\n", 2), vec!["b", "c"]);
+        assert_eq!(tail("a\nb", 5), vec!["a", "b"]);
+        assert!(tail("", 3).is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Environment for launched drafts
+//!
+//! Drafts would otherwise inherit whatever environment the launcher was started
+//! with, which differs from a login shell or xochitl and breaks some apps. Instead
+//! each one starts from the same baseline, plus any variables its draft file sets.
+use std::collections::BTreeMap;
+
+use raft::Draft;
+
+/// Variables every draft is launched with
+pub const BASE_ENVIRONMENT: [(&str, &str); 7] = [
+    ("HOME", "/home/root"),
+    ("USER", "root"),
+    ("LOGNAME", "root"),
+    ("SHELL", "/bin/sh"),
+    (
+        "PATH",
+        "/opt/bin:/opt/sbin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin",
+    ),
+    ("LANG", "C.UTF-8"),
+    ("QT_QPA_PLATFORM", "epaper"),
+];
+
+/// Variables passed through from the launcher's environment when set,
+/// such as an LD_PRELOAD framebuffer shim
+pub const INHERITED_VARIABLES: [&str; 3] = ["LD_PRELOAD", "TZ", "XDG_RUNTIME_DIR"];
+
+/// Build a draft's launch environment from the baseline, inherited and draft variables,
+/// with later sources taking precedence
+pub fn launch_environment<I: IntoIterator<Item = (String, String)>>(
+    draft: &Draft,
+    inherited: I,
+) -> BTreeMap<String, String> {
+    let mut env = BASE_ENVIRONMENT
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    env.extend(
+        inherited
+            .into_iter()
+            .filter(|(name, _)| INHERITED_VARIABLES.contains(&name.as_str())),
+    );
+
+    env.extend(draft.env.clone());
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draft_overrides_baseline() {
+        let mut draft = Draft::default();
+        draft.env.insert("HOME".into(), "/home/app".into());
+        draft.env.insert("QT_SCALE_FACTOR".into(), "2".into());
+
+        let env = launch_environment(
+            &draft,
+            [
+                ("TZ".to_string(), "UTC".to_string()),
+                ("SSH_TTY".to_string(), "/dev/pts/0".to_string()),
+            ],
+        );
+
+        assert_eq!(env["HOME"], "/home/app");
+        assert_eq!(env["QT_SCALE_FACTOR"], "2");
+        assert_eq!(env["TZ"], "UTC");
+        assert_eq!(env["USER"], "root");
+        assert!(!env.contains_key("SSH_TTY"));
+    }
+}
@@ -0,0 +1,48 @@
+//! Frontlight control through the backlight sysfs class
+//!
+//! Devices without a light have no backlight entries, in which case reads
+//! return None and writes do nothing.
+use std::path::PathBuf;
+
+pub const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// The first backlight device, if the device has one
+pub fn frontlight_path() -> Option<PathBuf> {
+    std::fs::read_dir(BACKLIGHT_DIR)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .next()
+}
+
+fn read_value(file: &str) -> Option<u32> {
+    std::fs::read_to_string(frontlight_path()?.join(file))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Current brightness as a percentage of the maximum
+pub fn brightness() -> Option<u8> {
+    let max = read_value("max_brightness")?.max(1);
+    Some((read_value("brightness")? * 100 / max) as u8)
+}
+
+/// Set brightness as a percentage of the maximum
+pub fn set_brightness(percent: u8) {
+    let path = match frontlight_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    let max = match read_value("max_brightness") {
+        Some(max) => max,
+        None => return,
+    };
+
+    let level = max * percent.min(100) as u32 / 100;
+    if let Err(e) = std::fs::write(path.join("brightness"), level.to_string()) {
+        println!("Failed to set frontlight brightness: {e:}");
+    }
+}
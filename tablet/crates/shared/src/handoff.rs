@@ -0,0 +1,49 @@
+//! Handing gestures from wave to a tray that's already running
+//!
+//! wave keeps a tray waiting in standby, its drafts parsed and icons decoded, and passes it
+//! the action to perform over a Unix socket instead of spawning a fresh tray per gesture.
+//! The tray holds the connection open until it exits, so wave can wait on it as it would
+//! on a child process.
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+};
+
+use crate::action::Action;
+
+/// Command line flag starting the tray in standby
+pub const STANDBY_ARG: &str = "--standby";
+
+/// Socket a standby tray listens on, kept outside the temp dir parchment clears
+pub const HANDOFF_SOCKET: &str = "/tmp/parchment-tray.sock";
+
+/// Start listening for a handoff, replacing any socket a previous standby left behind.
+/// Connections made before the tray is ready wait in the backlog.
+pub fn listen_handoff() -> std::io::Result<UnixListener> {
+    std::fs::remove_file(HANDOFF_SOCKET).ok();
+    UnixListener::bind(HANDOFF_SOCKET)
+}
+
+/// Wait for wave to hand over an action, returning it with the connection to hold until exit
+pub fn wait_for_handoff(listener: &UnixListener) -> std::io::Result<(Action, UnixStream)> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        match line.trim().parse() {
+            Ok(action) => {
+                std::fs::remove_file(HANDOFF_SOCKET).ok();
+                return Ok((action, stream));
+            }
+            Err(e) => println!("Ignoring handoff: {e:}"),
+        }
+    }
+}
+
+/// Have the standby tray perform an action, returning once it exits
+pub fn hand_off(action: Action) -> std::io::Result<()> {
+    let mut stream = UnixStream::connect(HANDOFF_SOCKET)?;
+    writeln!(stream, "{action:}")?;
+    stream.read_to_end(&mut vec![])?;
+    Ok(())
+}
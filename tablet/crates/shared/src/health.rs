@@ -0,0 +1,159 @@
+//! Spotting hung drafts, so the tray can offer to restart them
+//!
+//! wave watches the foreground draft. A draft is flagged as hung when its healthCheck
+//! command fails, or, without one, when it keeps a CPU busy for HUNG_AFTER while no
+//! input arrives. Flags are files under the temp dir, cleared when the draft is launched
+//! again or recovers.
+use std::{
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use proc::{cpu_percent, Proc, State};
+use raft::Draft;
+
+use crate::{path_temp_hung, path_temp_hungs, process_tree, ProcessTree};
+
+/// How often the foreground draft is checked
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// CPU use at or above which a draft counts as busy
+pub const HUNG_CPU_PERCENT: f32 = 95.0;
+
+/// How long a draft has to stay busy without input to count as hung
+pub const HUNG_AFTER: Duration = Duration::from_secs(30);
+
+/// Longest a healthCheck command may take before it's killed and counted as failed
+pub const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether a draft has been flagged as hung
+pub fn is_hung(id: &str) -> bool {
+    path_temp_hung(id).exists()
+}
+
+/// Flag a draft as hung, or clear the flag
+pub fn set_hung(id: &str, hung: bool) {
+    let path = path_temp_hung(id);
+    if !hung {
+        std::fs::remove_file(path).ok();
+    } else if !path.exists() {
+        println!("Flagging draft {id:?} as hung");
+        std::fs::create_dir_all(path_temp_hungs()).ok();
+        if let Err(e) = std::fs::write(&path, "") {
+            println!("Failed to flag draft {id:?} as hung: {e:}");
+        }
+    }
+}
+
+/// Run a draft's healthCheck command, None if it has none
+pub fn run_health_check(draft: &Draft) -> Option<bool> {
+    let command = draft.health_check.as_ref()?;
+    let mut child = match Command::new("sh")
+        .args(["-c", command])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            println!("Failed to run health check for {:?}: {e:}", draft.name);
+            return None;
+        }
+    };
+
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status.success()),
+            Ok(None) if start.elapsed() < HEALTH_CHECK_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(100))
+            }
+            _ => {
+                child.kill().ok();
+                child.wait().ok();
+                return Some(false);
+            }
+        }
+    }
+}
+
+/// CPU time used so far by a process and its descendants, and whether any of them is running
+pub fn tree_usage(procs: &ProcessTree, proc: &Proc) -> (Duration, bool) {
+    let pids = process_tree(procs, proc);
+    procs
+        .iter()
+        .filter(|proc| pids.contains(&proc.stat.process_id))
+        .fold((Duration::ZERO, false), |(cpu_time, running), proc| {
+            (
+                cpu_time + proc.stat.cpu_time(),
+                running || proc.stat.state == State::Running,
+            )
+        })
+}
+
+/// Memory held resident by a process and its descendants, in bytes
+pub fn tree_memory(procs: &ProcessTree, proc: &Proc) -> usize {
+    let pids = process_tree(procs, proc);
+    procs
+        .iter()
+        .filter(|proc| pids.contains(&proc.stat.process_id))
+        .map(|proc| proc.stat.rss_bytes())
+        .sum()
+}
+
+/// Tracks how long a draft has kept a CPU busy without input
+#[derive(Debug, Default)]
+pub struct HangDetector {
+    last: Option<(Instant, Duration)>,
+    busy_since: Option<Instant>,
+}
+
+impl HangDetector {
+    /// Record a sample of the draft's CPU time, returning whether it now counts as hung
+    pub fn sample(&mut self, now: Instant, cpu_time: Duration, running: bool, input: bool) -> bool {
+        let busy = match self.last {
+            Some((then, last_cpu_time)) => {
+                running
+                    && !input
+                    && cpu_percent(cpu_time.saturating_sub(last_cpu_time), now - then)
+                        >= HUNG_CPU_PERCENT
+            }
+            None => false,
+        };
+        self.last = Some((now, cpu_time));
+
+        if !busy {
+            self.busy_since = None;
+            return false;
+        }
+        now - *self.busy_since.get_or_insert(now) >= HUNG_AFTER
+    }
+
+    /// Forget earlier samples, as when a different draft comes to the foreground
+    pub fn reset(&mut self) {
+        *self = HangDetector::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hung_after_staying_busy_without_input() {
+        let start = Instant::now();
+        let mut detector = HangDetector::default();
+        let at = |secs| start + Duration::from_secs(secs);
+        let cpu = Duration::from_secs;
+
+        assert!(!detector.sample(at(0), cpu(0), true, false));
+        assert!(!detector.sample(at(5), cpu(5), true, false));
+        assert!(!detector.sample(at(20), cpu(20), true, false));
+        assert!(detector.sample(at(35), cpu(35), true, false));
+
+        // Input, or the draft going idle, starts the count over
+        assert!(!detector.sample(at(40), cpu(40), true, true));
+        assert!(!detector.sample(at(45), cpu(45), true, false));
+        assert!(!detector.sample(at(80), cpu(45), false, false));
+    }
+}
@@ -0,0 +1,165 @@
+//! Event hooks for user scripts
+//!
+//! Every executable in the hooks directory is run when the launcher reaches one of
+//! the events below, with the event name as its argument and a JSON description of
+//! the event on its stdin. Hooks aren't waited on, so a slow or failing script can't
+//! hold up the launcher. Every event is also recorded in the audit log.
+use std::{
+    io::Write,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::audit::audit;
+
+pub const HOOKS_DIR: &str = "/opt/etc/parchment/hooks.d";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookEvent {
+    /// The tray was opened over the running draft
+    TrayOpen,
+    /// A draft was brought to the foreground, either freshly launched or continued
+    AppLaunch { draft: String, resumed: bool },
+    /// A draft was killed from the tray
+    AppKill { draft: String },
+    /// A running draft was stopped as the tray opened over it
+    AppSuspend { draft: String },
+    /// wave recognized a gesture and started the tray to perform its action
+    Gesture { action: String },
+    /// The device woke from suspend
+    Resume,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::TrayOpen => "tray-open",
+            HookEvent::AppLaunch { .. } => "app-launch",
+            HookEvent::AppKill { .. } => "app-kill",
+            HookEvent::AppSuspend { .. } => "app-suspend",
+            HookEvent::Gesture { .. } => "gesture",
+            HookEvent::Resume => "resume",
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let fields = match self {
+            HookEvent::TrayOpen | HookEvent::Resume => String::new(),
+            HookEvent::AppLaunch { draft, resumed } => {
+                format!(",\"draft\":{},\"resumed\":{resumed:}", json_string(draft))
+            }
+            HookEvent::AppKill { draft } | HookEvent::AppSuspend { draft } => {
+                format!(",\"draft\":{}", json_string(draft))
+            }
+            HookEvent::Gesture { action } => format!(",\"action\":{}", json_string(action)),
+        };
+        format!("{{\"event\":{}{fields:}}}", json_string(self.name()))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Executable files in the hooks directory, in name order
+fn hooks() -> Vec<PathBuf> {
+    let dir = match std::fs::read_dir(HOOKS_DIR) {
+        Ok(dir) => dir,
+        Err(_) => return vec![],
+    };
+
+    let mut hooks = dir
+        .flatten()
+        .filter(|entry| {
+            entry
+                .metadata()
+                .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    hooks.sort();
+    hooks
+}
+
+/// Run every hook for an event
+///
+/// Hooks are started and handed their input straight away, so they still run if the
+/// launcher exits right after, then waited on from a background thread.
+pub fn run_hooks(event: HookEvent) {
+    audit(&event);
+    let json = event.to_json();
+    let children = hooks()
+        .into_iter()
+        .filter_map(|hook| {
+            println!("Running {} hook {hook:?}", event.name());
+            let mut child = match Command::new(&hook)
+                .arg(event.name())
+                .stdin(Stdio::piped())
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    println!("Failed to run hook {hook:?}: {e:}");
+                    return None;
+                }
+            };
+
+            // Dropping stdin once written closes it, so hooks can read to the end
+            if let Some(mut stdin) = child.stdin.take() {
+                writeln!(stdin, "{json:}").ok();
+            }
+
+            Some((hook, child))
+        })
+        .collect::<Vec<_>>();
+
+    if children.is_empty() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        for (hook, mut child) in children {
+            match child.wait() {
+                Ok(status) if !status.success() => {
+                    println!("Hook {hook:?} exited with {status:}")
+                }
+                Err(e) => println!("Failed to wait for hook {hook:?}: {e:}"),
+                _ => (),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_json() {
+        let event = HookEvent::AppLaunch {
+            draft: "Say \"hi\"\n".to_string(),
+            resumed: true,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"app-launch","draft":"Say \"hi\"\n","resumed":true}"#
+        );
+        assert_eq!(HookEvent::TrayOpen.to_json(), r#"{"event":"tray-open"}"#);
+    }
+}
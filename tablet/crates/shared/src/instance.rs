@@ -0,0 +1,79 @@
+//! Locks keeping a single tray and a single wave running
+//!
+//! Two trays would fight over input grabs and the framebuffer, so each binary takes an
+//! flock on its own runtime file at startup and a second instance exits instead.
+//! The lock is released by the kernel when its holder exits, however it exits.
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{flock, FlockArg},
+};
+
+/// Held for the life of the process that acquired it
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// Lock file for the named binary, kept outside the temp dir parchment clears
+pub fn instance_lock_path(name: &str) -> PathBuf {
+    PathBuf::from(format!("/tmp/parchment-{name:}.instance"))
+}
+
+/// Become the only running instance of the named binary.
+/// Err holds the PID of the instance already running, if it could be read.
+pub fn acquire_instance(name: &str) -> Result<InstanceLock, Option<usize>> {
+    let mut file = match OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(instance_lock_path(name))
+    {
+        Ok(file) => file,
+        Err(e) => {
+            // Running unguarded beats not running at all
+            println!("Failed to open the {name:} instance lock, continuing without it: {e:}");
+            return Ok(InstanceLock {
+                _file: File::open("/dev/null").unwrap(),
+            });
+        }
+    };
+
+    match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+        Ok(()) => (),
+        Err(Errno::EWOULDBLOCK) => {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid).ok();
+            return Err(pid.trim().parse().ok());
+        }
+        Err(e) => {
+            println!("Failed to take the {name:} instance lock, continuing without it: {e:}");
+            return Ok(InstanceLock { _file: file });
+        }
+    }
+
+    file.set_len(0).ok();
+    file.rewind().ok();
+    write!(file, "{}", std::process::id()).ok();
+    Ok(InstanceLock { _file: file })
+}
+
+/// Exit unless this is the only running instance of the named binary
+pub fn single_instance(name: &str) -> InstanceLock {
+    match acquire_instance(name) {
+        Ok(lock) => lock,
+        Err(pid) => {
+            match pid {
+                Some(pid) => println!("{name:} is already running with PID {pid:}, exiting"),
+                None => println!("{name:} is already running, exiting"),
+            }
+            std::process::exit(0);
+        }
+    }
+}
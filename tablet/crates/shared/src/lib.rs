@@ -1,17 +1,87 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    os::unix::{io::AsRawFd, net::UnixStream, process::CommandExt},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
 
-use nix::{sys::signal::kill, unistd::Pid};
+use nix::{
+    errno::Errno,
+    sched::{sched_setaffinity, CpuSet},
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{_exit, fork, setsid, write, ForkResult, Pid},
+};
 
-use proc::{proc_fs, Proc, State};
-use raft::Draft;
+use proc::{Io, Proc, ProcFsRoot, State};
+use raft::{Draft, DraftId};
+
+pub mod action;
+pub mod audit;
+pub mod cgroup;
+pub mod clock;
+pub mod cloud_sync;
+pub mod config;
+pub mod draft_log;
+pub mod environment;
+pub mod frontlight;
+pub mod handoff;
+pub mod health;
+pub mod hooks;
+pub mod instance;
+pub mod locale;
+pub mod metrics;
+pub mod notification;
+pub mod oom;
+pub mod opkg;
+pub mod pidfile;
+pub mod power;
+pub mod rm2fb;
+pub mod screenshot;
+pub mod session;
+pub mod storage;
+pub mod usb;
+pub mod user_profile;
 
 pub const TEMP_DIR: &'static str = "/tmp/parchment";
 pub const TEMP_DIR_SCREENSHOTS: &'static str = "screenshots";
 pub const TEMP_DIR_ICONS: &'static str = "icons";
 pub const TEMP_DIR_PIDS: &'static str = "processes";
+pub const TEMP_DIR_LOGS: &str = "logs";
+pub const TEMP_DIR_HUNG: &str = "hung";
+pub const TEMP_FILE_SESSION: &str = "session";
+pub const TEMP_FILE_NOTIFICATIONS: &str = "notifications";
+pub const TEMP_FILE_NOTIFICATION_HISTORY: &str = "notification_history";
+pub const TEMP_FILE_DO_NOT_DISTURB: &str = "do_not_disturb";
+pub const TEMP_FILE_AUDIT_LOG: &str = "audit.log";
+
+/// Interval between checks for terminated processes to exit
+const TERMINATE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Multiple of the terminate timeout allowed while processes are still writing to storage
+const TERMINATE_WRITE_GRACE: u32 = 3;
+
+/// Nice value given to suspended drafts so they yield to the foreground
+pub const SUSPENDED_NICE: i32 = 19;
 
 pub const TAP_HYSTERESIS: f32 = 32.0;
+
+/// Touch pressure at which a press counts as firm, standing in for a long press
+pub const FIRM_PRESS_PRESSURE: u16 = 180;
+
+/// Touch pressure and contact size past which a touch is taken for a resting palm
+pub const PALM_PRESSURE: u16 = 240;
+pub const PALM_CONTACT_SIZE: u16 = 40;
+
+/// Environment variable holding the draft's PID for resume hook commands
+pub const RESUME_HOOK_PID_VAR: &str = "DRAFT_PID";
+
 pub const INPUT_BUFFER_SIZE: usize = 512 * 8;
+pub const TOUCH_SLOTS: i32 = 10;
 
 pub fn path_temp_screenshots() -> PathBuf {
     let mut path = PathBuf::from(TEMP_DIR);
@@ -50,49 +120,537 @@ pub fn path_temp_pid<P: AsRef<Path>>(filename: P) -> PathBuf {
     path
 }
 
-pub fn stop_recursive(proc: &Proc) {
-    println!("Stopping process {:?}", proc.stat.filename);
-    kill(
-        Pid::from_raw(proc.stat.process_id as i32),
-        nix::sys::signal::Signal::SIGSTOP,
-    )
-    .unwrap();
-    for proc in processes().filter(is_child_process_of(proc.stat.process_id)) {
-        stop_recursive(&proc);
+pub fn path_temp_logs() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_DIR_LOGS);
+    path
+}
+
+pub fn path_temp_log<P: AsRef<Path>>(filename: P) -> PathBuf {
+    let mut path = path_temp_logs();
+    path.push(filename);
+    path.set_extension("log");
+    path
+}
+
+pub fn path_temp_hungs() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_DIR_HUNG);
+    path
+}
+
+pub fn path_temp_hung<P: AsRef<Path>>(filename: P) -> PathBuf {
+    let mut path = path_temp_hungs();
+    path.push(filename);
+    path
+}
+
+pub fn path_temp_session() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_FILE_SESSION);
+    path
+}
+
+pub fn path_temp_notifications() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_FILE_NOTIFICATIONS);
+    path
+}
+
+pub fn path_temp_notification_history() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_FILE_NOTIFICATION_HISTORY);
+    path
+}
+
+pub fn path_temp_do_not_disturb() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_FILE_DO_NOT_DISTURB);
+    path
+}
+
+pub fn path_temp_audit_log() -> PathBuf {
+    let mut path = PathBuf::from(TEMP_DIR);
+    path.push(TEMP_FILE_AUDIT_LOG);
+    path
+}
+
+/// Processes as of a single scan, passed to the helpers that walk process trees so
+/// callers choose how often procfs is read, and tests can supply their own
+#[derive(Debug, Default, Clone)]
+pub struct ProcessTree {
+    procs: Vec<Proc>,
+}
+
+impl ProcessTree {
+    /// Scan the system's procfs
+    pub fn scan() -> Self {
+        processes().collect()
+    }
+
+    /// Scan a procfs root
+    pub fn scan_in(root: &ProcFsRoot) -> Self {
+        processes_in(root).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Proc> {
+        self.procs.iter()
+    }
+
+    pub fn find(&self, pid: usize) -> Option<&Proc> {
+        self.procs.iter().find(|proc| proc.stat.process_id == pid)
+    }
+}
+
+impl FromIterator<Proc> for ProcessTree {
+    fn from_iter<T: IntoIterator<Item = Proc>>(iter: T) -> Self {
+        ProcessTree {
+            procs: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// PIDs of a process and its descendants
+///
+/// Read from the process' draft cgroup where it has one, falling back to walking
+/// parent PIDs, in which case parents come before their children.
+pub fn process_tree(procs: &ProcessTree, proc: &Proc) -> Vec<usize> {
+    if let Some(pids) = cgroup::cgroup_pids(proc.stat.process_id) {
+        return pids;
+    }
+
+    let mut pids = descendants(procs, proc);
+
+    // Launched drafts are left in a session of their own, whose leader exits straight after
+    // starting them. It still holds descendants whose parent exited and left them to be
+    // reparented. Processes leading their own session, such as xochitl, are swept the same way.
+    let session = proc.stat.session_id;
+    if session != 0 && (session == proc.stat.process_id || procs.find(session).is_none()) {
+        for proc in procs
+            .iter()
+            .filter(|other| other.stat.session_id == session)
+        {
+            if !pids.contains(&proc.stat.process_id) {
+                pids.push(proc.stat.process_id);
+            }
+        }
+    }
+    pids
+}
+
+/// PIDs of a process and its descendants by parent PID, parents before their children
+fn descendants(procs: &ProcessTree, proc: &Proc) -> Vec<usize> {
+    let mut pids = vec![proc.stat.process_id];
+    for child in procs
+        .iter()
+        .filter(|other| is_child_process_of(proc.stat.process_id)(other))
+    {
+        pids.extend(descendants(procs, child));
+    }
+    pids
+}
+
+/// A process beneath a draft's, and how many generations down it sits
+#[derive(Debug, Clone)]
+pub struct ProcessNode {
+    pub depth: usize,
+    pub proc: Proc,
+}
+
+/// A process and its descendants, each listed before its own children
+///
+/// Descendants left behind in the draft's cgroup or session by a parent that exited
+/// come last, one level beneath the draft's process.
+pub fn process_tree_nodes(procs: &ProcessTree, proc: &Proc) -> Vec<ProcessNode> {
+    let mut nodes = vec![];
+    push_process_nodes(proc, 0, procs, &mut nodes);
+
+    for pid in process_tree(procs, proc) {
+        if nodes.iter().any(|node| node.proc.stat.process_id == pid) {
+            continue;
+        }
+        if let Some(orphan) = procs.find(pid) {
+            push_process_nodes(orphan, 1, procs, &mut nodes);
+        }
+    }
+    nodes
+}
+
+fn push_process_nodes(
+    proc: &Proc,
+    depth: usize,
+    procs: &ProcessTree,
+    nodes: &mut Vec<ProcessNode>,
+) {
+    nodes.push(ProcessNode {
+        depth,
+        proc: proc.clone(),
+    });
+    for child in procs
+        .iter()
+        .filter(|other| is_child_process_of(proc.stat.process_id)(other))
+    {
+        push_process_nodes(child, depth + 1, procs, nodes);
     }
 }
 
-pub fn cont_recursive(proc: &Proc) {
-    for proc in processes().filter(is_child_process_of(proc.stat.process_id)) {
-        cont_recursive(&proc);
+/// Send a signal to a single process, ignoring it having already exited
+fn signal_pid(pid: usize, signal: Signal) {
+    match kill(Pid::from_raw(pid as i32), signal) {
+        Ok(()) | Err(Errno::ESRCH) => (),
+        Err(e) => println!("Failed to send {signal:} to process {pid:}: {e:}"),
     }
+}
+
+pub fn stop_recursive(procs: &ProcessTree, proc: &Proc) {
+    println!("Stopping process {:?}", proc.stat.filename);
+    for pid in process_tree(procs, proc) {
+        signal_pid(pid, Signal::SIGSTOP);
+    }
+}
+
+pub fn cont_recursive(procs: &ProcessTree, proc: &Proc) {
     println!("Continuing process {:?}", proc.stat.filename);
-    kill(
-        Pid::from_raw(proc.stat.process_id as i32),
-        nix::sys::signal::Signal::SIGCONT,
-    )
-    .unwrap();
+    for pid in process_tree(procs, proc).into_iter().rev() {
+        signal_pid(pid, Signal::SIGCONT);
+    }
 }
 
-pub fn kill_recursive(proc: &Proc) {
+/// Run a continued draft's resumeHook, sending a signal named by it to the draft's process,
+/// or otherwise running it with sh -c
+pub fn run_resume_hook(draft: &Draft, proc: &Proc) {
+    let Some(hook) = draft.resume_hook.as_deref().map(str::trim) else {
+        return;
+    };
+
     let pid = proc.stat.process_id;
-    for proc in processes().filter(is_child_process_of(pid)) {
-        kill_recursive(&proc);
+    if let Ok(signal) = hook.parse::<Signal>() {
+        println!("Sending {signal:} to {:?} on resume", draft.name);
+        signal_pid(pid, signal);
+        return;
+    }
+
+    println!("Running resume hook for {:?}", draft.name);
+    if let Err(e) = Command::new("sh")
+        .args(["-c", hook])
+        .env(RESUME_HOOK_PID_VAR, pid.to_string())
+        .spawn()
+    {
+        println!("Failed to run resume hook for {:?}: {e:}", draft.name);
     }
+}
+
+pub fn kill_recursive(procs: &ProcessTree, proc: &Proc) {
     println!("Killing process {:?}", proc.stat.filename);
-    kill(
-        Pid::from_raw(proc.stat.process_id as i32),
-        nix::sys::signal::Signal::SIGKILL,
-    )
-    .unwrap();
+    for pid in process_tree(procs, proc).into_iter().rev() {
+        signal_pid(pid, Signal::SIGKILL);
+    }
+}
+
+/// I/O counters for each of the provided processes that can still be read
+fn tree_io(pids: &[usize]) -> BTreeMap<usize, Io> {
+    pids.iter()
+        .filter_map(|pid| Some((*pid, ProcFsRoot::system().io(*pid).ok()?)))
+        .collect()
 }
 
+/// Ask a process and its descendants to exit with SIGTERM, waiting up to the timeout
+/// for all of them to go before killing whatever's left
+///
+/// The wait is extended while any of them are still writing to storage, so an app
+/// saving on exit isn't cut off part way. Returns whether everything exited by itself.
+pub fn terminate_recursive(procs: &ProcessTree, proc: &Proc, timeout: Duration) -> bool {
+    println!("Terminating process {:?}", proc.stat.filename);
+    let pids = process_tree(procs, proc);
+
+    // Stopped processes can't handle SIGTERM until they're continued
+    for pid in pids.iter().rev() {
+        signal_pid(*pid, Signal::SIGCONT);
+    }
+    for pid in &pids {
+        signal_pid(*pid, Signal::SIGTERM);
+    }
+
+    let start = Instant::now();
+    let mut io = tree_io(&pids);
+    loop {
+        let alive = processes().any(|proc| {
+            pids.contains(&proc.stat.process_id) && !matches!(proc.stat.state, State::Zombie)
+        });
+        if !alive {
+            return true;
+        }
+
+        std::thread::sleep(TERMINATE_POLL_INTERVAL);
+
+        // Give processes that are still flushing to storage extra time before killing them
+        let last_io = std::mem::replace(&mut io, tree_io(&pids));
+        let writing = io
+            .iter()
+            .any(|(pid, io)| last_io.get(pid).is_some_and(|last| io.writing_since(last)));
+        let limit = if writing {
+            timeout * TERMINATE_WRITE_GRACE
+        } else {
+            timeout
+        };
+        if start.elapsed() >= limit {
+            break;
+        }
+    }
+
+    println!(
+        "Process {:?} didn't exit within {timeout:?}, killing it",
+        proc.stat.filename
+    );
+    for pid in pids.into_iter().rev() {
+        signal_pid(pid, Signal::SIGKILL);
+    }
+    false
+}
+
+/// Set the scheduling priority of a single process
+pub fn set_nice(pid: usize, nice: i32) -> nix::Result<()> {
+    let result =
+        unsafe { nix::libc::setpriority(nix::libc::PRIO_PROCESS, pid as nix::libc::id_t, nice) };
+    if result == -1 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// Restrict a single process to the provided CPUs
+pub fn set_cpu_affinity(pid: usize, cpus: &[usize]) -> nix::Result<()> {
+    let mut cpu_set = CpuSet::new();
+    for cpu in cpus {
+        cpu_set.set(*cpu)?;
+    }
+    sched_setaffinity(Pid::from_raw(pid as i32), &cpu_set)
+}
+
+pub fn renice_recursive(procs: &ProcessTree, proc: &Proc, nice: i32) {
+    for pid in process_tree(procs, proc) {
+        match set_nice(pid, nice) {
+            Ok(()) | Err(Errno::ESRCH) => (),
+            Err(e) => println!("Failed to renice process {pid:} to {nice:}: {e:}"),
+        }
+    }
+}
+
+/// Apply a draft's nice and CPU affinity keys to its freshly launched process
+pub fn apply_draft_scheduling(draft: &Draft, pid: usize) {
+    if let Some(nice) = draft.nice {
+        if let Err(e) = set_nice(pid, nice) {
+            println!("Failed to set nice {} for {:?}: {e:}", nice, draft.name);
+        }
+    }
+
+    if !draft.cpu_affinity.is_empty() {
+        if let Err(e) = set_cpu_affinity(pid, &draft.cpu_affinity) {
+            println!(
+                "Failed to set CPU affinity {:?} for {:?}: {e:}",
+                draft.cpu_affinity, draft.name
+            );
+        }
+    }
+}
+
+/// Spawn a draft's launch target detached from the launcher and record its PID for
+/// stop / continue management
+pub fn launch_draft(draft: &Draft) -> std::io::Result<usize> {
+    println!("Launching {:#?}", draft);
+    // The zone may have changed since the launcher started, so it wins over an inherited TZ
+    let env = environment::launch_environment(
+        draft,
+        std::env::vars().chain(clock::timezone().map(|zone| ("TZ".to_string(), zone))),
+    );
+    println!("Launch environment for {:?}: {:#?}", draft.name, env);
+    let mut command = Command::new(&draft.call);
+    command.args(&draft.args).env_clear().envs(&env);
+    match draft_log::open_draft_log(&draft.id()).and_then(|log| Ok((log.try_clone()?, log))) {
+        Ok((stdout, stderr)) => {
+            command.stdout(stdout).stderr(stderr);
+        }
+        Err(e) => println!(
+            "Failed to open log for {:?}, output goes to ours: {e:}",
+            draft.name
+        ),
+    }
+    // Double fork: start a session of its own, so the launcher's exit or restart can't signal
+    // it and its descendants can be found by session once reparented, then fork again and
+    // exit, leaving the draft to parchment as subreaper rather than the launcher. Not leading
+    // its session, the draft can't take a controlling terminal. The intermediate process
+    // passes back the draft's PID, as spawn only sees its own.
+    // setsid, fork, write and _exit are async-signal-safe, so are sound between fork and exec.
+    let (mut pid_rx, pid_tx) = UnixStream::pair()?;
+    let pid_fd = pid_tx.as_raw_fd();
+    unsafe {
+        command.pre_exec(move || {
+            setsid()?;
+            if let ForkResult::Parent { child } = fork()? {
+                write(pid_fd, &child.as_raw().to_le_bytes()).ok();
+                _exit(0);
+            }
+            Ok(())
+        });
+    }
+    // Returns once the draft has been exec'd, or with the error it failed to be with
+    let mut intermediate = command.spawn()?;
+    drop(pid_tx);
+    intermediate.wait()?;
+    let mut pid = [0u8; 4];
+    pid_rx.read_exact(&mut pid)?;
+    let pid = i32::from_le_bytes(pid) as usize;
+    if let Some(cgroup) = cgroup::DraftCgroup::create(&draft.name) {
+        if let Err(e) = cgroup.add(pid) {
+            println!("Failed to add {:?} to its cgroup: {e:}", draft.name);
+        }
+        if let Some(limit) = draft.memory_limit {
+            if let Err(e) = cgroup.set_memory_limit(limit * 1024 * 1024) {
+                println!("Failed to set memory limit for {:?}: {e:}", draft.name);
+            }
+        }
+    }
+    apply_draft_scheduling(draft, pid);
+    let oom_score_adj = config::Config::load().draft_oom_score_adj;
+    if let Err(e) = oom::set_oom_score_adj(Some(pid), oom_score_adj) {
+        println!("Failed to set oom_score_adj for {:?}: {e:}", draft.name);
+    }
+    health::set_hung(&draft.id(), false);
+    if let Err(e) = pidfile::write_pid(&draft.id(), pid) {
+        println!("Failed to record PID {pid:} for {:?}: {e:}", draft.name);
+    }
+    Ok(pid)
+}
+
+/// Drafts exiting unsuccessfully this soon after launch are reported as having failed to start
+pub const LAUNCH_FAILURE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Lines of a failed draft's log quoted in its notification
+const LAUNCH_FAILURE_LOG_LINES: usize = 2;
+
+/// How a reaped draft ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DraftExit {
+    Code(i32),
+    Signal(Signal),
+    /// Reaped elsewhere, so how it ended isn't known
+    Unknown,
+}
+
+impl DraftExit {
+    /// Whether it's known to have ended badly
+    pub fn failed(&self) -> bool {
+        matches!(self, DraftExit::Code(code) if *code != 0) || matches!(self, DraftExit::Signal(_))
+    }
+
+    /// Whether it ended badly of its own accord, rather than being closed by the launcher
+    pub fn crashed(&self) -> bool {
+        match self {
+            DraftExit::Signal(signal) => !matches!(signal, Signal::SIGTERM | Signal::SIGKILL),
+            exit => exit.failed(),
+        }
+    }
+}
+
+/// Collect the exit status of a draft left to this process, so it doesn't linger as a zombie,
+/// and remove its pidfile. Unless blocking, returns None straight away if it's still running.
+pub fn reap_draft(id: &str, pid: usize, block: bool) -> Option<DraftExit> {
+    let flags = (!block).then_some(WaitPidFlag::WNOHANG);
+    let exit = loop {
+        match waitpid(Pid::from_raw(pid as i32), flags) {
+            Ok(WaitStatus::StillAlive) => return None,
+            Ok(WaitStatus::Exited(_, code)) => break DraftExit::Code(code),
+            Ok(WaitStatus::Signaled(_, signal, _)) => break DraftExit::Signal(signal),
+            Ok(status) => println!("Draft {id:?} changed state: {status:?}"),
+            Err(Errno::EINTR) => (),
+            // Already reaped, so it's gone all the same
+            Err(e) => {
+                println!("Can't wait on draft {id:?} with PID {pid:}: {e:}");
+                break DraftExit::Unknown;
+            }
+        }
+    };
+    println!("Draft {id:?} exited: {exit:?}");
+    pidfile::remove_pid(id, pid);
+    if exit.crashed() {
+        metrics::count(metrics::Counter::Crashes, 1);
+        metrics::flush_metrics();
+    }
+    Some(exit)
+}
+
+/// Why a draft exited, for telling the user
+pub fn describe_exit(exit: DraftExit) -> String {
+    match exit {
+        DraftExit::Code(code) => {
+            locale::tr_args("launch.exit_code", &[("code", &code.to_string())])
+        }
+        DraftExit::Signal(signal) => {
+            locale::tr_args("launch.signal", &[("signal", signal.as_str())])
+        }
+        DraftExit::Unknown => String::new(),
+    }
+}
+
+/// Post a notification that a draft failed to start, quoting the last of what it logged
+pub fn report_launch_failure(draft: &Draft, reason: &str) {
+    println!("Draft {:?} failed to start: {reason:}", draft.name);
+    let body = std::iter::once(reason.to_string())
+        .chain(draft_log::tail_draft_log(
+            &draft.id(),
+            LAUNCH_FAILURE_LOG_LINES,
+        ))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" / ");
+    let notification = notification::Notification {
+        title: locale::tr_args("launch.failed", &[("name", &draft.name)]),
+        body,
+        urgency: notification::Urgency::Normal,
+    };
+    if let Err(e) = notification::notify(&notification) {
+        println!("Failed to post launch failure: {e:}");
+    }
+}
+
+/// Ids of launched drafts that are running rather than stopped or exited
+pub fn running_drafts(procs: &ProcessTree) -> Vec<DraftId> {
+    let pids = pidfile::read_pids()
+        .into_iter()
+        .map(|pidfile| (pidfile.pid, pidfile.id))
+        .collect::<BTreeMap<usize, DraftId>>();
+
+    procs
+        .iter()
+        .filter(|proc| is_running(proc))
+        .filter_map(|proc| pids.get(&proc.stat.process_id).cloned())
+        .collect()
+}
+
+/// Whether any launched draft is running rather than stopped or exited
+pub fn draft_running(procs: &ProcessTree) -> bool {
+    !running_drafts(procs).is_empty()
+}
+
+/// Processes under the system's procfs
 pub fn processes() -> impl Iterator<Item = Proc> {
-    proc_fs().unwrap().flatten().map(|(_, proc)| proc)
+    processes_in(&ProcFsRoot::system())
 }
 
-pub fn system_xochitl_process() -> Option<Proc> {
-    processes().find(|proc| proc.cmdline == "/usr/bin/xochitl --system")
+/// Processes under a procfs root, none if it can't be read
+pub fn processes_in(root: &ProcFsRoot) -> impl Iterator<Item = Proc> {
+    root.processes()
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|(_, proc)| proc)
+}
+
+pub fn system_xochitl_process(procs: &ProcessTree) -> Option<&Proc> {
+    procs
+        .iter()
+        .find(|proc| proc.cmdline == "/usr/bin/xochitl --system")
 }
 
 pub fn has_session(session_id: usize) -> impl Fn(&Proc) -> bool {
@@ -113,9 +671,11 @@ pub fn is_stopped() -> impl Fn(&Proc) -> bool {
     }
 }
 
-pub fn is_draft<'a, I: IntoIterator<Item = &'a Draft> + Clone>(
+/// Pairs a process with the draft it was started from, matched by executable name,
+/// for filtering the processes of a ProcessTree
+pub fn is_draft<'a, 'p, I: IntoIterator<Item = &'a Draft> + Clone>(
     drafts: I,
-) -> impl FnMut(Proc) -> Option<(&'a Draft, Proc)> {
+) -> impl FnMut(&'p Proc) -> Option<(&'a Draft, &'p Proc)> {
     move |proc| {
         if let Some(draft) = drafts.clone().into_iter().find(|draft| {
             draft.file_name().unwrap().to_str().unwrap() == proc.stat.filename.as_str()
@@ -174,3 +734,99 @@ pub fn touch_flood_events() -> [libremarkable::evdev::InputEvent; 4] {
         ),
     ]
 }
+
+/// Lift every multitouch slot so clients see all touches end cleanly
+pub fn touch_release_events() -> Vec<libremarkable::evdev::InputEvent> {
+    (0..TOUCH_SLOTS)
+        .flat_map(|slot| {
+            [
+                libremarkable::evdev::InputEvent::new_now(
+                    libremarkable::evdev::EventType::ABSOLUTE,
+                    libremarkable::evdev::AbsoluteAxisType::ABS_MT_SLOT.0,
+                    slot,
+                ),
+                libremarkable::evdev::InputEvent::new_now(
+                    libremarkable::evdev::EventType::ABSOLUTE,
+                    libremarkable::evdev::AbsoluteAxisType::ABS_MT_TRACKING_ID.0,
+                    -1,
+                ),
+            ]
+        })
+        .chain([libremarkable::evdev::InputEvent::new_now(
+            libremarkable::evdev::EventType::SYNCHRONIZATION,
+            0,
+            0,
+        )])
+        .collect()
+}
+
+/// Lift the pen so clients don't see a stroke left in progress
+pub fn pen_release_events() -> Vec<libremarkable::evdev::InputEvent> {
+    vec![
+        libremarkable::evdev::InputEvent::new_now(
+            libremarkable::evdev::EventType::KEY,
+            libremarkable::evdev::Key::BTN_TOUCH.code(),
+            0,
+        ),
+        libremarkable::evdev::InputEvent::new_now(
+            libremarkable::evdev::EventType::KEY,
+            libremarkable::evdev::Key::BTN_TOOL_PEN.code(),
+            0,
+        ),
+        libremarkable::evdev::InputEvent::new_now(
+            libremarkable::evdev::EventType::SYNCHRONIZATION,
+            0,
+            0,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sleeping process, with PIDs above pid_max so none has a cgroup on the host
+    fn proc(pid: usize, parent: usize, session: usize, cmdline: &str) -> Proc {
+        let stat = format!(
+            "{pid:} (test) S {parent:} {pid:} {session:} {}",
+            ["0"; 46].join(" ")
+        );
+        Proc {
+            stat: stat.parse().unwrap(),
+            cmdline: cmdline.to_string(),
+        }
+    }
+
+    #[test]
+    fn walks_supplied_process_tree() {
+        let draft = proc(4194401, 1, 4194401, "/opt/bin/koreader");
+        let procs = [
+            draft.clone(),
+            proc(4194402, 4194401, 4194401, "sh"),
+            proc(4194403, 4194402, 4194401, "luajit"),
+            // Reparented after its parent exited, but still in the draft's session
+            proc(4194404, 1, 4194401, "sleep"),
+            proc(4194405, 1, 4194405, "/usr/bin/xochitl --system"),
+        ]
+        .into_iter()
+        .collect::<ProcessTree>();
+
+        assert_eq!(
+            process_tree(&procs, &draft),
+            [4194401, 4194402, 4194403, 4194404]
+        );
+        let depths = process_tree_nodes(&procs, &draft)
+            .into_iter()
+            .map(|node| (node.proc.stat.process_id, node.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            depths,
+            [(4194401, 0), (4194402, 1), (4194403, 2), (4194404, 1)]
+        );
+        assert_eq!(
+            system_xochitl_process(&procs).map(|proc| proc.stat.process_id),
+            Some(4194405)
+        );
+        assert!(system_xochitl_process(&ProcessTree::default()).is_none());
+    }
+}
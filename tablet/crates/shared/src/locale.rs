@@ -0,0 +1,284 @@
+//! Translations for user-visible strings
+//!
+//! Locales are flat TOML files at `/opt/etc/parchment/locale/<code>.toml`, with
+//! `[section]` headers prefixing the keys beneath them. Only string values are supported.
+//! Strings may contain `{name}` placeholders, filled in by `tr_args`.
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
+
+pub const LOCALE_DIR: &str = "/opt/etc/parchment/locale";
+
+/// Built-in English strings, used for any key a locale doesn't translate
+pub const DEFAULT_STRINGS: &[(&str, &str)] = &[
+    ("settings.back", "< Back"),
+    ("settings.night_mode", "Night mode"),
+    ("settings.on", "On"),
+    ("settings.off", "Off"),
+    ("settings.brightness", "Brightness: {percent}%"),
+    ("settings.do_not_disturb", "Do not disturb"),
+    ("settings.notifications", "Notifications ({count})"),
+    ("settings.usb", "USB: {mode}"),
+    ("settings.usb_connected", "USB: {mode}, connected"),
+    ("settings.usb_switching", "USB: switching..."),
+    ("settings.usb_failed", "USB: {error}"),
+    ("usb.ethernet", "Network"),
+    ("usb.mass_storage", "Mass storage"),
+    ("usb.mtp", "MTP"),
+    ("notifications.back", "< Back"),
+    ("notifications.clear", "Clear"),
+    ("notifications.empty", "No notifications"),
+    ("notifications.position", "{first}-{last} / {total}"),
+    ("network.back", "< Back"),
+    ("network.hostname", "Hostname: {hostname}"),
+    ("network.usb", "USB"),
+    ("network.wifi", "Wi-Fi"),
+    ("network.offline", "Not connected"),
+    ("network.ssh", "SSH: ssh root@{address}"),
+    ("network.ssh_offline", "SSH: running, no network"),
+    ("network.ssh_off", "SSH: not running"),
+    ("network.wifi_networks", "Wi-Fi networks >"),
+    ("wifi.back", "< Back"),
+    ("wifi.rescan", "Rescan"),
+    ("wifi.scanning", "Scanning..."),
+    ("wifi.none", "No networks found"),
+    ("wifi.scan_failed", "Failed to scan: {error}"),
+    ("wifi.passphrase", "{ssid}: {passphrase}"),
+    ("wifi.too_short", "Passphrases are at least 8 characters"),
+    ("wifi.connecting", "Connecting to {ssid}..."),
+    ("wifi.progress", "Connecting to {ssid}: {state}"),
+    ("wifi.addressing", "getting an address"),
+    ("wifi.connected", "Connected to {ssid} at {address}"),
+    (
+        "wifi.connect_failed",
+        "Failed to connect to {ssid}: {error}",
+    ),
+    ("wifi.timed_out", "Timed out connecting to {ssid}"),
+    ("settings.clock", "Clock >"),
+    ("settings.calibrate", "Calibrate touch >"),
+    ("settings.profile", "Profile: {profile} >"),
+    ("profiles.back", "< Back"),
+    ("pin.title", "Enter the PIN to open {draft}"),
+    ("pin.wrong", "Wrong PIN, try again"),
+    ("pin.locked_out", "Too many tries, wait {seconds}s"),
+    ("pin.cancel", "Cancel"),
+    ("pin.clear", "Clear"),
+    ("pin.backspace", "Del"),
+    ("profiles.default", "Default"),
+    (
+        "calibration.instructions",
+        "Tap the center of the cross ({step} of {total})",
+    ),
+    ("calibration.cancel", "Cancel"),
+    ("calibration.reset", "Reset"),
+    ("clock.back", "< Back"),
+    ("clock.sync", "Sync now"),
+    ("clock.time", "Time: {time}"),
+    ("clock.zone", "Time zone: {zone}"),
+    ("clock.no_match", "No matching time zone"),
+    ("clock.zone_set", "Time zone set to {zone}"),
+    ("clock.zone_failed", "Failed to set time zone: {error}"),
+    ("clock.synced", "Clock synced"),
+    ("clock.sync_failed", "Failed to sync clock: {error}"),
+    ("clock.syncing", "{time} (syncing)"),
+    ("clock.unsynced", "{time} (not synced)"),
+    ("launch.failed", "{name} failed to start"),
+    ("launch.exit_code", "Exited with code {code}"),
+    ("launch.signal", "Killed by {signal}"),
+    ("launch_menu.title", "Launch {name}"),
+    ("launch_menu.default", "Default"),
+    ("launch_menu.cancel", "Cancel"),
+    ("launch_menu.view_log", "View log"),
+    ("launch_menu.processes", "Processes"),
+    ("draft_log.title", "{name} log"),
+    ("draft_log.back", "< Back"),
+    ("draft_log.empty", "Nothing logged yet"),
+    ("processes.title", "{name} processes"),
+    ("processes.back", "< Back"),
+    ("processes.none", "Not running"),
+    ("processes.row", "{name} ({pid}) {state} {memory}"),
+    ("processes.more", "{count} more"),
+    ("processes.running", "running"),
+    ("processes.sleeping", "sleeping"),
+    ("processes.waiting", "waiting on I/O"),
+    ("processes.stopped", "stopped"),
+    ("processes.zombie", "zombie"),
+    ("palette.back", "< Back"),
+    ("palette.query", "Search: {query}_"),
+    ("palette.selected", "> {label}"),
+    ("palette.no_matches", "No matches"),
+    ("palette.screenshot", "Take screenshot"),
+    ("palette.lock", "Lock input"),
+    ("palette.quick_bar", "Quick bar"),
+    ("palette.night_mode", "Toggle night mode"),
+    ("palette.home", "Go home"),
+    ("palette.settings", "Settings"),
+    ("palette.network", "Network"),
+    ("palette.notifications", "Notifications"),
+    ("osk.shift", "Shift"),
+    ("osk.backspace", "Del"),
+    ("osk.symbols", "#+="),
+    ("osk.letters", "abc"),
+    ("osk.space", "Space"),
+    ("osk.done", "Done"),
+    ("tray.syncing", "Syncing..."),
+    ("tray.storage_free", "{free} free"),
+    ("tray.storage_low", "Storage low: {free} free"),
+    ("tray.recent", "Recent:"),
+    ("quick_bar.more", "More"),
+    ("confirm.close", "Close {name}? Unsaved work may be lost."),
+    ("confirm.yes", "Close"),
+    ("confirm.no", "Cancel"),
+    ("store.back", "< Back"),
+    ("store.loading", "Loading packages..."),
+    ("store.list_failed", "Failed to list packages: {error}"),
+    ("store.installing", "Installing {package}..."),
+    ("store.removing", "Removing {package}..."),
+    ("store.installed", "Installed {package}"),
+    ("store.removed", "Removed {package}"),
+    ("store.install_failed", "Failed to install {package}"),
+    ("store.remove_failed", "Failed to remove {package}"),
+    ("store.opkg_failed", "Failed to run opkg: {error}"),
+];
+
+static TRANSLATIONS: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+/// Read a quoted string value, unescaping it and allowing a trailing comment after it
+fn parse_string(value: &str) -> Option<String> {
+    let mut chars = value.trim().strip_prefix('"')?.chars();
+    let mut string = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let rest = chars.as_str().trim();
+                return (rest.is_empty() || rest.starts_with('#')).then_some(string);
+            }
+            '\\' => match chars.next()? {
+                'n' => string.push('\n'),
+                't' => string.push('\t'),
+                escaped @ ('"' | '\\') => string.push(escaped),
+                other => {
+                    string.push('\\');
+                    string.push(other);
+                }
+            },
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+/// Parse a locale file into a flat key map
+pub fn parse_locale(input: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut strings = BTreeMap::new();
+    let mut section = String::new();
+
+    for line in input
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+    {
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Locale line {line:?} is not a key = value pair"))?;
+
+        let value = parse_string(value)
+            .ok_or_else(|| format!("Locale value for {key:?} is not a string"))?;
+
+        let key = if section.is_empty() {
+            key.trim().to_string()
+        } else {
+            format!("{section:}.{}", key.trim())
+        };
+
+        strings.insert(key, value);
+    }
+
+    Ok(strings)
+}
+
+/// Load translations for the given locale code, must be called before any lookups
+pub fn locale_init(code: Option<&str>) {
+    let mut strings = DEFAULT_STRINGS
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect::<BTreeMap<_, _>>();
+
+    if let Some(code) = code {
+        let mut path = PathBuf::from(LOCALE_DIR);
+        path.push(code);
+        path.set_extension("toml");
+
+        match std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|input| parse_locale(&input))
+        {
+            Ok(translations) => strings.extend(translations),
+            Err(e) => println!("Failed to load locale {path:?}: {e:}"),
+        }
+    }
+
+    if TRANSLATIONS.set(strings).is_err() {
+        println!("Locale already initialized");
+    }
+}
+
+/// Translate a string, falling back to its key if nothing provides it
+pub fn tr(key: &str) -> String {
+    TRANSLATIONS
+        .get()
+        .and_then(|strings| strings.get(key).cloned())
+        .or_else(|| {
+            DEFAULT_STRINGS
+                .iter()
+                .find(|(candidate, _)| *candidate == key)
+                .map(|(_, value)| value.to_string())
+        })
+        .unwrap_or_else(|| key.to_string())
+}
+
+/// Translate a string, substituting `{name}` placeholders
+pub fn tr_args(key: &str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(tr(key), |string, (name, value)| {
+        string.replace(&format!("{{{name:}}}"), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_escapes_and_comments() {
+        let strings = parse_locale(
+            "# Comment\n\
+             [confirm]\n\
+             yes = \"Fermer\" # trailing comment\n\
+             no = \"Say \\\"no\\\"\\nthen go\"\n\
+             path = \"C:\\\\new # not a comment\"\n",
+        )
+        .unwrap();
+        assert_eq!(strings["confirm.yes"], "Fermer");
+        assert_eq!(strings["confirm.no"], "Say \"no\"\nthen go");
+        assert_eq!(strings["confirm.path"], "C:\\new # not a comment");
+
+        assert!(parse_locale("yes = \"Fermer\" trailing").is_err());
+        assert!(parse_locale("yes = \"unterminated").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_defaults_and_fills_placeholders() {
+        assert_eq!(tr("confirm.yes"), "Close");
+        assert_eq!(tr("no.such.key"), "no.such.key");
+        assert_eq!(
+            tr_args("launch.failed", &[("name", "yaft")]),
+            "yaft failed to start"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Opt-in counters for monitoring a device over long stretches, exported in Prometheus text format
+//!
+//! Off unless metricsFile is set in the config. Each process counts in memory and adds its
+//! counts to the totals in the file when flushed, under an advisory lock since the tray and
+//! parchment both write to it. The file is replaced whole, so it suits node_exporter's textfile
+//! collector.
+use std::{
+    collections::BTreeMap, fs::OpenOptions, os::unix::io::AsRawFd, path::PathBuf, sync::Mutex,
+};
+
+use nix::fcntl::{flock, FlockArg};
+
+use crate::config::Config;
+
+/// Lock file guarding the metrics file, kept outside the temp dir parchment clears
+pub const METRICS_LOCK_PATH: &str = "/tmp/parchment-metrics.lock";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// Drafts freshly launched, not counting those continued from being stopped
+    Launches,
+    /// Drafts that exited with an error or were killed by a signal
+    Crashes,
+    /// Display refreshes issued by the tray
+    Refreshes,
+    /// Resident memory held by drafts closed from the tray
+    MemoryReclaimed,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 4] = [
+        Counter::Launches,
+        Counter::Crashes,
+        Counter::Refreshes,
+        Counter::MemoryReclaimed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Counter::Launches => "parchment_launches_total",
+            Counter::Crashes => "parchment_crashes_total",
+            Counter::Refreshes => "parchment_refreshes_total",
+            Counter::MemoryReclaimed => "parchment_memory_reclaimed_bytes_total",
+        }
+    }
+
+    fn help(&self) -> &'static str {
+        match self {
+            Counter::Launches => "Drafts launched",
+            Counter::Crashes => "Drafts that exited with an error or signal",
+            Counter::Refreshes => "Display refreshes issued by the tray",
+            Counter::MemoryReclaimed => "Resident memory freed by closing drafts",
+        }
+    }
+}
+
+static METRICS_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+static PENDING: Mutex<BTreeMap<Counter, u64>> = Mutex::new(BTreeMap::new());
+
+/// Start counting if the config names a metrics file
+pub fn metrics_init(config: &Config) {
+    *METRICS_FILE.lock().unwrap() = config.metrics_file.clone();
+}
+
+/// Add to a counter, held in memory until the next flush
+pub fn count(counter: Counter, amount: u64) {
+    if METRICS_FILE.lock().unwrap().is_none() {
+        return;
+    }
+    *PENDING.lock().unwrap().entry(counter).or_default() += amount;
+}
+
+/// Add everything counted since the last flush to the totals in the metrics file
+pub fn flush_metrics() {
+    let Some(path) = METRICS_FILE.lock().unwrap().clone() else {
+        return;
+    };
+    let pending = std::mem::take(&mut *PENDING.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+
+    if let Err(e) = add_to_file(&path, &pending) {
+        println!("Failed to write metrics to {path:?}: {e:}");
+    }
+}
+
+fn add_to_file(path: &PathBuf, pending: &BTreeMap<Counter, u64>) -> std::io::Result<()> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(METRICS_LOCK_PATH)?;
+    flock(lock.as_raw_fd(), FlockArg::LockExclusive).map_err(std::io::Error::from)?;
+
+    let mut totals = parse_metrics(&std::fs::read_to_string(path).unwrap_or_default());
+    for (counter, amount) in pending {
+        *totals.entry(*counter).or_default() += amount;
+    }
+
+    let mut temp = path.clone().into_os_string();
+    temp.push(".tmp");
+    std::fs::write(&temp, format_metrics(&totals))?;
+    let result = std::fs::rename(&temp, path);
+    flock(lock.as_raw_fd(), FlockArg::Unlock).ok();
+    result
+}
+
+/// Counter totals from an exported file, skipping anything it doesn't recognize
+fn parse_metrics(text: &str) -> BTreeMap<Counter, u64> {
+    text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, value) = line.split_once(' ')?;
+            let counter = Counter::ALL.into_iter().find(|c| c.name() == name)?;
+            Some((counter, value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn format_metrics(totals: &BTreeMap<Counter, u64>) -> String {
+    Counter::ALL
+        .iter()
+        .map(|counter| {
+            format!(
+                "# HELP {name:} {}\n# TYPE {name:} counter\n{name:} {}\n",
+                counter.help(),
+                totals.get(counter).copied().unwrap_or_default(),
+                name = counter.name(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_round_trip() {
+        let totals = [(Counter::Launches, 3), (Counter::MemoryReclaimed, 4096)]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let text = format_metrics(&totals);
+        assert!(
+            text.contains("# TYPE parchment_launches_total counter\nparchment_launches_total 3\n")
+        );
+        assert!(text.contains("parchment_crashes_total 0\n"));
+
+        let mut parsed = parse_metrics(&text);
+        parsed.retain(|_, value| *value > 0);
+        assert_eq!(parsed, totals);
+    }
+}
@@ -0,0 +1,261 @@
+//! Notifications posted by running drafts
+//!
+//! wave listens on a named pipe at /tmp/parchment/notifications, and hands each
+//! notification to the tray to show as a banner over whatever is in the foreground.
+//! A notification is one line of tab-separated fields: title, then optionally body
+//! and urgency, e.g.
+//!
+//! ```sh
+//! printf 'Export finished\tnotes.pdf is ready\tlow\n' > /tmp/parchment/notifications
+//! ```
+//!
+//! Every notification is also kept in a history for the rest of the session, so ones
+//! missed while reading or held back by do not disturb can be caught up on in the tray.
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    os::unix::fs::{FileTypeExt, OpenOptionsExt},
+    str::FromStr,
+    thread::JoinHandle,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nix::{fcntl::OFlag, sys::stat::Mode, unistd::mkfifo};
+
+use crate::{path_temp_do_not_disturb, path_temp_notification_history, path_temp_notifications};
+
+/// Most notifications the history returns
+pub const HISTORY_LIMIT: usize = 100;
+
+/// Command line flag carrying the notification the tray should show
+pub const NOTIFICATION_ARG: &str = "--notification";
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Low,
+    #[default]
+    Normal,
+    Critical,
+}
+
+impl FromStr for Urgency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "low" => Urgency::Low,
+            "normal" => Urgency::Normal,
+            "critical" => Urgency::Critical,
+            _ => return Err(format!("Unknown urgency {s:?}")),
+        })
+    }
+}
+
+impl Display for Urgency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub urgency: Urgency,
+}
+
+impl Notification {
+    /// The notification passed to this process, if any
+    pub fn from_args() -> Option<Notification> {
+        let mut args = std::env::args()
+            .skip_while(|arg| arg != NOTIFICATION_ARG)
+            .skip(1);
+        match args.next()?.parse() {
+            Ok(notification) => Some(notification),
+            Err(e) => {
+                println!("{e:}");
+                None
+            }
+        }
+    }
+}
+
+impl FromStr for Notification {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.trim_end_matches(['\r', '\n']).split('\t');
+        let title = fields.next().unwrap_or_default().trim();
+        if title.is_empty() {
+            return Err(format!("Notification {s:?} has no title"));
+        }
+
+        Ok(Notification {
+            title: title.to_string(),
+            body: fields.next().unwrap_or_default().trim().to_string(),
+            urgency: match fields.next().map(str::trim) {
+                Some(urgency) if !urgency.is_empty() => urgency.parse()?,
+                _ => Urgency::default(),
+            },
+        })
+    }
+}
+
+impl Display for Notification {
+    /// Protocol line, without its newline. Tabs and newlines in the text become spaces.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let field = |s: &str| s.replace(['\t', '\r', '\n'], " ");
+        write!(
+            f,
+            "{}\t{}\t{}",
+            field(&self.title),
+            field(&self.body),
+            self.urgency
+        )
+    }
+}
+
+/// Post a notification, failing if nothing is listening
+pub fn notify(notification: &Notification) -> std::io::Result<()> {
+    let mut pipe = OpenOptions::new()
+        .write(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open(path_temp_notifications())?;
+    writeln!(pipe, "{notification:}")
+}
+
+/// Whether banners are held back, except for critical notifications
+pub fn do_not_disturb() -> bool {
+    path_temp_do_not_disturb().exists()
+}
+
+pub fn set_do_not_disturb(enabled: bool) -> std::io::Result<()> {
+    if enabled {
+        std::fs::write(path_temp_do_not_disturb(), "")
+    } else {
+        std::fs::remove_file(path_temp_do_not_disturb())
+    }
+}
+
+/// Add a notification to the session's history, stamped with the time it arrived
+pub fn record(notification: &Notification) -> std::io::Result<()> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path_temp_notification_history())?;
+    writeln!(file, "{secs:}\t{notification:}")
+}
+
+/// Notifications posted this session with their arrival times in seconds since the epoch, newest first
+pub fn history() -> Vec<(u64, Notification)> {
+    let history = std::fs::read_to_string(path_temp_notification_history()).unwrap_or_default();
+    parse_history(&history)
+}
+
+fn parse_history(history: &str) -> Vec<(u64, Notification)> {
+    history
+        .lines()
+        .rev()
+        .filter_map(|line| {
+            let (secs, notification) = line.split_once('\t')?;
+            Some((secs.parse().ok()?, notification.parse().ok()?))
+        })
+        .take(HISTORY_LIMIT)
+        .collect()
+}
+
+pub fn clear_history() -> std::io::Result<()> {
+    std::fs::remove_file(path_temp_notification_history())
+}
+
+/// Create the notification pipe and call back with each notification written to it
+pub fn listen(
+    mut callback: impl FnMut(Notification) + Send + 'static,
+) -> std::io::Result<JoinHandle<()>> {
+    let path = path_temp_notifications();
+    let is_fifo = std::fs::metadata(&path)
+        .map(|metadata| metadata.file_type().is_fifo())
+        .unwrap_or(false);
+    if !is_fifo {
+        std::fs::remove_file(&path).ok();
+        mkfifo(&path, Mode::from_bits_truncate(0o666))?;
+    }
+
+    // Holding the write end open too means the pipe never reports end of file
+    // between writers, so one read loop serves every draft
+    let pipe = OpenOptions::new().read(true).write(true).open(&path)?;
+    Ok(std::thread::spawn(move || {
+        for line in BufReader::new(pipe).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    println!("Failed to read notification: {e:}");
+                    continue;
+                }
+            };
+            match line.parse() {
+                Ok(notification) => callback(notification),
+                Err(e) => println!("Ignoring notification: {e:}"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_protocol_lines() {
+        assert_eq!(
+            "Export finished\tnotes.pdf is ready\tlow\n".parse(),
+            Ok(Notification {
+                title: "Export finished".to_string(),
+                body: "notes.pdf is ready".to_string(),
+                urgency: Urgency::Low,
+            })
+        );
+        assert_eq!(
+            "Battery".parse::<Notification>().map(|n| n.urgency),
+            Ok(Urgency::Normal)
+        );
+        assert!("\tNo title".parse::<Notification>().is_err());
+        assert!("Title\tBody\turgent".parse::<Notification>().is_err());
+
+        let notification = Notification {
+            title: "Two\tfields".to_string(),
+            body: "Two\nlines".to_string(),
+            urgency: Urgency::Critical,
+        };
+        assert_eq!(notification.to_string(), "Two fields\tTwo lines\tcritical");
+        assert_eq!(
+            notification
+                .to_string()
+                .parse::<Notification>()
+                .unwrap()
+                .body,
+            "Two lines"
+        );
+    }
+
+    #[test]
+    fn history_is_newest_first() {
+        let history = parse_history("10\tFirst\t\tlow\nbad line\n20\tSecond\tBody\tnormal\n");
+        assert_eq!(
+            history
+                .iter()
+                .map(|(secs, notification)| (*secs, notification.title.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(20, "Second"), (10, "First")]
+        );
+    }
+}
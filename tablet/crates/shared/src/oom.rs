@@ -0,0 +1,36 @@
+//! OOM killer preferences, written to each process' oom_score_adj
+//!
+//! The launcher protects itself so the device always has a way back to the tray,
+//! while drafts are raised so a runaway app is killed before anything else.
+use std::path::PathBuf;
+
+use proc::ProcFsRoot;
+
+pub const OOM_SCORE_ADJ_MIN: i32 = -1000;
+pub const OOM_SCORE_ADJ_MAX: i32 = 1000;
+
+/// Default for the launcher, which the kernel will never choose to kill
+pub const LAUNCHER_OOM_SCORE_ADJ: i32 = OOM_SCORE_ADJ_MIN;
+/// Default for drafts, which would otherwise inherit the launcher's protection
+pub const DRAFT_OOM_SCORE_ADJ: i32 = 300;
+
+fn oom_score_adj_path(pid: Option<usize>) -> PathBuf {
+    match pid {
+        Some(pid) => ProcFsRoot::system().pid_path(pid, "oom_score_adj"),
+        None => ProcFsRoot::system().join("self/oom_score_adj"),
+    }
+}
+
+/// Set the OOM score adjustment of the provided process, or this one if None
+pub fn set_oom_score_adj(pid: Option<usize>, value: i32) -> std::io::Result<()> {
+    let value = value.clamp(OOM_SCORE_ADJ_MIN, OOM_SCORE_ADJ_MAX);
+    std::fs::write(oom_score_adj_path(pid), value.to_string())
+}
+
+/// Apply the launcher's OOM score adjustment to this process
+pub fn protect_launcher(value: i32) {
+    match set_oom_score_adj(None, value) {
+        Ok(()) => println!("Set launcher oom_score_adj to {value:}"),
+        Err(e) => println!("Failed to set launcher oom_score_adj: {e:}"),
+    }
+}
@@ -0,0 +1,175 @@
+//! Package management via opkg, used to install and remove toltec apps
+//!
+//! Toltec apps don't ship drafts directly, they're generated from the desktop files
+//! they install, so available packages are treated as apps based on their section.
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use raft::DRAFT_PATH;
+
+pub const OPKG: &str = "opkg";
+pub const OPKG_LISTS_DIR: &str = "/opt/var/opkg-lists";
+
+/// Toltec sections containing launchable applications
+pub const APP_SECTIONS: &[&str] = &["drawing", "games", "math", "readers", "utils"];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub section: Option<String>,
+    pub description: String,
+    pub installed: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PackageAction {
+    Install,
+    Remove,
+}
+
+impl PackageAction {
+    pub fn verb(&self) -> &'static str {
+        match self {
+            PackageAction::Install => "install",
+            PackageAction::Remove => "remove",
+        }
+    }
+}
+
+/// Parse the stanzas of an opkg Packages list
+pub fn parse_packages(input: &str) -> Vec<Package> {
+    input
+        .split("\n\n")
+        .filter_map(|stanza| {
+            let mut package = Package::default();
+            for line in stanza.lines() {
+                let (key, value) = match line.split_once(": ") {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+
+                match key {
+                    "Package" => package.name = value.to_string(),
+                    "Version" => package.version = value.to_string(),
+                    "Section" => package.section = Some(value.to_string()),
+                    "Description" => package.description = value.to_string(),
+                    _ => (),
+                }
+            }
+
+            (!package.name.is_empty()).then_some(package)
+        })
+        .collect()
+}
+
+/// Packages from every downloaded feed list
+pub fn available_packages() -> std::io::Result<Vec<Package>> {
+    let mut packages = vec![];
+    for entry in std::fs::read_dir(OPKG_LISTS_DIR)?.flatten() {
+        packages.extend(parse_packages(&std::fs::read_to_string(entry.path())?));
+    }
+    Ok(packages)
+}
+
+/// Names of installed packages
+pub fn installed_packages() -> std::io::Result<BTreeSet<String>> {
+    let output = Command::new(OPKG).arg("list-installed").output()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split(" - ").next())
+        .map(str::to_string)
+        .collect())
+}
+
+/// Whether an installed package has put a draft file in place
+pub fn ships_draft(name: &str) -> bool {
+    Command::new(OPKG)
+        .args(["files", name])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout).lines().any(|line| {
+                let path = Path::new(line.trim());
+                path.starts_with(DRAFT_PATH)
+                    && path.extension().and_then(|ext| ext.to_str()) == Some("draft")
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Available app packages, marked with their install state, sorted by name
+pub fn app_packages() -> std::io::Result<Vec<Package>> {
+    let installed = installed_packages()?;
+
+    let mut packages = available_packages()?
+        .into_iter()
+        .filter(|package| {
+            package
+                .section
+                .as_deref()
+                .map(|section| APP_SECTIONS.contains(&section))
+                .unwrap_or(false)
+                || (installed.contains(&package.name) && ships_draft(&package.name))
+        })
+        .map(|mut package| {
+            package.installed = installed.contains(&package.name);
+            package
+        })
+        .collect::<Vec<_>>();
+
+    packages.sort_by(|lhs, rhs| lhs.name.cmp(&rhs.name));
+    packages.dedup_by(|lhs, rhs| lhs.name == rhs.name);
+    Ok(packages)
+}
+
+/// Run an opkg action, reporting each line of output as it arrives.
+/// Returns whether opkg exited successfully.
+pub fn run_package_action(
+    action: PackageAction,
+    name: &str,
+    mut on_line: impl FnMut(&str),
+) -> std::io::Result<bool> {
+    let mut child = Command::new(OPKG)
+        .args([action.verb(), name])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    if let Some(stdout) = child.stdout.take() {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            on_line(&line);
+        }
+    }
+
+    // Errors are only reported once opkg is done with stdout
+    if let Some(stderr) = child.stderr.take() {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            on_line(&line);
+        }
+    }
+
+    Ok(child.wait()?.success())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_package_stanzas() {
+        let packages = parse_packages(
+            "Package: koreader\nVersion: 2022.01-1\nSection: readers\nDescription: Ebook reader\n\n\
+             Package: rmkit\nVersion: 0.0.1-1\n",
+        );
+
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].name, "koreader");
+        assert_eq!(packages[0].section.as_deref(), Some("readers"));
+        assert_eq!(packages[1].version, "0.0.1-1");
+        assert_eq!(packages[1].section, None);
+    }
+}
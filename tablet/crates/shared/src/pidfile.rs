@@ -0,0 +1,136 @@
+//! Records of launched draft processes, one file per draft id under the pid directory
+//!
+//! Tray and parchment both modify the directory, parchment clearing it on startup while
+//! a restarting tray may still be writing, so changes are made under an advisory lock.
+//! Files are written whole to a temporary name then renamed into place, so readers
+//! never see a partial PID.
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use nix::fcntl::{flock, FlockArg};
+
+use crate::{path_temp_pid, path_temp_pids};
+
+/// Lock file guarding the pid directory, kept outside the temp dir parchment clears
+pub const PID_LOCK_PATH: &str = "/tmp/parchment-pids.lock";
+
+const PID_EXTENSION: &str = "pid";
+const PID_TEMP_EXTENSION: &str = "pid.tmp";
+
+/// A recorded draft process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pidfile {
+    /// Id of the draft that was launched
+    pub id: String,
+    pub path: PathBuf,
+    pub pid: usize,
+}
+
+/// Exclusive hold on the pid directory, released when dropped
+pub struct PidLock(File);
+
+/// Wait for exclusive access to the pid directory
+pub fn lock_pids() -> std::io::Result<PidLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(PID_LOCK_PATH)?;
+    flock(file.as_raw_fd(), FlockArg::LockExclusive).map_err(std::io::Error::from)?;
+    Ok(PidLock(file))
+}
+
+impl PidLock {
+    /// Record the PID a draft was launched with, replacing any earlier record
+    pub fn write(&self, id: &str, pid: usize) -> std::io::Result<()> {
+        let path = path_temp_pid(id);
+        let mut temp = path.clone();
+        temp.set_extension(PID_TEMP_EXTENSION);
+        std::fs::write(&temp, pid.to_string())?;
+        std::fs::rename(&temp, &path)
+    }
+
+    pub fn remove(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::remove_file(path)
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        flock(self.0.as_raw_fd(), FlockArg::Unlock).ok();
+    }
+}
+
+/// Record the PID a draft was launched with
+pub fn write_pid(id: &str, pid: usize) -> std::io::Result<()> {
+    lock_pids()?.write(id, pid)
+}
+
+/// Delete a draft's record if it still holds the given PID, and wasn't replaced by a relaunch
+pub fn remove_pid(id: &str, pid: usize) {
+    let lock = match lock_pids() {
+        Ok(lock) => lock,
+        Err(e) => {
+            println!("Failed to lock the pid directory: {e:}");
+            return;
+        }
+    };
+    let path = path_temp_pid(id);
+    let current = std::fs::read_to_string(&path).ok();
+    if current.is_some_and(|current| current.trim() == pid.to_string()) {
+        lock.remove(&path).ok();
+    }
+}
+
+/// Every complete record in the pid directory, skipping temporary and unreadable files
+pub fn read_pids() -> Vec<Pidfile> {
+    let dir = match std::fs::read_dir(path_temp_pids()) {
+        Ok(dir) => dir,
+        Err(_) => return vec![],
+    };
+
+    dir.flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .filter_map(|entry| {
+            parse_pidfile(entry.path(), &std::fs::read_to_string(entry.path()).ok()?)
+        })
+        .collect()
+}
+
+fn parse_pidfile(path: PathBuf, contents: &str) -> Option<Pidfile> {
+    if path.extension()? != PID_EXTENSION {
+        return None;
+    }
+    let id = path.file_stem()?.to_string_lossy().into_owned();
+    let pid = contents.trim().parse().ok()?;
+    Some(Pidfile { id, path, pid })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_temporary_and_partial_records() {
+        let pidfile = parse_pidfile(PathBuf::from("/pids/koreader-koreader.pid"), "1234\n");
+        assert_eq!(
+            pidfile,
+            Some(Pidfile {
+                id: "koreader-koreader".into(),
+                path: PathBuf::from("/pids/koreader-koreader.pid"),
+                pid: 1234,
+            })
+        );
+        assert_eq!(
+            parse_pidfile(PathBuf::from("/pids/koreader-koreader.pid.tmp"), "1234"),
+            None
+        );
+        assert_eq!(
+            parse_pidfile(PathBuf::from("/pids/yaft-yaft.pid"), ""),
+            None
+        );
+    }
+}
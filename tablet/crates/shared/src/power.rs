@@ -0,0 +1,46 @@
+//! Battery state from the kernel power supply class
+use std::path::{Path, PathBuf};
+
+pub const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Charge percentage below which e-paper activity should be reduced
+pub const LOW_BATTERY_THRESHOLD: u8 = 20;
+
+/// Find the first power supply reporting itself as a battery
+pub fn battery_path() -> Option<PathBuf> {
+    std::fs::read_dir(POWER_SUPPLY_DIR)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| read_attribute(path, "type").as_deref() == Some("Battery"))
+}
+
+fn read_attribute(path: &Path, attribute: &str) -> Option<String> {
+    Some(
+        std::fs::read_to_string(path.join(attribute))
+            .ok()?
+            .trim()
+            .to_string(),
+    )
+}
+
+/// Battery charge as a percentage
+pub fn battery_capacity() -> Option<u8> {
+    read_attribute(&battery_path()?, "capacity")?.parse().ok()
+}
+
+/// Whether the battery is charging, in which case there's no need to save power
+pub fn battery_charging() -> bool {
+    battery_path()
+        .and_then(|path| read_attribute(&path, "status"))
+        .map(|status| status == "Charging" || status == "Full")
+        .unwrap_or(false)
+}
+
+/// Whether the battery is low and discharging
+pub fn battery_low() -> bool {
+    battery_capacity()
+        .map(|capacity| capacity < LOW_BATTERY_THRESHOLD)
+        .unwrap_or(false)
+        && !battery_charging()
+}
@@ -0,0 +1,74 @@
+//! Coordination with the rm2fb framebuffer server on reMarkable 2
+//!
+//! The rM2 has no usable /dev/fb0, so apps draw into a shared memory buffer and send
+//! updates to the rm2fb server over a SysV message queue, which it applies to the
+//! panel asynchronously. libremarkable opens the same buffer on rM2, so the server
+//! has to be up before the tray draws, and its queue drained before the tray reads
+//! or refreshes the screen to avoid interleaving with an app's last updates.
+use std::time::{Duration, Instant};
+
+pub const MACHINE_PATH: &str = "/sys/devices/soc0/machine";
+pub const SWTFB_SHM_PATH: &str = "/dev/shm/swtfb.01";
+pub const SWTFB_QUEUE_KEY: i32 = 0x2257c;
+
+/// How long to wait for the server to start before drawing anyway
+pub const SERVER_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for queued updates to be applied
+pub const IDLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Interval between checks while waiting on the server
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Whether this device is a reMarkable 2
+pub fn is_rm2() -> bool {
+    std::fs::read_to_string(MACHINE_PATH)
+        .map(|machine| machine.trim().starts_with("reMarkable 2"))
+        .unwrap_or(false)
+}
+
+fn queue_id() -> Option<i32> {
+    let id = unsafe { nix::libc::msgget(SWTFB_QUEUE_KEY, 0) };
+    (id >= 0).then_some(id)
+}
+
+/// Number of updates the server has yet to apply, None if its queue doesn't exist
+fn queued_updates() -> Option<u64> {
+    let id = queue_id()?;
+    let mut stat = unsafe { std::mem::zeroed::<nix::libc::msqid_ds>() };
+    let result = unsafe { nix::libc::msgctl(id, nix::libc::IPC_STAT, &mut stat) };
+    (result == 0).then_some(stat.msg_qnum as u64)
+}
+
+/// Whether the rm2fb server has its buffer and queue set up
+pub fn server_running() -> bool {
+    std::path::Path::new(SWTFB_SHM_PATH).exists() && queue_id().is_some()
+}
+
+/// Wait for the rm2fb server to come up, returning false if it doesn't within the timeout
+pub fn wait_for_server(timeout: Duration) -> bool {
+    let start = Instant::now();
+    while !server_running() {
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
+
+/// Wait for the rm2fb server to apply every queued update, giving up after the timeout
+///
+/// Returns immediately when the server isn't running.
+pub fn wait_idle(timeout: Duration) {
+    let start = Instant::now();
+    while let Some(queued) = queued_updates() {
+        if queued == 0 {
+            return;
+        }
+        if start.elapsed() >= timeout {
+            println!("Warning: rm2fb still has {queued:} updates queued");
+            return;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
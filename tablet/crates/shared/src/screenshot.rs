@@ -0,0 +1,144 @@
+//! Compressed framebuffer dumps
+//!
+//! Dumps are rgb565 and mostly long runs of white, so pixels are packed with
+//! a PackBits-style run-length encoding. A header records the raw length and a
+//! checksum so truncated or stale files are rejected rather than restored.
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"PRLE";
+const HEADER_LEN: usize = 12;
+
+/// Longest literal or repeat run a single control byte can describe
+const MAX_RUN: usize = 128;
+
+/// FNV-1a, enough to catch truncation and corruption
+fn checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+/// Compress a raw framebuffer dump
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + data.len() / 16);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&checksum(data).to_le_bytes());
+
+    // Operate on whole pixels, padding an odd trailing byte
+    let pixels = data
+        .chunks(2)
+        .map(|chunk| [chunk[0], chunk.get(1).copied().unwrap_or_default()])
+        .collect::<Vec<_>>();
+
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = pixels[i..]
+            .iter()
+            .take(MAX_RUN + 1)
+            .take_while(|pixel| **pixel == pixels[i])
+            .count();
+
+        if run >= 2 {
+            flush_literals(&mut out, &pixels[literal_start..i]);
+            // 0x80 | (run - 2) encodes repeats of 2..=129
+            out.push(0x80 | (run - 2) as u8);
+            out.extend_from_slice(&pixels[i]);
+            i += run;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    flush_literals(&mut out, &pixels[literal_start..]);
+
+    out
+}
+
+fn flush_literals(out: &mut Vec<u8>, pixels: &[[u8; 2]]) {
+    for chunk in pixels.chunks(MAX_RUN) {
+        // 0..=127 encodes 1..=128 literal pixels
+        out.push((chunk.len() - 1) as u8);
+        out.extend(chunk.iter().flatten());
+    }
+}
+
+/// Decompress a dump, verifying its length and checksum
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+        return Err("Not a compressed screenshot".into());
+    }
+
+    let len = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+    let expected = u32::from_le_bytes(data[8..12].try_into().unwrap());
+
+    let mut out = Vec::with_capacity(len + 1);
+    let mut body = &data[HEADER_LEN..];
+    while let Some((&control, rest)) = body.split_first() {
+        if control & 0x80 != 0 {
+            let pixel = rest.get(..2).ok_or("Truncated run")?;
+            for _ in 0..(control & 0x7f) as usize + 2 {
+                out.extend_from_slice(pixel);
+            }
+            body = &rest[2..];
+        } else {
+            let count = (control as usize + 1) * 2;
+            out.extend_from_slice(rest.get(..count).ok_or("Truncated literal")?);
+            body = &rest[count..];
+        }
+    }
+
+    if out.len() < len || out.len() > len + 1 {
+        return Err(format!("Expected {len:} bytes, decoded {}", out.len()));
+    }
+    out.truncate(len);
+
+    if checksum(&out) != expected {
+        return Err("Checksum mismatch".into());
+    }
+
+    Ok(out)
+}
+
+/// Compress and write a dump, replacing any previous file atomically
+pub fn save_screenshot(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut temp_path = path.to_path_buf();
+    temp_path.set_extension("partial");
+    std::fs::write(&temp_path, encode(data))?;
+    std::fs::rename(temp_path, path)
+}
+
+/// Read and decompress a dump written by save_screenshot
+pub fn load_screenshot(path: &Path) -> Result<Vec<u8>, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{e:}"))?;
+    decode(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut data = vec![0xffu8; 1000];
+        data.extend((0..=255u8).cycle().take(301));
+        data.extend(vec![0x12; 600]);
+
+        let encoded = encode(&data);
+        assert!(encoded.len() < data.len());
+        assert_eq!(decode(&encoded).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let data = (0..=255u8).cycle().take(4096).collect::<Vec<_>>();
+        let mut encoded = encode(&data);
+
+        assert!(decode(&encoded[..encoded.len() - 10]).is_err());
+
+        let last = encoded.len() - 1;
+        encoded[last] ^= 0xff;
+        assert!(decode(&encoded).is_err());
+    }
+}
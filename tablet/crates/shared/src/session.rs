@@ -0,0 +1,131 @@
+//! Session manifest, persisted so that a launcher restart can recover suspended drafts
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, str::FromStr};
+
+use raft::DraftId;
+
+use crate::path_temp_session;
+
+/// Number of closed drafts remembered for relaunching
+pub const RECENT_LIMIT: usize = 4;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Session {
+    /// Id of the draft that was in the foreground when the tray last opened or launched something
+    pub foreground: Option<DraftId>,
+    /// Id of the draft that was in the foreground before the current one
+    pub previous: Option<DraftId>,
+    /// Ids of drafts that were left stopped
+    pub stopped: Vec<DraftId>,
+    /// Drafts killed or exited this session, most recent first
+    pub recent: Vec<String>,
+    /// Full screenshot associated with each draft by id, used to restore its framebuffer
+    pub screenshots: BTreeMap<DraftId, PathBuf>,
+}
+
+impl Session {
+    pub fn load() -> Option<Self> {
+        std::fs::read_to_string(path_temp_session())
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(path_temp_session(), self.to_string())
+    }
+
+    /// Record a draft as closed, moving it to the front if it was already listed
+    pub fn push_recent(&mut self, name: &str) {
+        self.recent.retain(|recent| recent != name);
+        self.recent.insert(0, name.to_string());
+        self.recent.truncate(RECENT_LIMIT);
+    }
+}
+
+impl FromStr for Session {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut session = Session::default();
+
+        for line in s
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.is_empty())
+        {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or("Session line is not a key=value pair")?;
+
+            match key {
+                "foreground" => session.foreground = Some(value.to_string()),
+                "previous" => session.previous = Some(value.to_string()),
+                "stopped" => session.stopped.push(value.to_string()),
+                "recent" => session.recent.push(value.to_string()),
+                key => {
+                    if let Some(draft) = key.strip_prefix("screenshot.") {
+                        session
+                            .screenshots
+                            .insert(draft.to_string(), PathBuf::from(value));
+                    }
+                }
+            }
+        }
+
+        Ok(session)
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(foreground) = &self.foreground {
+            writeln!(f, "foreground={foreground:}")?;
+        }
+
+        if let Some(previous) = &self.previous {
+            writeln!(f, "previous={previous:}")?;
+        }
+
+        for stopped in &self.stopped {
+            writeln!(f, "stopped={stopped:}")?;
+        }
+
+        for recent in &self.recent {
+            writeln!(f, "recent={recent:}")?;
+        }
+
+        for (draft, path) in &self.screenshots {
+            writeln!(f, "screenshot.{draft:}={}", path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut session = Session {
+            foreground: Some("koreader-koreader".to_string()),
+            previous: Some("yaft-yaft".to_string()),
+            stopped: vec!["yaft-yaft".to_string(), "plato-plato".to_string()],
+            recent: vec!["Plato".to_string()],
+            screenshots: [(
+                "yaft-yaft".to_string(),
+                PathBuf::from("/tmp/parchment/screenshots/yaft"),
+            )]
+            .into_iter()
+            .collect(),
+        };
+        session.push_recent("Plato");
+        session.push_recent("yaft");
+        assert_eq!(session.recent, ["yaft", "Plato"]);
+
+        let written = session.to_string();
+        assert_eq!(written.parse::<Session>(), Ok(session));
+        assert_eq!("".parse::<Session>(), Ok(Session::default()));
+        assert!("# a comment\nstopped".parse::<Session>().is_err());
+    }
+}
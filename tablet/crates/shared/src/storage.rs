@@ -0,0 +1,60 @@
+//! Disk usage from statvfs
+use nix::sys::statvfs::statvfs;
+
+/// Mount holding user documents
+pub const HOME_PATH: &str = "/home";
+
+/// Free space below which the user is warned
+pub const LOW_STORAGE_THRESHOLD: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total: u64,
+    /// Space available to unprivileged users, excluding the root reserve
+    pub available: u64,
+}
+
+/// Usage of the filesystem containing the provided path
+pub fn disk_usage(path: &str) -> Option<DiskUsage> {
+    let stat = statvfs(path).ok()?;
+    Some(DiskUsage {
+        total: stat.blocks() as u64 * stat.fragment_size() as u64,
+        available: stat.blocks_available() as u64 * stat.fragment_size() as u64,
+    })
+}
+
+/// Whether the home filesystem is running out of space
+pub fn storage_low() -> bool {
+    disk_usage(HOME_PATH)
+        .map(|usage| usage.available < LOW_STORAGE_THRESHOLD)
+        .unwrap_or(false)
+}
+
+/// Format a byte count with a binary unit, to one decimal place above bytes
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{bytes:} B");
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
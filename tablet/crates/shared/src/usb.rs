@@ -0,0 +1,317 @@
+//! USB gadget modes, switched through configfs
+//!
+//! The stock system loads g_ether, so the device shows up to a host as a network adapter.
+//! The other modes unload it and bind a gadget built under configfs instead:
+//! mass storage exposes a FAT image file, which is unmounted on the device while the host
+//! has it, and MTP runs umtprd over FunctionFS.
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use proc::ProcFsRoot;
+
+pub const GADGET_DIR: &str = "/sys/kernel/config/usb_gadget/parchment";
+pub const UDC_DIR: &str = "/sys/class/udc";
+pub const G_ETHER: &str = "g_ether";
+pub const UMTPRD: &str = "umtprd";
+pub const FFS_MTP_MOUNT: &str = "/dev/ffs-mtp";
+
+/// Linux Foundation multifunction composite gadget
+const VENDOR_ID: &str = "0x1d6b";
+const PRODUCT_ID: &str = "0x0104";
+
+const CONFIG: &str = "configs/c.1";
+const MASS_STORAGE_FUNCTION: &str = "mass_storage.usb0";
+const MTP_FUNCTION: &str = "ffs.mtp";
+
+/// How long umtprd gets to bring up its endpoints before the gadget is bound anyway
+const MTP_START_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum UsbMode {
+    Ethernet,
+    MassStorage,
+    Mtp,
+}
+
+impl UsbMode {
+    /// Mode a quick settings tap switches to
+    pub fn next(self) -> UsbMode {
+        match self {
+            UsbMode::Ethernet => UsbMode::MassStorage,
+            UsbMode::MassStorage => UsbMode::Mtp,
+            UsbMode::Mtp => UsbMode::Ethernet,
+        }
+    }
+
+    fn function(self) -> Option<&'static str> {
+        match self {
+            UsbMode::Ethernet => None,
+            UsbMode::MassStorage => Some(MASS_STORAGE_FUNCTION),
+            UsbMode::Mtp => Some(MTP_FUNCTION),
+        }
+    }
+}
+
+impl FromStr for UsbMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ethernet" => UsbMode::Ethernet,
+            "massStorage" => UsbMode::MassStorage,
+            "mtp" => UsbMode::Mtp,
+            _ => return Err(format!("Unknown USB mode {s:?}")),
+        })
+    }
+}
+
+impl Display for UsbMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UsbMode::Ethernet => "ethernet",
+            UsbMode::MassStorage => "massStorage",
+            UsbMode::Mtp => "mtp",
+        })
+    }
+}
+
+/// FAT image exposed in mass storage mode, and where it's mounted on the device otherwise
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbStorage {
+    pub image: PathBuf,
+    pub mount: PathBuf,
+}
+
+impl Default for UsbStorage {
+    fn default() -> Self {
+        UsbStorage {
+            image: PathBuf::from("/home/root/usb.img"),
+            mount: PathBuf::from("/home/root/usb"),
+        }
+    }
+}
+
+/// Run a command, failing with its error output if it doesn't succeed
+pub(crate) fn run(program: &str, args: &[&str]) -> Result<(), String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to run {program:}: {e:}"))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{program:} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn write(path: impl AsRef<Path>, value: &str) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::write(path, value).map_err(|e| format!("Failed to write {path:?}: {e:}"))
+}
+
+fn mkdir(path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    std::fs::create_dir_all(path).map_err(|e| format!("Failed to create {path:?}: {e:}"))
+}
+
+fn mounted(path: &Path) -> bool {
+    std::fs::read_to_string(ProcFsRoot::system().join("mounts"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .any(|mount| Path::new(mount) == path)
+}
+
+fn g_ether_loaded() -> bool {
+    Path::new("/sys/module").join(G_ETHER).exists()
+}
+
+/// The device's USB controller
+fn udc() -> Option<String> {
+    std::fs::read_dir(UDC_DIR)
+        .ok()?
+        .flatten()
+        .next()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+/// Current mode, or None if no gadget is bound
+pub fn usb_mode() -> Option<UsbMode> {
+    if g_ether_loaded() {
+        return Some(UsbMode::Ethernet);
+    }
+
+    let gadget = Path::new(GADGET_DIR);
+    let bound = std::fs::read_to_string(gadget.join("UDC")).unwrap_or_default();
+    if bound.trim().is_empty() {
+        return None;
+    }
+    [UsbMode::MassStorage, UsbMode::Mtp]
+        .into_iter()
+        .find(|mode| gadget.join(CONFIG).join(mode.function().unwrap()).exists())
+}
+
+/// Whether a host has set up the device
+pub fn usb_connected() -> bool {
+    udc()
+        .and_then(|udc| std::fs::read_to_string(Path::new(UDC_DIR).join(udc).join("state")).ok())
+        .map(|state| state.trim() == "configured")
+        .unwrap_or(false)
+}
+
+/// Unbind and remove the configfs gadget and anything serving it
+fn remove_gadget() -> Result<(), String> {
+    let gadget = Path::new(GADGET_DIR);
+    if !gadget.exists() {
+        return Ok(());
+    }
+
+    write(gadget.join("UDC"), "\n").ok();
+    if Path::new(FFS_MTP_MOUNT).exists() {
+        run("killall", &[UMTPRD]).ok();
+        if mounted(Path::new(FFS_MTP_MOUNT)) {
+            run("umount", &[FFS_MTP_MOUNT])?;
+        }
+    }
+
+    // configfs only lets go of a gadget when it's taken apart in reverse order
+    for function in [MASS_STORAGE_FUNCTION, MTP_FUNCTION] {
+        std::fs::remove_file(gadget.join(CONFIG).join(function)).ok();
+    }
+    for dir in [
+        format!("{CONFIG:}/strings/0x409"),
+        CONFIG.to_string(),
+        format!("functions/{MASS_STORAGE_FUNCTION:}"),
+        format!("functions/{MTP_FUNCTION:}"),
+        "strings/0x409".to_string(),
+        String::new(),
+    ] {
+        let path = gadget.join(dir);
+        if path.exists() {
+            std::fs::remove_dir(&path).map_err(|e| format!("Failed to remove {path:?}: {e:}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Build a gadget with the mode's function, ready to bind
+fn create_gadget(mode: UsbMode, storage: &UsbStorage) -> Result<(), String> {
+    let gadget = Path::new(GADGET_DIR);
+    let function = mode.function().unwrap();
+
+    mkdir(gadget.join("strings/0x409"))?;
+    write(gadget.join("idVendor"), VENDOR_ID)?;
+    write(gadget.join("idProduct"), PRODUCT_ID)?;
+    write(gadget.join("strings/0x409/manufacturer"), "reMarkable")?;
+    write(gadget.join("strings/0x409/product"), "parchment")?;
+
+    mkdir(gadget.join(CONFIG).join("strings/0x409"))?;
+    write(
+        gadget.join(CONFIG).join("strings/0x409/configuration"),
+        &mode.to_string(),
+    )?;
+
+    mkdir(gadget.join("functions").join(function))?;
+    if mode == UsbMode::MassStorage {
+        write(
+            gadget.join("functions").join(function).join("lun.0/file"),
+            &storage.image.to_string_lossy(),
+        )?;
+    }
+
+    std::os::unix::fs::symlink(
+        gadget.join("functions").join(function),
+        gadget.join(CONFIG).join(function),
+    )
+    .map_err(|e| format!("Failed to link {function:}: {e:}"))
+}
+
+/// Start umtprd on a FunctionFS mount, waiting for it to bring up its endpoints
+fn start_mtp() -> Result<(), String> {
+    mkdir(FFS_MTP_MOUNT)?;
+    run("mount", &["-t", "functionfs", "mtp", FFS_MTP_MOUNT])?;
+    Command::new(UMTPRD)
+        .spawn()
+        .map_err(|e| format!("Failed to start {UMTPRD:}: {e:}"))?;
+
+    let start = Instant::now();
+    while !Path::new(FFS_MTP_MOUNT).join("ep1").exists() {
+        if start.elapsed() > MTP_START_TIMEOUT {
+            return Err(format!("{UMTPRD:} didn't start"));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
+
+/// Switch to a mode, handing the storage image between the device and the host as needed
+pub fn set_usb_mode(mode: UsbMode, storage: &UsbStorage) -> Result<(), String> {
+    println!("Switching USB to {mode:}");
+    if mode == UsbMode::MassStorage && !storage.image.exists() {
+        return Err(format!("No storage image at {:?}", storage.image));
+    }
+
+    if g_ether_loaded() {
+        run("modprobe", &["-r", G_ETHER])?;
+    }
+    remove_gadget()?;
+
+    match mode {
+        UsbMode::MassStorage => {
+            // The host gets the filesystem to itself, so flush and let go of it first
+            nix::unistd::sync();
+            if mounted(&storage.mount) {
+                run("umount", &[&storage.mount.to_string_lossy()])?;
+            }
+        }
+        _ => {
+            if storage.image.exists() && !mounted(&storage.mount) {
+                mkdir(&storage.mount)?;
+                run(
+                    "mount",
+                    &[
+                        "-o",
+                        "loop",
+                        &storage.image.to_string_lossy(),
+                        &storage.mount.to_string_lossy(),
+                    ],
+                )?;
+            }
+        }
+    }
+
+    match mode {
+        UsbMode::Ethernet => run("modprobe", &[G_ETHER]),
+        mode => {
+            create_gadget(mode, storage)?;
+            if mode == UsbMode::Mtp {
+                start_mtp()?;
+            }
+            let udc = udc().ok_or("No USB device controller")?;
+            write(Path::new(GADGET_DIR).join("UDC"), &udc)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes_cycle_and_round_trip() {
+        let mut mode = UsbMode::Ethernet;
+        for _ in 0..3 {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            mode = mode.next();
+        }
+        assert_eq!(mode, UsbMode::Ethernet);
+    }
+}
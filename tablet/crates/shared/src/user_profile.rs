@@ -0,0 +1,77 @@
+//! Named user profiles, such as "work" or "kids", each with its own pins, theme and allowed drafts
+//!
+//! Declared with profile.<name>.<field> config keys, and chosen with activeProfile.
+//! Fields a profile leaves unset fall back to the top-level config.
+
+/// Parse a comma-separated list of draft names
+pub(crate) fn name_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UserProfile {
+    /// Drafts pinned to the quick bar and pie menu, in place of quickBarApps
+    pub quick_bar_apps: Option<Vec<String>>,
+    /// Names of the only drafts shown and launchable, all of them if None
+    pub allowed_apps: Option<Vec<String>>,
+    /// Draw white-on-black, in place of invert
+    pub invert: Option<bool>,
+    /// Gray level of the tray panel background, in place of panelBackground
+    pub panel_background: Option<u8>,
+}
+
+impl UserProfile {
+    pub(crate) fn set(&mut self, field: &str, value: &str) -> Result<(), String> {
+        match field {
+            "quickBarApps" => self.quick_bar_apps = Some(name_list(value)),
+            "allowedApps" => self.allowed_apps = Some(name_list(value)),
+            "invert" => self.invert = Some(value == "true"),
+            "panelBackground" => {
+                let level = value
+                    .parse()
+                    .map_err(|e| format!("Invalid profile panelBackground {value:?}: {e:}"))?;
+                self.panel_background = Some(level);
+            }
+            _ => return Err(format!("Unknown profile field {field:?}")),
+        }
+        Ok(())
+    }
+
+    /// Whether a draft may be shown and launched under this profile
+    pub fn allows(&self, name: &str) -> bool {
+        self.allowed_apps
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|candidate| candidate == name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    #[test]
+    fn active_profile_overrides_config() {
+        let config = "quickBarApps=Notes\n\
+            invert=true\n\
+            profile.kids.quickBarApps=Paint, Books\n\
+            profile.kids.allowedApps=Paint,Books\n\
+            activeProfile=kids\n"
+            .parse::<Config>()
+            .unwrap();
+
+        assert_eq!(config.pinned_apps(), ["Paint", "Books"]);
+        assert!(config.inverted());
+        let profile = config.profile().unwrap();
+        assert!(profile.allows("Paint"));
+        assert!(!profile.allows("Notes"));
+
+        // Unknown or unset profiles leave everything allowed
+        let config = "activeProfile=work\n".parse::<Config>().unwrap();
+        assert!(config.profile().is_none());
+    }
+}
@@ -0,0 +1,403 @@
+//! Frame-based animations, paced for e-ink
+//!
+//! Widgets ask for an animation's progress while drawing, which starts it on first use.
+//! While any animation is running, the render thread redraws the current view at a low
+//! frame rate using fast refreshes, then once more normally when they finish. Every
+//! animation ends on its own, looping ones after [`MAX_LOOP_DURATION`], so nothing
+//! keeps the display refreshing indefinitely.
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet},
+    f32::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use libremarkable::{cgmath::Point2, framebuffer::refresh::PartialRefreshMode};
+
+use crate::{
+    display::{Display, Offscreen},
+    framebuffer::{Color, DisplayTemp, DitherMode, MxcfbRect, WaveformMode},
+    state::StateStore,
+    theme::panel_background,
+    ui::{
+        circle_smooth_fill, dither_fill, offset_relative, overlay, partial_refresh, DrawContext,
+        DrawFn, ThenTrait,
+    },
+};
+
+pub const DEFAULT_ANIMATION_FPS: u32 = 4;
+
+/// Frames a slide transition takes to cover its full height
+pub const SLIDE_FRAMES: u32 = 3;
+
+/// Looping animations stop after this long
+pub const MAX_LOOP_DURATION: Duration = Duration::from_secs(10);
+
+/// Widget state id of the running animations
+const TIMELINE: &str = "animation.timeline";
+
+static FPS: AtomicU32 = AtomicU32::new(DEFAULT_ANIMATION_FPS);
+static ANIMATION_FRAME: AtomicBool = AtomicBool::new(false);
+
+/// Set the rate animations are drawn at, with 0 disabling them
+pub fn set_animation_fps(fps: u32) {
+    FPS.store(fps, Ordering::Relaxed);
+}
+
+/// Time between animation frames, None when animations are disabled
+pub fn frame_interval() -> Option<Duration> {
+    match FPS.load(Ordering::Relaxed) {
+        0 => None,
+        fps => Some(Duration::from_secs(1) / fps),
+    }
+}
+
+/// Whether the draw in progress is an intermediate animation frame
+pub fn animation_frame() -> bool {
+    ANIMATION_FRAME.load(Ordering::Relaxed)
+}
+
+pub fn set_animation_frame(frame: bool) {
+    ANIMATION_FRAME.store(frame, Ordering::Relaxed);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    Once,
+    Loop,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Animation {
+    start: Instant,
+    duration: Duration,
+    repeat: Repeat,
+}
+
+impl Animation {
+    fn end(&self) -> Instant {
+        self.start
+            + match self.repeat {
+                Repeat::Once => self.duration,
+                Repeat::Loop => MAX_LOOP_DURATION,
+            }
+    }
+
+    fn progress(&self, now: Instant) -> f32 {
+        let cycles = now.saturating_duration_since(self.start).as_secs_f32()
+            / self.duration.as_secs_f32().max(f32::EPSILON);
+        match self.repeat {
+            Repeat::Once => cycles.min(1.0),
+            Repeat::Loop if now >= self.end() => 0.0,
+            Repeat::Loop => cycles.fract(),
+        }
+    }
+}
+
+/// Animations by widget id, along with those drawn since the last prune
+#[derive(Debug, Default)]
+struct Timeline {
+    animations: BTreeMap<String, Animation>,
+    seen: BTreeSet<String>,
+}
+
+impl Timeline {
+    fn progress(&mut self, id: &str, duration: Duration, repeat: Repeat, now: Instant) -> f32 {
+        self.seen.insert(id.to_string());
+        self.animations
+            .entry(id.to_string())
+            .or_insert(Animation {
+                start: now,
+                duration,
+                repeat,
+            })
+            .progress(now)
+    }
+
+    fn running(&self, now: Instant) -> bool {
+        self.animations
+            .values()
+            .any(|animation| animation.end() > now)
+    }
+
+    /// Forget animations whose widgets weren't drawn, so they start over when next shown
+    fn prune(&mut self) {
+        let seen = std::mem::take(&mut self.seen);
+        self.animations.retain(|id, _| seen.contains(id));
+    }
+}
+
+/// Progress through an animation from 0 to 1, starting it if this is its first frame
+///
+/// With animations disabled, one-shot animations are always finished and loops stay still.
+pub fn progress(state: &StateStore, id: &str, duration: Duration, repeat: Repeat) -> f32 {
+    if frame_interval().is_none() {
+        return match repeat {
+            Repeat::Once => 1.0,
+            Repeat::Loop => 0.0,
+        };
+    }
+
+    let now = Instant::now();
+    state.update(TIMELINE, |timeline: &mut Timeline| {
+        timeline.progress(id, duration, repeat, now)
+    })
+}
+
+/// Whether any animation still needs frames drawing
+pub fn animating(state: &StateStore) -> bool {
+    let now = Instant::now();
+    state.update(TIMELINE, |timeline: &mut Timeline| timeline.running(now))
+}
+
+/// Stop animations that weren't drawn as part of the latest full view
+pub fn prune_animations(state: &StateStore) {
+    state.update(TIMELINE, Timeline::prune);
+}
+
+/// Draw the result of f for an animation's current progress
+pub fn animated<D: DrawFn>(
+    id: &'static str,
+    duration: Duration,
+    repeat: Repeat,
+    f: impl Fn(f32) -> D,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let t = progress(&ctx.state, id, duration, repeat);
+        f(t)(ctx)
+    }
+}
+
+/// Reveal what's already drawn in the current rect by dithering it away from a solid color
+pub fn fade_in(id: &'static str, duration: Duration, color: Color) -> impl DrawFn {
+    animated(id, duration, Repeat::Once, move |t| {
+        dither_fill(1.0 - t, color)
+    })
+}
+
+/// Ring of dots centered on the current position, with one dark dot circling once a second
+pub fn spinner(id: &'static str, radius: i32) -> impl DrawFn {
+    const DOTS: usize = 8;
+    animated(id, Duration::from_secs(1), Repeat::Loop, move |t| {
+        move |mut ctx: DrawContext| {
+            let active = (t * DOTS as f32) as usize;
+            for dot in 0..DOTS {
+                let angle = dot as f32 / DOTS as f32 * TAU;
+                let offset = Point2::new(
+                    (angle.sin() * radius as f32) as i32,
+                    (-angle.cos() * radius as f32) as i32,
+                );
+                let color = if dot == active {
+                    Color::BLACK
+                } else {
+                    Color::GRAY(192)
+                };
+                ctx = overlay(offset_relative(offset).then(circle_smooth_fill(
+                    (radius / 4).max(1) as u32,
+                    color,
+                    panel_background(),
+                )))(ctx);
+            }
+            ctx
+        }
+    })
+}
+
+/// Compose a slide frame, showing the top rows of a buffer dumped from rect at its bottom edge
+pub fn compose_slide(
+    fb: &mut dyn Display,
+    rect: MxcfbRect,
+    background: &[u8],
+    buffer: &[u8],
+    visible: f32,
+) {
+    fb.restore_region(rect, background).ok();
+
+    let rows = (rect.height as f32 * visible.clamp(0.0, 1.0)) as u32;
+    if rows > 0 {
+        let row = buffer.len() / rect.height.max(1) as usize;
+        fb.restore_region(
+            MxcfbRect {
+                top: rect.top + rect.height - rows,
+                height: rows,
+                ..rect
+            },
+            &buffer[..row * rows as usize],
+        )
+        .ok();
+    }
+}
+
+/// Fast refresh for intermediate slide frames, regardless of refresh policy
+pub fn slide_refresh() -> impl DrawFn {
+    partial_refresh(
+        PartialRefreshMode::Async,
+        WaveformMode::WAVEFORM_MODE_DU,
+        DisplayTemp::TEMP_USE_REMARKABLE_DRAW,
+        DitherMode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+        0,
+        false,
+    )
+}
+
+/// Slide what draw puts in region up from its bottom edge, the first time it's shown
+///
+/// Each frame draws into an offscreen buffer, which is composed over what was in the
+/// region beforehand. Once the slide finishes, draw is drawn directly as normal.
+pub fn slide_in<D: DrawFn>(id: &'static str, region: MxcfbRect, draw: D) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let done = format!("{id}.done");
+        let interval = match frame_interval() {
+            Some(interval) if !ctx.state.get::<bool>(&done) => interval,
+            _ => return draw(ctx),
+        };
+
+        let t = progress(&ctx.state, id, interval * SLIDE_FRAMES, Repeat::Once);
+        let background_id = format!("{id}.background");
+        if t >= 1.0 {
+            ctx.state.remove(&background_id);
+            ctx.state.set(&done, true);
+            return draw(ctx);
+        }
+
+        let background = match ctx.state.get::<Option<Arc<Vec<u8>>>>(&background_id) {
+            Some(background) => background,
+            None => match ctx.fb.dump_region(region) {
+                Ok(background) => {
+                    let background = Arc::new(background);
+                    ctx.state.set(&background_id, Some(background.clone()));
+                    background
+                }
+                Err(_) => return draw(ctx),
+            },
+        };
+
+        let DrawContext {
+            fb,
+            rect,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        } = ctx;
+        let display = Arc::new(Mutex::new(fb));
+        let DrawContext {
+            fb: offscreen,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+            ..
+        } = draw(DrawContext {
+            fb: Box::new(Offscreen(display.clone())),
+            rect,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        });
+        drop(offscreen);
+        let mut fb = Arc::try_unwrap(display)
+            .ok()
+            .expect("Offscreen draw kept hold of the display")
+            .into_inner()
+            .unwrap();
+
+        if let Ok(buffer) = fb.dump_region(region) {
+            compose_slide(&mut *fb, region, &background, &buffer, t);
+        }
+
+        let ctx = DrawContext {
+            fb,
+            rect: region,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        };
+        // Nothing shows on the first frame, so don't spend a refresh on it
+        let ctx = if t > 0.0 { slide_refresh()(ctx) } else { ctx };
+        DrawContext { rect, ..ctx }
+    }
+}
+
+/// Slide what's currently in the rect down out of view, uncovering background
+///
+/// Frames are drawn immediately in sequence, for dismissing UI right before handing
+/// the display to something else. The final frame is left for the caller to refresh.
+pub fn slide_out<T: Borrow<[u8]>>(background: T) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let background = background.borrow();
+        let rect = ctx.rect;
+        if let (Some(interval), Ok(buffer)) = (frame_interval(), ctx.fb.dump_region(rect)) {
+            for frame in 1..SLIDE_FRAMES {
+                let visible = 1.0 - frame as f32 / SLIDE_FRAMES as f32;
+                compose_slide(&mut *ctx.fb, rect, background, &buffer, visible);
+                ctx = slide_refresh()(ctx);
+                std::thread::sleep(interval);
+            }
+        }
+
+        ctx.fb.restore_region(rect, background).ok();
+        ctx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animations_finish_and_prune() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut timeline = Timeline::default();
+
+        assert_eq!(timeline.progress("once", second, Repeat::Once, start), 0.0);
+        let half = start + second / 2;
+        assert_eq!(timeline.progress("once", second, Repeat::Once, half), 0.5);
+        assert_eq!(timeline.progress("loop", second, Repeat::Loop, half), 0.0);
+
+        let later = start + second * 3 + second / 4;
+        assert_eq!(timeline.progress("once", second, Repeat::Once, later), 1.0);
+        assert_eq!(timeline.progress("loop", second, Repeat::Loop, later), 0.75);
+        assert!(timeline.running(later));
+        assert!(!timeline.running(later + MAX_LOOP_DURATION));
+
+        timeline.prune();
+        timeline.progress("loop", second, Repeat::Loop, later);
+        timeline.prune();
+        assert!(!timeline.animations.contains_key("once"));
+        assert!(timeline.animations.contains_key("loop"));
+    }
+
+    #[test]
+    fn slide_shows_top_rows_at_bottom_edge() {
+        let rect = MxcfbRect {
+            left: 0,
+            top: 0,
+            width: 2,
+            height: 6,
+        };
+        let mut fb = crate::display::Simulator::new(2, 6);
+        let background = fb.dump_region(rect).unwrap();
+        fb.fill_rect(
+            Point2::new(0, 0),
+            libremarkable::cgmath::Vector2::new(2, 2),
+            Color::BLACK,
+        );
+        let buffer = fb.dump_region(rect).unwrap();
+
+        compose_slide(&mut fb, rect, &background, &buffer, 1.0 / 3.0);
+        let row = buffer.len() / 6;
+        assert_eq!(
+            fb.dump_region(rect).unwrap()[..row * 4],
+            background[..row * 4]
+        );
+        assert_eq!(fb.dump_region(rect).unwrap()[row * 4..], buffer[..row * 2]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Banner for a notification posted by a draft
+//!
+//! wave starts a short-lived tray for each notification, which draws the banner along
+//! the top edge over whatever is in the foreground and puts back what was there once it
+//! times out, or once wave closes its input to make way for the tray. The foreground
+//! draft keeps running meanwhile.
+use std::{io::Read, sync::mpsc::channel, time::Duration};
+
+use libremarkable::cgmath::Point2;
+use shared::{
+    config::DisplayBackend,
+    notification::{Notification, Urgency},
+    rm2fb,
+};
+
+use crate::{
+    display::{open_display, DISPLAY_WIDTH},
+    focus::FocusMap,
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    partial_refresh_wait,
+    state::StateStore,
+    ui::{
+        margin, offset_relative, overlay, rect_border, restore_region, set_rect, text, Draw,
+        DrawContext, DrawFn, ThenTrait,
+    },
+};
+
+/// How long a banner stays up, longer for more urgent notifications
+pub fn banner_duration(urgency: Urgency) -> Duration {
+    match urgency {
+        Urgency::Low => Duration::from_secs(2),
+        Urgency::Normal => Duration::from_secs(4),
+        Urgency::Critical => Duration::from_secs(8),
+    }
+}
+
+/// Strip along the top of the display with room for a title and a line of body text
+pub fn banner_rect() -> MxcfbRect {
+    let layout = layout();
+    MxcfbRect {
+        left: layout.icon_spacing as u32,
+        top: layout.icon_spacing as u32,
+        width: DISPLAY_WIDTH as u32 - layout.icon_spacing as u32 * 2,
+        height: (layout.line_height * 2 + layout.icon_spacing) as u32,
+    }
+}
+
+pub fn banner(notification: &Notification) -> impl DrawFn + '_ {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let height = layout.line_height;
+        let border = match notification.urgency {
+            Urgency::Critical => 6,
+            _ => 2,
+        };
+
+        let mut ctx = set_rect(banner_rect())
+            .then(rect_border(border, Color::WHITE, Color::BLACK))
+            .then(margin(layout.icon_spacing / 2))
+            .draw(ctx);
+
+        ctx = overlay(offset_relative(Point2::new(0, height / 4)).then(text(
+            &notification.title,
+            layout.font_size,
+            Color::BLACK,
+        )))(ctx);
+
+        overlay(
+            offset_relative(Point2::new(0, height + height / 4)).then(text(
+                &notification.body,
+                layout.font_size * 0.8,
+                Color::GRAY(64),
+            )),
+        )(ctx)
+    }
+}
+
+/// Show a banner for the notification, then restore the screen beneath it
+pub fn show_banner(backend: DisplayBackend, notification: &Notification) {
+    println!("Showing notification {notification:?}");
+    let rect = banner_rect();
+    let mut ctx = DrawContext {
+        fb: open_display(backend),
+        rect,
+        gesture_recognizer: Default::default(),
+        focus: FocusMap::default(),
+        refresh: None,
+        state: StateStore::default(),
+    };
+
+    // Let updates queued by the foreground draft land first, so they're what gets put back
+    rm2fb::wait_idle(rm2fb::IDLE_TIMEOUT);
+    let under = ctx.fb.dump_region(rect).unwrap();
+
+    ctx = banner(notification)
+        .then(set_rect(rect))
+        .then(partial_refresh_wait())
+        .draw(ctx);
+
+    // Anything arriving on stdin, including it closing, dismisses the banner early
+    let (dismiss_tx, dismiss_rx) = channel();
+    std::thread::spawn(move || {
+        std::io::stdin().read_exact(&mut [0]).ok();
+        dismiss_tx.send(()).ok();
+    });
+    dismiss_rx
+        .recv_timeout(banner_duration(notification.urgency))
+        .ok();
+
+    set_rect(rect)
+        .then(restore_region(under))
+        .then(partial_refresh_wait())
+        .draw(ctx);
+}
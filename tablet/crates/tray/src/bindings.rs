@@ -0,0 +1,121 @@
+//! Gestures made in the open tray, dispatched to the actions bound to them in the config
+use std::{collections::BTreeMap, sync::Mutex};
+
+use raft::Draft;
+use shared::{action::Action, config::Config};
+
+use crate::{
+    channel::Sender, exit_to, home::go_home, launch_menu::launch_menu, theme::toggle_inverted,
+    MainEvent, View,
+};
+
+#[derive(Debug, Default)]
+struct Bindings {
+    gestures: BTreeMap<String, Action>,
+    /// Draft the tray was opened over, returned to on dismissal
+    stopped_draft: Option<Draft>,
+}
+
+static BINDINGS: Mutex<Option<Bindings>> = Mutex::new(None);
+
+pub fn bindings_init(config: &Config, stopped_draft: Option<&Draft>) {
+    *BINDINGS.lock().unwrap() = Some(Bindings {
+        gestures: config.gestures.clone(),
+        stopped_draft: stopped_draft.cloned(),
+    });
+}
+
+/// Action bound to a gesture, None if it's unbound
+pub fn binding(gesture: &str) -> Option<Action> {
+    BINDINGS
+        .lock()
+        .unwrap()
+        .as_ref()?
+        .gestures
+        .get(gesture)
+        .copied()
+}
+
+/// Multi-finger taps bound in the tray, as the number of fingers and the action
+pub fn tray_taps() -> Vec<(usize, Action)> {
+    let bindings = BINDINGS.lock().unwrap();
+    let Some(bindings) = bindings.as_ref() else {
+        return vec![];
+    };
+    bindings
+        .gestures
+        .iter()
+        .filter_map(|(gesture, action)| {
+            let fingers = gesture.strip_prefix("tray.tap")?.parse().ok()?;
+            Some((fingers, *action))
+        })
+        .collect()
+}
+
+/// Swipes down the panel bound in the tray, as the number of fingers and the action
+pub fn tray_swipes() -> Vec<(usize, Action)> {
+    let bindings = BINDINGS.lock().unwrap();
+    let Some(bindings) = bindings.as_ref() else {
+        return vec![];
+    };
+    bindings
+        .gestures
+        .iter()
+        .filter_map(|(gesture, action)| {
+            let fingers = match gesture.strip_prefix("tray.swipe")? {
+                "" => 1,
+                fingers => fingers.parse().ok()?,
+            };
+            Some((fingers, *action))
+        })
+        .collect()
+}
+
+/// Close the tray, returning to the draft it was opened over
+fn dismiss(event_tx: &Sender<MainEvent>) {
+    println!("Dismissing the tray");
+    let stopped_draft = BINDINGS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|bindings| bindings.stopped_draft.clone());
+    exit_to(event_tx, stopped_draft);
+}
+
+/// Carry out an action bound in the tray, with the draft whose icon the gesture was made on
+pub fn run_tray_action(action: Action, event_tx: &Sender<MainEvent>, draft: Option<&Draft>) {
+    match (action, draft) {
+        (Action::Dismiss, _) => dismiss(event_tx),
+        (Action::Home, _) => go_home(event_tx),
+        (Action::Screenshot, _) => {
+            event_tx.send(MainEvent::Screenshot).ok();
+            dismiss(event_tx);
+        }
+        (Action::LockInput, _) => {
+            event_tx.send(MainEvent::ShowView(View::Locked)).ok();
+        }
+        (Action::QuickBar, _) => {
+            event_tx.send(MainEvent::ShowView(View::QuickBar)).ok();
+        }
+        (Action::Palette, _) => {
+            event_tx.send(MainEvent::ShowView(View::Palette)).ok();
+        }
+        (Action::ToggleNightMode, _) => {
+            toggle_inverted();
+            event_tx.send(MainEvent::Redraw).ok();
+        }
+        (Action::Launch, Some(draft)) => {
+            println!("Launching {:?}", draft.name);
+            exit_to(event_tx, Some(draft.clone()));
+        }
+        (Action::LaunchMenu, Some(draft)) => {
+            event_tx
+                .send(MainEvent::set_draw(Some(launch_menu(
+                    event_tx.clone(),
+                    draft.clone(),
+                ))))
+                .ok();
+        }
+        (action, _) => println!("Ignoring {action:}, it can't be run from here"),
+    }
+}
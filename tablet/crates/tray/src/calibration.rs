@@ -0,0 +1,192 @@
+//! Touch calibration, correcting how reported touch positions map onto the display
+//!
+//! Crosses are shown one at a time. Where each is tapped, already passed through the current
+//! correction, pins down a further correction that's composed onto it and saved as
+//! touchTransform.
+use std::sync::Mutex;
+
+use gesture::TouchTransform;
+use libremarkable::cgmath::Point2;
+use shared::{
+    config::update_config,
+    locale::{tr, tr_args},
+};
+
+use crate::{
+    channel::Sender,
+    display::{DISPLAY_HEIGHT, DISPLAY_RECT, DISPLAY_WIDTH},
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    partial_refresh,
+    state::StateStore,
+    text_button,
+    ui::{
+        line, offset_absolute, offset_relative, overlay, recognize_gesture, rect_fill, set_rect,
+        text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the positions tapped so far
+pub const CALIBRATION_TAPS: &str = "calibration.taps";
+
+/// Crosses to tap, as fractions of the display size, spread out so that small errors in
+/// tapping them make for small errors in the correction
+const TARGETS: [(f32, f32); 3] = [(0.15, 0.15), (0.85, 0.3), (0.3, 0.85)];
+
+/// Distance from a cross within which a tap counts toward it
+const TARGET_REACH: u32 = 200;
+
+/// Length of each arm of a cross
+const CROSS_ARM: i32 = 40;
+
+static TOUCH_TRANSFORM: Mutex<TouchTransform> = Mutex::new(TouchTransform::IDENTITY);
+
+/// Correction applied to touches before they reach the gesture recognizer
+pub fn touch_transform() -> TouchTransform {
+    *TOUCH_TRANSFORM.lock().unwrap()
+}
+
+pub fn set_touch_transform(transform: TouchTransform) {
+    *TOUCH_TRANSFORM.lock().unwrap() = transform;
+}
+
+/// Start applying a correction and persist it to the config file
+fn save_touch_transform(transform: TouchTransform) {
+    println!("Touch transform {transform:}");
+    set_touch_transform(transform);
+    if let Err(e) = update_config("touchTransform", &transform.to_string()) {
+        println!("Failed to save touch transform: {e:}");
+    }
+}
+
+pub fn reset_calibration(state: &StateStore) {
+    state.remove(CALIBRATION_TAPS);
+}
+
+fn target(i: usize) -> Point2<f32> {
+    let (x, y) = TARGETS[i];
+    Point2::new(x * DISPLAY_WIDTH as f32, y * DISPLAY_HEIGHT as f32)
+}
+
+/// Record a tap on the current cross, applying the correction once every cross has one
+fn record_tap(state: &StateStore, event_tx: &Sender<MainEvent>, pos: Point2<f32>) {
+    let taps = state.update::<Vec<Point2<f32>>, _>(CALIBRATION_TAPS, |taps| {
+        taps.push(pos);
+        taps.clone()
+    });
+    let Ok(taps) = <[Point2<f32>; 3]>::try_from(taps) else {
+        event_tx.send(MainEvent::Redraw).ok();
+        return;
+    };
+
+    reset_calibration(state);
+    match TouchTransform::from_points(taps, [0, 1, 2].map(target)) {
+        Some(correction) => {
+            save_touch_transform(touch_transform().then(&correction));
+            event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+        }
+        None => {
+            println!("Calibration taps {taps:?} too close to a line, starting over");
+            event_tx.send(MainEvent::Redraw).ok();
+        }
+    }
+}
+
+/// Full-screen calibration, with cancel and reset buttons in the middle, clear of the crosses
+pub fn calibration(event_tx: Sender<MainEvent>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let step = ctx
+            .state
+            .get::<Vec<Point2<f32>>>(CALIBRATION_TAPS)
+            .len()
+            .min(TARGETS.len() - 1);
+        let instructions = tr_args(
+            "calibration.instructions",
+            &[
+                ("step", &(step + 1).to_string()),
+                ("total", &TARGETS.len().to_string()),
+            ],
+        );
+        let cancel_label = tr("calibration.cancel");
+        let reset_label = tr("calibration.reset");
+        let button = |x: f32| {
+            set_rect(MxcfbRect {
+                left: (DISPLAY_WIDTH as f32 * x) as u32,
+                top: DISPLAY_HEIGHT as u32 / 2,
+                width: DISPLAY_WIDTH as u32 / 4,
+                height: layout.line_height as u32,
+            })
+        };
+
+        let mut ctx = set_rect(DISPLAY_RECT)
+            .then(rect_fill(Color::WHITE))
+            .draw(ctx);
+
+        ctx = overlay(
+            offset_absolute(Point2::new(0.5, 0.5))
+                .then(offset_relative(Point2::new(0, -layout.line_height * 2)))
+                .then(text_aligned(
+                    &instructions,
+                    layout.font_size,
+                    Point2::new(0.5, 0.0),
+                    Color::BLACK,
+                )),
+        )(ctx);
+        ctx = overlay(button(0.3).then(text_button(&cancel_label, {
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move || {
+                reset_calibration(&state);
+                event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+            }
+        })))(ctx);
+        ctx = overlay(button(0.55).then(text_button(&reset_label, {
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move || {
+                reset_calibration(&state);
+                save_touch_transform(TouchTransform::IDENTITY);
+                event_tx.send(MainEvent::Redraw).ok();
+            }
+        })))(ctx);
+
+        // Current cross, and the area around it that takes its tap
+        let center = target(step).cast::<i32>().unwrap();
+        ctx = overlay(
+            set_rect(DISPLAY_RECT)
+                .then(line(
+                    Point2::new(center.x - CROSS_ARM, center.y),
+                    Point2::new(center.x + CROSS_ARM, center.y),
+                    3,
+                    Color::BLACK,
+                ))
+                .then(set_rect(DISPLAY_RECT))
+                .then(line(
+                    Point2::new(center.x, center.y - CROSS_ARM),
+                    Point2::new(center.x, center.y + CROSS_ARM),
+                    3,
+                    Color::BLACK,
+                )),
+        )(ctx);
+        ctx = overlay(
+            set_rect(MxcfbRect {
+                left: (center.x as u32).saturating_sub(TARGET_REACH),
+                top: (center.y as u32).saturating_sub(TARGET_REACH),
+                width: TARGET_REACH * 2,
+                height: TARGET_REACH * 2,
+            })
+            .then(recognize_gesture(gesture::recognize_tap(
+                layout.tap_hysteresis,
+                {
+                    let state = ctx.state.clone();
+                    let event_tx = event_tx.clone();
+                    move |pos| record_tap(&state, &event_tx, pos.cast().unwrap())
+                },
+            ))),
+        )(ctx);
+
+        set_rect(DISPLAY_RECT).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -1,4 +1,322 @@
-pub use crossbeam_channel::{
-    unbounded as channel, Receiver, RecvError, SendError, Sender, TryRecvError,
-    TrySendError,
+//! Multi-producer single-consumer channel with priority lanes
+//!
+//! Each message is sorted into a lane by its priority, and the receiver always drains
+//! higher-priority lanes first. Lanes can be bounded, with senders either blocking or
+//! dropping when full, and a new message can replace the last one queued in its lane
+//! so bursts of redundant events collapse into one.
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Display},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
 };
+
+/// Message priority, in the order lanes are drained
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Input,
+    Render,
+    Background,
+}
+
+const LANES: usize = 3;
+
+/// What a sender does when its lane is at capacity
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wait for the receiver to make space
+    Block,
+    /// Discard the message being sent
+    DropNewest,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Lane {
+    pub capacity: Option<usize>,
+    pub overflow: Overflow,
+}
+
+impl Lane {
+    pub const UNBOUNDED: Lane = Lane {
+        capacity: None,
+        overflow: Overflow::Block,
+    };
+}
+
+/// How messages of a given type are prioritized and coalesced
+pub struct Policy<T> {
+    pub priority: fn(&T) -> Priority,
+    /// Whether a new message (second) should replace the last one queued in its lane (first)
+    pub coalesce: fn(&T, &T) -> bool,
+    pub lanes: [Lane; LANES],
+}
+
+impl<T> Clone for Policy<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Policy<T> {}
+
+impl<T> Policy<T> {
+    /// A single unbounded FIFO lane
+    pub const FIFO: Policy<T> = Policy {
+        priority: |_| Priority::Render,
+        coalesce: |_, _| false,
+        lanes: [Lane::UNBOUNDED; LANES],
+    };
+}
+
+pub struct SendError<T>(pub T);
+
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("sending on a disconnected channel")
+    }
+}
+
+impl<T> std::error::Error for SendError<T> {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RecvError;
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("receiving on an empty and disconnected channel")
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+struct State<T> {
+    lanes: [VecDeque<T>; LANES],
+    senders: usize,
+    receiver: bool,
+}
+
+struct Inner<T> {
+    policy: Policy<T>,
+    state: Mutex<State<T>>,
+    ready: Condvar,
+    space: Condvar,
+}
+
+pub struct Sender<T>(Arc<Inner<T>>);
+
+pub struct Receiver<T>(Arc<Inner<T>>);
+
+/// Create a channel that sorts messages according to the provided policy
+pub fn priority_channel<T>(policy: Policy<T>) -> (Sender<T>, Receiver<T>) {
+    let inner = Arc::new(Inner {
+        policy,
+        state: Mutex::new(State {
+            lanes: Default::default(),
+            senders: 1,
+            receiver: true,
+        }),
+        ready: Condvar::new(),
+        space: Condvar::new(),
+    });
+
+    (Sender(inner.clone()), Receiver(inner))
+}
+
+/// Create an unbounded FIFO channel
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    priority_channel(Policy::FIFO)
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, message: T) -> Result<(), SendError<T>> {
+        let inner = &*self.0;
+        let index = (inner.policy.priority)(&message) as usize;
+        let lane = inner.policy.lanes[index];
+
+        let mut state = inner.state.lock().unwrap();
+        if !state.receiver {
+            return Err(SendError(message));
+        }
+
+        if let Some(last) = state.lanes[index].back_mut() {
+            if (inner.policy.coalesce)(last, &message) {
+                *last = message;
+                return Ok(());
+            }
+        }
+
+        if let Some(capacity) = lane.capacity {
+            match lane.overflow {
+                Overflow::Block => {
+                    state = inner
+                        .space
+                        .wait_while(state, |state| {
+                            state.receiver && state.lanes[index].len() >= capacity
+                        })
+                        .unwrap();
+
+                    if !state.receiver {
+                        return Err(SendError(message));
+                    }
+                }
+                Overflow::DropNewest => {
+                    if state.lanes[index].len() >= capacity {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        state.lanes[index].push_back(message);
+        inner.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.0.state.lock().unwrap().senders += 1;
+        Sender(self.0.clone())
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().senders -= 1;
+        self.0.ready.notify_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    fn pop(&self, state: &mut State<T>) -> Option<T> {
+        let message = state.lanes.iter_mut().find_map(|lane| lane.pop_front())?;
+        self.0.space.notify_all();
+        Some(message)
+    }
+
+    /// Block until a message arrives, or every sender is dropped
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.0.state.lock().unwrap();
+        loop {
+            if let Some(message) = self.pop(&mut state) {
+                return Ok(message);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+
+            state = self.0.ready.wait(state).unwrap();
+        }
+    }
+
+    /// Block until a message arrives, every sender is dropped, or the timeout passes
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.0.state.lock().unwrap();
+        loop {
+            if let Some(message) = self.pop(&mut state) {
+                return Ok(message);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            state = self.0.ready.wait_timeout(state, remaining).unwrap().0;
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.0.state.lock().unwrap();
+        if let Some(message) = self.pop(&mut state) {
+            Ok(message)
+        } else if state.senders == 0 {
+            Err(TryRecvError::Disconnected)
+        } else {
+            Err(TryRecvError::Empty)
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().receiver = false;
+        self.0.space.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy<(Priority, u32)> {
+        Policy {
+            priority: |(priority, _)| *priority,
+            coalesce: |(queued, _), (priority, _)| {
+                *queued == Priority::Render && *priority == Priority::Render
+            },
+            lanes: [
+                Lane::UNBOUNDED,
+                Lane::UNBOUNDED,
+                Lane {
+                    capacity: Some(1),
+                    overflow: Overflow::DropNewest,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn drains_by_priority() {
+        let (tx, rx) = priority_channel(policy());
+        tx.send((Priority::Background, 0)).unwrap();
+        tx.send((Priority::Render, 1)).unwrap();
+        tx.send((Priority::Input, 2)).unwrap();
+        tx.send((Priority::Input, 3)).unwrap();
+
+        let order = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|(_, n)| n)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn coalesces_and_drops() {
+        let (tx, rx) = priority_channel(policy());
+        tx.send((Priority::Render, 0)).unwrap();
+        tx.send((Priority::Render, 1)).unwrap();
+        tx.send((Priority::Background, 2)).unwrap();
+        tx.send((Priority::Background, 3)).unwrap();
+
+        assert_eq!(rx.try_recv(), Ok((Priority::Render, 1)));
+        assert_eq!(rx.try_recv(), Ok((Priority::Background, 2)));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+
+        drop(tx);
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
@@ -0,0 +1,213 @@
+//! Clock settings: time zone and network time
+//!
+//! The zone is picked by typing part of its name on the on-screen keyboard, with the
+//! closest matches listed as it's typed.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, OnceLock,
+};
+
+use libremarkable::cgmath::Point2;
+use shared::{
+    clock::{match_timezones, set_timezone, sync_clock, timezone, timezones},
+    locale::{tr, tr_args},
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    idle::clock_status,
+    layout::layout,
+    osk::{clear_keyboard, on_screen_keyboard},
+    panel::panel_rect,
+    partial_refresh,
+    state::StateStore,
+    text_button,
+    ui::{
+        margin, margin_bottom, margin_left, margin_top, offset_relative, overlay, rect_border,
+        set_rect, text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of whether a zone is being typed
+pub const CLOCK_ZONE_ENTRY: &str = "clock.zone_entry";
+
+/// Widget state id of the zone query typed so far
+pub const CLOCK_ZONE_QUERY: &str = "clock.zone_query";
+
+/// Matches listed beneath the keyboard
+const ZONE_MATCHES: usize = 3;
+
+/// Whether a network time sync is in progress
+static SYNCING: AtomicBool = AtomicBool::new(false);
+
+/// Outcome of the last sync or zone change, shown until the next
+static CLOCK_RESULT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Installed zones, which don't change while the tray is open
+static ZONES: OnceLock<Vec<String>> = OnceLock::new();
+
+fn zones() -> &'static [String] {
+    ZONES.get_or_init(timezones)
+}
+
+pub fn clock_syncing() -> bool {
+    SYNCING.load(Ordering::SeqCst)
+}
+
+fn set_result(result: String, event_tx: &Sender<MainEvent>) {
+    *CLOCK_RESULT.lock().unwrap() = Some(result);
+    event_tx.send(MainEvent::Redraw).ok();
+}
+
+/// Sync with a time server in the background, as reaching one can take a while
+fn start_sync(event_tx: &Sender<MainEvent>) {
+    if SYNCING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    CLOCK_RESULT.lock().unwrap().take();
+    event_tx.send(MainEvent::Redraw).ok();
+
+    let event_tx = event_tx.clone();
+    std::thread::spawn(move || {
+        let result = match sync_clock() {
+            Ok(()) => tr("clock.synced"),
+            Err(e) => {
+                println!("Failed to sync the clock: {e:}");
+                tr_args("clock.sync_failed", &[("error", &e)])
+            }
+        };
+        SYNCING.store(false, Ordering::SeqCst);
+        set_result(result, &event_tx);
+    });
+}
+
+/// Leave zone entry and forget the query
+pub fn reset_clock_settings(state: &StateStore) {
+    state.remove(CLOCK_ZONE_ENTRY);
+    clear_keyboard(state, CLOCK_ZONE_QUERY);
+}
+
+fn status_line(status: &str) -> impl Draw + '_ {
+    text_aligned(
+        status,
+        layout().font_size,
+        Point2::new(0.0, 0.0),
+        Color::BLACK,
+    )
+}
+
+/// Full-panel clock settings, swapped for a keyboard while a zone is typed
+pub fn clock_settings(event_tx: Sender<MainEvent>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let back_label = tr("clock.back");
+        let result = CLOCK_RESULT.lock().unwrap().clone().unwrap_or_default();
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+        let header = ctx.rect;
+
+        if ctx.state.get::<bool>(CLOCK_ZONE_ENTRY) {
+            let query = ctx.state.get::<String>(CLOCK_ZONE_QUERY);
+            let matches = match_timezones(zones(), &query);
+            let field = tr_args("clock.zone", &[("zone", &query)]);
+            let matches_label = match matches.is_empty() {
+                true => tr("clock.no_match"),
+                false => matches
+                    .iter()
+                    .take(ZONE_MATCHES)
+                    .map(|zone| zone.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+
+            ctx = overlay(text_button(&back_label, {
+                let state = ctx.state.clone();
+                let event_tx = event_tx.clone();
+                move || {
+                    reset_clock_settings(&state);
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            }))(ctx);
+            ctx = overlay(
+                margin_left(header.width as i32 / 4)
+                    .then(offset_relative(Point2::new(0, height / 4)))
+                    .then(status_line(&field)),
+            )(ctx);
+
+            ctx = overlay(
+                margin_top(height)
+                    .then(margin_bottom(height))
+                    .then(on_screen_keyboard(CLOCK_ZONE_QUERY, event_tx.clone(), {
+                        let state = ctx.state.clone();
+                        let event_tx = event_tx.clone();
+                        move |query: String| {
+                            // Done takes the best match, so a zone rarely needs typing in full
+                            let Some(zone) = match_timezones(zones(), &query).first().cloned()
+                            else {
+                                return;
+                            };
+                            reset_clock_settings(&state);
+                            let result = match set_timezone(zone) {
+                                Ok(()) => tr_args("clock.zone_set", &[("zone", zone.as_str())]),
+                                Err(e) => {
+                                    println!("Failed to set the time zone: {e:}");
+                                    tr_args("clock.zone_failed", &[("error", &e)])
+                                }
+                            };
+                            set_result(result, &event_tx);
+                        }
+                    })),
+            )(ctx);
+
+            ctx = overlay(
+                offset_relative(Point2::new(0, header.height as i32 - height))
+                    .then(status_line(&matches_label)),
+            )(ctx);
+        } else {
+            let sync_label = tr("clock.sync");
+            let time_label = tr_args("clock.time", &[("time", &clock_status())]);
+            let zone_label = tr_args(
+                "clock.zone",
+                &[("zone", &timezone().unwrap_or_else(|| "UTC".to_string()))],
+            );
+
+            ctx = overlay(text_button(&back_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+                }
+            }))(ctx);
+            ctx = overlay(
+                margin_left(header.width as i32 / 2).then(text_button(&sync_label, {
+                    let event_tx = event_tx.clone();
+                    move || start_sync(&event_tx)
+                })),
+            )(ctx);
+
+            ctx = overlay(
+                offset_relative(Point2::new(0, height + height / 4)).then(status_line(&time_label)),
+            )(ctx);
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * 2)).then(text_button(&zone_label, {
+                    let state = ctx.state.clone();
+                    let event_tx = event_tx.clone();
+                    move || {
+                        reset_clock_settings(&state);
+                        state.set(CLOCK_ZONE_ENTRY, true);
+                        event_tx.send(MainEvent::Redraw).ok();
+                    }
+                })),
+            )(ctx);
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * 3 + height / 4)).then(status_line(&result)),
+            )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -0,0 +1,59 @@
+//! Confirmation prompt shown in place of the panel
+use std::time::Duration;
+
+use libremarkable::cgmath::Point2;
+use shared::locale::tr;
+
+use crate::{
+    animation::fade_in,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    partial_refresh, text_button,
+    ui::{
+        margin, offset_relative, overlay, rect_border, set_rect, text, Draw, DrawContext, DrawFn,
+        ThenTrait,
+    },
+};
+
+pub const FADE_DURATION: Duration = Duration::from_millis(750);
+
+/// A message with buttons to confirm or cancel
+pub fn confirm_dialog(
+    message: String,
+    on_confirm: impl Fn() + Clone + Send + Sync + 'static,
+    on_cancel: impl Fn() + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let confirm_label = tr("confirm.yes");
+        let cancel_label = tr("confirm.no");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        ctx = overlay(offset_relative(Point2::new(0, height / 4)).then(text(
+            &message,
+            layout().font_size,
+            Color::BLACK,
+        )))(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height))
+                .then(text_button(&confirm_label, on_confirm.clone())),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 2))
+                .then(text_button(&cancel_label, on_cancel.clone())),
+        )(ctx);
+
+        // Appear gradually so the prompt reads as a response to the tap that opened it
+        set_rect(panel_rect())
+            .then(fade_in("confirm.fade", FADE_DURATION, Color::WHITE))
+            .then(partial_refresh())
+            .draw(ctx)
+    }
+}
@@ -2,7 +2,18 @@ pub use libremarkable::dimensions::{
     DISPLAYHEIGHT as DISPLAY_HEIGHT, DISPLAYWIDTH as DISPLAY_WIDTH,
 };
 
-use libremarkable::framebuffer::common::mxcfb_rect as MxcfbRect;
+use libremarkable::{
+    cgmath::{Point2, Vector2},
+    framebuffer::{
+        core::Framebuffer, refresh::PartialRefreshMode, FramebufferDraw, FramebufferIO,
+        FramebufferRefresh,
+    },
+    image::RgbImage,
+};
+use shared::{config::DisplayBackend, rm2fb};
+use std::sync::{Arc, Mutex};
+
+use crate::framebuffer::{Color, DisplayTemp, DitherMode, MxcfbRect, WaveformMode};
 
 pub const DISPLAY_RECT: MxcfbRect = MxcfbRect {
     top: 0,
@@ -10,3 +21,683 @@ pub const DISPLAY_RECT: MxcfbRect = MxcfbRect {
     width: DISPLAY_WIDTH as u32,
     height: DISPLAY_HEIGHT as u32,
 };
+
+pub const MXCFB_PATH: &str = "/dev/fb0";
+
+/// Surface that draw functions render to
+pub trait Display: Send {
+    fn clear(&mut self);
+    fn set_pixel(&mut self, pos: Point2<i32>, color: Color);
+    fn fill_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, color: Color);
+    fn draw_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, border_px: u32, color: Color);
+    fn draw_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        color: Color,
+    ) -> MxcfbRect;
+    fn draw_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect;
+    fn fill_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect;
+    /// Draw a line of text, or only measure it if dryrun is set
+    fn draw_text(
+        &mut self,
+        pos: Point2<f32>,
+        text: &str,
+        size: f32,
+        color: Color,
+        dryrun: bool,
+    ) -> MxcfbRect;
+    fn draw_image(&mut self, image: &RgbImage, pos: Point2<i32>) -> MxcfbRect;
+
+    #[allow(clippy::too_many_arguments)]
+    fn partial_refresh(
+        &self,
+        region: &MxcfbRect,
+        mode: PartialRefreshMode,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        force_full_refresh: bool,
+    ) -> u32;
+    fn full_refresh(
+        &self,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        wait_completion: bool,
+    ) -> u32;
+    /// Block until the refresh with the given update marker has reached the panel
+    fn wait_refresh_complete(&self, marker: u32) -> u32;
+
+    /// Copy a region out as native pixel data
+    fn dump_region(&self, rect: MxcfbRect) -> Result<Vec<u8>, &'static str>;
+    /// Write native pixel data from dump_region back into a region
+    fn restore_region(&mut self, rect: MxcfbRect, data: &[u8]) -> Result<u32, &'static str>;
+}
+
+/// libremarkable's framebuffer, which backs both mxcfb and rm2fb depending on the device it opens
+impl Display for Framebuffer {
+    fn clear(&mut self) {
+        FramebufferDraw::clear(self)
+    }
+
+    fn set_pixel(&mut self, pos: Point2<i32>, color: Color) {
+        FramebufferIO::write_pixel(self, pos, color)
+    }
+
+    fn fill_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, color: Color) {
+        FramebufferDraw::fill_rect(self, pos, size, color)
+    }
+
+    fn draw_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, border_px: u32, color: Color) {
+        FramebufferDraw::draw_rect(self, pos, size, border_px, color)
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        color: Color,
+    ) -> MxcfbRect {
+        FramebufferDraw::draw_line(self, start, end, width, color)
+    }
+
+    fn draw_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        FramebufferDraw::draw_circle(self, pos, rad, color)
+    }
+
+    fn fill_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        FramebufferDraw::fill_circle(self, pos, rad, color)
+    }
+
+    fn draw_text(
+        &mut self,
+        pos: Point2<f32>,
+        text: &str,
+        size: f32,
+        color: Color,
+        dryrun: bool,
+    ) -> MxcfbRect {
+        FramebufferDraw::draw_text(self, pos, text, size, color, dryrun)
+    }
+
+    fn draw_image(&mut self, image: &RgbImage, pos: Point2<i32>) -> MxcfbRect {
+        FramebufferDraw::draw_image(self, image, pos)
+    }
+
+    fn partial_refresh(
+        &self,
+        region: &MxcfbRect,
+        mode: PartialRefreshMode,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        force_full_refresh: bool,
+    ) -> u32 {
+        FramebufferRefresh::partial_refresh(
+            self,
+            region,
+            mode,
+            waveform_mode,
+            display_temp,
+            dither_mode,
+            quant_bit,
+            force_full_refresh,
+        )
+    }
+
+    fn full_refresh(
+        &self,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        wait_completion: bool,
+    ) -> u32 {
+        FramebufferRefresh::full_refresh(
+            self,
+            waveform_mode,
+            display_temp,
+            dither_mode,
+            quant_bit,
+            wait_completion,
+        )
+    }
+
+    fn wait_refresh_complete(&self, marker: u32) -> u32 {
+        FramebufferRefresh::wait_refresh_complete(self, marker)
+    }
+
+    fn dump_region(&self, rect: MxcfbRect) -> Result<Vec<u8>, &'static str> {
+        FramebufferIO::dump_region(self, rect)
+    }
+
+    fn restore_region(&mut self, rect: MxcfbRect, data: &[u8]) -> Result<u32, &'static str> {
+        FramebufferIO::restore_region(self, rect, data)
+    }
+}
+
+const BYTES_PER_PIXEL: usize = 2;
+
+/// In-memory display with the same size and pixel format as the panel
+///
+/// Text isn't rasterized, only measured with a fixed advance per character.
+/// Refreshes do nothing.
+pub struct Simulator {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Simulator {
+    pub fn new(width: u32, height: u32) -> Self {
+        Simulator {
+            width,
+            height,
+            data: vec![0xFF; width as usize * height as usize * BYTES_PER_PIXEL],
+        }
+    }
+
+    /// Clip a rect to the display, returning its top-left and bottom-right corners
+    fn clip(&self, pos: Point2<i32>, size: Vector2<u32>) -> (Point2<u32>, Point2<u32>) {
+        let clamp = |v: i64, max: u32| v.clamp(0, max as i64) as u32;
+        (
+            Point2::new(
+                clamp(pos.x as i64, self.width),
+                clamp(pos.y as i64, self.height),
+            ),
+            Point2::new(
+                clamp(pos.x as i64 + size.x as i64, self.width),
+                clamp(pos.y as i64 + size.y as i64, self.height),
+            ),
+        )
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize {
+        (y as usize * self.width as usize + x as usize) * BYTES_PER_PIXEL
+    }
+
+    pub fn read_pixel(&self, pos: Point2<u32>) -> [u8; 2] {
+        let offset = self.offset(pos.x, pos.y);
+        [self.data[offset], self.data[offset + 1]]
+    }
+
+    fn write_pixel(&mut self, x: i32, y: i32, native: [u8; 2]) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let offset = self.offset(x as u32, y as u32);
+        self.data[offset..offset + BYTES_PER_PIXEL].copy_from_slice(&native);
+    }
+
+    fn circle(&mut self, pos: Point2<i32>, rad: u32, color: Color, filled: bool) -> MxcfbRect {
+        let native = color.as_native();
+        let rad = rad as i32;
+        for y in -rad..=rad {
+            for x in -rad..=rad {
+                let distance = ((x * x + y * y) as f32).sqrt();
+                if distance <= rad as f32 && (filled || distance > rad as f32 - 1.0) {
+                    self.write_pixel(pos.x + x, pos.y + y, native);
+                }
+            }
+        }
+        bounds(
+            Point2::new(pos.x - rad, pos.y - rad),
+            Vector2::new(rad as u32 * 2 + 1, rad as u32 * 2 + 1),
+        )
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Simulator::new(DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32)
+    }
+}
+
+fn bounds(pos: Point2<i32>, size: Vector2<u32>) -> MxcfbRect {
+    MxcfbRect {
+        top: pos.y.max(0) as u32,
+        left: pos.x.max(0) as u32,
+        width: size.x,
+        height: size.y,
+    }
+}
+
+impl Display for Simulator {
+    fn clear(&mut self) {
+        self.data.fill(0xFF);
+    }
+
+    fn set_pixel(&mut self, pos: Point2<i32>, color: Color) {
+        self.write_pixel(pos.x, pos.y, color.as_native());
+    }
+
+    fn fill_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, color: Color) {
+        let native = color.as_native();
+        let (min, max) = self.clip(pos, size);
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                self.write_pixel(x as i32, y as i32, native);
+            }
+        }
+    }
+
+    fn draw_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, border_px: u32, color: Color) {
+        let border = border_px as i32;
+        let (w, h) = (size.x as i32, size.y as i32);
+        self.fill_rect(pos, Vector2::new(size.x, border_px), color);
+        self.fill_rect(
+            Point2::new(pos.x, pos.y + h - border),
+            Vector2::new(size.x, border_px),
+            color,
+        );
+        self.fill_rect(pos, Vector2::new(border_px, size.y), color);
+        self.fill_rect(
+            Point2::new(pos.x + w - border, pos.y),
+            Vector2::new(border_px, size.y),
+            color,
+        );
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        color: Color,
+    ) -> MxcfbRect {
+        let native = color.as_native();
+        let half = width as i32 / 2;
+        let (dx, dy) = ((end.x - start.x).abs(), -(end.y - start.y).abs());
+        let (sx, sy) = ((end.x - start.x).signum(), (end.y - start.y).signum());
+        let (mut x, mut y, mut error) = (start.x, start.y, dx + dy);
+        loop {
+            for oy in -half..=half {
+                for ox in -half..=half {
+                    self.write_pixel(x + ox, y + oy, native);
+                }
+            }
+            if x == end.x && y == end.y {
+                break;
+            }
+            let e2 = error * 2;
+            if e2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+
+        bounds(
+            Point2::new(start.x.min(end.x) - half, start.y.min(end.y) - half),
+            Vector2::new((dx + half * 2 + 1) as u32, (-dy + half * 2 + 1) as u32),
+        )
+    }
+
+    fn draw_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        self.circle(pos, rad, color, false)
+    }
+
+    fn fill_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        self.circle(pos, rad, color, true)
+    }
+
+    fn draw_text(
+        &mut self,
+        pos: Point2<f32>,
+        text: &str,
+        size: f32,
+        _color: Color,
+        _dryrun: bool,
+    ) -> MxcfbRect {
+        // Text is drawn up from its baseline
+        let width = (text.chars().count() as f32 * size * 0.5) as u32;
+        bounds(
+            Point2::new(pos.x as i32, (pos.y - size) as i32),
+            Vector2::new(width, size as u32),
+        )
+    }
+
+    fn draw_image(&mut self, image: &RgbImage, pos: Point2<i32>) -> MxcfbRect {
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let native = Color::RGB(pixel.0[0], pixel.0[1], pixel.0[2]).as_native();
+            self.write_pixel(pos.x + x as i32, pos.y + y as i32, native);
+        }
+        bounds(pos, Vector2::new(image.width(), image.height()))
+    }
+
+    fn partial_refresh(
+        &self,
+        _region: &MxcfbRect,
+        _mode: PartialRefreshMode,
+        _waveform_mode: WaveformMode,
+        _display_temp: DisplayTemp,
+        _dither_mode: DitherMode,
+        _quant_bit: i32,
+        _force_full_refresh: bool,
+    ) -> u32 {
+        0
+    }
+
+    fn full_refresh(
+        &self,
+        _waveform_mode: WaveformMode,
+        _display_temp: DisplayTemp,
+        _dither_mode: DitherMode,
+        _quant_bit: i32,
+        _wait_completion: bool,
+    ) -> u32 {
+        0
+    }
+
+    fn wait_refresh_complete(&self, _marker: u32) -> u32 {
+        0
+    }
+
+    fn dump_region(&self, rect: MxcfbRect) -> Result<Vec<u8>, &'static str> {
+        if rect.left + rect.width > self.width || rect.top + rect.height > self.height {
+            return Err("Region is out of bounds");
+        }
+
+        let mut data =
+            Vec::with_capacity(rect.width as usize * rect.height as usize * BYTES_PER_PIXEL);
+        for y in rect.top..rect.top + rect.height {
+            let offset = self.offset(rect.left, y);
+            data.extend_from_slice(
+                &self.data[offset..offset + rect.width as usize * BYTES_PER_PIXEL],
+            );
+        }
+        Ok(data)
+    }
+
+    fn restore_region(&mut self, rect: MxcfbRect, data: &[u8]) -> Result<u32, &'static str> {
+        if rect.left + rect.width > self.width || rect.top + rect.height > self.height {
+            return Err("Region is out of bounds");
+        }
+
+        let row = rect.width as usize * BYTES_PER_PIXEL;
+        if data.len() != row * rect.height as usize {
+            return Err("Data doesn't match region size");
+        }
+
+        for (y, chunk) in (rect.top..rect.top + rect.height).zip(data.chunks(row)) {
+            let offset = self.offset(rect.left, y);
+            self.data[offset..offset + row].copy_from_slice(chunk);
+        }
+        Ok(data.len() as u32)
+    }
+}
+
+/// Wraps a display so that draws are only measured, while refreshes still reach the panel
+///
+/// Lets a subtree be replayed over pixels restored from a cache, registering its
+/// gestures and focus targets without drawing it again.
+pub struct MeasureOnly(pub Arc<Mutex<Box<dyn Display>>>);
+
+impl Display for MeasureOnly {
+    fn clear(&mut self) {}
+
+    fn set_pixel(&mut self, _pos: Point2<i32>, _color: Color) {}
+
+    fn fill_rect(&mut self, _pos: Point2<i32>, _size: Vector2<u32>, _color: Color) {}
+
+    fn draw_rect(
+        &mut self,
+        _pos: Point2<i32>,
+        _size: Vector2<u32>,
+        _border_px: u32,
+        _color: Color,
+    ) {
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        _color: Color,
+    ) -> MxcfbRect {
+        let half = width as i32 / 2;
+        let pos = Point2::new(start.x.min(end.x) - half, start.y.min(end.y) - half);
+        bounds(
+            pos,
+            Vector2::new(
+                ((start.x - end.x).abs() + half * 2) as u32,
+                ((start.y - end.y).abs() + half * 2) as u32,
+            ),
+        )
+    }
+
+    fn draw_circle(&mut self, pos: Point2<i32>, rad: u32, _color: Color) -> MxcfbRect {
+        bounds(
+            Point2::new(pos.x - rad as i32, pos.y - rad as i32),
+            Vector2::new(rad * 2 + 1, rad * 2 + 1),
+        )
+    }
+
+    fn fill_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        self.draw_circle(pos, rad, color)
+    }
+
+    fn draw_text(
+        &mut self,
+        pos: Point2<f32>,
+        text: &str,
+        size: f32,
+        color: Color,
+        _dryrun: bool,
+    ) -> MxcfbRect {
+        self.0
+            .lock()
+            .unwrap()
+            .draw_text(pos, text, size, color, true)
+    }
+
+    fn draw_image(&mut self, image: &RgbImage, pos: Point2<i32>) -> MxcfbRect {
+        bounds(pos, Vector2::new(image.width(), image.height()))
+    }
+
+    fn partial_refresh(
+        &self,
+        region: &MxcfbRect,
+        mode: PartialRefreshMode,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        force_full_refresh: bool,
+    ) -> u32 {
+        self.0.lock().unwrap().partial_refresh(
+            region,
+            mode,
+            waveform_mode,
+            display_temp,
+            dither_mode,
+            quant_bit,
+            force_full_refresh,
+        )
+    }
+
+    fn full_refresh(
+        &self,
+        waveform_mode: WaveformMode,
+        display_temp: DisplayTemp,
+        dither_mode: DitherMode,
+        quant_bit: i32,
+        wait_completion: bool,
+    ) -> u32 {
+        self.0.lock().unwrap().full_refresh(
+            waveform_mode,
+            display_temp,
+            dither_mode,
+            quant_bit,
+            wait_completion,
+        )
+    }
+
+    fn wait_refresh_complete(&self, marker: u32) -> u32 {
+        self.0.lock().unwrap().wait_refresh_complete(marker)
+    }
+
+    fn dump_region(&self, rect: MxcfbRect) -> Result<Vec<u8>, &'static str> {
+        self.0.lock().unwrap().dump_region(rect)
+    }
+
+    fn restore_region(&mut self, _rect: MxcfbRect, data: &[u8]) -> Result<u32, &'static str> {
+        Ok(data.len() as u32)
+    }
+}
+
+/// Wraps a display so draws land in its memory, but never refresh the panel
+///
+/// Lets a subtree be rendered and dumped as an offscreen buffer, for composing
+/// transitions before anything is shown.
+pub struct Offscreen(pub Arc<Mutex<Box<dyn Display>>>);
+
+impl Display for Offscreen {
+    fn clear(&mut self) {
+        self.0.lock().unwrap().clear()
+    }
+
+    fn set_pixel(&mut self, pos: Point2<i32>, color: Color) {
+        self.0.lock().unwrap().set_pixel(pos, color)
+    }
+
+    fn fill_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, color: Color) {
+        self.0.lock().unwrap().fill_rect(pos, size, color)
+    }
+
+    fn draw_rect(&mut self, pos: Point2<i32>, size: Vector2<u32>, border_px: u32, color: Color) {
+        self.0
+            .lock()
+            .unwrap()
+            .draw_rect(pos, size, border_px, color)
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point2<i32>,
+        end: Point2<i32>,
+        width: u32,
+        color: Color,
+    ) -> MxcfbRect {
+        self.0.lock().unwrap().draw_line(start, end, width, color)
+    }
+
+    fn draw_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        self.0.lock().unwrap().draw_circle(pos, rad, color)
+    }
+
+    fn fill_circle(&mut self, pos: Point2<i32>, rad: u32, color: Color) -> MxcfbRect {
+        self.0.lock().unwrap().fill_circle(pos, rad, color)
+    }
+
+    fn draw_text(
+        &mut self,
+        pos: Point2<f32>,
+        text: &str,
+        size: f32,
+        color: Color,
+        dryrun: bool,
+    ) -> MxcfbRect {
+        self.0
+            .lock()
+            .unwrap()
+            .draw_text(pos, text, size, color, dryrun)
+    }
+
+    fn draw_image(&mut self, image: &RgbImage, pos: Point2<i32>) -> MxcfbRect {
+        self.0.lock().unwrap().draw_image(image, pos)
+    }
+
+    fn partial_refresh(
+        &self,
+        _region: &MxcfbRect,
+        _mode: PartialRefreshMode,
+        _waveform_mode: WaveformMode,
+        _display_temp: DisplayTemp,
+        _dither_mode: DitherMode,
+        _quant_bit: i32,
+        _force_full_refresh: bool,
+    ) -> u32 {
+        0
+    }
+
+    fn full_refresh(
+        &self,
+        _waveform_mode: WaveformMode,
+        _display_temp: DisplayTemp,
+        _dither_mode: DitherMode,
+        _quant_bit: i32,
+        _wait_completion: bool,
+    ) -> u32 {
+        0
+    }
+
+    fn wait_refresh_complete(&self, _marker: u32) -> u32 {
+        0
+    }
+
+    fn dump_region(&self, rect: MxcfbRect) -> Result<Vec<u8>, &'static str> {
+        self.0.lock().unwrap().dump_region(rect)
+    }
+
+    fn restore_region(&mut self, rect: MxcfbRect, data: &[u8]) -> Result<u32, &'static str> {
+        self.0.lock().unwrap().restore_region(rect, data)
+    }
+}
+
+/// Open the display for the configured backend
+pub fn open_display(backend: DisplayBackend) -> Box<dyn Display> {
+    let backend = match backend {
+        DisplayBackend::Auto if rm2fb::is_rm2() => DisplayBackend::Rm2fb,
+        DisplayBackend::Auto => DisplayBackend::Mxcfb,
+        backend => backend,
+    };
+    println!("Using {backend:} display backend");
+
+    match backend {
+        DisplayBackend::Rm2fb => {
+            // The shared buffer only exists once the server is up
+            if !rm2fb::wait_for_server(rm2fb::SERVER_TIMEOUT) {
+                println!("Warning: rm2fb server isn't running, drawing may not reach the display");
+            }
+            Box::new(Framebuffer::from_path(rm2fb::SWTFB_SHM_PATH))
+        }
+        DisplayBackend::Simulator => Box::new(Simulator::default()),
+        _ => Box::new(Framebuffer::from_path(MXCFB_PATH)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulator_dump_restore() {
+        let mut display = Simulator::new(16, 16);
+        let rect = MxcfbRect {
+            top: 2,
+            left: 4,
+            width: 8,
+            height: 6,
+        };
+
+        display.fill_rect(Point2::new(4, 2), Vector2::new(8, 6), Color::BLACK);
+        let dump = display.dump_region(rect).unwrap();
+        assert_eq!(dump.len(), 8 * 6 * BYTES_PER_PIXEL);
+
+        display.clear();
+        display.restore_region(rect, &dump).unwrap();
+        assert_eq!(display.dump_region(rect).unwrap(), dump);
+        assert_eq!(display.read_pixel(Point2::new(0, 0)), [0xFF; 2]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Tail of a draft's log, opened from its launch menu to see why a launch failed.
+//! Dragging the lines up or down scrolls back through earlier output.
+use libremarkable::cgmath::Point2;
+use raft::Draft;
+use shared::{
+    draft_log::tail_draft_log,
+    locale::{tr, tr_args},
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::{panel_height, panel_rect},
+    partial_refresh, text_button,
+    ui::{
+        margin, margin_left, offset_relative, overlay, recognize_gesture, rect_border, set_rect,
+        text, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of how many lines the log is scrolled back from its end
+pub const DRAFT_LOG_SCROLL: &str = "draft_log.scroll";
+
+/// Most lines read back from the log
+const DRAFT_LOG_LINES: usize = 500;
+
+/// Log lines that fit beneath the header
+fn rows_per_page() -> usize {
+    (((panel_height() - layout().icon_spacing * 2) / layout().line_height) - 1).max(1) as usize
+}
+
+/// Full-panel view of the end of a draft's log
+pub fn draft_log(event_tx: Sender<MainEvent>, draft: Draft) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let lines = tail_draft_log(&draft.id(), DRAFT_LOG_LINES);
+        let max_scroll = lines.len().saturating_sub(rows_per_page());
+        let scroll = ctx.state.get::<usize>(DRAFT_LOG_SCROLL).min(max_scroll);
+        let first = lines.len() - rows_per_page().min(lines.len()) - scroll;
+
+        let title = tr_args("draft_log.title", &[("name", &draft.name)]);
+        let back_label = tr("draft_log.back");
+        let empty_label = tr("draft_log.empty");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        // Dragging down reveals earlier lines
+        ctx = overlay(recognize_gesture(gesture::recognize_drag_release({
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move |delta| {
+                let rows = (delta.y / height as f32).round() as isize;
+                if rows == 0 {
+                    return false;
+                }
+
+                let scroll = (scroll as isize + rows).clamp(0, max_scroll as isize);
+                state.set(DRAFT_LOG_SCROLL, scroll as usize);
+                event_tx.send(MainEvent::Redraw).ok();
+                true
+            }
+        })))(ctx);
+
+        // Header
+        let header = ctx.rect;
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 / 3)
+                .then(offset_relative(Point2::new(0, height / 4)))
+                .then(text(&title, layout().font_size, Color::BLACK)),
+        )(ctx);
+
+        if lines.is_empty() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height + height / 4)).then(text(
+                    &empty_label,
+                    layout().font_size,
+                    Color::BLACK,
+                )),
+            )(ctx);
+        }
+
+        for (i, line) in lines[first..].iter().take(rows_per_page()).enumerate() {
+            ctx =
+                overlay(
+                    offset_relative(Point2::new(0, height * (i as i32 + 1) + height / 4))
+                        .then(text(line, layout().font_size, Color::BLACK)),
+                )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
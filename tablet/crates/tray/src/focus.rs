@@ -0,0 +1,132 @@
+//! Keyboard focus
+//!
+//! Focusable widgets register their rect and an activation callback while drawing,
+//! and the resulting map is handed to the main loop alongside the gesture recognizer.
+use std::sync::Arc;
+
+use crate::{framebuffer::MxcfbRect, rect::Position};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+pub trait FocusCallback: Fn() + Send + Sync {}
+impl<F> FocusCallback for F where F: Fn() + Send + Sync {}
+
+#[derive(Clone)]
+pub struct FocusTarget {
+    pub rect: MxcfbRect,
+    callback: Arc<dyn FocusCallback>,
+}
+
+impl FocusTarget {
+    pub fn activate(&self) {
+        (self.callback)()
+    }
+
+    fn center(&self) -> (i32, i32) {
+        let position = self.rect.position();
+        let size = self.rect.size();
+        (
+            position.x + size.x as i32 / 2,
+            position.y + size.y as i32 / 2,
+        )
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct FocusMap {
+    targets: Vec<FocusTarget>,
+}
+
+impl FocusMap {
+    pub fn with_target(mut self, rect: MxcfbRect, callback: impl FocusCallback + 'static) -> Self {
+        self.targets.push(FocusTarget {
+            rect,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    pub fn get(&self, index: usize) -> Option<&FocusTarget> {
+        self.targets.get(index)
+    }
+
+    /// Index of the target occupying the given rect, used to carry focus across redraws
+    pub fn find(&self, rect: &MxcfbRect) -> Option<usize> {
+        self.targets.iter().position(|target| target.rect == *rect)
+    }
+
+    /// Nearest target in the given direction, or the first target if nothing is focused.
+    /// Off-axis distance is weighted more heavily so movement favours the same row or column.
+    pub fn neighbour(&self, from: Option<usize>, direction: Direction) -> Option<usize> {
+        let from = match from.and_then(|from| self.targets.get(from)) {
+            Some(from) => from.center(),
+            None => return (!self.targets.is_empty()).then_some(0),
+        };
+
+        self.targets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, target)| {
+                let (x, y) = target.center();
+                let (dx, dy) = (x - from.0, y - from.1);
+                let (along, across) = match direction {
+                    Direction::Left => (-dx, dy),
+                    Direction::Right => (dx, dy),
+                    Direction::Up => (-dy, dx),
+                    Direction::Down => (dy, dx),
+                };
+                (along > 0).then(|| (i, along + across.abs() * 2))
+            })
+            .min_by_key(|(_, score)| *score)
+            .map(|(i, _)| i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> FocusMap {
+        (0..2)
+            .flat_map(|row| (0..3).map(move |column| (row, column)))
+            .fold(FocusMap::default(), |map, (row, column)| {
+                map.with_target(
+                    MxcfbRect {
+                        top: row * 100,
+                        left: column * 100,
+                        width: 80,
+                        height: 80,
+                    },
+                    || (),
+                )
+            })
+    }
+
+    #[test]
+    fn first_move_focuses_first_target() {
+        assert_eq!(grid().neighbour(None, Direction::Right), Some(0));
+        assert_eq!(FocusMap::default().neighbour(None, Direction::Right), None);
+    }
+
+    #[test]
+    fn moves_spatially() {
+        let map = grid();
+        assert_eq!(map.neighbour(Some(0), Direction::Right), Some(1));
+        assert_eq!(map.neighbour(Some(1), Direction::Down), Some(4));
+        assert_eq!(map.neighbour(Some(4), Direction::Left), Some(3));
+        assert_eq!(map.neighbour(Some(3), Direction::Up), Some(0));
+    }
+
+    #[test]
+    fn stops_at_edges() {
+        let map = grid();
+        assert_eq!(map.neighbour(Some(0), Direction::Left), None);
+        assert_eq!(map.neighbour(Some(2), Direction::Up), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! Home app, a draft the tray can always return to from a panel button or a bound gesture
+use std::sync::{Arc, Mutex};
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::cgmath::Point2;
+use shared::config::Config;
+
+use crate::{
+    channel::Sender,
+    exit_to,
+    framebuffer::Color,
+    layout::layout,
+    panel_button,
+    ui::{line, overlay, Draw, ThenTrait},
+    MainEvent, View,
+};
+
+/// Panel button slot taken by the home button, left of the store's
+pub const HOME_BUTTON_SLOT: i32 = 3;
+
+struct Home {
+    name: String,
+    drafts: Arc<DraftPrograms>,
+}
+
+static HOME: Mutex<Option<Home>> = Mutex::new(None);
+
+pub fn home_init(config: &Config, drafts: Arc<DraftPrograms>) {
+    *HOME.lock().unwrap() = config.home_app.clone().map(|name| Home { name, drafts });
+}
+
+/// Whether there's a home app to go to
+pub fn has_home() -> bool {
+    HOME.lock().unwrap().is_some()
+}
+
+/// Switch to the home app, continuing it if it's stopped, or open the tray if there's none to go to
+pub fn go_home(event_tx: &Sender<MainEvent>) {
+    let home = HOME.lock().unwrap().as_ref().and_then(|home| {
+        home.drafts
+            .draft_named(&home.name)
+            .filter(|draft| home.drafts.is_allowed(draft))
+            .cloned()
+    });
+    match home {
+        Some(draft) => {
+            println!("Going home to {:?}", draft.name);
+            exit_to(event_tx, Some(draft));
+        }
+        None => {
+            println!("No home app to go to");
+            event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+        }
+    }
+}
+
+/// Button in the top-right corner of the panel that switches to the home app
+pub fn home_button(event_tx: Sender<MainEvent>) -> impl Draw {
+    let size = layout().close_button_size;
+    let roof = Point2::new(0, -size / 3);
+    let (left, right) = (-size / 3, size / 3);
+    let (eaves, floor) = (0, size / 3);
+    panel_button(
+        HOME_BUTTON_SLOT,
+        move || go_home(&event_tx),
+        overlay(line(roof, Point2::new(left, eaves), 3, Color::BLACK))
+            .then(overlay(line(
+                roof,
+                Point2::new(right, eaves),
+                3,
+                Color::BLACK,
+            )))
+            .then(overlay(line(
+                Point2::new(left * 2 / 3, eaves),
+                Point2::new(left * 2 / 3, floor),
+                3,
+                Color::BLACK,
+            )))
+            .then(overlay(line(
+                Point2::new(right * 2 / 3, eaves),
+                Point2::new(right * 2 / 3, floor),
+                3,
+                Color::BLACK,
+            )))
+            .then(line(
+                Point2::new(left * 2 / 3, floor),
+                Point2::new(right * 2 / 3, floor),
+                3,
+                Color::BLACK,
+            )),
+    )
+}
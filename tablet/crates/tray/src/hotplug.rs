@@ -0,0 +1,62 @@
+//! Input device hotplug detection
+//!
+//! Watches /dev/input so that input threads whose device node disappeared
+//! can be respawned once it comes back.
+use std::time::Duration;
+
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use crate::{channel::Sender, MainEvent};
+
+pub const INPUT_DEVICE_DIR: &str = "/dev/input";
+
+/// Wait before rescanning, as nodes can appear before they're usable
+pub const HOTPLUG_SETTLE_DURATION: Duration = Duration::from_millis(250);
+
+pub fn hotplug_monitor(event_tx: Sender<MainEvent>) {
+    let inotify = match Inotify::init(InitFlags::IN_CLOEXEC) {
+        Ok(inotify) => inotify,
+        Err(e) => {
+            println!("Failed to initialize inotify, hotplug disabled: {e:}");
+            return;
+        }
+    };
+
+    if let Err(e) = inotify.add_watch(
+        INPUT_DEVICE_DIR,
+        AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE | AddWatchFlags::IN_ATTRIB,
+    ) {
+        println!("Failed to watch {INPUT_DEVICE_DIR:}, hotplug disabled: {e:}");
+        return;
+    }
+
+    std::thread::spawn(move || loop {
+        let events = match inotify.read_events() {
+            Ok(events) => events,
+            Err(e) => {
+                println!("Failed to read hotplug events: {e:}");
+                break;
+            }
+        };
+
+        // Only event nodes matter, ignore mice / js nodes and the like
+        let changed = events.iter().any(|event| {
+            event
+                .name
+                .as_ref()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("event"))
+                .unwrap_or(false)
+        });
+
+        if !changed {
+            continue;
+        }
+
+        // Settle here rather than in the main loop, which would hold up input and rendering
+        std::thread::sleep(HOTPLUG_SETTLE_DURATION);
+        if event_tx.send(MainEvent::InputHotplug).is_err() {
+            break;
+        }
+    });
+}
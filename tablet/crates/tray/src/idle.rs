@@ -0,0 +1,144 @@
+//! Idle screen, shown by wave when nothing is running and the device is left alone
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use libremarkable::{cgmath::Point2, image::RgbImage};
+use shared::{
+    clock::{clock_synchronized, utc_offset},
+    locale::tr_args,
+    path_temp_screenshot,
+    power::battery_capacity,
+};
+
+use crate::{
+    channel::Sender,
+    clock::clock_syncing,
+    display::{DISPLAY_HEIGHT, DISPLAY_RECT, DISPLAY_WIDTH},
+    framebuffer::Color,
+    layout::layout,
+    partial_refresh,
+    ui::{
+        image, offset_absolute, offset_relative, overlay, recognize_gesture, rect_fill, set_rect,
+        text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// User-supplied image drawn in the middle of the idle screen
+pub const IDLE_IMAGE_PATH: &str = "/opt/etc/parchment/suspend.png";
+
+/// How often the clock overlay is redrawn
+pub const CLOCK_INTERVAL: Duration = Duration::from_secs(60);
+
+static SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Where the screen under the idle screen is saved
+pub fn idle_screenshot_path() -> PathBuf {
+    path_temp_screenshot("idle")
+}
+
+pub fn idle_image() -> Option<RgbImage> {
+    match libremarkable::image::open(IDLE_IMAGE_PATH) {
+        Ok(image) => Some(image.to_rgb8()),
+        Err(e) => {
+            println!("No idle image at {IDLE_IMAGE_PATH:}: {e:}");
+            None
+        }
+    }
+}
+
+/// Wall clock time as HH:MM in the configured zone
+fn clock() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    time_of_day(secs.saturating_add_signed(utc_offset()))
+}
+
+/// Clock flagged while it's syncing, or when it hasn't been set from a time server
+pub fn clock_status() -> String {
+    let time = clock();
+    if clock_syncing() {
+        tr_args("clock.syncing", &[("time", &time)])
+    } else if !clock_synchronized() {
+        tr_args("clock.unsynced", &[("time", &time)])
+    } else {
+        time
+    }
+}
+
+/// Seconds since the epoch as HH:MM
+pub fn time_of_day(secs: u64) -> String {
+    format!("{:02}:{:02}", secs / 3600 % 24, secs / 60 % 60)
+}
+
+/// Redraw the idle screen periodically so its clock stays current
+pub fn clock_ticker(event_tx: Sender<MainEvent>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(CLOCK_INTERVAL);
+        if SHOWN.load(Ordering::Relaxed) && event_tx.send(MainEvent::Redraw).is_err() {
+            break;
+        }
+    });
+}
+
+/// Full-screen image with battery and clock, swiping up restores the screen and opens the tray
+pub fn idle_screen(event_tx: Sender<MainEvent>, idle_image: Option<Arc<RgbImage>>) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        SHOWN.store(true, Ordering::Relaxed);
+
+        ctx = set_rect(DISPLAY_RECT)
+            .then(rect_fill(Color::WHITE))
+            .draw(ctx);
+
+        if let Some(idle_image) = &idle_image {
+            let position = Point2::new(
+                (DISPLAY_WIDTH as i32 - idle_image.width() as i32) / 2,
+                (DISPLAY_HEIGHT as i32 - idle_image.height() as i32) / 2,
+            );
+            ctx = overlay(offset_relative(position).then(image(idle_image)))(ctx);
+        }
+
+        let status = match battery_capacity() {
+            Some(capacity) => format!("{}  {capacity:}%", clock_status()),
+            None => clock_status(),
+        };
+        ctx = overlay(
+            offset_absolute(Point2::new(0.5, 1.0))
+                .then(offset_relative(Point2::new(0, -layout().line_height * 2)))
+                .then(text_aligned(
+                    &status,
+                    layout().font_size,
+                    Point2::new(0.5, 0.0),
+                    Color::BLACK,
+                )),
+        )(ctx);
+
+        set_rect(DISPLAY_RECT)
+            .then(recognize_gesture(gesture::recognize_drag({
+                let event_tx = event_tx.clone();
+                move |delta| {
+                    if delta.y > layout().tap_hysteresis {
+                        println!("Swiped, leaving idle screen");
+                        SHOWN.store(false, Ordering::Relaxed);
+                        event_tx
+                            .send(MainEvent::RestoreScreen(idle_screenshot_path()))
+                            .ok();
+                        event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                        true
+                    } else {
+                        false
+                    }
+                }
+            })))
+            .then(partial_refresh())
+            .draw(ctx)
+    }
+}
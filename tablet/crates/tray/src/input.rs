@@ -1,9 +1,16 @@
 use libremarkable::{
     epoll,
     evdev::InputEvent as EvInputEvent,
-    input::{scan::SCANNED, InputDevice, InputDeviceState, InputEvent},
+    input::{
+        scan::{EvDevsScan, SCANNED},
+        InputDevice, InputDeviceState, InputEvent,
+    },
+};
+use nix::poll::{poll, PollFd, PollFlags};
+use shared::{
+    button_flood_events, pen_release_events, touch_flood_events, touch_release_events,
+    INPUT_BUFFER_SIZE,
 };
-use shared::{button_flood_events, touch_flood_events, INPUT_BUFFER_SIZE};
 
 use std::{any::Any, error::Error, os::unix::prelude::AsRawFd, thread::JoinHandle};
 
@@ -18,7 +25,9 @@ pub enum InputCommand {
     Stop,
     Grab,
     Ungrab,
-    ClearBuffer,
+    /// Discard our pending events, then ungrab and release any held touches,
+    /// falling back to flooding the queue if the release events can't be sent
+    DrainQueue,
 }
 
 pub struct InputHandles {
@@ -29,16 +38,73 @@ pub struct InputHandles {
     pub gpio_handle: Option<JoinHandle<()>>,
     pub multitouch_handle: Option<JoinHandle<()>>,
     pub wacom_handle: Option<JoinHandle<()>>,
+
+    /// Whether devices should be grabbed, re-applied to respawned threads
+    pub grabbed: bool,
+    /// Set once the threads have been told to stop, so they aren't respawned
+    pub stopped: bool,
+
+    event_tx: Sender<MainEvent>,
 }
 
 impl InputHandles {
-    pub fn broadcast(&self, event: InputCommand) -> Result<(), SendError<InputCommand>> {
+    pub fn broadcast(&mut self, event: InputCommand) -> Result<(), SendError<InputCommand>> {
+        match event {
+            InputCommand::Grab => self.grabbed = true,
+            InputCommand::Ungrab | InputCommand::DrainQueue => self.grabbed = false,
+            InputCommand::Stop => self.stopped = true,
+        }
+
         self.gpio_command.send(event)?;
         self.multitouch_command.send(event)?;
         self.wacom_command.send(event)?;
         Ok(())
     }
 
+    /// Restart any input thread whose device went away, using a fresh device scan
+    pub fn respawn_lost(&mut self) {
+        if self.stopped {
+            return;
+        }
+
+        let scan = EvDevsScan::new();
+        let grabbed = self.grabbed;
+
+        for (device_type, command, handle) in [
+            (
+                InputDevice::GPIO,
+                &mut self.gpio_command,
+                &mut self.gpio_handle,
+            ),
+            (
+                InputDevice::Multitouch,
+                &mut self.multitouch_command,
+                &mut self.multitouch_handle,
+            ),
+            (
+                InputDevice::Wacom,
+                &mut self.wacom_command,
+                &mut self.wacom_handle,
+            ),
+        ] {
+            if !handle.as_ref().map(JoinHandle::is_finished).unwrap_or(true) {
+                continue;
+            }
+
+            match device_thread(device_type, &scan, self.event_tx.clone()) {
+                Ok((new_command, new_handle)) => {
+                    println!("Respawned {device_type:?} input thread");
+                    if grabbed {
+                        new_command.send(InputCommand::Grab).ok();
+                    }
+                    *command = new_command;
+                    *handle = Some(new_handle);
+                }
+                Err(e) => println!("Failed to respawn {device_type:?} input thread: {e:}"),
+            }
+        }
+    }
+
     pub fn join(&mut self) -> Result<(), Box<dyn Any + Send>> {
         self.gpio_handle.take().unwrap().join()?;
         self.multitouch_handle.take().unwrap().join()?;
@@ -48,29 +114,14 @@ impl InputHandles {
 }
 
 pub fn input_init(event_tx: Sender<MainEvent>) -> InputHandles {
-    let (gpio_command, gpio_handle) = input_thread(
-        InputDevice::GPIO,
-        event_tx.clone(),
-        libremarkable::input::gpio::decode,
-        button_flood_events(),
-    )
-    .unwrap();
+    let (gpio_command, gpio_handle) =
+        device_thread(InputDevice::GPIO, &SCANNED, event_tx.clone()).unwrap();
 
-    let (multitouch_command, multitouch_handle) = input_thread(
-        InputDevice::Multitouch,
-        event_tx.clone(),
-        libremarkable::input::multitouch::decode,
-        touch_flood_events(),
-    )
-    .unwrap();
+    let (multitouch_command, multitouch_handle) =
+        device_thread(InputDevice::Multitouch, &SCANNED, event_tx.clone()).unwrap();
 
-    let (wacom_command, wacom_handle) = input_thread(
-        InputDevice::Wacom,
-        event_tx.clone(),
-        libremarkable::input::wacom::decode,
-        touch_flood_events(),
-    )
-    .unwrap();
+    let (wacom_command, wacom_handle) =
+        device_thread(InputDevice::Wacom, &SCANNED, event_tx.clone()).unwrap();
 
     InputHandles {
         gpio_command,
@@ -79,21 +130,61 @@ pub fn input_init(event_tx: Sender<MainEvent>) -> InputHandles {
         gpio_handle: Some(gpio_handle),
         multitouch_handle: Some(multitouch_handle),
         wacom_handle: Some(wacom_handle),
+        grabbed: false,
+        stopped: false,
+        event_tx,
+    }
+}
+
+/// Spawn the input thread for a given device with its decoder and queue-clearing events
+fn device_thread(
+    device_type: InputDevice,
+    scan: &EvDevsScan,
+    event_tx: Sender<MainEvent>,
+) -> Result<(Sender<InputCommand>, JoinHandle<()>), Box<dyn Error>> {
+    match device_type {
+        InputDevice::GPIO => input_thread(
+            device_type,
+            scan,
+            event_tx,
+            libremarkable::input::gpio::decode,
+            button_flood_events(),
+            vec![],
+        ),
+        InputDevice::Multitouch => input_thread(
+            device_type,
+            scan,
+            event_tx,
+            libremarkable::input::multitouch::decode,
+            touch_flood_events(),
+            touch_release_events(),
+        ),
+        InputDevice::Wacom => input_thread(
+            device_type,
+            scan,
+            event_tx,
+            libremarkable::input::wacom::decode,
+            touch_flood_events(),
+            pen_release_events(),
+        ),
+        _ => Err(format!("Unsupported input device {device_type:?}").into()),
     }
 }
 
 pub fn input_thread<F, R, I>(
     device_type: InputDevice,
+    scan: &EvDevsScan,
     event_tx: Sender<MainEvent>,
     callback: F,
     flood_events: I,
+    release_events: Vec<libremarkable::evdev::InputEvent>,
 ) -> Result<(Sender<InputCommand>, JoinHandle<()>), Box<dyn Error>>
 where
     F: Fn(&EvInputEvent, &libremarkable::input::InputDeviceState) -> R + Send + 'static,
     R: IntoIterator<Item = InputEvent>,
     I: IntoIterator<Item = libremarkable::evdev::InputEvent> + Clone + Send + 'static,
 {
-    let mut device = SCANNED.get_device(device_type)?;
+    let mut device = scan.get_device(device_type)?;
     let state = InputDeviceState::new(device_type);
     let (command_tx, command_rx) = channel();
 
@@ -134,13 +225,8 @@ where
                             device.ungrab().unwrap();
                             println!("Ungrabbed input.");
                         }
-                        InputCommand::ClearBuffer => {
-                            if flood_events.len() == 0 {
-                                println!("No flood events for device, skipping");
-                            } else {
-                                println!("Clearing buffer...");
-                                device.send_events(&flood_events[..]).unwrap();
-                            }
+                        InputCommand::DrainQueue => {
+                            drain_queue(&mut device, &release_events, &flood_events)
                         }
                     },
                     Err(e) => match e {
@@ -156,7 +242,16 @@ where
                         continue;
                     }
 
-                    for ev in device.fetch_events().unwrap() {
+                    // The device node has gone away, exit so hotplug handling can respawn us
+                    let events = match device.fetch_events() {
+                        Ok(events) => events,
+                        Err(e) => {
+                            println!("Lost {device_type:?} input device: {e:}");
+                            break 'input;
+                        }
+                    };
+
+                    for ev in events {
                         for event in callback(&ev, &state) {
                             if let Err(e) = event_tx.send(MainEvent::Input(event)) {
                                 eprintln!("Failed to write InputEvent into the channel: {}", e);
@@ -180,3 +275,36 @@ where
 
     Ok((command_tx, join_handle))
 }
+
+fn drain_queue(
+    device: &mut libremarkable::evdev::Device,
+    release_events: &[libremarkable::evdev::InputEvent],
+    flood_events: &[libremarkable::evdev::InputEvent],
+) {
+    // Read and discard anything queued for us while grabbed
+    let mut drained = 0;
+    let mut fds = [PollFd::new(device.as_raw_fd(), PollFlags::POLLIN)];
+    while poll(&mut fds, 0).unwrap_or(0) > 0 {
+        match device.fetch_events() {
+            Ok(events) => drained += events.count(),
+            Err(_) => break,
+        }
+    }
+    println!("Drained {drained:} pending events");
+
+    device.ungrab().unwrap();
+    println!("Ungrabbed input.");
+
+    // Let other clients see held touches end, rather than flooding their queues
+    if release_events.is_empty() {
+        return;
+    }
+
+    if let Err(e) = device.send_events(release_events) {
+        println!("Failed to send release events: {e:}");
+        if !flood_events.is_empty() {
+            println!("Clearing buffer...");
+            device.send_events(flood_events).unwrap();
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! External keyboard input
+//!
+//! Any evdev node that reports letter and enter keys is treated as a keyboard,
+//! which covers the Type Folio as well as generic USB HID keyboards.
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use libremarkable::evdev::{Device, EventType, Key};
+
+use crate::{channel::Sender, hotplug::INPUT_DEVICE_DIR, MainEvent};
+
+/// Key event value for a press, 2 is autorepeat and 0 is release
+pub const KEY_PRESS: i32 = 1;
+pub const KEY_REPEAT: i32 = 2;
+
+/// Rows of a US layout, each with the key its characters start from
+const KEY_ROWS: [(Key, &str); 4] = [
+    (Key::KEY_1, "1234567890"),
+    (Key::KEY_Q, "qwertyuiop"),
+    (Key::KEY_A, "asdfghjkl"),
+    (Key::KEY_Z, "zxcvbnm"),
+];
+
+/// Open keyboard devices, each read on its own thread
+#[derive(Clone)]
+pub struct Keyboards {
+    event_tx: Sender<MainEvent>,
+    open: Arc<Mutex<BTreeSet<PathBuf>>>,
+}
+
+impl Keyboards {
+    pub fn new(event_tx: Sender<MainEvent>) -> Self {
+        Keyboards {
+            event_tx,
+            open: Default::default(),
+        }
+    }
+
+    /// Open any keyboards that have appeared since the last scan
+    pub fn scan(&self) {
+        let entries = match std::fs::read_dir(INPUT_DEVICE_DIR) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Failed to scan for keyboards: {e:}");
+                return;
+            }
+        };
+
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let is_event_node = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("event"))
+                .unwrap_or(false);
+
+            if !is_event_node || self.open.lock().unwrap().contains(&path) {
+                continue;
+            }
+
+            let mut device = match Device::open(&path) {
+                Ok(device) => device,
+                Err(_) => continue,
+            };
+
+            if !is_keyboard(&device) {
+                continue;
+            }
+
+            println!("Found keyboard {:?} at {path:?}", device.name());
+
+            // Keep key presses from reaching whatever is behind the tray
+            if let Err(e) = device.grab() {
+                println!("Failed to grab keyboard: {e:}");
+            }
+
+            self.open.lock().unwrap().insert(path.clone());
+
+            let keyboards = self.clone();
+            std::thread::spawn(move || keyboards.read(&path, device));
+        }
+    }
+
+    fn read(&self, path: &Path, mut device: Device) {
+        'read: loop {
+            let events = match device.fetch_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    println!("Lost keyboard at {path:?}: {e:}");
+                    break 'read;
+                }
+            };
+
+            for event in events {
+                if event.event_type() != EventType::KEY {
+                    continue;
+                }
+
+                if event.value() != KEY_PRESS && event.value() != KEY_REPEAT {
+                    continue;
+                }
+
+                if self
+                    .event_tx
+                    .send(MainEvent::Key(Key::new(event.code())))
+                    .is_err()
+                {
+                    break 'read;
+                }
+            }
+        }
+
+        self.open.lock().unwrap().remove(path);
+    }
+}
+
+pub fn is_keyboard(device: &Device) -> bool {
+    device
+        .supported_keys()
+        .map(|keys| keys.contains(Key::KEY_A) && keys.contains(Key::KEY_ENTER))
+        .unwrap_or(false)
+}
+
+/// Character a key types, ignoring modifiers, for typing into search fields
+pub fn key_char(key: Key) -> Option<char> {
+    if key == Key::KEY_SPACE {
+        return Some(' ');
+    }
+    KEY_ROWS.iter().find_map(|(first, row)| {
+        let index = key.code().checked_sub(first.code())?;
+        row.chars().nth(index as usize)
+    })
+}
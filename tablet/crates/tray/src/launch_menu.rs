@@ -0,0 +1,137 @@
+//! Menu of a draft's launch profiles, log and processes, opened by pressing and holding its icon
+use std::time::Duration;
+
+use libremarkable::cgmath::Point2;
+use raft::Draft;
+use shared::{
+    draft_log::has_draft_log,
+    locale::{tr, tr_args},
+    ProcessTree,
+};
+
+use crate::{
+    channel::Sender,
+    draft_log::{draft_log, DRAFT_LOG_SCROLL},
+    exit_to,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    partial_refresh,
+    processes::{draft_process, draft_processes},
+    text_button,
+    ui::{
+        margin, margin_left, offset_relative, overlay, rect_border, set_rect, text, Draw,
+        DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// How long an icon has to be held to open its launch menu
+pub const LAUNCH_MENU_HOLD: Duration = Duration::from_millis(500);
+
+/// Profiles listed beneath the title, after the plain launch
+const LAUNCH_MENU_PROFILES: usize = 4;
+
+/// The plain launch, then each of the draft's profiles
+pub fn launch_menu(event_tx: Sender<MainEvent>, draft: Draft) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let title = tr_args("launch_menu.title", &[("name", &draft.name)]);
+        let cancel_label = tr("launch_menu.cancel");
+        let log_label = tr("launch_menu.view_log");
+        let processes_label = tr("launch_menu.processes");
+
+        let mut choices = vec![(tr("launch_menu.default"), draft.clone())];
+        choices.extend(
+            draft
+                .profiles
+                .iter()
+                .take(LAUNCH_MENU_PROFILES)
+                .filter_map(|profile| {
+                    Some((profile.name.clone(), draft.with_profile(&profile.name)?))
+                }),
+        );
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+        let header = ctx.rect;
+
+        ctx = overlay(offset_relative(Point2::new(0, height / 4)).then(text(
+            &title,
+            layout().font_size,
+            Color::BLACK,
+        )))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 * 3 / 4).then(text_button(&cancel_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                }
+            })),
+        )(ctx);
+
+        for (i, (label, choice)) in choices.iter().enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * (i as i32 + 1))).then(text_button(
+                    label,
+                    {
+                        let event_tx = event_tx.clone();
+                        let choice = choice.clone();
+                        move || {
+                            println!(
+                                "Launching {:?} with arguments {:?}",
+                                choice.name, choice.args
+                            );
+                            exit_to(&event_tx, Some(choice.clone()));
+                        }
+                    },
+                )),
+            )(ctx);
+        }
+
+        let mut row = choices.len() as i32 + 1;
+        if has_draft_log(&draft.id()) {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * row)).then(text_button(&log_label, {
+                    let event_tx = event_tx.clone();
+                    let state = ctx.state.clone();
+                    let draft = draft.clone();
+                    move || {
+                        state.remove(DRAFT_LOG_SCROLL);
+                        event_tx
+                            .send(MainEvent::set_draw(Some(draft_log(
+                                event_tx.clone(),
+                                draft.clone(),
+                            ))))
+                            .ok();
+                    }
+                })),
+            )(ctx);
+            row += 1;
+        }
+
+        if draft_process(&ProcessTree::scan(), &draft).is_some() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * row)).then(text_button(
+                    &processes_label,
+                    {
+                        let event_tx = event_tx.clone();
+                        let draft = draft.clone();
+                        move || {
+                            event_tx
+                                .send(MainEvent::set_draw(Some(draft_processes(
+                                    event_tx.clone(),
+                                    draft.clone(),
+                                ))))
+                                .ok();
+                        }
+                    },
+                )),
+            )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -0,0 +1,77 @@
+//! Runtime layout metrics, derived from the configured UI scale
+use std::sync::OnceLock;
+
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+pub const BASE_ICON_SIZE: f32 = (DISPLAY_HEIGHT as f32 / 4.0) / 3.0;
+pub const BASE_FONT_SIZE: f32 = 42.0;
+pub const BASE_BADGE_RADIUS: f32 = 8.0;
+pub const BASE_BADGE_OFFSET: f32 = 20.0;
+pub const BASE_CLOSE_BUTTON_SIZE: f32 = 32.0;
+pub const BASE_FOCUS_MARGIN: f32 = 4.0;
+pub const BASE_FOCUS_STROKE: f32 = 3.0;
+
+pub const ROWS: usize = 2;
+pub const MAX_COLUMNS: usize = 7;
+
+static LAYOUT: OnceLock<Layout> = OnceLock::new();
+
+#[derive(Debug, Copy, Clone)]
+pub struct Layout {
+    pub scale: f32,
+    pub icon_size: i32,
+    pub icon_spacing: i32,
+    pub font_size: f32,
+    /// Height of a single line of text in list views
+    pub line_height: i32,
+    pub columns: usize,
+    pub row_height: i32,
+    pub row_margin: i32,
+    pub badge_radius: u32,
+    pub badge_offset: i32,
+    pub close_button_size: i32,
+    pub focus_margin: i32,
+    pub focus_stroke: u32,
+    pub tap_hysteresis: f32,
+}
+
+impl Layout {
+    pub fn new(scale: f32) -> Self {
+        let icon_size = (BASE_ICON_SIZE * scale) as i32;
+        let icon_spacing = icon_size / 4;
+        let font_size = BASE_FONT_SIZE * scale;
+
+        // Drop columns rather than overflowing the display at large scales
+        let columns = ((DISPLAY_WIDTH as i32 + icon_spacing) / (icon_size + icon_spacing))
+            .clamp(1, MAX_COLUMNS as i32) as usize;
+        let row_width = (icon_size * columns as i32) + (icon_spacing * (columns as i32 - 1));
+
+        Layout {
+            scale,
+            icon_size,
+            icon_spacing,
+            font_size,
+            line_height: (font_size * 1.5) as i32,
+            columns,
+            row_height: icon_size + font_size as i32 * 2,
+            row_margin: (DISPLAY_WIDTH as i32 - row_width) / 2,
+            badge_radius: (BASE_BADGE_RADIUS * scale) as u32,
+            badge_offset: (BASE_BADGE_OFFSET * scale) as i32,
+            close_button_size: (BASE_CLOSE_BUTTON_SIZE * scale) as i32,
+            focus_margin: (BASE_FOCUS_MARGIN * scale) as i32,
+            focus_stroke: (BASE_FOCUS_STROKE * scale) as u32,
+            tap_hysteresis: shared::TAP_HYSTERESIS * scale,
+        }
+    }
+}
+
+/// Set the UI scale, must be called before anything is drawn
+pub fn layout_init(scale: f32) {
+    if LAYOUT.set(Layout::new(scale)).is_err() {
+        println!("Layout already initialized, ignoring scale {scale:}");
+    }
+}
+
+pub fn layout() -> &'static Layout {
+    LAYOUT.get_or_init(|| Layout::new(1.0))
+}
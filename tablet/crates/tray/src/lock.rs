@@ -0,0 +1,79 @@
+//! Input lock, touch is grabbed and discarded until the unlock gesture is made
+//!
+//! The unlock gesture is a long press in the top-left and bottom-right corners at once,
+//! awkward enough that a child or a bag won't make it by accident.
+use std::time::Duration;
+
+use libremarkable::cgmath::{Point2, Vector2};
+use raft::Draft;
+
+use crate::{
+    channel::Sender,
+    display::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    exit_to,
+    framebuffer::Color,
+    layout::layout,
+    partial_refresh,
+    ui::{
+        circle_stroke, offset_relative, overlay, recognize_chord, rect_stroke, set_position,
+        set_size, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// How long both corners must be held to unlock
+pub const UNLOCK_HOLD: Duration = Duration::from_secs(2);
+
+/// Small padlock in the bottom-right corner, inside the panel so resuming a draft covers it
+fn lock_indicator() -> impl Draw {
+    move |ctx: DrawContext| {
+        let size = (layout().close_button_size / 2) as u32;
+        let x = DISPLAY_WIDTH as u32 - size * 2;
+        let y = DISPLAY_HEIGHT as u32 - size * 2;
+
+        set_position(x, y)
+            .then(set_size(size, size))
+            .then(overlay(
+                offset_relative(Point2::new((size / 2) as i32, (size / 3) as i32))
+                    .then(circle_stroke(size / 4, Color::GRAY(160))),
+            ))
+            .then(overlay(
+                offset_relative(Point2::new(0, (size / 3) as i32))
+                    .then(set_size(size, size - size / 3))
+                    .then(rect_stroke(1, Color::GRAY(160))),
+            ))
+            .then(partial_refresh())
+            .draw(ctx)
+    }
+}
+
+/// Draws only the lock indicator, so the locked draft stays on screen while the tray holds the grab
+pub fn locked(event_tx: Sender<MainEvent>, stopped_draft: Option<Draft>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let corner = layout().icon_size as u16;
+        let zones = vec![
+            (Point2::new(0, 0), Vector2::new(corner, corner)),
+            (
+                Point2::new(DISPLAY_WIDTH - corner, DISPLAY_HEIGHT - corner),
+                Vector2::new(corner, corner),
+            ),
+        ];
+
+        let unlock = {
+            let event_tx = event_tx.clone();
+            let stopped_draft = stopped_draft.clone();
+            move || {
+                println!("Unlocking input");
+                if stopped_draft.is_some() {
+                    exit_to(&event_tx, stopped_draft.clone());
+                } else {
+                    event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                }
+            }
+        };
+
+        lock_indicator()
+            .then(recognize_chord(zones, UNLOCK_HOLD, unlock))
+            .draw(ctx)
+    }
+}
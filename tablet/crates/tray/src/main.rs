@@ -98,73 +98,201 @@
 //           * Wave as icon bar, tray as card UI
 //
 
+mod animation;
+mod banner;
+mod bindings;
+mod calibration;
 pub mod channel;
+mod clock;
+mod confirm;
 pub mod display;
 pub mod panel;
 
-mod draft_program;
+mod draft_log;
+mod focus;
 mod framebuffer;
+mod home;
+mod hotplug;
+mod idle;
 mod input;
+mod keyboard;
+mod launch_menu;
+mod layout;
+mod lock;
+mod network;
+mod nine_patch;
+mod notifications;
+mod osk;
+mod palette;
+mod pan;
+mod pie;
+mod pin;
+mod processes;
+mod profile;
+mod quick_bar;
+mod recent;
 mod rect;
+mod refresh;
 mod render;
+mod repaint;
+mod settings;
+mod state;
+mod storage_indicator;
+mod store;
+mod stream;
+mod suspend;
+mod sync_indicator;
+mod theme;
 mod ui;
+mod user_profile;
+mod widget;
+mod wifi;
 
-use channel::channel;
-use display::DISPLAY_HEIGHT;
+use channel::{channel, priority_channel, Lane, Overflow, Policy, Priority};
+use display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
 use input::InputHandles;
-use panel::PANEL_HEIGHT;
+use panel::panel_height;
 
-use gesture::GestureRecognizer;
+use gesture::{Clock, EventType, GestureRecognizer, Pan, SystemClock, TouchFilter};
+use launcher_core::{
+    capture::{capture_worker, screenshot_path, CaptureWorker},
+    drafts::{close_process, exit_watcher, get_draft_icon, DraftPrograms, DraftState, RunType},
+};
 use libremarkable::{
-    cgmath::Point2,
+    cgmath::{Point2, Vector2},
+    evdev::Key,
     framebuffer::refresh::PartialRefreshMode,
-    image::{ImageBuffer, Rgb},
+    image::{ImageBuffer, Rgba},
     input::{multitouch::MultitouchEvent, InputEvent},
 };
-use raft::{Draft, Drafts};
+use raft::{Draft, DraftId, Drafts, OnSwitch, SafeKill};
 use shared::{
-    kill_recursive, path_temp_pid, path_temp_screenshot, processes, system_xochitl_process,
-    TAP_HYSTERESIS,
+    action::Action,
+    cloud_sync::XOCHITL_PROCESS,
+    config::{update_config, Config},
+    draft_log::has_draft_log,
+    frontlight::set_brightness,
+    handoff::{listen_handoff, wait_for_handoff, STANDBY_ARG},
+    health::{is_hung, set_hung},
+    hooks::{run_hooks, HookEvent},
+    instance::single_instance,
+    locale::{locale_init, tr_args},
+    metrics::{count, flush_metrics, metrics_init, Counter},
+    notification::Notification,
+    oom::protect_launcher,
+    path_temp_screenshot,
+    pidfile::write_pid,
+    screenshot::load_screenshot,
+    session::Session,
+    storage::{format_bytes, storage_low, HOME_PATH, LOW_STORAGE_THRESHOLD},
+    system_xochitl_process, ProcessTree, FIRM_PRESS_PRESSURE,
 };
 
-use std::{sync::Arc, thread::JoinHandle, time::Duration};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
 
 use crate::{
+    animation::{frame_interval, set_animation_fps, slide_in, slide_out},
+    banner::show_banner,
+    bindings::{binding, bindings_init, run_tray_action, tray_swipes, tray_taps},
+    calibration::{calibration, reset_calibration, set_touch_transform, touch_transform},
     channel::{Receiver, Sender},
+    clock::{clock_settings, reset_clock_settings},
+    confirm::confirm_dialog,
     display::DISPLAY_RECT,
-    draft_program::{get_draft_icon, DraftPrograms, RunType},
-    framebuffer::{Color, DisplayTemp, DitherMode, WaveformMode},
+    focus::{Direction, FocusMap},
+    framebuffer::{Color, DisplayTemp, DitherMode, MxcfbRect, WaveformMode},
+    home::{go_home, has_home, home_button, home_init},
+    hotplug::hotplug_monitor,
+    idle::{clock_ticker, idle_image, idle_screen, idle_screenshot_path},
     input::{input_init, InputCommand},
-    panel::PANEL_RECT,
-    render::{render_thread, RenderEvent},
+    keyboard::{key_char, Keyboards},
+    launch_menu::LAUNCH_MENU_HOLD,
+    layout::{layout, layout_init},
+    lock::locked,
+    network::{network_button, network_info},
+    nine_patch::{panel_chrome, panel_skin_init},
+    notifications::{notification_history, NOTIFICATIONS_SCROLL},
+    palette::{palette, palette_key, reset_palette, PALETTE_QUERY},
+    pan::PanelPan,
+    panel::panel_rect,
+    pie::pie_menu,
+    pin::{needs_pin, pin_init, pin_prompt},
+    profile::{input_received, mark, set_hud_enabled, startup_begin, timed, Metric},
+    quick_bar::{quick_bar, quick_bar_rect},
+    recent::{recent_strip, Recent},
+    refresh::{battery_monitor, partial_waveform},
+    render::{render_thread, wait_for_refresh_completion, RenderEvent},
+    repaint::check_repaint,
+    settings::{settings, settings_button},
+    state::StateStore,
+    storage_indicator::storage_indicator,
+    store::{package_store, store_button, PackageStore, STORE_PAGE},
+    stream::stream_init,
+    suspend::suspend_monitor,
+    sync_indicator::{sync_indicator, sync_monitor, syncing},
+    theme::panel_background,
     ui::{
-        circle_fill, clear, dump_region, horizontal, image, line, margin, margin_bottom,
-        margin_horizontal, margin_left, margin_top, offset_absolute, offset_relative, overlay,
-        recognize_gesture, rect_border, rect_stroke, restore_region, set_rect, text_aligned, unit,
-        vertical_fixed, Draw, DrawContext, DrawFn, OverlayTrait, ThenTrait,
+        circle_fill, circle_smooth_fill, circle_smooth_stroke, clear, dump_png, dump_screenshot,
+        expand, flex_row, focusable, grid, image_alpha, line_smooth, margin, margin_bottom,
+        margin_horizontal, margin_left, margin_right, margin_top, memo, offset_absolute,
+        offset_relative, recognize_gesture, recognize_multi_tap, recognize_pan, rect_stroke,
+        restore_region, rounded_rect_border, set_height, set_rect, text_aligned, unit,
+        vertical_fixed, when, Draw, DrawContext, DrawFn, Flexible, OverlayTrait, ThenTrait,
     },
+    user_profile::{apply_allowed, user_profile_init, user_profile_picker},
+    widget::{widgets_init, Widgets},
+    wifi::{reset_wifi, wifi_picker, WifiPicker},
 };
 
-pub const ICON_SIZE: i32 = (DISPLAY_HEIGHT as i32 / 4) / 3;
-pub const ICON_SPACING: i32 = ICON_SIZE / 4;
-pub const FONT_SIZE: f32 = 42.0;
-
-pub const ROWS: usize = 2;
-pub const COLUMNS: usize = 7;
-pub const ROW_WIDTH: i32 =
-    (ICON_SIZE as i32 * COLUMNS as i32) + (ICON_SPACING as i32 * (COLUMNS as i32 - 1));
-pub const ROW_HEIGHT: i32 = ICON_SIZE as i32 + FONT_SIZE as i32 * 2;
-pub const ROW_MARGIN: i32 = (DISPLAY_RECT.width as i32 - ROW_WIDTH) / 2;
-
-pub const KILL_SLEEP_DURATION: Duration = std::time::Duration::from_millis(100);
+/// Top-level screens the main loop can switch between
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum View {
+    Tray,
+    QuickBar,
+    PackageStore,
+    Settings,
+    Clock,
+    Notifications,
+    Calibration,
+    UserProfiles,
+    Network,
+    Wifi,
+    Locked,
+    Idle,
+    Palette,
+}
 
 pub enum MainEvent {
-    LoadIcon(String, ImageBuffer<Rgb<u8>, Vec<u8>>),
+    LoadIcon(String, ImageBuffer<Rgba<u8>, Vec<u8>>),
     SetGestureRecognizer(Option<GestureRecognizer>),
+    SetFocusMap(FocusMap),
     SetDraw(Option<Arc<Box<dyn Draw + Send + Sync>>>),
+    ShowView(View),
     Redraw,
+    Resumed,
+    InputHotplug,
     Input(InputEvent),
-    Run(Draft),
+    Key(Key),
+    /// Set the frontlight and remember the level for the draft behind the tray
+    SetBrightness(u8),
+    Run(Box<Draft>),
+    /// A draft was killed or found to have exited, and should be offered for relaunch
+    Closed(String),
+    /// A draft launched by this tray exited and was reaped
+    ProcessExited(DraftId),
+    /// Draw a saved full screenshot back to the display
+    RestoreScreen(PathBuf),
+    /// A screenshot finished writing, and whether it succeeded
+    Captured(PathBuf, bool),
+    /// Save what the tray was opened over to a PNG for the user
+    Screenshot,
+    /// The drafts panel was dragged, with the action to run if it's dragged away
+    PanPanel(Pan, Action),
     StopInput,
     StopRenderer,
     Exit,
@@ -178,52 +306,170 @@ impl MainEvent {
             MainEvent::SetDraw(None)
         }
     }
+
+    fn priority(&self) -> Priority {
+        match self {
+            MainEvent::Input(_) | MainEvent::Key(_) => Priority::Input,
+            MainEvent::Resumed | MainEvent::InputHotplug | MainEvent::Captured(..) => {
+                Priority::Background
+            }
+            _ => Priority::Render,
+        }
+    }
+
+    /// Collapse repeated redraws and hotplug notices, and successive moves of one finger or pan
+    fn coalesces(queued: &Self, event: &Self) -> bool {
+        match (queued, event) {
+            (MainEvent::Redraw, MainEvent::Redraw) => true,
+            (MainEvent::InputHotplug, MainEvent::InputHotplug) => true,
+            (MainEvent::PanPanel(Pan::Moved(_), _), MainEvent::PanPanel(Pan::Moved(_), _)) => true,
+            (
+                MainEvent::Input(InputEvent::MultitouchEvent {
+                    event: MultitouchEvent::Move { finger: queued },
+                }),
+                MainEvent::Input(InputEvent::MultitouchEvent {
+                    event: MultitouchEvent::Move { finger },
+                }),
+            ) => queued.tracking_id == finger.tracking_id,
+            _ => false,
+        }
+    }
 }
 
+/// Queued input events before input threads block
+pub const INPUT_QUEUE_CAPACITY: usize = 256;
+
+/// Queued background notices before new ones are dropped
+pub const BACKGROUND_QUEUE_CAPACITY: usize = 32;
+
+/// Input ahead of drawing, housekeeping last, input threads wait rather than flood the loop
+pub const MAIN_EVENT_POLICY: Policy<MainEvent> = Policy {
+    priority: MainEvent::priority,
+    coalesce: MainEvent::coalesces,
+    lanes: [
+        Lane {
+            capacity: Some(INPUT_QUEUE_CAPACITY),
+            overflow: Overflow::Block,
+        },
+        Lane::UNBOUNDED,
+        Lane {
+            capacity: Some(BACKGROUND_QUEUE_CAPACITY),
+            overflow: Overflow::DropNewest,
+        },
+    ],
+};
+
 fn main() {
+    // In standby, listen straight away so a gesture made while drafts load waits its turn
+    let standby = std::env::args()
+        .any(|arg| arg == STANDBY_ARG)
+        .then(|| listen_handoff().expect("Failed to listen for handoff"));
+    if standby.is_none() {
+        startup_begin();
+    }
     println!("tray startup");
 
-    println!("Loading drafts...");
-    let drafts = Arc::new(DraftPrograms::new(
-        Drafts::new().expect("Failed to parse draft files"),
-    ));
+    let config = Config::load();
+    protect_launcher(config.launcher_oom_score_adj);
+    metrics_init(&config);
+    layout_init(config.ui_scale);
+    locale_init(config.locale.as_deref());
+    user_profile_init(&config);
+    set_touch_transform(config.touch_transform);
+    panel_skin_init(config.panel_skin.as_deref());
+    set_hud_enabled(config.perf_hud);
+    set_animation_fps(config.animation_fps);
 
-    // Cache the system xochitl PID to disk if it exists
-    if let Some(xochitl_proc) = system_xochitl_process() {
-        println!("System xochitl process: {xochitl_proc:#?}");
-        std::fs::write(
-            path_temp_pid("xochitl"),
-            xochitl_proc.stat.process_id.to_string(),
-        )
-        .unwrap();
+    // Parse drafts and decode icons ahead of time, then wait for wave to hand over a gesture,
+    // timing startup from there
+    let mut preloaded = None;
+    let (action, _handoff) = match standby {
+        Some(listener) => {
+            println!("Loading drafts for standby...");
+            let drafts = load_drafts();
+            preload_icons(&drafts);
+            preloaded = Some(drafts);
+
+            println!("Waiting for handoff...");
+            let (action, stream) = wait_for_handoff(&listener).expect("Failed to receive handoff");
+            startup_begin();
+            (Some(action), Some(stream))
+        }
+        None => (Action::from_args(), None),
+    };
+    if let Some(action) = action {
+        println!("Performing action {action:}");
+    }
+    let _instance = single_instance("tray");
+    mark("config");
+
+    // Banners are drawn over the running draft, so skip stopping drafts and grabbing input
+    if action == Some(Action::Notify) {
+        match Notification::from_args() {
+            Some(notification) => show_banner(config.display_backend, &notification),
+            None => println!("No notification to show"),
+        }
+        return;
     }
 
+    let drafts = preloaded.unwrap_or_else(load_drafts);
+    apply_allowed(&drafts);
+    mark("drafts");
+
+    // Load the manifest left behind by the previous tray instance, if any
+    let mut session = Session::load().unwrap_or_default();
+
     // Stop running draft processes from this session, pick one to resume on close
     let stopped_drafts = drafts.stop_draft_programs();
-    let stopped_draft = stopped_drafts.get(0).cloned();
+    for draft in &stopped_drafts {
+        run_hooks(HookEvent::AppSuspend {
+            draft: draft.name.clone(),
+        });
+    }
+    let stopped_draft = stopped_drafts.first().cloned().or_else(|| {
+        // Nothing was running, so recover the foreground draft if a previous tray left it stopped
+        let draft = drafts.stopped_draft(session.foreground.as_ref()?)?;
+        println!(
+            "Recovered stopped draft {:?} from previous session",
+            draft.name
+        );
+        Some(draft)
+    });
+    pin_init(&config, stopped_draft.as_ref());
+    bindings_init(&config, stopped_draft.as_ref());
+    home_init(&config, drafts.clone());
+    mark("stopped drafts");
 
     // Create an MPSC channel to receive input events
     println!("Initializing MPSC channels...");
-    let (event_tx, event_rx) = channel::<MainEvent>();
+    let (event_tx, event_rx) = priority_channel(MAIN_EVENT_POLICY);
     let (render_tx, render_rx) = channel::<RenderEvent>();
 
-    // Start event channels
-    println!("Starting event channels...");
-    let input_handles = input_init(event_tx.clone());
-
-    input_handles.broadcast(InputCommand::Grab).unwrap();
+    // Start remote stream server, if configured
+    let stream = stream_init(event_tx.clone());
 
     // Start render thread
     println!("Starting renderer...");
-    let render_handle = std::thread::spawn(render_thread(event_tx.clone(), render_rx));
+    let state = StateStore::default();
+    let render_handle = std::thread::spawn(render_thread(
+        event_tx.clone(),
+        render_rx,
+        stream,
+        config.display_backend,
+        state.clone(),
+    ));
 
+    // Capture the screen before anything is drawn over it
+    let capture = capture_worker({
+        let event_tx = event_tx.clone();
+        move |path, saved| event_tx.send(MainEvent::Captured(path, saved)).is_ok()
+    });
     render_tx
         .send(RenderEvent::execute(
-            set_rect(PANEL_RECT).then(dump_region(move |data| {
-                let path = path_temp_screenshot("panel");
-                println!("Saving panel screenshot...");
-                std::fs::write(path, data).unwrap();
-            })),
+            set_rect(panel_rect()).then(dump_screenshot(
+                path_temp_screenshot("panel"),
+                capture.clone(),
+            )),
             false,
         ))
         .unwrap();
@@ -231,21 +477,144 @@ fn main() {
     if let Some(draft) = stopped_drafts.get(0) {
         println!("Dumping full screenshot...");
 
-        let draft = draft.clone();
+        let path = path_temp_screenshot(draft.file_name().unwrap());
+        session.screenshots.insert(draft.id(), path.clone());
+
         render_tx
             .send(RenderEvent::execute(
-                set_rect(DISPLAY_RECT).then(dump_region(move |data| {
-                    let file_name = draft.file_name().unwrap().to_str().unwrap();
-                    let path = path_temp_screenshot(file_name);
-
-                    println!("Saving full screenshot...");
-                    std::fs::write(path, data).unwrap();
-                })),
+                set_rect(DISPLAY_RECT).then(dump_screenshot(path, capture.clone())),
                 false,
             ))
             .unwrap()
     }
 
+    // Save whatever is on screen so it can be put back when the idle screen is dismissed
+    if action == Some(Action::Idle) {
+        render_tx
+            .send(RenderEvent::execute(
+                set_rect(DISPLAY_RECT)
+                    .then(dump_screenshot(idle_screenshot_path(), capture.clone())),
+                false,
+            ))
+            .unwrap();
+    }
+
+    // Draw empty panel chrome straight away, the tray view fills it in once built.
+    // When animated, the view slides in over the draft instead, so leave it be.
+    if matches!(action, None | Some(Action::OpenTray)) {
+        run_hooks(HookEvent::TrayOpen);
+    }
+    if matches!(action, None | Some(Action::OpenTray)) && frame_interval().is_none() {
+        render_tx
+            .send(RenderEvent::execute(
+                set_rect(panel_rect())
+                    .then(panel_chrome())
+                    .then(partial_refresh())
+                    .then(|ctx| {
+                        mark("panel chrome");
+                        ctx
+                    }),
+                false,
+            ))
+            .unwrap();
+    }
+
+    // Start event channels
+    println!("Starting event channels...");
+    let mut input_handles = input_init(event_tx.clone());
+
+    input_handles.broadcast(InputCommand::Grab).unwrap();
+
+    // Throttle refreshes while the battery is low
+    battery_monitor();
+
+    if storage_low() {
+        println!(
+            "Warning: Less than {} free on {HOME_PATH:}",
+            format_bytes(LOW_STORAGE_THRESHOLD)
+        );
+    }
+
+    // Notice drafts launched from here exiting
+    exit_watcher(drafts.clone(), {
+        let event_tx = event_tx.clone();
+        move |id| event_tx.send(MainEvent::ProcessExited(id)).is_ok()
+    });
+
+    // Show when xochitl is syncing, so it isn't closed mid-sync
+    sync_monitor(event_tx.clone());
+
+    // Watch for device sleep so state can be resynchronized on wake
+    suspend_monitor(event_tx.clone());
+
+    // Pick up any attached keyboards, more are opened as they're plugged in
+    let keyboards = Keyboards::new(event_tx.clone());
+    keyboards.scan();
+
+    // Respawn input threads if their device nodes are removed and re-added
+    hotplug_monitor(event_tx.clone());
+    mark("input");
+
+    // Drafts the previous tray left running, to spot any that have since exited
+    let known_drafts = session
+        .foreground
+        .iter()
+        .chain(session.stopped.iter())
+        .cloned()
+        .collect::<Vec<_>>();
+    let recent: Recent = Arc::new(Mutex::new(session.recent.clone()));
+
+    // Scan /proc for bookkeeping that nothing on screen depends on
+    session.foreground = stopped_draft.as_ref().map(Draft::id);
+    {
+        let event_tx = event_tx.clone();
+        let drafts = drafts.clone();
+        let mut session = session.clone();
+        std::thread::spawn(move || {
+            // Cache the system xochitl PID to disk under each draft launching it, if it exists
+            if let Some(xochitl_proc) = system_xochitl_process(&ProcessTree::scan()) {
+                println!("System xochitl process: {xochitl_proc:#?}");
+                for draft in drafts
+                    .drafts()
+                    .values()
+                    .filter(|draft| draft.file_name() == Some(XOCHITL_PROCESS.as_ref()))
+                {
+                    if let Err(e) = write_pid(&draft.id(), xochitl_proc.stat.process_id) {
+                        println!("Failed to record the system xochitl PID: {e:}");
+                    }
+                }
+            }
+
+            // Drafts the previous tray left stopped, such as before it crashed
+            let recovered = drafts.recover_stopped(&session.stopped);
+            if !recovered.is_empty() {
+                println!("Recovered stopped drafts {recovered:?} from previous session");
+            }
+            session.stopped = drafts.stopped_draft_ids();
+            if let Err(e) = session.save() {
+                println!("Failed to save session: {e:}");
+            }
+
+            let running = timed(Metric::ProcScan, || drafts.refresh_procs());
+            let mut exited = false;
+            for draft in known_drafts
+                .iter()
+                .filter(|id| !running.contains_key(*id))
+                .filter_map(|id| drafts.drafts().get(id))
+            {
+                println!("Draft {:?} exited since the last session", draft.name);
+                event_tx
+                    .send(MainEvent::Closed(draft.name.clone()))
+                    .unwrap();
+                exited = true;
+            }
+            if exited {
+                event_tx.send(MainEvent::Redraw).unwrap();
+            }
+            mark("proc scan");
+        });
+    }
+
     // Start icon loading thread
     {
         let event_tx = event_tx.clone();
@@ -253,13 +622,17 @@ fn main() {
         std::thread::spawn(move || {
             let mut loaded = false;
             for (id, draft) in drafts.drafts() {
-                if let Ok(icon) = get_draft_icon(draft) {
+                if drafts.draft_icons().contains_key(id) {
+                    continue;
+                }
+                if let Ok(icon) = get_draft_icon(draft, layout().icon_size as u32) {
                     event_tx
                         .send(MainEvent::LoadIcon(id.clone(), icon))
                         .unwrap();
                     loaded = true;
                 }
             }
+            mark("icons");
 
             if loaded {
                 event_tx.send(MainEvent::Redraw).unwrap();
@@ -269,44 +642,221 @@ fn main() {
 
     println!("Initializing gesture recognizer...");
 
-    event_tx
-        .send(MainEvent::set_draw(Some(tray(
+    let store = Arc::new(PackageStore::default());
+    let widgets = widgets_init(config.widgets.clone(), event_tx.clone());
+
+    let mut views = BTreeMap::<View, Arc<Box<dyn Draw + Send + Sync>>>::new();
+    views.insert(
+        View::Tray,
+        Arc::new(Box::new(tray(
+            event_tx.clone(),
+            drafts.clone(),
+            store.clone(),
+            widgets,
+            recent.clone(),
+            config.pie_menu,
+        ))),
+    );
+    views.insert(
+        View::Settings,
+        Arc::new(Box::new(settings(
+            event_tx.clone(),
+            config.usb_storage.clone(),
+        ))),
+    );
+    views.insert(
+        View::Clock,
+        Arc::new(Box::new(clock_settings(event_tx.clone()))),
+    );
+    let wifi = Arc::new(WifiPicker::default());
+    views.insert(
+        View::Network,
+        Arc::new(Box::new(network_info(event_tx.clone(), wifi.clone()))),
+    );
+    views.insert(
+        View::Wifi,
+        Arc::new(Box::new(wifi_picker(event_tx.clone(), wifi))),
+    );
+    views.insert(
+        View::Calibration,
+        Arc::new(Box::new(calibration(event_tx.clone()))),
+    );
+    views.insert(
+        View::UserProfiles,
+        Arc::new(Box::new(user_profile_picker(
+            event_tx.clone(),
+            drafts.clone(),
+        ))),
+    );
+    views.insert(
+        View::Notifications,
+        Arc::new(Box::new(notification_history(event_tx.clone()))),
+    );
+    views.insert(
+        View::PackageStore,
+        Arc::new(Box::new(package_store(event_tx.clone(), store))),
+    );
+
+    views.insert(
+        View::QuickBar,
+        Arc::new(Box::new(quick_bar(
             event_tx.clone(),
             drafts.clone(),
             stopped_draft.clone(),
-        ))))
-        .unwrap();
+            recent.clone(),
+        ))),
+    );
+
+    views.insert(
+        View::Palette,
+        Arc::new(Box::new(palette(event_tx.clone(), drafts.clone()))),
+    );
+
+    views.insert(
+        View::Locked,
+        Arc::new(Box::new(locked(event_tx.clone(), stopped_draft.clone()))),
+    );
+
+    match action {
+        None | Some(Action::OpenTray) => event_tx.send(MainEvent::ShowView(View::Tray)).unwrap(),
+        Some(Action::LastApp) => {
+            let previous = session
+                .previous
+                .as_ref()
+                .filter(|id| Some(*id) != session.foreground.as_ref())
+                .and_then(|id| drafts.drafts().get(id))
+                .filter(|draft| drafts.is_allowed(draft))
+                .cloned();
 
+            if previous.is_some() {
+                exit_to(&event_tx, previous);
+            } else {
+                println!("No previous draft to switch to");
+                event_tx.send(MainEvent::ShowView(View::Tray)).unwrap();
+            }
+        }
+        Some(Action::Screenshot) => {
+            render_tx
+                .send(RenderEvent::execute(
+                    set_rect(DISPLAY_RECT).then(dump_png(screenshot_path(), capture.clone())),
+                    false,
+                ))
+                .unwrap();
+
+            exit_to(&event_tx, stopped_draft.clone());
+        }
+        Some(Action::Home) => go_home(&event_tx),
+        Some(Action::LockInput) => event_tx.send(MainEvent::ShowView(View::Locked)).unwrap(),
+        Some(Action::QuickBar) => event_tx.send(MainEvent::ShowView(View::QuickBar)).unwrap(),
+        Some(Action::Palette) => event_tx.send(MainEvent::ShowView(View::Palette)).unwrap(),
+        Some(Action::Notify) => unreachable!("Notifications are shown before startup"),
+        Some(
+            action @ (Action::Dismiss
+            | Action::Launch
+            | Action::LaunchMenu
+            | Action::ToggleNightMode),
+        ) => {
+            println!("{action:} acts on the open tray, opening it");
+            event_tx.send(MainEvent::ShowView(View::Tray)).unwrap();
+        }
+        Some(Action::Idle) => {
+            views.insert(
+                View::Idle,
+                Arc::new(Box::new(idle_screen(
+                    event_tx.clone(),
+                    idle_image().map(Arc::new),
+                ))),
+            );
+            clock_ticker(event_tx.clone());
+            event_tx.send(MainEvent::ShowView(View::Idle)).unwrap();
+        }
+    }
+
+    // Shared by the touch filter and gesture recognizers, so held presses keep their timing
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock::default());
     MainLoop {
+        event_tx,
         event_rx,
 
         input_handles,
+        keyboards,
 
         render_handle: Some(render_handle),
         render_tx,
 
         drafts,
         stopped_drafts,
+        session,
+        recent,
+        capture,
+        panel_pan: PanelPan::default(),
+        draft_brightness: config.draft_brightness,
+        on_switch: config.on_switch,
 
+        touch_filter: TouchFilter::new((
+            Point2::new(0, 0),
+            Vector2::new(DISPLAY_WIDTH, DISPLAY_HEIGHT),
+        ))
+        .with_clock(clock.clone())
+        .with_edge_margin(config.touch_edge_margin)
+        .with_min_contact(config.touch_min_contact),
+        clock,
         gesture_recognizer: None,
+        focus: FocusMap::default(),
+        focused: None,
+        views,
+        view: None,
         draw: None,
+        state,
     }
     .run();
 }
 
+fn load_drafts() -> Arc<DraftPrograms> {
+    println!("Loading drafts...");
+    Arc::new(DraftPrograms::new(
+        Drafts::new().expect("Failed to parse draft files"),
+    ))
+}
+
+/// Decode every draft icon up front, for a standby tray to have them ready when shown
+fn preload_icons(drafts: &DraftPrograms) {
+    for (id, draft) in drafts.drafts() {
+        if let Ok(icon) = get_draft_icon(draft, layout().icon_size as u32) {
+            drafts.set_icon(id.clone(), icon);
+        }
+    }
+}
+
 struct MainLoop {
+    event_tx: Sender<MainEvent>,
     event_rx: Receiver<MainEvent>,
 
     input_handles: InputHandles,
+    keyboards: Keyboards,
 
     render_tx: Sender<RenderEvent>,
     render_handle: Option<JoinHandle<()>>,
 
     drafts: Arc<DraftPrograms>,
     stopped_drafts: Vec<Draft>,
+    session: Session,
+    recent: Recent,
+    capture: CaptureWorker,
+    panel_pan: PanelPan,
+    draft_brightness: BTreeMap<String, u8>,
+    on_switch: OnSwitch,
 
+    clock: Arc<dyn Clock>,
+    touch_filter: TouchFilter,
     gesture_recognizer: Option<GestureRecognizer>,
+    focus: FocusMap,
+    focused: Option<usize>,
+    views: BTreeMap<View, Arc<Box<dyn Draw + Send + Sync>>>,
+    /// View currently shown, None while a one-off draw such as a prompt is up
+    view: Option<View>,
     draw: Option<Arc<Box<dyn Draw + Send + Sync>>>,
+    state: StateStore,
 }
 
 impl MainLoop {
@@ -319,11 +869,21 @@ impl MainLoop {
                     self.drafts.set_icon(key, icon);
                 }
                 MainEvent::SetGestureRecognizer(gesture_recognizer) => {
-                    // Reverse priority of callbacks to ensure frontmost elements check first
-                    self.gesture_recognizer =
-                        gesture_recognizer.map(GestureRecognizer::reverse_callback_priority);
+                    // Reverse priority of callbacks to ensure frontmost elements check first,
+                    // and keep fingers already down so held gestures survive a redraw
+                    let previous = self.gesture_recognizer.take();
+                    self.gesture_recognizer = gesture_recognizer.map(|gesture_recognizer| {
+                        let gesture_recognizer = gesture_recognizer
+                            .with_clock(self.clock.clone())
+                            .reverse_callback_priority();
+                        match previous {
+                            Some(previous) => gesture_recognizer.with_touches_from(previous),
+                            None => gesture_recognizer,
+                        }
+                    });
                 }
                 MainEvent::SetDraw(draw) => {
+                    self.view = None;
                     self.draw = draw;
                     if let Some(draw) = &self.draw {
                         self.render_tx
@@ -331,6 +891,37 @@ impl MainLoop {
                             .unwrap();
                     }
                 }
+                MainEvent::ShowView(view) => {
+                    // Lists are reloaded on open, so start them from the top
+                    if view == View::PackageStore {
+                        self.state.remove(STORE_PAGE);
+                    }
+                    if view == View::Notifications {
+                        self.state.remove(NOTIFICATIONS_SCROLL);
+                    }
+                    if view == View::Wifi {
+                        reset_wifi(&self.state);
+                    }
+                    if view == View::Clock {
+                        reset_clock_settings(&self.state);
+                    }
+                    if view == View::Calibration {
+                        reset_calibration(&self.state);
+                    }
+                    // Typing in the tray opens the palette with what was typed, otherwise
+                    // it's left empty for next time
+                    if view != View::Palette {
+                        reset_palette(&self.state);
+                    }
+
+                    if let Some(draw) = self.views.get(&view) {
+                        self.view = Some(view);
+                        self.draw = Some(draw.clone());
+                        self.render_tx
+                            .send(RenderEvent::execute_boxed(draw, true))
+                            .unwrap();
+                    }
+                }
                 MainEvent::Redraw => {
                     if let Some(draw) = &self.draw {
                         self.render_tx
@@ -338,88 +929,322 @@ impl MainLoop {
                             .unwrap();
                     }
                 }
+                MainEvent::Resumed => {
+                    println!("Resynchronizing after resume");
+                    run_hooks(HookEvent::Resume);
+
+                    // Re-scan /proc, dropping pidfiles for processes that died while asleep
+                    if let Err(e) = self.drafts.draft_procs() {
+                        println!("Failed to re-scan draft processes: {e:}");
+                    }
+
+                    // Input grabs may not survive sleep, so re-establish them
+                    self.input_handles.broadcast(InputCommand::Ungrab).ok();
+                    self.input_handles.broadcast(InputCommand::Grab).ok();
+
+                    // The renderer may already be gone if we slept mid-exit
+                    if let Some(draw) = &self.draw {
+                        self.render_tx
+                            .send(RenderEvent::execute_boxed(draw, true))
+                            .ok();
+                    }
+                }
+                MainEvent::InputHotplug => {
+                    self.input_handles.respawn_lost();
+                    self.keyboards.scan();
+                }
+                MainEvent::SetFocusMap(focus) => {
+                    // Carry focus over to the same rect, redrawing its ring since the frame was replaced
+                    let focused_rect = self
+                        .focused
+                        .and_then(|focused| self.focus.get(focused))
+                        .map(|target| target.rect);
+                    self.focus = focus;
+                    self.focused = focused_rect.and_then(|rect| self.focus.find(&rect));
+
+                    if let Some(rect) = focused_rect.filter(|_| self.focused.is_some()) {
+                        self.render_tx
+                            .send(RenderEvent::execute(focus_ring(rect, Color::BLACK), false))
+                            .ok();
+                    }
+                }
+                MainEvent::Key(key) => {
+                    input_received();
+                    if self.view == Some(View::Palette) {
+                        palette_key(key, &self.drafts, &self.event_tx, &self.state);
+                        continue;
+                    }
+                    if let Some(c) = key_char(key).filter(|_| self.view == Some(View::Tray)) {
+                        self.state.set(PALETTE_QUERY, c.to_string());
+                        self.event_tx
+                            .send(MainEvent::ShowView(View::Palette))
+                            .unwrap();
+                        continue;
+                    }
+
+                    let direction = match key {
+                        Key::KEY_LEFT => Direction::Left,
+                        Key::KEY_RIGHT => Direction::Right,
+                        Key::KEY_UP => Direction::Up,
+                        Key::KEY_DOWN => Direction::Down,
+                        Key::KEY_ENTER => {
+                            if let Some(target) =
+                                self.focused.and_then(|focused| self.focus.get(focused))
+                            {
+                                target.activate();
+                            }
+                            continue;
+                        }
+                        _ => continue,
+                    };
+
+                    let focused = match self.focus.neighbour(self.focused, direction) {
+                        Some(focused) => focused,
+                        None => continue,
+                    };
+
+                    // Move the ring with partial refreshes rather than redrawing the whole tray
+                    if let Some(previous) = self.focused.and_then(|i| self.focus.get(i)) {
+                        self.render_tx
+                            .send(RenderEvent::execute(
+                                focus_ring(previous.rect, Color::WHITE),
+                                false,
+                            ))
+                            .unwrap();
+                    }
+
+                    let rect = self.focus.get(focused).unwrap().rect;
+                    self.render_tx
+                        .send(RenderEvent::execute(focus_ring(rect, Color::BLACK), false))
+                        .unwrap();
+
+                    self.focused = Some(focused);
+                }
                 MainEvent::Input(input) => match input {
                     InputEvent::MultitouchEvent { event } => {
-                        if let Some(gesture_recognizer) = &mut self.gesture_recognizer {
-                            match event {
-                                MultitouchEvent::Press { finger } => {
-                                    gesture_recognizer.finger_press(finger);
-                                }
-                                MultitouchEvent::Release { finger } => {
-                                    gesture_recognizer.finger_release(finger);
-                                }
-                                MultitouchEvent::Move { finger } => {
-                                    gesture_recognizer.finger_move(finger);
-                                }
-                                _ => (),
+                        let (event_type, finger) = match event {
+                            MultitouchEvent::Press { finger } => {
+                                input_received();
+                                (EventType::Press, finger)
+                            }
+                            MultitouchEvent::Release { finger } => (EventType::Release, finger),
+                            MultitouchEvent::Move { finger } => (EventType::Move, finger),
+                            _ => continue,
+                        };
+                        let touch = touch_transform().apply(finger.into());
+                        for (event_type, touch) in self.touch_filter.filter(event_type, touch) {
+                            if let Some(gesture_recognizer) = &mut self.gesture_recognizer {
+                                gesture_recognizer.finger_event(event_type, touch);
                             }
                         }
                     }
                     _ => (),
                 },
+                MainEvent::SetBrightness(brightness) => {
+                    set_brightness(brightness);
+
+                    if let Some(draft) = self.stopped_drafts.first() {
+                        self.draft_brightness.insert(draft.id(), brightness);
+                        let key = format!("brightness.{}", draft.id());
+                        if let Err(e) = update_config(&key, &brightness.to_string()) {
+                            println!("Failed to save brightness: {e:}");
+                        }
+                    }
+                }
+                MainEvent::RestoreScreen(path) => {
+                    self.capture.wait(&path);
+                    match load_screenshot(&path) {
+                        Ok(screenshot) => self
+                            .render_tx
+                            .send(RenderEvent::execute(
+                                set_rect(DISPLAY_RECT)
+                                    .then(restore_region(screenshot))
+                                    .then(full_refresh()),
+                                false,
+                            ))
+                            .unwrap(),
+                        Err(e) => println!("Warning: Can't restore screenshot {path:?}: {e:}"),
+                    }
+                }
+                MainEvent::Screenshot => {
+                    // Put back what the tray covers first, so that's what gets saved
+                    let (path, rect) = match self
+                        .stopped_drafts
+                        .first()
+                        .and_then(|draft| self.session.screenshots.get(&draft.id()))
+                    {
+                        Some(path) => (path.clone(), DISPLAY_RECT),
+                        None => (path_temp_screenshot("panel"), panel_rect()),
+                    };
+                    self.capture.wait(&path);
+                    match load_screenshot(&path) {
+                        Ok(screenshot) => self
+                            .render_tx
+                            .send(RenderEvent::execute(
+                                set_rect(rect).then(restore_region(screenshot)),
+                                false,
+                            ))
+                            .unwrap(),
+                        Err(e) => println!("Warning: Can't restore screenshot {path:?}: {e:}"),
+                    }
+
+                    self.render_tx
+                        .send(RenderEvent::execute(
+                            set_rect(DISPLAY_RECT)
+                                .then(dump_png(screenshot_path(), self.capture.clone())),
+                            false,
+                        ))
+                        .unwrap();
+                }
+                MainEvent::PanPanel(pan, action) => {
+                    if self.panel_pan.update(pan, &self.capture, &self.render_tx) {
+                        println!("Panned the panel away");
+                        run_tray_action(action, &self.event_tx, None);
+                    }
+                }
+                MainEvent::Captured(path, saved) => {
+                    println!(
+                        "Screenshot {path:?} {}",
+                        if saved { "saved" } else { "failed" }
+                    );
+                }
                 MainEvent::Run(draft) => {
-                    // Restart stopped draft program if it's still running
-                    match self.drafts.run_draft_program(&draft) {
-                        RunType::Continue => {
-                            if let Some(stopped_draft) = self.stopped_drafts.get(0) {
-                                if stopped_draft.call == draft.call {
-                                    println!(
-                                        "No application switch, restoring partial framebuffer..."
-                                    );
-                                    let path = path_temp_screenshot("panel");
-                                    if let Ok(panel_screenshot) = std::fs::read(path) {
-                                        self.render_tx
-                                            .send(RenderEvent::execute(
-                                                set_rect(PANEL_RECT)
-                                                    .then(restore_region(panel_screenshot))
-                                                    .then(partial_refresh()),
-                                                false,
-                                            ))
-                                            .unwrap();
-                                    } else {
-                                        println!("Warning: No full screenshot for continued draft, clearing framebuffer...");
-                                        self.render_tx
-                                            .send(RenderEvent::execute(
-                                                clear().then(full_refresh()),
-                                                false,
-                                            ))
-                                            .unwrap();
-                                    }
-
-                                    continue;
-                                }
-                            }
+                    // Restore the frontlight level last used with this draft
+                    if let Some(brightness) = self.draft_brightness.get(&draft.id()) {
+                        set_brightness(*brightness);
+                    }
 
-                            println!("Application switched, restoring full framebuffer...");
-                            let path = path_temp_screenshot(draft.file_name().unwrap());
-                            if let Ok(full_screenshot) = std::fs::read(path) {
-                                self.render_tx
-                                    .send(RenderEvent::execute(
-                                        set_rect(DISPLAY_RECT)
-                                            .then(restore_region(full_screenshot))
-                                            .then(full_refresh()),
-                                        false,
-                                    ))
-                                    .unwrap();
-                            } else {
-                                println!("Warning: No full screenshot for continued draft, clearing framebuffer...");
-                                self.render_tx
-                                    .send(RenderEvent::execute(clear().then(full_refresh()), false))
-                                    .unwrap();
-                            }
+                    // Drafts being switched away from stay stopped, unless set to be killed
+                    for previous in &self.stopped_drafts {
+                        if previous.id() != draft.id()
+                            && previous.on_switch.unwrap_or(self.on_switch) == OnSwitch::Kill
+                        {
+                            kill_switched_draft(&self.drafts, previous);
+                        }
+                    }
+
+                    let resumed = self.drafts.stopped_draft(&draft.id()).is_some();
+                    run_hooks(HookEvent::AppLaunch {
+                        draft: draft.name.clone(),
+                        resumed,
+                    });
+
+                    let id = draft.id();
+                    if self.session.foreground.as_ref() != Some(&id) {
+                        self.session.previous = self.session.foreground.take();
+                    }
+                    self.session.foreground = Some(id.clone());
+                    self.session.stopped = self
+                        .drafts
+                        .stopped_draft_ids()
+                        .into_iter()
+                        .filter(|stopped| *stopped != id)
+                        .collect();
+                    if let Err(e) = self.session.save() {
+                        println!("Failed to save session: {e:}");
+                    }
+
+                    if !resumed {
+                        count(Counter::Launches, 1);
+                        // Launched drafts draw over whatever is left, so there's nothing to hand off
+                        self.drafts.run_draft_program(&draft);
+                        continue;
+                    }
+
+                    // Put back what the draft last drew, and only continue it once that
+                    // refresh and any still in flight have landed, so the two can't tear
+                    let switched = self
+                        .stopped_drafts
+                        .first()
+                        .is_none_or(|stopped_draft| stopped_draft.call != draft.call);
+                    let (path, rect) = if switched {
+                        println!("Application switched, restoring full framebuffer...");
+                        let path = self
+                            .session
+                            .screenshots
+                            .get(&draft.id())
+                            .cloned()
+                            .unwrap_or_else(|| path_temp_screenshot(draft.file_name().unwrap()));
+                        (path, DISPLAY_RECT)
+                    } else {
+                        println!("No application switch, restoring partial framebuffer...");
+                        (path_temp_screenshot("panel"), panel_rect())
+                    };
+
+                    let covered = match self.view {
+                        Some(View::Tray) => Some(panel_rect()),
+                        Some(View::QuickBar) => Some(quick_bar_rect()),
+                        _ => None,
+                    };
+
+                    self.capture.wait(&path);
+                    let event = match load_screenshot(&path) {
+                        Ok(screenshot) if switched => RenderEvent::execute(
+                            set_rect(rect)
+                                .then(restore_region(screenshot))
+                                .then(full_refresh_wait()),
+                            false,
+                        ),
+                        Ok(screenshot) => {
+                            // Dismissing the tray or quick bar over the same draft slides it away.
+                            // Both span the panel's width from its bottom edge, so the screenshot's
+                            // last rows are what's behind them.
+                            let slide = covered.map(|covered| {
+                                let row = screenshot.len() / rect.height.max(1) as usize;
+                                let skip = (covered.top - rect.top) as usize * row;
+                                set_rect(covered).then(slide_out(screenshot[skip..].to_vec()))
+                            });
+                            RenderEvent::execute(
+                                (move |ctx: DrawContext| match &slide {
+                                    Some(slide) => slide.draw(ctx),
+                                    None => ctx,
+                                })
+                                .then(set_rect(rect))
+                                .then(restore_region(screenshot))
+                                .then(partial_refresh_wait()),
+                                false,
+                            )
+                        }
+                        Err(e) => {
+                            println!("Warning: Can't restore screenshot for continued draft ({e:}), clearing framebuffer...");
+                            RenderEvent::execute(clear().then(full_refresh_wait()), false)
+                        }
+                    };
+                    self.render_tx.send(event).unwrap();
+                    wait_for_refresh_completion(&self.render_tx, None);
+                    if let RunType::Continue = self.drafts.run_draft_program(&draft) {
+                        check_repaint(&self.render_tx, &draft);
+                    }
+                }
+                MainEvent::ProcessExited(id) => {
+                    timed(Metric::ProcScan, || self.drafts.refresh_procs());
+                    if let Some(draft) = self.drafts.drafts().get(&id) {
+                        println!("Draft {:?} exited", draft.name);
+                        self.session.push_recent(&draft.name);
+                        *self.recent.lock().unwrap() = self.session.recent.clone();
+                        if let Err(e) = self.session.save() {
+                            println!("Failed to save session: {e:}");
                         }
-                        _ => (),
+                    }
+                    if let Some(draw) = &self.draw {
+                        self.render_tx
+                            .send(RenderEvent::execute_boxed(draw, true))
+                            .ok();
+                    }
+                }
+                MainEvent::Closed(name) => {
+                    self.session.push_recent(&name);
+                    *self.recent.lock().unwrap() = self.session.recent.clone();
+                    if let Err(e) = self.session.save() {
+                        println!("Failed to save session: {e:}");
                     }
                 }
                 MainEvent::StopInput => {
                     println!("Stopping input");
 
-                    println!("Ungrabbing input devices");
-                    self.input_handles.broadcast(InputCommand::Ungrab).unwrap();
-
-                    println!("Clearing event queues");
+                    println!("Draining event queues and ungrabbing input devices");
                     self.input_handles
-                        .broadcast(InputCommand::ClearBuffer)
+                        .broadcast(InputCommand::DrainQueue)
                         .unwrap();
 
                     println!("Stopping input threads");
@@ -437,6 +1262,8 @@ impl MainLoop {
                 }
                 MainEvent::Exit => {
                     println!("tray exiting");
+                    self.capture.flush();
+                    flush_metrics();
                     break;
                 }
             }
@@ -444,9 +1271,43 @@ impl MainLoop {
     }
 }
 
+/// Close the tray, resuming the given draft
+pub fn exit_to(event_tx: &Sender<MainEvent>, draft: Option<Draft>) {
+    if let Some(draft) = draft.as_ref().filter(|draft| needs_pin(draft)) {
+        println!("{:?} is protected, asking for the PIN", draft.name);
+        event_tx
+            .send(MainEvent::set_draw(Some(pin_prompt(
+                event_tx.clone(),
+                draft.clone(),
+            ))))
+            .unwrap();
+        return;
+    }
+
+    event_tx.send(MainEvent::StopInput).unwrap();
+    if let Some(draft) = draft {
+        event_tx.send(MainEvent::Run(Box::new(draft))).unwrap();
+    }
+    event_tx.send(MainEvent::StopRenderer).unwrap();
+    event_tx.send(MainEvent::Exit).unwrap();
+}
+
+/// Partial refresh using the waveform chosen by the current refresh policy
 pub fn partial_refresh() -> impl DrawFn {
-    crate::ui::partial_refresh(
-        PartialRefreshMode::Async,
+    move |ctx: DrawContext| {
+        crate::ui::partial_refresh(
+            PartialRefreshMode::Async,
+            partial_waveform(),
+            DisplayTemp::TEMP_USE_REMARKABLE_DRAW,
+            DitherMode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+            0,
+            false,
+        )(ctx)
+    }
+}
+
+pub fn full_refresh() -> impl DrawFn {
+    crate::ui::full_refresh(
         WaveformMode::WAVEFORM_MODE_GC16_FAST,
         DisplayTemp::TEMP_USE_REMARKABLE_DRAW,
         DitherMode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
@@ -455,116 +1316,264 @@ pub fn partial_refresh() -> impl DrawFn {
     )
 }
 
-pub fn full_refresh() -> impl DrawFn {
+/// Partial refresh that blocks until the panel has finished updating
+pub fn partial_refresh_wait() -> impl DrawFn {
+    move |ctx: DrawContext| {
+        crate::ui::partial_refresh(
+            PartialRefreshMode::Wait,
+            partial_waveform(),
+            DisplayTemp::TEMP_USE_REMARKABLE_DRAW,
+            DitherMode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+            0,
+            false,
+        )(ctx)
+    }
+}
+
+/// Full refresh that blocks until the panel has finished updating
+pub fn full_refresh_wait() -> impl DrawFn {
     crate::ui::full_refresh(
         WaveformMode::WAVEFORM_MODE_GC16_FAST,
         DisplayTemp::TEMP_USE_REMARKABLE_DRAW,
         DitherMode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
         0,
-        false,
+        true,
     )
 }
 
 pub fn tray(
     event_tx: Sender<MainEvent>,
     drafts: Arc<DraftPrograms>,
-    stopped_draft: Option<Draft>,
+    store: Arc<PackageStore>,
+    widgets: Widgets,
+    recent: Recent,
+    pie: bool,
 ) -> impl DrawFn + Clone {
     move |ctx: DrawContext| {
-        unit()
+        // Snapshot process state once for all widgets drawn this frame
+        timed(Metric::ProcScan, || drafts.refresh_procs());
+
+        let tap_outside = binding("tray.tapOutside").map(|action| {
+            let event_tx = event_tx.clone();
+            move |_| {
+                println!("Tapped outside the panel");
+                run_tray_action(action, &event_tx, None);
+            }
+        });
+        // Acting on press would swallow the hold that summons the pie menu
+        let tap_outside_gesture = move |ctx: DrawContext| match (tap_outside.clone(), pie) {
+            (None, _) => ctx,
+            (Some(callback), true) => {
+                recognize_gesture(gesture::recognize_tap(layout().tap_hysteresis, callback))(ctx)
+            }
+            (Some(callback), false) => recognize_gesture(gesture::recognize_press(callback))(ctx),
+        };
+
+        let tray = unit()
             .overlay(
                 unit()
-                    .then(margin_bottom(PANEL_HEIGHT))
-                    .then(recognize_gesture(gesture::recognize_press({
-                        let event_tx = event_tx.clone();
-                        let stopped_draft = stopped_draft.clone();
-                        move |_| {
-                            println!("Tapped, exiting");
-                            event_tx.send(MainEvent::StopInput).unwrap();
-                            if let Some(draft) = &stopped_draft {
-                                event_tx.send(MainEvent::Run(draft.clone())).unwrap();
-                            }
-                            event_tx.send(MainEvent::StopRenderer).unwrap();
-                            event_tx.send(MainEvent::Exit).unwrap();
-                        }
-                    }))),
+                    .then(margin_bottom(panel_height()))
+                    .then(tap_outside_gesture),
             )
             .overlay(
                 unit()
-                    .then(margin_top(DISPLAY_HEIGHT as i32 - PANEL_HEIGHT))
-                    .then(drafts_panel(
-                        event_tx.clone(),
-                        drafts.clone(),
-                        stopped_draft.clone(),
-                    )),
+                    .then(margin_top(DISPLAY_HEIGHT as i32 - panel_height()))
+                    .then(drafts_panel(event_tx.clone(), drafts.clone())),
             )
-            .draw(ctx)
+            .overlay(store_button(event_tx.clone(), store.clone()))
+            .overlay(when(has_home(), home_button(event_tx.clone())))
+            .overlay(settings_button(event_tx.clone()))
+            .overlay(network_button(event_tx.clone()))
+            .overlay(widget::widgets(widgets.clone()))
+            .overlay(status_bar())
+            .overlay(recent_strip(
+                event_tx.clone(),
+                drafts.clone(),
+                recent.clone(),
+            ))
+            .then({
+                let event_tx = event_tx.clone();
+                move |mut ctx: DrawContext| {
+                    for (fingers, action) in tray_taps() {
+                        let event_tx = event_tx.clone();
+                        ctx = recognize_multi_tap(fingers, layout().tap_hysteresis, move || {
+                            run_tray_action(action, &event_tx, None)
+                        })(ctx);
+                    }
+                    ctx
+                }
+            });
+
+        let ctx = slide_in("tray.slide", panel_rect(), move |ctx| tray.draw(ctx))(ctx);
+        match pie {
+            true => pie_menu(event_tx.clone(), drafts.clone(), recent.clone())(ctx),
+            false => ctx,
+        }
     }
 }
 
-/// Draw an icon panel for the provided set of draft programs
-pub fn drafts_panel<'a>(
-    event_tx: Sender<MainEvent>,
-    drafts: Arc<DraftPrograms>,
-    stopped_draft: Option<Draft>,
+/// Row along the top of the panel, left of its buttons, with storage and sync status
+pub fn status_bar() -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let spacing = layout.focus_margin * 4;
+        let panel = panel_rect();
+        let slots = if has_home() { 4 } else { 3 };
+        let buttons = (layout.close_button_size + spacing) * slots;
+
+        let items: [Flexible<Box<dyn DrawFn>>; 2] = [
+            expand(Box::new(storage_indicator())),
+            expand(Box::new(when(syncing(), sync_indicator()))),
+        ];
+        let ctx = set_rect(MxcfbRect {
+            left: panel.left + spacing as u32,
+            top: panel.top + spacing as u32,
+            width: (panel.width as i32 - buttons - spacing * 2).max(0) as u32,
+            height: layout.close_button_size as u32,
+        })
+        .then(flex_row(spacing, &items))
+        .draw(ctx);
+        ctx
+    }
+}
+
+/// Square button in the top-right corner of the panel, with higher slots further left
+pub fn panel_button(
+    slot: i32,
+    callback: impl Fn() + Clone + Send + Sync + 'static,
+    glyph: impl Draw,
+) -> impl Draw {
+    let layout = layout();
+    let size = layout.close_button_size;
+    let spacing = layout.focus_margin * 4;
+    let panel = panel_rect();
+
+    set_rect(MxcfbRect {
+        left: (panel.left as i32 + panel.width as i32 - (size + spacing) * (slot + 1)) as u32,
+        top: panel.top + spacing as u32,
+        width: size as u32,
+        height: size as u32,
+    })
+    .then(recognize_gesture(gesture::recognize_tap(
+        layout.tap_hysteresis,
+        {
+            let callback = callback.clone();
+            move |_| callback()
+        },
+    )))
+    .then(focusable(callback))
+    .then(rounded_rect_border(
+        size as u32 / 4,
+        2,
+        Color::WHITE,
+        Color::BLACK,
+        Some(panel_background()),
+    ))
+    .overlay(offset_absolute(Point2::new(0.5, 0.5)).then(glyph))
+    .then(partial_refresh())
+}
+
+/// Tappable single line of text, for list views
+pub fn text_button<'a>(
+    string: &'a str,
+    callback: impl Fn() + Clone + Send + Sync + 'static,
 ) -> impl Draw + 'a {
+    let layout = layout();
+    set_height(layout.line_height as u32)
+        .then(recognize_gesture(gesture::recognize_tap(
+            layout.tap_hysteresis,
+            {
+                let callback = callback.clone();
+                move |_| callback()
+            },
+        )))
+        .then(focusable(callback))
+        .then(offset_relative(Point2::new(0, layout.line_height / 4)))
+        .then(text_aligned(
+            string,
+            layout.font_size,
+            Point2::new(0.0, 0.0),
+            Color::BLACK,
+        ))
+}
+
+/// Draw an icon panel for the provided set of draft programs
+pub fn drafts_panel<'a>(event_tx: Sender<MainEvent>, drafts: Arc<DraftPrograms>) -> impl Draw + 'a {
     unit()
-        .then(recognize_gesture({
+        .then({
             let event_tx = event_tx.clone();
-            gesture::recognize_drag(move |delta| {
-                if delta.y < -TAP_HYSTERESIS {
-                    println!("Swiped, exiting");
-                    event_tx.send(MainEvent::StopInput).unwrap();
-                    if let Some(draft) = &stopped_draft {
-                        event_tx.send(MainEvent::Run(draft.clone())).unwrap();
-                    }
-                    event_tx.send(MainEvent::StopRenderer).unwrap();
-                    event_tx.send(MainEvent::Exit).unwrap();
-
-                    true
-                } else {
-                    false
+            move |mut ctx: DrawContext| {
+                for (fingers, action) in tray_swipes() {
+                    let event_tx = event_tx.clone();
+                    ctx = recognize_pan(fingers, layout().tap_hysteresis, move |pan| {
+                        event_tx.send(MainEvent::PanPanel(pan, action)).unwrap()
+                    })(ctx);
                 }
-            })
-        }))
-        .then(rect_border(2, Color::WHITE, Color::BLACK))
-        .then(margin_horizontal(ROW_MARGIN))
-        .then(margin_top(ROW_MARGIN))
+                ctx
+            }
+        })
+        .then(panel_chrome())
+        .then(margin_horizontal(layout().row_margin))
+        .then(margin_top(layout().row_margin))
         .then(draft_icons(event_tx, drafts))
-        .then(set_rect(PANEL_RECT))
+        .then(set_rect(panel_rect()))
         .then(partial_refresh())
 }
 
 /// Draw a horizontal set of icons for the provided draft programs
 pub fn draft_icons(event_tx: Sender<MainEvent>, drafts: Arc<DraftPrograms>) -> impl DrawFn {
-    move |mut ctx: DrawContext| {
+    move |ctx: DrawContext| {
+        let draft_states = drafts.draft_states();
         let draft_icons = drafts.draft_icons();
         let draft_icons = drafts
-            .drafts()
-            .keys()
-            .map(|key| (drafts.drafts().get(key).unwrap(), draft_icons.get(key)))
-            .map(|(draft, icon)| draft_program(event_tx.clone(), drafts.clone(), draft, icon))
+            .visible_drafts()
+            .map(|(id, draft)| {
+                (
+                    id,
+                    draft,
+                    draft_icons.get(id),
+                    draft_states.get(id).copied(),
+                )
+            })
+            .map(|(id, draft, icon, state)| {
+                // Redraw an icon only when something it shows changes, padded to cover its outline
+                let closable = drafts.cached_procs().contains_key(id);
+                let hung = closable && is_hung(id);
+                let key = (id.clone(), icon.is_some(), state, closable, hung);
+                let program = margin(2).then(draft_program(
+                    event_tx.clone(),
+                    drafts.clone(),
+                    draft,
+                    icon,
+                    state,
+                    hung,
+                ));
+                let cell = margin(-2).then(memo(key, program));
+                move |ctx: DrawContext| cell.draw(ctx)
+            })
             .collect::<Vec<_>>();
 
-        for (i, row) in draft_icons.chunks(COLUMNS).enumerate() {
-            ctx = overlay(
-                offset_relative(Point2::new(0, ROW_HEIGHT * i as i32))
-                    .then(horizontal(ICON_SPACING as i32, row)),
-            )(ctx);
-        }
-
+        let layout = layout();
+        let rows = draft_icons.len().div_ceil(layout.columns);
+        let ctx = set_height((layout.row_height * rows as i32) as u32)
+            .then(grid(
+                layout.columns,
+                Vector2::new(layout.icon_spacing, 0),
+                &draft_icons,
+            ))
+            .draw(ctx);
         ctx
     }
 }
 
-pub fn draft_icon<'a>(icon: Option<&'a ImageBuffer<Rgb<u8>, Vec<u8>>>) -> impl DrawFn + 'a {
+pub fn draft_icon<'a>(icon: Option<&'a ImageBuffer<Rgba<u8>, Vec<u8>>>) -> impl DrawFn + 'a {
     move |ctx: DrawContext| {
         if let Some(icon) = &icon {
             offset_relative(Point2::new(
-                (ICON_SIZE as i32 - icon.width() as i32) / 2,
-                (ICON_SIZE as i32 - icon.height() as i32) / 2,
+                (layout().icon_size - icon.width() as i32) / 2,
+                (layout().icon_size - icon.height() as i32) / 2,
             ))
-            .then(image(icon))
+            .then(image_alpha(icon, panel_background()))
             .draw(ctx)
         } else {
             spinner(16, 4, Color::BLACK).draw(ctx)
@@ -578,46 +1587,41 @@ pub fn close_button(
     draft: Draft,
 ) -> impl DrawFn {
     move |ctx: DrawContext| {
-        if draft_programs
-            .draft_procs()
-            .unwrap()
-            .into_iter()
-            .any(|(candidate, _)| candidate.file_name() == draft.file_name())
-        {
+        if draft_programs.cached_procs().contains_key(&draft.id()) {
             unit()
-                .then(margin_left(ICON_SIZE - 32))
-                .then(margin_bottom(ICON_SIZE - 32))
+                .then(margin_left(layout().icon_size - layout().close_button_size))
+                .then(margin_bottom(
+                    layout().icon_size - layout().close_button_size,
+                ))
                 .then(recognize_gesture({
                     let draft_programs = draft_programs.clone();
                     let draft = draft.clone();
                     let event_tx = event_tx.clone();
-                    gesture::recognize_tap(TAP_HYSTERESIS, move |_| {
-                        if let Some((_, proc)) = draft_programs
-                            .draft_procs()
-                            .unwrap()
-                            .into_iter()
-                            .find(|(candidate, _)| candidate.file_name() == draft.file_name())
-                        {
-                            kill_recursive(&proc);
-                            std::thread::sleep(KILL_SLEEP_DURATION);
-
-                            event_tx.send(MainEvent::Redraw).unwrap();
-                        }
+                    gesture::recognize_tap(layout().tap_hysteresis, move |_| {
+                        close_draft(&event_tx, &draft_programs, &draft)
                     })
                 }))
-                .then(rect_border(2, Color::WHITE, Color::BLACK))
+                .then(rounded_rect_border(
+                    layout().close_button_size as u32 / 4,
+                    2,
+                    Color::WHITE,
+                    Color::BLACK,
+                    Some(panel_background()),
+                ))
                 .then(offset_absolute(Point2::new(0.5, 0.5)))
-                .overlay(line(
+                .overlay(line_smooth(
                     Point2::new(-10, -10),
                     Point2::new(10, 10),
                     3,
                     Color::BLACK,
+                    Color::WHITE,
                 ))
-                .overlay(line(
+                .overlay(line_smooth(
                     Point2::new(10, -10),
                     Point2::new(-10, 10),
                     3,
                     Color::BLACK,
+                    Color::WHITE,
                 ))
                 .draw(ctx)
         } else {
@@ -626,12 +1630,171 @@ pub fn close_button(
     }
 }
 
+/// Marks a draft flagged as hung, tapped to kill and relaunch it
+pub fn restart_button(
+    event_tx: Sender<MainEvent>,
+    draft_programs: Arc<DraftPrograms>,
+    draft: Draft,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        unit()
+            .then(margin_right(
+                layout().icon_size - layout().close_button_size,
+            ))
+            .then(margin_bottom(
+                layout().icon_size - layout().close_button_size,
+            ))
+            .then(recognize_gesture({
+                let draft_programs = draft_programs.clone();
+                let draft = draft.clone();
+                let event_tx = event_tx.clone();
+                gesture::recognize_tap(layout().tap_hysteresis, move |_| {
+                    restart_draft(&event_tx, &draft_programs, &draft)
+                })
+            }))
+            .then(rounded_rect_border(
+                layout().close_button_size as u32 / 4,
+                2,
+                Color::BLACK,
+                Color::BLACK,
+                Some(panel_background()),
+            ))
+            .then(offset_absolute(Point2::new(0.5, 0.5)))
+            .then(text_aligned(
+                "!",
+                layout().font_size,
+                Point2::new(0.5, 0.5),
+                Color::WHITE,
+            ))
+            .draw(ctx)
+    }
+}
+
+/// Kill a draft left behind by switching to another, freeing its memory
+fn kill_switched_draft(draft_programs: &DraftPrograms, draft: &Draft) {
+    let Some((_, proc)) = draft_programs.draft_proc(draft) else {
+        return;
+    };
+
+    println!("Killing {:?} on switching away from it", draft.name);
+    close_process(draft, &proc, false);
+}
+
+/// Kill a hung draft outright and launch it again
+fn restart_draft(event_tx: &Sender<MainEvent>, draft_programs: &Arc<DraftPrograms>, draft: &Draft) {
+    println!("Force restarting hung draft {:?}", draft.name);
+    if let Some((_, proc)) = draft_programs.draft_proc(draft) {
+        close_process(draft, &proc, false);
+    }
+    set_hung(&draft.id(), false);
+    exit_to(event_tx, Some(draft.clone()));
+}
+
+/// Close a draft according to its safeKill policy, prompting first if it asks for confirmation
+fn close_draft(event_tx: &Sender<MainEvent>, draft_programs: &Arc<DraftPrograms>, draft: &Draft) {
+    let (candidate, proc) = match draft_programs.draft_proc(draft) {
+        Some((candidate, proc)) => (candidate.clone(), proc),
+        None => return,
+    };
+
+    if proc.stat.filename == XOCHITL_PROCESS && syncing() {
+        println!("Not closing xochitl while it's syncing");
+        return;
+    }
+
+    let close = {
+        let event_tx = event_tx.clone();
+        let candidate = candidate.clone();
+        move |graceful: bool| {
+            close_process(&candidate, &proc, graceful);
+            event_tx
+                .send(MainEvent::Closed(candidate.name.clone()))
+                .unwrap();
+            event_tx.send(MainEvent::Redraw).unwrap();
+        }
+    };
+
+    match candidate.safe_kill {
+        SafeKill::Kill => close(false),
+        // Waiting on the app to exit would block input, so do it in the background
+        SafeKill::Term => {
+            std::thread::spawn(move || close(true));
+        }
+        SafeKill::Confirm => {
+            let message = tr_args("confirm.close", &[("name", &candidate.name)]);
+            let on_confirm = {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                    let close = close.clone();
+                    std::thread::spawn(move || close(true));
+                }
+            };
+            let on_cancel = {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                }
+            };
+            event_tx
+                .send(MainEvent::set_draw(Some(confirm_dialog(
+                    message, on_confirm, on_cancel,
+                ))))
+                .unwrap();
+        }
+    }
+}
+
+/// Draw a badge in the bottom-left corner of an icon reflecting its process state
+pub fn state_badge(state: Option<DraftState>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let badge = offset_relative(Point2::new(
+            layout.badge_offset,
+            layout.icon_size - layout.badge_offset,
+        ));
+        match state {
+            Some(DraftState::Running) => badge
+                .then(circle_smooth_fill(
+                    layout.badge_radius,
+                    Color::BLACK,
+                    panel_background(),
+                ))
+                .draw(ctx),
+            Some(DraftState::Suspended) => badge
+                .then(circle_smooth_fill(
+                    layout.badge_radius,
+                    Color::WHITE,
+                    panel_background(),
+                ))
+                .then(circle_smooth_stroke(
+                    layout.badge_radius,
+                    2,
+                    Color::BLACK,
+                    panel_background(),
+                ))
+                .draw(ctx),
+            None => ctx,
+        }
+    }
+}
+
+/// Draw a keyboard focus ring around the provided rect, erasing it if drawn in white
+pub fn focus_ring(rect: MxcfbRect, color: Color) -> impl Draw {
+    set_rect(rect)
+        .then(margin(-layout().focus_margin))
+        .then(rect_stroke(layout().focus_stroke, color))
+        .then(partial_refresh())
+}
+
 /// Draw a titled icon
 pub fn draft_program<'a>(
     event_tx: Sender<MainEvent>,
     draft_programs: Arc<DraftPrograms>,
     draft: &'a Draft,
-    icon: Option<&'a ImageBuffer<Rgb<u8>, Vec<u8>>>,
+    icon: Option<&'a ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    state: Option<DraftState>,
+    hung: bool,
 ) -> impl DrawFn + 'a {
     move |mut ctx: DrawContext| {
         let event_tx = event_tx.clone();
@@ -641,30 +1804,83 @@ pub fn draft_program<'a>(
             .name
             .split_ascii_whitespace()
             //.map(|word| text_aligned(word, FONT_SIZE, Point2::new(0.5, 0.0), Color::BLACK))
-            .map(|word| text_aligned(word, FONT_SIZE, Point2::new(0.5, 0.0), Color::BLACK))
+            .map(|word| {
+                text_aligned(
+                    word,
+                    layout().font_size,
+                    Point2::new(0.5, 0.0),
+                    Color::BLACK,
+                )
+            })
             .collect::<Vec<_>>();
 
+        // Shared between tapping the icon and activating it via keyboard focus
+        let launch = {
+            let event_tx = event_tx.clone();
+            let draft = draft.clone();
+            move || {
+                println!("Sending run / exit events");
+                exit_to(&event_tx, Some(draft.clone()));
+            }
+        };
+
+        // Gestures on the icon run the actions bound to them, leaving out an empty launch menu
+        let has_menu = !draft.profiles.is_empty() || has_draft_log(&draft.id()) || state.is_some();
+        let icon_binding = |gesture: &str| {
+            binding(gesture).filter(|action| has_menu || *action != Action::LaunchMenu)
+        };
+        let icon_action = |action: Action| {
+            let event_tx = event_tx.clone();
+            let draft = draft.clone();
+            move |_| run_tray_action(action, &event_tx, Some(&draft))
+        };
+        let layout = layout();
+        let tap = icon_binding("icon.tap")
+            .map(|action| gesture::recognize_tap(layout.tap_hysteresis, icon_action(action)));
+        let long_press = icon_binding("icon.hold").map(|action| {
+            gesture::recognize_long_press(
+                LAUNCH_MENU_HOLD,
+                layout.tap_hysteresis,
+                icon_action(action),
+            )
+        });
+        // Pressing firmly acts without waiting out the hold
+        let firm_press = icon_binding("icon.firmPress").map(|action| {
+            gesture::recognize_firm_press(
+                FIRM_PRESS_PRESSURE,
+                layout.tap_hysteresis,
+                icon_action(action),
+            )
+        });
+        let press_gestures = move |ctx: DrawContext| match (long_press.clone(), firm_press.clone())
+        {
+            (Some(long_press), Some(firm_press)) => {
+                recognize_gesture(gesture::recognize_either(long_press, firm_press))(ctx)
+            }
+            (Some(long_press), None) => recognize_gesture(long_press)(ctx),
+            (None, Some(firm_press)) => recognize_gesture(firm_press)(ctx),
+            (None, None) => ctx,
+        };
+
         // Draw icon
-        ctx = crate::ui::set_width(ICON_SIZE as u32)
+        ctx = crate::ui::set_width(layout.icon_size as u32)
             .overlay(
-                crate::ui::set_height(ICON_SIZE as u32)
-                    .then(crate::ui::recognize_gesture(gesture::recognize_tap(
-                        TAP_HYSTERESIS,
-                        {
-                            let event_tx = event_tx.clone();
-                            let draft = draft.clone();
-                            move |_| {
-                                println!("Sending run / exit events");
-                                event_tx.send(MainEvent::StopInput).unwrap();
-                                event_tx.send(MainEvent::Run(draft.clone())).unwrap();
-                                event_tx.send(MainEvent::StopRenderer).unwrap();
-                                event_tx.send(MainEvent::Exit).unwrap();
-                            }
-                        },
-                    )))
+                crate::ui::aspect_ratio(1.0)
+                    .then(move |ctx: DrawContext| match tap.clone() {
+                        Some(tap) => recognize_gesture(tap)(ctx),
+                        None => ctx,
+                    })
+                    // Registered after the tap, so a hold is checked first
+                    .then(press_gestures)
+                    .then(focusable(launch))
                     .then(margin(-1))
                     .then(rect_stroke(2, Color::BLACK))
                     .overlay(draft_icon(icon))
+                    .overlay(state_badge(state))
+                    .overlay(when(
+                        hung,
+                        restart_button(event_tx.clone(), draft_programs.clone(), draft.clone()),
+                    ))
                     .overlay(close_button(
                         event_tx,
                         draft_programs.clone(),
@@ -672,9 +1888,12 @@ pub fn draft_program<'a>(
                     )),
             )
             .overlay(
-                margin_top(ICON_SIZE as i32 + ICON_SPACING as i32)
-                    .then(offset_relative(Point2::new(ICON_SIZE as i32 / 2, 0)))
-                    .then(vertical_fixed(FONT_SIZE as i32 - 8, &word_strings)),
+                margin_top(layout.icon_size + layout.icon_spacing)
+                    .then(offset_relative(Point2::new(layout.icon_size / 2, 0)))
+                    .then(vertical_fixed(
+                        layout.font_size as i32 - (8.0 * layout.scale) as i32,
+                        &word_strings,
+                    )),
             )
             .draw(ctx);
 
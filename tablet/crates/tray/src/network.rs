@@ -0,0 +1,113 @@
+//! Network info card, with what's needed to reach the device over SSH
+use std::sync::Arc;
+
+use libremarkable::cgmath::Point2;
+use net::{hostname, interface_addresses, tcp_listening, SSH_PORT};
+use shared::locale::{tr, tr_args};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    panel_button, partial_refresh, text_button,
+    ui::{
+        circle_fill, line, margin, margin_left, offset_relative, overlay, rect_border, set_rect,
+        text, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    wifi::WifiPicker,
+    MainEvent, View,
+};
+
+/// Button in the top-right corner of the panel that opens the network card
+pub fn network_button(event_tx: Sender<MainEvent>) -> impl Draw {
+    let size = layout().close_button_size;
+    panel_button(
+        2,
+        move || {
+            event_tx.send(MainEvent::ShowView(View::Network)).ok();
+        },
+        overlay(offset_relative(Point2::new(0, -size / 4)).then(circle_fill(3, Color::BLACK)))
+            .then(line(
+                Point2::new(0, -size / 10),
+                Point2::new(0, size / 4),
+                3,
+                Color::BLACK,
+            )),
+    )
+}
+
+/// Interface names as the user knows them
+fn interface_label(interface: &str) -> String {
+    if interface.starts_with("usb") {
+        tr("network.usb")
+    } else if interface.starts_with("wlan") {
+        tr("network.wifi")
+    } else {
+        interface.to_string()
+    }
+}
+
+/// Full-panel card with the hostname, each interface's address and SSH availability,
+/// and a way into the Wi-Fi picker
+pub fn network_info(event_tx: Sender<MainEvent>, wifi: Arc<WifiPicker>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let back_label = tr("network.back");
+        let wifi_label = tr("network.wifi_networks");
+
+        let addresses = interface_addresses();
+        let mut lines = vec![tr_args(
+            "network.hostname",
+            &[("hostname", &hostname().unwrap_or_default())],
+        )];
+        if addresses.is_empty() {
+            lines.push(tr("network.offline"));
+        }
+        lines.extend(
+            addresses
+                .iter()
+                .map(|(interface, address)| format!("{}: {address:}", interface_label(interface))),
+        );
+        lines.push(match (tcp_listening(SSH_PORT), addresses.first()) {
+            (true, Some((_, address))) => {
+                tr_args("network.ssh", &[("address", &address.to_string())])
+            }
+            (true, None) => tr("network.ssh_offline"),
+            (false, _) => tr("network.ssh_off"),
+        });
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        let header = ctx.rect;
+        ctx = overlay(
+            margin_left(header.width as i32 / 2).then(text_button(&wifi_label, {
+                let wifi = wifi.clone();
+                let event_tx = event_tx.clone();
+                move || {
+                    wifi.scan(event_tx.clone());
+                    event_tx.send(MainEvent::ShowView(View::Wifi)).ok();
+                }
+            })),
+        )(ctx);
+
+        for (i, label) in lines.iter().enumerate() {
+            ctx =
+                overlay(
+                    offset_relative(Point2::new(0, height * (i as i32 + 1) + height / 4))
+                        .then(text(label, layout().font_size, Color::BLACK)),
+                )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -0,0 +1,150 @@
+//! Scalable skinned panels drawn from nine-patch images
+//!
+//! Follows the Android .9.png convention: a one pixel border around the image marks
+//! the stretchable columns along its top edge and rows along its left edge in black,
+//! and is stripped on load. Corners are drawn as-is, edges and the center are stretched.
+use std::{error::Error, path::Path, sync::OnceLock};
+
+use libremarkable::image::{Rgba, RgbaImage};
+
+use crate::{
+    framebuffer::Color,
+    theme::panel_background,
+    ui::{image_alpha, rect_border, Draw, DrawContext, DrawFn},
+};
+
+static PANEL_SKIN: OnceLock<Option<NinePatch>> = OnceLock::new();
+
+pub struct NinePatch {
+    image: RgbaImage,
+    /// Fixed columns on the left and right, and fixed rows on the top and bottom
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+}
+
+impl NinePatch {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error + Send + Sync + 'static>> {
+        let marked = libremarkable::image::open(path)?.into_rgba8();
+        Ok(NinePatch::from_marked(&marked)?)
+    }
+
+    /// Split an image carrying stretch markers in its outer pixel border
+    pub fn from_marked(marked: &RgbaImage) -> Result<Self, &'static str> {
+        let (width, height) = marked.dimensions();
+        if width < 3 || height < 3 {
+            return Err("Nine-patch image is too small");
+        }
+
+        let is_marker = |x: u32, y: u32| *marked.get_pixel(x, y) == Rgba([0, 0, 0, u8::MAX]);
+        let columns = (1..width - 1)
+            .filter(|x| is_marker(*x, 0))
+            .collect::<Vec<_>>();
+        let rows = (1..height - 1)
+            .filter(|y| is_marker(0, *y))
+            .collect::<Vec<_>>();
+        let (first_column, last_column) = columns
+            .first()
+            .zip(columns.last())
+            .ok_or("Nine-patch has no stretchable columns marked")?;
+        let (first_row, last_row) = rows
+            .first()
+            .zip(rows.last())
+            .ok_or("Nine-patch has no stretchable rows marked")?;
+
+        Ok(NinePatch {
+            image: RgbaImage::from_fn(width - 2, height - 2, |x, y| {
+                *marked.get_pixel(x + 1, y + 1)
+            }),
+            left: first_column - 1,
+            right: width - 2 - last_column,
+            top: first_row - 1,
+            bottom: height - 2 - last_row,
+        })
+    }
+
+    /// Render the patch stretched to the given size
+    pub fn render(&self, width: u32, height: u32) -> RgbaImage {
+        let (src_width, src_height) = self.image.dimensions();
+        RgbaImage::from_fn(width, height, |x, y| {
+            *self.image.get_pixel(
+                stretch(x, width, src_width, self.left, self.right),
+                stretch(y, height, src_height, self.top, self.bottom),
+            )
+        })
+    }
+}
+
+/// Map a coordinate along the rendered size back to the source image,
+/// keeping both ends fixed and stretching the middle
+fn stretch(dst: u32, dst_len: u32, src_len: u32, start: u32, end: u32) -> u32 {
+    if dst < start {
+        dst
+    } else if dst >= dst_len.saturating_sub(end) {
+        src_len - (dst_len - dst)
+    } else {
+        let src_middle = src_len - start - end;
+        let dst_middle = dst_len - start - end;
+        start + (dst - start) * src_middle / dst_middle
+    }
+}
+
+/// Draw a nine-patch filling the current rect, blended over a background color
+pub fn nine_patch(patch: &NinePatch, background: Color) -> impl DrawFn + '_ {
+    move |ctx: DrawContext| {
+        let rect = ctx.rect;
+        let rendered = patch.render(rect.width, rect.height);
+        let mut ctx = image_alpha(&rendered, background)(ctx);
+        ctx.rect = rect;
+        ctx
+    }
+}
+
+/// Load the configured panel skin, must be called before the panel is drawn
+pub fn panel_skin_init(path: Option<&Path>) {
+    let skin = path.and_then(|path| match NinePatch::open(path) {
+        Ok(skin) => Some(skin),
+        Err(e) => {
+            println!("Failed to load panel skin {path:?}: {e:}");
+            None
+        }
+    });
+
+    if PANEL_SKIN.set(skin).is_err() {
+        println!("Panel skin already initialized");
+    }
+}
+
+pub fn panel_skin() -> Option<&'static NinePatch> {
+    PANEL_SKIN.get_or_init(|| None).as_ref()
+}
+
+/// Panel background, skinned if one is configured, otherwise a plain bordered rect
+pub fn panel_chrome() -> impl DrawFn {
+    move |ctx: DrawContext| match panel_skin() {
+        Some(skin) => nine_patch(skin, panel_background())(ctx),
+        None => rect_border(2, panel_background(), Color::BLACK).draw(ctx),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretch_keeps_ends_fixed() {
+        // 10px source with 3px fixed on the left and 2px on the right, drawn at 20px
+        let mapped = (0..20)
+            .map(|x| stretch(x, 20, 10, 3, 2))
+            .collect::<Vec<_>>();
+        assert_eq!(&mapped[..3], &[0, 1, 2]);
+        assert_eq!(&mapped[18..], &[8, 9]);
+        assert!(mapped[3..18].iter().all(|x| (3..8).contains(x)));
+        assert!(mapped.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Squashed below the fixed size, the ends still map to the source ends
+        assert_eq!(stretch(0, 4, 10, 3, 2), 0);
+        assert_eq!(stretch(3, 4, 10, 3, 2), 9);
+    }
+}
@@ -0,0 +1,132 @@
+//! Notification history view, for catching up on banners missed while reading or held
+//! back by do not disturb. Dragging the list up or down scrolls it.
+use libremarkable::cgmath::Point2;
+use shared::{
+    clock::utc_offset,
+    locale::{tr, tr_args},
+    notification::{clear_history, history},
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    idle::time_of_day,
+    layout::layout,
+    panel::{panel_height, panel_rect},
+    partial_refresh, text_button,
+    ui::{
+        margin, margin_left, offset_relative, overlay, recognize_gesture, rect_border, set_rect,
+        text, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the first notification shown
+pub const NOTIFICATIONS_SCROLL: &str = "notifications.scroll";
+
+/// Notification rows that fit beneath the header
+fn rows_per_page() -> usize {
+    (((panel_height() - layout().icon_spacing * 2) / layout().line_height) - 1).max(1) as usize
+}
+
+/// Full-panel list of this session's notifications, newest first
+pub fn notification_history(event_tx: Sender<MainEvent>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let history = history();
+        let offset = utc_offset();
+        let max_scroll = history.len().saturating_sub(rows_per_page());
+        // Clamp in case the history was cleared since it was scrolled
+        let scroll = ctx.state.get::<usize>(NOTIFICATIONS_SCROLL).min(max_scroll);
+
+        let labels = history
+            .iter()
+            .skip(scroll)
+            .take(rows_per_page())
+            .map(|(secs, notification)| match notification.body.is_empty() {
+                true => format!(
+                    "{}  {}",
+                    time_of_day(secs.saturating_add_signed(offset)),
+                    notification.title
+                ),
+                false => format!(
+                    "{}  {}: {}",
+                    time_of_day(secs.saturating_add_signed(offset)),
+                    notification.title,
+                    notification.body
+                ),
+            })
+            .collect::<Vec<_>>();
+        let back_label = tr("notifications.back");
+        let clear_label = tr("notifications.clear");
+        let position_label = match history.len() {
+            0 => tr("notifications.empty"),
+            total => tr_args(
+                "notifications.position",
+                &[
+                    ("first", &(scroll + 1).to_string()),
+                    ("last", &(scroll + labels.len()).to_string()),
+                    ("total", &total.to_string()),
+                ],
+            ),
+        };
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        // Scrolling, by as many rows as the finger travelled
+        ctx = overlay(recognize_gesture(gesture::recognize_drag_release({
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move |delta| {
+                let rows = (delta.y / height as f32).round() as isize;
+                if rows == 0 {
+                    return false;
+                }
+
+                let scroll = (scroll as isize + rows).clamp(0, max_scroll as isize);
+                state.set(NOTIFICATIONS_SCROLL, scroll as usize);
+                event_tx.send(MainEvent::Redraw).ok();
+                true
+            }
+        })))(ctx);
+
+        // Header
+        let header = ctx.rect;
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+            }
+        }))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 / 3).then(text_button(&clear_label, {
+                let state = ctx.state.clone();
+                let event_tx = event_tx.clone();
+                move || {
+                    clear_history().ok();
+                    state.remove(NOTIFICATIONS_SCROLL);
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            })),
+        )(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 * 2 / 3)
+                .then(offset_relative(Point2::new(0, height / 4)))
+                .then(text(&position_label, layout().font_size, Color::BLACK)),
+        )(ctx);
+
+        // Notification rows
+        for (i, label) in labels.iter().enumerate() {
+            ctx =
+                overlay(
+                    offset_relative(Point2::new(0, height * (i as i32 + 1) + height / 4))
+                        .then(text(label, layout().font_size, Color::BLACK)),
+                )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -0,0 +1,220 @@
+//! On-screen keyboard, for text entry without a keyboard plugged in
+//!
+//! Entered text lives in widget state under the caller's id, so the field it's typed
+//! into reads it back with `ctx.state.get::<String>(id)`. Shift applies to one key, and
+//! a layer key swaps the letters for symbols.
+use libremarkable::cgmath::Point2;
+use shared::locale::tr;
+
+use crate::{
+    channel::Sender,
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    state::StateStore,
+    ui::{
+        focusable, margin, offset_absolute, overlay, recognize_gesture, rect_border, set_rect,
+        text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent,
+};
+
+const LOWER: [&str; 4] = ["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+const UPPER: [&str; 4] = ["1234567890", "QWERTYUIOP", "ASDFGHJKL", "ZXCVBNM"];
+const SYMBOLS: [&str; 4] = ["!@#$%^&*()", "-_=+[]{}\\|", ";:'\",.<>/?", "`~"];
+
+/// Keys in the widest row, which sets the width of a key
+const ROW_KEYS: u32 = 10;
+const ROWS: u32 = 5;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum Layer {
+    #[default]
+    Lower,
+    Upper,
+    Symbols,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Shift,
+    Backspace,
+    Layer,
+    Space,
+    Done,
+}
+
+impl Key {
+    fn label(self, layer: Layer) -> String {
+        match self {
+            Key::Char(c) => c.to_string(),
+            Key::Shift => tr("osk.shift"),
+            Key::Backspace => tr("osk.backspace"),
+            Key::Layer if layer == Layer::Symbols => tr("osk.letters"),
+            Key::Layer => tr("osk.symbols"),
+            Key::Space => tr("osk.space"),
+            Key::Done => tr("osk.done"),
+        }
+    }
+}
+
+/// Rows of keys for a layer, each with its width in keys
+fn rows(layer: Layer) -> Vec<Vec<(Key, u32)>> {
+    let chars = match layer {
+        Layer::Lower => LOWER,
+        Layer::Upper => UPPER,
+        Layer::Symbols => SYMBOLS,
+    };
+
+    let mut rows = chars
+        .iter()
+        .map(|row| row.chars().map(|c| (Key::Char(c), 1)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    if layer != Layer::Symbols {
+        rows[3].insert(0, (Key::Shift, 1));
+    }
+    rows[3].push((Key::Backspace, 1));
+    rows.push(vec![(Key::Layer, 2), (Key::Space, 6), (Key::Done, 2)]);
+    rows
+}
+
+/// Apply a key to the text and layer, returning whether it finished entry
+fn press(key: Key, text: &mut String, layer: &mut Layer) -> bool {
+    match key {
+        Key::Char(c) => {
+            text.push(c);
+            if *layer == Layer::Upper {
+                *layer = Layer::Lower;
+            }
+        }
+        Key::Space => text.push(' '),
+        Key::Backspace => {
+            text.pop();
+        }
+        Key::Shift => {
+            *layer = match layer {
+                Layer::Lower => Layer::Upper,
+                _ => Layer::Lower,
+            }
+        }
+        Key::Layer => {
+            *layer = match layer {
+                Layer::Symbols => Layer::Lower,
+                _ => Layer::Symbols,
+            }
+        }
+        Key::Done => return true,
+    }
+    false
+}
+
+fn layer_id(id: &str) -> String {
+    format!("{id:}.layer")
+}
+
+/// Forget entered text and go back to lowercase letters
+pub fn clear_keyboard(state: &StateStore, id: &str) {
+    state.remove(id);
+    state.remove(&layer_id(id));
+}
+
+fn key_button(
+    key: Key,
+    label: &str,
+    callback: impl Fn() + Clone + Send + Sync + 'static,
+) -> impl Draw + '_ {
+    let layout = layout();
+    recognize_gesture(gesture::recognize_tap(layout.tap_hysteresis, {
+        let callback = callback.clone();
+        move |_| callback()
+    }))
+    .then(focusable(callback))
+    .then(margin(layout.focus_margin))
+    .then(rect_border(
+        if key == Key::Done { 4 } else { 2 },
+        Color::WHITE,
+        Color::BLACK,
+    ))
+    .then(overlay(offset_absolute(Point2::new(0.5, 0.5)).then(
+        text_aligned(label, layout.font_size, Point2::new(0.5, 0.5), Color::BLACK),
+    )))
+}
+
+/// Keyboard filling the current rect, editing the text held under id and passing
+/// it to on_done when Done is tapped
+pub fn on_screen_keyboard(
+    id: &'static str,
+    event_tx: Sender<MainEvent>,
+    on_done: impl Fn(String) + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let rect = ctx.rect;
+        let layer = ctx.state.get::<Layer>(&layer_id(id));
+        let key_width = rect.width / ROW_KEYS;
+        let key_height = rect.height / ROWS;
+
+        for (row, keys) in rows(layer).into_iter().enumerate() {
+            let row_width = keys.iter().map(|(_, width)| width).sum::<u32>() * key_width;
+            let mut left = rect.left + (rect.width - row_width) / 2;
+
+            for (key, width) in keys {
+                let key_rect = MxcfbRect {
+                    left,
+                    top: rect.top + row as u32 * key_height,
+                    width: width * key_width,
+                    height: key_height,
+                };
+                left += key_rect.width;
+
+                let label = key.label(layer);
+                let callback = {
+                    let state = ctx.state.clone();
+                    let event_tx = event_tx.clone();
+                    let on_done = on_done.clone();
+                    move || {
+                        let mut layer = state.get::<Layer>(&layer_id(id));
+                        let done =
+                            state.update(id, |text: &mut String| press(key, text, &mut layer));
+                        state.set(&layer_id(id), layer);
+                        if done {
+                            on_done(state.get::<String>(id));
+                        }
+                        event_tx.send(MainEvent::Redraw).ok();
+                    }
+                };
+                ctx = overlay(set_rect(key_rect).then(key_button(key, &label, callback)))(ctx);
+            }
+        }
+
+        ctx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_edit_text_and_layers() {
+        let mut text = String::new();
+        let mut layer = Layer::Lower;
+
+        press(Key::Shift, &mut text, &mut layer);
+        press(Key::Char('H'), &mut text, &mut layer);
+        assert_eq!(layer, Layer::Lower);
+        press(Key::Char('i'), &mut text, &mut layer);
+        press(Key::Layer, &mut text, &mut layer);
+        press(Key::Char('!'), &mut text, &mut layer);
+        assert_eq!(layer, Layer::Symbols);
+        press(Key::Space, &mut text, &mut layer);
+        press(Key::Backspace, &mut text, &mut layer);
+        assert_eq!(text, "Hi!");
+        assert!(press(Key::Done, &mut text, &mut layer));
+
+        for layer in [Layer::Lower, Layer::Upper, Layer::Symbols] {
+            assert!(rows(layer)
+                .iter()
+                .all(|row| row.iter().map(|(_, width)| width).sum::<u32>() <= ROW_KEYS));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Command palette, to reach a draft or tray action by typing part of its name
+//!
+//! Typing on a keyboard plugged in while the tray is open brings it up with what was typed,
+//! and the on-screen keyboard beneath the matches covers the rest of the time. Enter or Done
+//! runs the selected match, the arrow keys move the selection and Escape closes it.
+use std::sync::Arc;
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::{cgmath::Point2, evdev::Key};
+use raft::Draft;
+use shared::{
+    action::Action,
+    locale::{tr, tr_args},
+};
+
+use crate::{
+    bindings::run_tray_action,
+    channel::Sender,
+    exit_to,
+    framebuffer::Color,
+    home::has_home,
+    keyboard::key_char,
+    layout::layout,
+    osk::{clear_keyboard, on_screen_keyboard},
+    panel::panel_rect,
+    partial_refresh,
+    state::StateStore,
+    text_button,
+    ui::{
+        margin, margin_left, margin_top, offset_relative, overlay, rect_border, set_rect, text,
+        Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the search text, shared with the on-screen keyboard
+pub const PALETTE_QUERY: &str = "palette.query";
+
+/// Widget state id of the index of the selected match
+const PALETTE_SELECTED: &str = "palette.selected";
+
+/// Matches listed above the keyboard
+const PALETTE_ROWS: usize = 5;
+
+/// Something the palette can run
+#[derive(Debug, Clone)]
+enum Command {
+    Launch(Box<Draft>),
+    Action(Action),
+    Show(View),
+}
+
+impl Command {
+    fn label(&self) -> String {
+        match self {
+            Command::Launch(draft) => draft.name.clone(),
+            Command::Action(Action::Screenshot) => tr("palette.screenshot"),
+            Command::Action(Action::LockInput) => tr("palette.lock"),
+            Command::Action(Action::QuickBar) => tr("palette.quick_bar"),
+            Command::Action(Action::ToggleNightMode) => tr("palette.night_mode"),
+            Command::Action(Action::Home) => tr("palette.home"),
+            Command::Action(action) => action.to_string(),
+            Command::Show(View::Settings) => tr("palette.settings"),
+            Command::Show(View::Network) => tr("palette.network"),
+            Command::Show(View::Notifications) => tr("palette.notifications"),
+            Command::Show(view) => format!("{view:?}"),
+        }
+    }
+
+    fn run(&self, event_tx: &Sender<MainEvent>, state: &StateStore) {
+        reset_palette(state);
+        match self {
+            Command::Launch(draft) => {
+                println!("Launching {:?} from the palette", draft.name);
+                exit_to(event_tx, Some(*draft.clone()));
+            }
+            Command::Action(action) => run_tray_action(*action, event_tx, None),
+            Command::Show(view) => {
+                event_tx.send(MainEvent::ShowView(*view)).ok();
+            }
+        }
+    }
+}
+
+/// Every draft the active profile shows, then the tray's own actions and views
+fn commands(drafts: &DraftPrograms) -> Vec<Command> {
+    let mut commands = drafts
+        .visible_drafts()
+        .map(|(_, draft)| Command::Launch(Box::new(draft.clone())))
+        .collect::<Vec<_>>();
+    commands.extend(
+        [
+            Action::Screenshot,
+            Action::LockInput,
+            Action::QuickBar,
+            Action::ToggleNightMode,
+        ]
+        .map(Command::Action),
+    );
+    if has_home() {
+        commands.push(Command::Action(Action::Home));
+    }
+    commands.extend([View::Settings, View::Network, View::Notifications].map(Command::Show));
+    commands
+}
+
+/// How loosely a label matches the query, lower being closer, or None if it doesn't
+///
+/// Each typed character has to appear in the label in order, and is scored by how far it is
+/// from the character matched before it, so prefixes and runs of letters come first.
+fn fuzzy_score(query: &str, label: &str) -> Option<usize> {
+    let mut label = label.chars().flat_map(char::to_lowercase).enumerate();
+    let mut last = None;
+    let mut score = 0;
+    for wanted in query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+    {
+        let (index, _) = label.find(|(_, c)| *c == wanted)?;
+        score += match last {
+            Some(last) => index - last - 1,
+            None => index,
+        };
+        last = Some(index);
+    }
+    Some(score)
+}
+
+/// Commands matching the query, closest first
+fn palette_matches(drafts: &DraftPrograms, query: &str) -> Vec<Command> {
+    let mut matches = commands(drafts)
+        .into_iter()
+        .filter_map(|command| {
+            let label = command.label();
+            Some((fuzzy_score(query, &label)?, label, command))
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|(a, a_label, _), (b, b_label, _)| a.cmp(b).then(a_label.cmp(b_label)));
+    matches
+        .into_iter()
+        .take(PALETTE_ROWS)
+        .map(|(_, _, command)| command)
+        .collect()
+}
+
+/// Forget the search text and selection
+pub fn reset_palette(state: &StateStore) {
+    clear_keyboard(state, PALETTE_QUERY);
+    state.remove(PALETTE_SELECTED);
+}
+
+fn run_selected(drafts: &DraftPrograms, event_tx: &Sender<MainEvent>, state: &StateStore) {
+    let matches = palette_matches(drafts, &state.get::<String>(PALETTE_QUERY));
+    let selected = state.get::<usize>(PALETTE_SELECTED);
+    if let Some(command) = matches.get(selected).or(matches.last()) {
+        command.run(event_tx, state);
+    }
+}
+
+/// Apply a key typed on a keyboard while the palette is shown
+pub fn palette_key(
+    key: Key,
+    drafts: &DraftPrograms,
+    event_tx: &Sender<MainEvent>,
+    state: &StateStore,
+) {
+    match key {
+        Key::KEY_ESC => {
+            reset_palette(state);
+            event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            return;
+        }
+        Key::KEY_ENTER => {
+            run_selected(drafts, event_tx, state);
+            return;
+        }
+        Key::KEY_UP => state.update(PALETTE_SELECTED, |selected: &mut usize| {
+            *selected = selected.saturating_sub(1)
+        }),
+        Key::KEY_DOWN => state.update(PALETTE_SELECTED, |selected: &mut usize| {
+            *selected = (*selected + 1).min(PALETTE_ROWS - 1)
+        }),
+        Key::KEY_BACKSPACE => {
+            state.update(PALETTE_QUERY, |query: &mut String| query.pop());
+            state.remove(PALETTE_SELECTED);
+        }
+        key => match key_char(key) {
+            Some(c) => {
+                state.update(PALETTE_QUERY, |query: &mut String| query.push(c));
+                state.remove(PALETTE_SELECTED);
+            }
+            None => return,
+        },
+    }
+    event_tx.send(MainEvent::Redraw).ok();
+}
+
+/// Full-panel view of the search text, its closest matches and an on-screen keyboard
+pub fn palette(event_tx: Sender<MainEvent>, drafts: Arc<DraftPrograms>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let query = ctx.state.get::<String>(PALETTE_QUERY);
+        let matches = palette_matches(&drafts, &query);
+        let selected = ctx
+            .state
+            .get::<usize>(PALETTE_SELECTED)
+            .min(matches.len().saturating_sub(1));
+
+        let back_label = tr("palette.back");
+        let field = tr_args("palette.query", &[("query", &query)]);
+        let labels = matches
+            .iter()
+            .enumerate()
+            .map(|(i, command)| {
+                if i == selected {
+                    tr_args("palette.selected", &[("label", &command.label())])
+                } else {
+                    command.label()
+                }
+            })
+            .collect::<Vec<_>>();
+        let empty_label = tr("palette.no_matches");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        // Header
+        let header = ctx.rect;
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            let state = ctx.state.clone();
+            move || {
+                reset_palette(&state);
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 / 4)
+                .then(offset_relative(Point2::new(0, height / 4)))
+                .then(text(&field, layout().font_size, Color::BLACK)),
+        )(ctx);
+
+        if matches.is_empty() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height + height / 4)).then(text(
+                    &empty_label,
+                    layout().font_size,
+                    Color::BLACK,
+                )),
+            )(ctx);
+        }
+
+        for (i, (command, label)) in matches.iter().zip(&labels).enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * (i as i32 + 1))).then(text_button(
+                    label,
+                    {
+                        let event_tx = event_tx.clone();
+                        let state = ctx.state.clone();
+                        let command = command.clone();
+                        move || command.run(&event_tx, &state)
+                    },
+                )),
+            )(ctx);
+        }
+
+        ctx = overlay(
+            margin_top(height * (PALETTE_ROWS as i32 + 1)).then(on_screen_keyboard(
+                PALETTE_QUERY,
+                event_tx.clone(),
+                {
+                    let drafts = drafts.clone();
+                    let event_tx = event_tx.clone();
+                    let state = ctx.state.clone();
+                    move |_| run_selected(&drafts, &event_tx, &state)
+                },
+            )),
+        )(ctx);
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches_in_order() {
+        assert_eq!(fuzzy_score("", "KOReader"), Some(0));
+        assert_eq!(fuzzy_score("kor", "KOReader"), Some(0));
+        assert_eq!(fuzzy_score("krd", "KOReader"), Some(3));
+        assert_eq!(fuzzy_score("rk", "KOReader"), None);
+        assert!(fuzzy_score("scr", "Screenshot") < fuzzy_score("scr", "Lock screen"));
+    }
+}
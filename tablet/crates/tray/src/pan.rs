@@ -0,0 +1,168 @@
+//! Dragging the drafts panel down to dismiss the tray.
+//!
+//! While the fingers are down the panel follows them, composed from a dump of its
+//! pixels over the screenshot taken of what it covers. Letting go past a fraction
+//! of its height completes the dismiss, anywhere short of that snaps it back.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use gesture::Pan;
+use launcher_core::capture::CaptureWorker;
+use shared::{path_temp_screenshot, screenshot::load_screenshot};
+
+use crate::{
+    animation::{compose_slide, frame_interval, slide_refresh},
+    channel::Sender,
+    panel::panel_rect,
+    partial_refresh,
+    render::RenderEvent,
+    ui::{DrawContext, DrawFn},
+};
+
+/// Fraction of the panel's height a pan must travel down to dismiss the tray
+pub const PAN_DISMISS_FRACTION: f32 = 1.0 / 3.0;
+
+/// State id of the panel's pixels, dumped on the first frame of a pan
+const PAN_BUFFER: &str = "pan.buffer";
+
+/// Fraction of a panel of the given height still showing after a pan
+pub fn pan_visible(travel: f32, height: u32) -> f32 {
+    1.0 - (travel / height.max(1) as f32).clamp(0.0, 1.0)
+}
+
+/// Whether a pan released after the given travel dismisses a panel of the given height
+pub fn pan_dismisses(travel: f32, height: u32) -> bool {
+    travel >= height as f32 * PAN_DISMISS_FRACTION
+}
+
+/// A pan of the drafts panel, from its first frame until the fingers lift
+#[derive(Default)]
+pub struct PanelPan {
+    background: Option<Arc<Vec<u8>>>,
+    last_frame: Option<Instant>,
+}
+
+impl PanelPan {
+    /// Follow a pan, sending a frame to the render thread if one's due.
+    /// Returns true once a pan is released far enough to dismiss the tray.
+    pub fn update(
+        &mut self,
+        pan: Pan,
+        capture: &CaptureWorker,
+        render_tx: &Sender<RenderEvent>,
+    ) -> bool {
+        let height = panel_rect().height;
+        match pan {
+            Pan::Moved(travel) => {
+                let Some(interval) = frame_interval() else {
+                    return false;
+                };
+                if self.frame_pending(interval) {
+                    return false;
+                }
+                let Some(background) = self.background(capture) else {
+                    return false;
+                };
+                self.last_frame = Some(Instant::now());
+                render_tx
+                    .send(RenderEvent::execute(
+                        pan_frame(background, pan_visible(travel.y, height)),
+                        false,
+                    ))
+                    .unwrap();
+                false
+            }
+            Pan::Released(travel) => {
+                let dismissed = pan_dismisses(travel.y, height);
+                // Without a background no frames were drawn, so there's nothing to put back
+                if let Some(background) = self.background.take() {
+                    render_tx
+                        .send(RenderEvent::execute(
+                            pan_release(background, dismissed),
+                            false,
+                        ))
+                        .unwrap();
+                }
+                self.last_frame = None;
+                dismissed
+            }
+        }
+    }
+
+    /// Whether the last frame is too recent for another at the animation frame rate
+    fn frame_pending(&self, interval: Duration) -> bool {
+        self.last_frame
+            .is_some_and(|last_frame| last_frame.elapsed() < interval)
+    }
+
+    /// What the panel covers, loaded once per pan from the screenshot taken when it was shown
+    fn background(&mut self, capture: &CaptureWorker) -> Option<Arc<Vec<u8>>> {
+        if self.background.is_none() {
+            let path = path_temp_screenshot("panel");
+            capture.wait(&path);
+            match load_screenshot(&path) {
+                Ok(background) => self.background = Some(Arc::new(background)),
+                Err(e) => println!("Warning: Can't follow pan without {path:?}: {e:}"),
+            }
+        }
+        self.background.clone()
+    }
+}
+
+/// Draw the panel with only the given fraction of it showing above the bottom edge
+fn pan_frame(background: Arc<Vec<u8>>, visible: f32) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let rect = panel_rect();
+        let buffer = match ctx.state.get::<Option<Arc<Vec<u8>>>>(PAN_BUFFER) {
+            Some(buffer) => buffer,
+            None => match ctx.fb.dump_region(rect) {
+                Ok(buffer) => {
+                    let buffer = Arc::new(buffer);
+                    ctx.state.set(PAN_BUFFER, Some(buffer.clone()));
+                    buffer
+                }
+                Err(_) => return ctx,
+            },
+        };
+
+        compose_slide(&mut *ctx.fb, rect, &background, &buffer, visible);
+        let prev = ctx.rect;
+        let ctx = slide_refresh()(DrawContext { rect, ..ctx });
+        DrawContext { rect: prev, ..ctx }
+    }
+}
+
+/// Put the panel back where it was, or uncover what it hid if the pan dismissed it
+fn pan_release(background: Arc<Vec<u8>>, dismissed: bool) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let Some(buffer) = ctx.state.get::<Option<Arc<Vec<u8>>>>(PAN_BUFFER) else {
+            return ctx;
+        };
+        ctx.state.remove(PAN_BUFFER);
+
+        let rect = panel_rect();
+        let visible = if dismissed { 0.0 } else { 1.0 };
+        compose_slide(&mut *ctx.fb, rect, &background, &buffer, visible);
+        let prev = ctx.rect;
+        let ctx = partial_refresh()(DrawContext { rect, ..ctx });
+        DrawContext { rect: prev, ..ctx }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pans_past_threshold_dismiss() {
+        assert_eq!(pan_visible(-20.0, 300), 1.0);
+        assert_eq!(pan_visible(150.0, 300), 0.5);
+        assert_eq!(pan_visible(600.0, 300), 0.0);
+
+        assert!(!pan_dismisses(99.0, 300));
+        assert!(pan_dismisses(100.0, 300));
+    }
+}
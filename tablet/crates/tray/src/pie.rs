@@ -0,0 +1,156 @@
+//! Radial launcher summoned by pressing and holding on the tray
+//!
+//! Pinned drafts are laid out in slices around the finger; dragging out over one and
+//! releasing launches it, while releasing near the center dismisses the menu. The menu
+//! appears on the first move once the hold has elapsed, which the panel's own jitter
+//! supplies in practice.
+use std::{sync::Arc, time::Duration};
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::cgmath::Point2;
+use raft::Draft;
+
+use crate::{
+    channel::Sender,
+    display::{DISPLAY_HEIGHT, DISPLAY_RECT, DISPLAY_WIDTH},
+    draft_icon, exit_to,
+    framebuffer::MxcfbRect,
+    layout::layout,
+    partial_refresh,
+    quick_bar::pinned_drafts,
+    recent::Recent,
+    theme::panel_background,
+    ui::{
+        radial_menu, recognize_gesture, restore_region, set_position, set_rect, Draw, DrawContext,
+        DrawFn, ThenTrait,
+    },
+    user_profile::pinned_apps,
+    MainEvent,
+};
+
+/// Most drafts the menu offers
+pub const PIE_SLICES: usize = 6;
+
+/// How long a press has to be held in place to summon the menu
+pub const PIE_HOLD: Duration = Duration::from_millis(500);
+
+const PIE_MENU: &str = "pie.menu";
+const PIE_UNDER: &str = "pie.under";
+
+/// An open menu, and the slice under the finger
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PieMenu {
+    center: Point2<u16>,
+    hovered: Option<usize>,
+}
+
+fn pie_radius() -> i32 {
+    layout().icon_size * 2
+}
+
+/// Square around the menu, moved inward so the whole disc stays on the display
+fn pie_rect(center: Point2<u16>) -> MxcfbRect {
+    let radius = pie_radius() + 2;
+    let clamp = |v: u16, max: i32| (v as i32).clamp(radius, (max - radius).max(radius));
+    MxcfbRect {
+        left: (clamp(center.x, DISPLAY_WIDTH as i32) - radius) as u32,
+        top: (clamp(center.y, DISPLAY_HEIGHT as i32) - radius) as u32,
+        width: radius as u32 * 2,
+        height: radius as u32 * 2,
+    }
+}
+
+pub fn pie_menu(
+    event_tx: Sender<MainEvent>,
+    drafts: Arc<DraftPrograms>,
+    recent: Recent,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let choices = pinned_drafts(&pinned_apps(), &drafts, &recent, PIE_SLICES);
+
+        let hover = {
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move |center, hovered| {
+                let menu = Some(PieMenu { center, hovered });
+                if state.get::<Option<PieMenu>>(PIE_MENU) != menu {
+                    state.set(PIE_MENU, menu);
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            }
+        };
+        let select = {
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            let choices = choices.clone();
+            move |slice: Option<usize>| {
+                state.set(PIE_MENU, None::<PieMenu>);
+                // Close the menu first, so what was beneath it is back before the tray goes
+                event_tx.send(MainEvent::Redraw).ok();
+                if let Some(draft) = slice.and_then(|slice| choices.get(slice)) {
+                    println!("Launching {:?} from the pie menu", draft.name);
+                    exit_to(&event_tx, Some(draft.clone()));
+                }
+            }
+        };
+
+        let ctx = set_rect(DISPLAY_RECT)
+            .then(recognize_gesture(gesture::recognize_radial(
+                PIE_HOLD,
+                layout.tap_hysteresis,
+                choices.len(),
+                pie_radius() as f32 * 0.25,
+                hover,
+                select,
+            )))
+            .draw(ctx);
+
+        let under = ctx
+            .state
+            .get::<Option<(MxcfbRect, Arc<Vec<u8>>)>>(PIE_UNDER);
+        match ctx.state.get::<Option<PieMenu>>(PIE_MENU) {
+            Some(menu) => {
+                let rect = pie_rect(menu.center);
+                if under.is_none() {
+                    let pixels = Arc::new(ctx.fb.dump_region(rect).unwrap());
+                    ctx.state.set(PIE_UNDER, Some((rect, pixels)));
+                }
+
+                let draft_icons = drafts.draft_icons();
+                let icons = choices
+                    .iter()
+                    .map(|draft: &Draft| {
+                        let icon = draft_icons.get(&draft.id()).cloned();
+                        move |ctx: DrawContext| draft_icon(icon.as_ref())(ctx)
+                    })
+                    .collect::<Vec<_>>();
+                drop(draft_icons);
+
+                let radius = pie_radius();
+                let menu_draw =
+                    set_position(rect.left + radius as u32 + 2, rect.top + radius as u32 + 2)
+                        .then(radial_menu(
+                            radius as u32,
+                            layout.icon_size as u32,
+                            panel_background(),
+                            &icons,
+                            menu.hovered,
+                        ))
+                        .then(set_rect(rect))
+                        .then(partial_refresh());
+                menu_draw.draw(ctx)
+            }
+            None => match under {
+                Some((rect, pixels)) => {
+                    ctx.state.remove(PIE_UNDER);
+                    let restore = set_rect(rect)
+                        .then(restore_region(pixels.to_vec()))
+                        .then(partial_refresh());
+                    restore.draw(ctx)
+                }
+                None => ctx,
+            },
+        }
+    }
+}
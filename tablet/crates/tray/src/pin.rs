@@ -0,0 +1,255 @@
+//! Launch PIN, gating drafts listed in protectedApps behind a numeric pad
+//!
+//! Returning to the draft the tray was opened over is never gated, nor is a draft once its
+//! PIN has been entered in this tray. Repeated wrong entries lock the pad for a while.
+use std::{
+    collections::BTreeSet,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use libremarkable::cgmath::{Point2, Vector2};
+use raft::Draft;
+use shared::{
+    config::Config,
+    locale::{tr, tr_args},
+};
+
+use crate::{
+    channel::Sender,
+    exit_to,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    partial_refresh,
+    state::StateStore,
+    text_button,
+    ui::{
+        focusable, grid_cells, margin, margin_top, offset_absolute, offset_relative, overlay,
+        recognize_gesture, rect_border, set_rect, text_aligned, Draw, DrawContext, DrawFn,
+        ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the digits entered so far
+pub const PIN_ENTRY: &str = "pin.entry";
+
+/// Widget state id of the message shown above the pad after a wrong entry
+const PIN_MESSAGE: &str = "pin.message";
+
+/// Wrong entries allowed before the pad locks
+const PIN_ATTEMPTS: usize = 5;
+
+/// How long the pad stays locked after too many wrong entries
+const PIN_LOCKOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+struct PinGate {
+    pin: Option<String>,
+    protected: Vec<String>,
+    /// Drafts switching to which skips the PIN, by name
+    unlocked: BTreeSet<String>,
+    failures: usize,
+    locked_until: Option<Instant>,
+}
+
+static GATE: Mutex<Option<PinGate>> = Mutex::new(None);
+
+/// Read protected drafts from the config, letting the draft the tray opened over through
+pub fn pin_init(config: &Config, stopped_draft: Option<&Draft>) {
+    *GATE.lock().unwrap() = Some(PinGate {
+        pin: config.launch_pin.clone(),
+        protected: config.protected_apps.clone(),
+        unlocked: stopped_draft
+            .map(|draft| draft.name.clone())
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    });
+}
+
+/// Whether switching to a draft needs the PIN first
+pub fn needs_pin(draft: &Draft) -> bool {
+    GATE.lock().unwrap().as_ref().is_some_and(|gate| {
+        gate.pin.is_some()
+            && gate.protected.contains(&draft.name)
+            && !gate.unlocked.contains(&draft.name)
+    })
+}
+
+/// Seconds left before the pad takes entries again, None if it isn't locked
+fn lockout_remaining() -> Option<u64> {
+    let gate = GATE.lock().unwrap();
+    let remaining = gate
+        .as_ref()?
+        .locked_until?
+        .checked_duration_since(Instant::now())?;
+    Some(remaining.as_secs() + 1)
+}
+
+/// Check a complete entry, unlocking the draft if it matches
+fn check_pin(draft: &Draft, entry: &str) -> bool {
+    let mut gate = GATE.lock().unwrap();
+    let Some(gate) = gate.as_mut() else {
+        return false;
+    };
+
+    if gate.pin.as_deref() == Some(entry) {
+        gate.failures = 0;
+        gate.unlocked.insert(draft.name.clone());
+        return true;
+    }
+
+    gate.failures += 1;
+    println!("Wrong PIN for {:?}, {} tries", draft.name, gate.failures);
+    if gate.failures >= PIN_ATTEMPTS {
+        gate.failures = 0;
+        gate.locked_until = Some(Instant::now() + PIN_LOCKOUT);
+    }
+    false
+}
+
+fn pin_length() -> usize {
+    GATE.lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|gate| gate.pin.as_ref())
+        .map_or(0, String::len)
+}
+
+pub fn reset_pin(state: &StateStore) {
+    state.remove(PIN_ENTRY);
+    state.remove(PIN_MESSAGE);
+}
+
+/// Square key on the pad
+fn pad_key(label: &str, callback: impl Fn() + Clone + Send + Sync + 'static) -> impl Draw + '_ {
+    let layout = layout();
+    recognize_gesture(gesture::recognize_tap(layout.tap_hysteresis, {
+        let callback = callback.clone();
+        move |_| callback()
+    }))
+    .then(focusable(callback))
+    .then(rect_border(2, Color::WHITE, Color::BLACK))
+    .then(overlay(offset_absolute(Point2::new(0.5, 0.5)).then(
+        text_aligned(label, layout.font_size, Point2::new(0.5, 0.5), Color::BLACK),
+    )))
+}
+
+/// Numeric pad filling the current rect, editing the digits held under id and passing
+/// them to on_entry once there are length of them
+pub fn numeric_pad(
+    id: &'static str,
+    length: usize,
+    event_tx: Sender<MainEvent>,
+    on_entry: impl Fn(String) + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let labels = ["1", "2", "3", "4", "5", "6", "7", "8", "9"]
+            .map(str::to_string)
+            .into_iter()
+            .chain([tr("pin.clear"), "0".to_string(), tr("pin.backspace")])
+            .collect::<Vec<_>>();
+        let spacing = layout().icon_spacing / 2;
+        let cells = grid_cells(ctx.rect, 3, Vector2::new(spacing, spacing), labels.len());
+
+        for (i, (label, cell)) in labels.iter().zip(cells).enumerate() {
+            let callback = {
+                let state = ctx.state.clone();
+                let event_tx = event_tx.clone();
+                let on_entry = on_entry.clone();
+                move || {
+                    let entry = state.update(id, |entry: &mut String| {
+                        match i {
+                            9 => entry.clear(),
+                            11 => {
+                                entry.pop();
+                            }
+                            10 => entry.push('0'),
+                            _ => entry.push(char::from(b'1' + i as u8)),
+                        }
+                        entry.clone()
+                    });
+                    if entry.len() >= length {
+                        state.remove(id);
+                        on_entry(entry);
+                    }
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            };
+            ctx = overlay(set_rect(cell).then(pad_key(label, callback)))(ctx);
+        }
+
+        ctx
+    }
+}
+
+/// Panel asking for the PIN before switching to a protected draft
+pub fn pin_prompt(event_tx: Sender<MainEvent>, draft: Draft) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let height = layout.line_height;
+        let entry = ctx.state.get::<String>(PIN_ENTRY);
+        let title = tr_args("pin.title", &[("draft", &draft.name)]);
+        let status = match lockout_remaining() {
+            Some(seconds) => tr_args("pin.locked_out", &[("seconds", &seconds.to_string())]),
+            // A wrong entry's message stays up until the next digit
+            None if entry.is_empty() => ctx.state.get::<String>(PIN_MESSAGE),
+            None => "*".repeat(entry.len()),
+        };
+        let cancel_label = tr("pin.cancel");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout.icon_spacing))
+            .draw(ctx);
+
+        ctx = overlay(text_button(&cancel_label, {
+            let state = ctx.state.clone();
+            let event_tx = event_tx.clone();
+            move || {
+                reset_pin(&state);
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        for (row, line) in [&title, &status].into_iter().enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * (row as i32 + 1) + height / 4)).then(
+                    text_aligned(line, layout.font_size, Point2::new(0.0, 0.0), Color::BLACK),
+                ),
+            )(ctx);
+        }
+
+        // Pad beneath the text, held back while locked out
+        if lockout_remaining().is_none() {
+            ctx = overlay(margin_top(height * 4).then(numeric_pad(
+                PIN_ENTRY,
+                pin_length(),
+                event_tx.clone(),
+                {
+                    let state = ctx.state.clone();
+                    let event_tx = event_tx.clone();
+                    let draft = draft.clone();
+                    move |entry| {
+                        if check_pin(&draft, &entry) {
+                            reset_pin(&state);
+                            exit_to(&event_tx, Some(draft.clone()));
+                        } else if lockout_remaining().is_some() {
+                            // Bring the pad back once the lockout ends
+                            let event_tx = event_tx.clone();
+                            std::thread::spawn(move || {
+                                std::thread::sleep(PIN_LOCKOUT);
+                                event_tx.send(MainEvent::Redraw).ok();
+                            });
+                        } else {
+                            state.set(PIN_MESSAGE, tr("pin.wrong"));
+                        }
+                    }
+                },
+            )))(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
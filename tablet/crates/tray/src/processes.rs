@@ -0,0 +1,123 @@
+//! A draft's process tree, opened from its launch menu to spot children that are stuck
+//! or holding on to memory
+use libremarkable::cgmath::Point2;
+use proc::{Proc, State};
+use raft::Draft;
+use shared::{
+    locale::{tr, tr_args},
+    pidfile::read_pids,
+    process_tree_nodes,
+    storage::format_bytes,
+    ProcessTree,
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::{panel_height, panel_rect},
+    partial_refresh, text_button,
+    ui::{
+        margin, margin_left, offset_relative, overlay, rect_border, set_rect, text, Draw,
+        DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Rows that fit beneath the header
+fn rows_per_page() -> usize {
+    (((panel_height() - layout().icon_spacing * 2) / layout().line_height) - 1).max(1) as usize
+}
+
+/// The process a draft was launched as, if it's still around
+pub fn draft_process(procs: &ProcessTree, draft: &Draft) -> Option<Proc> {
+    let pid = read_pids()
+        .into_iter()
+        .find(|pidfile| pidfile.id == draft.id())?
+        .pid;
+    procs.find(pid).cloned()
+}
+
+fn state_label(state: &State) -> String {
+    match state {
+        State::Running => tr("processes.running"),
+        State::Sleeping => tr("processes.sleeping"),
+        State::Delay => tr("processes.waiting"),
+        State::Traced => tr("processes.stopped"),
+        State::Zombie => tr("processes.zombie"),
+        State::Unknown(state) => state.clone(),
+    }
+}
+
+/// Full-panel view of a draft's processes, children indented beneath their parents
+pub fn draft_processes(event_tx: Sender<MainEvent>, draft: Draft) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let indent = layout().icon_spacing;
+        let procs = ProcessTree::scan();
+        let nodes = draft_process(&procs, &draft)
+            .map(|proc| process_tree_nodes(&procs, &proc))
+            .unwrap_or_default();
+
+        let title = tr_args("processes.title", &[("name", &draft.name)]);
+        let back_label = tr("processes.back");
+
+        let mut rows = nodes
+            .iter()
+            .map(|node| {
+                let stat = &node.proc.stat;
+                let label = tr_args(
+                    "processes.row",
+                    &[
+                        ("name", &stat.filename),
+                        ("pid", &stat.process_id.to_string()),
+                        ("state", &state_label(&stat.state)),
+                        ("memory", &format_bytes(stat.rss_bytes() as u64)),
+                    ],
+                );
+                (node.depth, label)
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            rows.push((0, tr("processes.none")));
+        } else if rows.len() > rows_per_page() {
+            let hidden = rows.len() - (rows_per_page() - 1);
+            rows.truncate(rows_per_page() - 1);
+            rows.push((
+                0,
+                tr_args("processes.more", &[("count", &hidden.to_string())]),
+            ));
+        }
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        // Header
+        let header = ctx.rect;
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 / 3)
+                .then(offset_relative(Point2::new(0, height / 4)))
+                .then(text(&title, layout().font_size, Color::BLACK)),
+        )(ctx);
+
+        for (i, (depth, label)) in rows.iter().enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(
+                    indent * *depth as i32,
+                    height * (i as i32 + 1) + height / 4,
+                ))
+                .then(text(label, layout().font_size, Color::BLACK)),
+            )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
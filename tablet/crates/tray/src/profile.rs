@@ -0,0 +1,178 @@
+//! Startup latency and frame timing instrumentation
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use libremarkable::cgmath::Point2;
+
+use crate::{
+    framebuffer::Color,
+    layout::layout,
+    partial_refresh,
+    ui::{offset_relative, overlay, rect_fill, set_size, text, Draw, DrawContext, ThenTrait},
+};
+
+/// Target time from process start to the first interactive frame
+pub const STARTUP_BUDGET: Duration = Duration::from_millis(300);
+
+static START: OnceLock<Instant> = OnceLock::new();
+static FIRST_FRAME: AtomicBool = AtomicBool::new(false);
+
+/// Start the startup clock, later calls have no effect
+pub fn startup_begin() {
+    START.get_or_init(Instant::now);
+}
+
+pub fn startup_elapsed() -> Duration {
+    START.get_or_init(Instant::now).elapsed()
+}
+
+/// Log the time taken to reach a startup stage
+pub fn mark(stage: &str) {
+    println!("[startup] {stage:} at {}ms", startup_elapsed().as_millis());
+}
+
+/// Log the first interactive frame, warning if it missed the budget
+pub fn first_frame() {
+    if FIRST_FRAME.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    mark("first frame");
+    if startup_elapsed() > STARTUP_BUDGET {
+        println!(
+            "Warning: Startup exceeded {}ms budget",
+            STARTUP_BUDGET.as_millis()
+        );
+    }
+}
+
+/// Timed stages of the render and main loops
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Metric {
+    /// Executing a draw command, including any refreshes it issues
+    Draw,
+    /// Time spent inside framebuffer refresh calls
+    RefreshWait,
+    /// From an input event reaching the main loop to the next draw completing
+    InputLatency,
+    /// Scanning /proc for draft processes
+    ProcScan,
+}
+
+impl Metric {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Draw => "draw",
+            Metric::RefreshWait => "refresh",
+            Metric::InputLatency => "input",
+            Metric::ProcScan => "proc",
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Stat {
+    pub last: Duration,
+    pub max: Duration,
+    pub total: Duration,
+    pub count: u32,
+}
+
+impl Stat {
+    pub fn mean(&self) -> Duration {
+        self.total / self.count.max(1)
+    }
+}
+
+static METRICS: Mutex<BTreeMap<Metric, Stat>> = Mutex::new(BTreeMap::new());
+static PENDING_INPUT: Mutex<Option<Instant>> = Mutex::new(None);
+static HUD: AtomicBool = AtomicBool::new(false);
+
+pub fn record(metric: Metric, duration: Duration) {
+    let mut metrics = METRICS.lock().unwrap();
+    let stat = metrics.entry(metric).or_default();
+    stat.last = duration;
+    stat.max = stat.max.max(duration);
+    stat.total += duration;
+    stat.count += 1;
+}
+
+/// Run f, recording how long it took
+pub fn timed<T>(metric: Metric, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(metric, start.elapsed());
+    result
+}
+
+pub fn metrics() -> BTreeMap<Metric, Stat> {
+    METRICS.lock().unwrap().clone()
+}
+
+/// Note an input event, the next completed draw is measured against the earliest one
+pub fn input_received() {
+    PENDING_INPUT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(Instant::now);
+}
+
+/// Record input latency if input arrived since the last completed draw
+pub fn draw_completed() {
+    if let Some(start) = PENDING_INPUT.lock().unwrap().take() {
+        record(Metric::InputLatency, start.elapsed());
+    }
+}
+
+pub fn hud_enabled() -> bool {
+    HUD.load(Ordering::Relaxed)
+}
+
+pub fn set_hud_enabled(enabled: bool) {
+    HUD.store(enabled, Ordering::Relaxed);
+}
+
+/// Overlay the current metrics in the top-left corner of the display
+pub fn perf_hud() -> impl Draw {
+    move |ctx: DrawContext| {
+        let font_size = layout().font_size / 2.0;
+        let line_height = layout().line_height / 2;
+        let lines = metrics()
+            .into_iter()
+            .map(|(metric, stat)| {
+                format!(
+                    "{}: {}ms avg {}ms max {}ms",
+                    metric.label(),
+                    stat.last.as_millis(),
+                    stat.mean().as_millis(),
+                    stat.max.as_millis()
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let width = (font_size * 16.0) as u32;
+        let height = (line_height * lines.len().max(1) as i32) as u32;
+
+        let mut ctx = set_size(width, height)
+            .then(rect_fill(Color::WHITE))
+            .draw(ctx);
+
+        for (i, line) in lines.iter().enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, line_height * i as i32)).then(text(
+                    line,
+                    font_size,
+                    Color::BLACK,
+                )),
+            )(ctx);
+        }
+
+        set_size(width, height).then(partial_refresh()).draw(ctx)
+    }
+}
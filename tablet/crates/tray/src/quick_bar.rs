@@ -0,0 +1,166 @@
+//! Slim bar of pinned drafts along the bottom edge, opened by a short swipe up
+//!
+//! Lighter than the full tray for hopping between a handful of drafts, with a button
+//! to open the tray proper when the one wanted isn't there.
+use std::sync::Arc;
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::{
+    cgmath::Point2,
+    image::{ImageBuffer, Rgba},
+};
+use raft::Draft;
+use shared::locale::tr;
+
+use crate::{
+    animation::slide_in,
+    channel::Sender,
+    display::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    draft_icon, exit_to,
+    focus::FocusCallback,
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    nine_patch::panel_chrome,
+    partial_refresh,
+    recent::Recent,
+    text_button,
+    ui::{
+        aspect_ratio, focusable, horizontal, margin, margin_bottom, margin_left, offset_relative,
+        recognize_gesture, rect_stroke, set_rect, set_width, unit, Draw, DrawContext, DrawFn,
+        OverlayTrait, ThenTrait,
+    },
+    user_profile::pinned_apps,
+    MainEvent, View,
+};
+
+/// Most drafts the bar shows, leaving room for the button that opens the tray
+pub const QUICK_BAR_APPS: usize = 4;
+
+/// Strip along the bottom of the display covered by the bar
+pub fn quick_bar_rect() -> MxcfbRect {
+    let layout = layout();
+    let height = (layout.icon_size + layout.icon_spacing * 2) as u32;
+    MxcfbRect {
+        left: 0,
+        top: DISPLAY_HEIGHT as u32 - height,
+        width: DISPLAY_WIDTH as u32,
+        height,
+    }
+}
+
+/// Up to limit drafts for a launcher: pinned ones in order, topped up with recently closed then the rest
+pub fn pinned_drafts(
+    pinned: &[String],
+    drafts: &DraftPrograms,
+    recent: &Recent,
+    limit: usize,
+) -> Vec<Draft> {
+    let recent = recent.lock().unwrap();
+
+    let named = pinned
+        .iter()
+        .chain(recent.iter())
+        .filter_map(|name| drafts.draft_named(name))
+        .filter(|draft| drafts.is_allowed(draft));
+    let mut chosen = Vec::<&Draft>::new();
+    for draft in named.chain(drafts.visible_drafts().map(|(_, draft)| draft)) {
+        if chosen.len() == limit {
+            break;
+        }
+        if !chosen.iter().any(|other| other.id() == draft.id()) {
+            chosen.push(draft);
+        }
+    }
+
+    chosen.into_iter().cloned().collect()
+}
+
+/// Icon that launches its draft when tapped
+fn quick_bar_icon(
+    icon: Option<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    launch: impl FocusCallback + Clone + 'static,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        set_width(layout.icon_size as u32)
+            .then(aspect_ratio(1.0))
+            .then(recognize_gesture(gesture::recognize_tap(
+                layout.tap_hysteresis,
+                {
+                    let launch = launch.clone();
+                    move |_| launch()
+                },
+            )))
+            .then(focusable(launch.clone()))
+            .then(margin(-1))
+            .then(rect_stroke(2, Color::BLACK))
+            .overlay(draft_icon(icon.as_ref()))
+            .draw(ctx)
+    }
+}
+
+pub fn quick_bar(
+    event_tx: Sender<MainEvent>,
+    drafts: Arc<DraftPrograms>,
+    stopped_draft: Option<Draft>,
+    recent: Recent,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let bar = quick_bar_rect();
+
+        let draft_icons = drafts.draft_icons();
+        let icons = pinned_drafts(&pinned_apps(), &drafts, &recent, QUICK_BAR_APPS)
+            .into_iter()
+            .map(|draft| {
+                let icon = draft_icons.get(&draft.id()).cloned();
+                let event_tx = event_tx.clone();
+                quick_bar_icon(icon, move || {
+                    println!("Launching {:?} from the quick bar", draft.name);
+                    exit_to(&event_tx, Some(draft.clone()));
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(draft_icons);
+
+        let more = tr("quick_bar.more");
+        let more_button = text_button(&more, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        });
+
+        let contents = unit()
+            .overlay(margin_bottom(bar.height as i32).then(recognize_gesture(
+                gesture::recognize_press({
+                    let event_tx = event_tx.clone();
+                    let stopped_draft = stopped_draft.clone();
+                    move |_| {
+                        println!("Tapped outside the quick bar, exiting");
+                        exit_to(&event_tx, stopped_draft.clone());
+                    }
+                }),
+            )))
+            .overlay(
+                set_rect(bar)
+                    .then(panel_chrome())
+                    .then(margin(layout.icon_spacing))
+                    .overlay(horizontal(layout.icon_spacing, &icons))
+                    .overlay(
+                        margin_left(bar.width as i32 - layout.icon_size * 2).then(
+                            offset_relative(Point2::new(
+                                0,
+                                (layout.icon_size - layout.line_height) / 2,
+                            ))
+                            .then(more_button),
+                        ),
+                    )
+                    .then(set_rect(bar))
+                    .then(partial_refresh()),
+            );
+
+        let slide = slide_in("quick_bar.slide", bar, move |ctx| contents.draw(ctx));
+        slide(ctx)
+    }
+}
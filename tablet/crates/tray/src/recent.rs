@@ -0,0 +1,91 @@
+//! Strip of drafts closed this session, for relaunching with a single tap
+use std::sync::{Arc, Mutex};
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::cgmath::Point2;
+use shared::locale::tr;
+
+use crate::{
+    channel::Sender,
+    exit_to,
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    panel::panel_rect,
+    partial_refresh, text_button,
+    ui::{
+        horizontal, offset_relative, set_rect, text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent,
+};
+
+/// Names of closed drafts, most recent first, shared between the main loop and the tray view
+pub type Recent = Arc<Mutex<Vec<String>>>;
+
+/// Row along the bottom of the panel listing closed drafts that haven't been relaunched
+pub fn recent_strip(
+    event_tx: Sender<MainEvent>,
+    drafts: Arc<DraftPrograms>,
+    recent: Recent,
+) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let closed = recent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|name| drafts.draft_named(name).cloned())
+            .filter(|draft| !drafts.cached_procs().contains_key(&draft.id()))
+            .collect::<Vec<_>>();
+
+        if closed.is_empty() {
+            return ctx;
+        }
+
+        let buttons = closed
+            .into_iter()
+            .map(|draft| {
+                let event_tx = event_tx.clone();
+                move |ctx: DrawContext| {
+                    let name = draft.name.clone();
+                    let draft = draft.clone();
+                    let event_tx = event_tx.clone();
+                    let ctx = text_button(&name, move || {
+                        println!("Relaunching recent draft {:?}", draft.name);
+                        exit_to(&event_tx, Some(draft.clone()));
+                    })
+                    .draw(ctx);
+                    ctx
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let layout = layout();
+        let spacing = layout.focus_margin * 4;
+        let panel = panel_rect();
+        let strip = MxcfbRect {
+            left: panel.left + spacing as u32,
+            top: panel.top + panel.height - (layout.line_height + spacing) as u32,
+            width: panel.width - spacing as u32 * 2,
+            height: layout.line_height as u32,
+        };
+
+        let label = tr("tray.recent");
+        let label = move |ctx: DrawContext| {
+            offset_relative(Point2::new(0, layout.line_height / 4))
+                .then(text_aligned(
+                    &label,
+                    layout.font_size,
+                    Point2::new(0.0, 0.0),
+                    Color::GRAY(96),
+                ))
+                .draw(ctx)
+        };
+
+        let ctx = set_rect(strip)
+            .then(horizontal(spacing, &[label]))
+            .then(horizontal(layout.icon_spacing, &buttons))
+            .then(set_rect(strip))
+            .then(partial_refresh())
+            .draw(ctx);
+        ctx
+    }
+}
@@ -0,0 +1,46 @@
+//! Refresh policy, reducing e-paper activity while the battery is low
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use shared::power::battery_low;
+
+use crate::{animation::animation_frame, framebuffer::WaveformMode};
+
+pub const BATTERY_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+static LOW_POWER: AtomicBool = AtomicBool::new(false);
+
+/// Whether refreshes are currently being throttled
+pub fn low_power() -> bool {
+    LOW_POWER.load(Ordering::Relaxed)
+}
+
+/// Waveform for partial refreshes, trading quality for speed and power when low
+/// and for intermediate animation frames
+pub fn partial_waveform() -> WaveformMode {
+    if low_power() || animation_frame() {
+        WaveformMode::WAVEFORM_MODE_DU
+    } else {
+        WaveformMode::WAVEFORM_MODE_GC16_FAST
+    }
+}
+
+/// Poll battery state in the background, updating the policy as it changes
+pub fn battery_monitor() {
+    LOW_POWER.store(battery_low(), Ordering::Relaxed);
+
+    std::thread::spawn(|| loop {
+        std::thread::sleep(BATTERY_POLL_INTERVAL);
+
+        let low = battery_low();
+        if LOW_POWER.swap(low, Ordering::Relaxed) != low {
+            println!(
+                "Battery {}, {} refresh throttling",
+                if low { "low" } else { "recovered" },
+                if low { "enabling" } else { "disabling" }
+            );
+        }
+    });
+}
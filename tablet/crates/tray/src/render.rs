@@ -1,18 +1,28 @@
 use std::sync::Arc;
 
-use crossbeam_channel::Sender;
 use gesture::GestureRecognizer;
-use libremarkable::framebuffer::core::Framebuffer;
+use shared::config::DisplayBackend;
 
 use crate::{
-    channel::Receiver,
-    display::DISPLAY_RECT,
-    ui::{Draw, DrawContext},
+    animation::{
+        animating, animation_frame, frame_interval, prune_animations, set_animation_frame,
+    },
+    channel::{channel, Receiver, RecvTimeoutError, Sender},
+    display::{open_display, DISPLAY_RECT},
+    focus::FocusMap,
+    profile::{draw_completed, first_frame, hud_enabled, perf_hud, timed, Metric},
+    state::StateStore,
+    stream::StreamHandle,
+    ui::{wait_for_refresh, wait_refreshes, Draw, DrawContext, RefreshToken},
     MainEvent,
 };
 
 pub enum RenderEvent {
     Execute(Arc<Box<dyn Draw + Send + Sync>>, bool),
+    /// Wait for a refresh, or every refresh issued so far if none is given, then reply
+    WaitForRefresh(Option<RefreshToken>, Sender<()>),
+    /// Redraw the current view as the next animation frame
+    Tick,
     Exit,
 }
 
@@ -36,35 +46,136 @@ impl RenderEvent {
     }
 }
 
+/// Block until the render thread has drawn everything queued before this call
+/// and the given refresh has reached the panel
+///
+/// Lets callers order work after a draw, such as handing the display to another process.
+pub fn wait_for_refresh_completion(render_tx: &Sender<RenderEvent>, token: Option<RefreshToken>) {
+    let (done_tx, done_rx) = channel();
+    if render_tx
+        .send(RenderEvent::WaitForRefresh(token, done_tx))
+        .is_ok()
+    {
+        done_rx.recv().ok();
+    }
+}
+
 pub fn render_thread(
     event_tx: Sender<MainEvent>,
     command_rx: Receiver<RenderEvent>,
+    stream: Option<StreamHandle>,
+    backend: DisplayBackend,
+    state: StateStore,
 ) -> impl FnOnce() + Send + 'static {
     move || {
-        let mut framebuffer = Framebuffer::new();
+        let mut framebuffer = open_display(backend);
+        let mut refresh = None;
+        // Most recent full view, redrawn for animation frames
+        let mut view: Option<Arc<Box<dyn Draw + Send + Sync>>> = None;
+        // Whether the last draw was a fast animation frame, and needs a normal one after it
+        let mut cleanup = false;
 
         loop {
-            match command_rx.recv() {
+            let event = match frame_interval().filter(|_| cleanup || animating(&state)) {
+                Some(interval) => match command_rx.recv_timeout(interval) {
+                    Ok(event) => Ok(event),
+                    Err(RecvTimeoutError::Timeout) => Ok(RenderEvent::Tick),
+                    Err(RecvTimeoutError::Disconnected) => Err("render channel disconnected"),
+                },
+                None => command_rx.recv().map_err(|_| "render channel disconnected"),
+            };
+
+            let event = match event {
+                // Frames are drawn with fast refreshes, except the last, which cleans up after them
+                Ok(RenderEvent::Tick) => match &view {
+                    Some(view) => {
+                        set_animation_frame(animating(&state));
+                        Ok(RenderEvent::Execute(view.clone(), false))
+                    }
+                    None => continue,
+                },
+                event => event,
+            };
+
+            match event {
                 Ok(event) => match event {
                     RenderEvent::Execute(f, replace_gesture_recognizer) => {
+                        if replace_gesture_recognizer {
+                            view = Some(f.clone());
+                        }
+
                         let DrawContext {
                             fb,
                             gesture_recognizer,
+                            focus,
+                            refresh: last_refresh,
                             ..
-                        } = f.draw(DrawContext {
-                            fb: framebuffer,
-                            rect: DISPLAY_RECT,
-                            gesture_recognizer: GestureRecognizer::default(),
+                        } = timed(Metric::Draw, || {
+                            f.draw(DrawContext {
+                                fb: framebuffer,
+                                rect: DISPLAY_RECT,
+                                gesture_recognizer: GestureRecognizer::default(),
+                                focus: FocusMap::default(),
+                                refresh,
+                                state: state.clone(),
+                            })
                         });
 
                         framebuffer = fb;
+                        refresh = last_refresh;
+                        draw_completed();
+                        cleanup = animation_frame();
+                        set_animation_frame(false);
+
+                        if view.as_ref().is_some_and(|view| Arc::ptr_eq(view, &f)) {
+                            prune_animations(&state);
+                        }
+
+                        // Redraw the HUD over each new view so it stays visible
+                        if replace_gesture_recognizer && hud_enabled() {
+                            let ctx = perf_hud().draw(DrawContext {
+                                fb: framebuffer,
+                                rect: DISPLAY_RECT,
+                                gesture_recognizer: GestureRecognizer::default(),
+                                focus: FocusMap::default(),
+                                refresh,
+                                state: state.clone(),
+                            });
+                            framebuffer = ctx.fb;
+                            refresh = ctx.refresh;
+                        }
+
+                        if let Some(stream) = stream.as_ref().filter(|stream| stream.active()) {
+                            if let Ok(frame) = framebuffer.dump_region(DISPLAY_RECT) {
+                                stream.send_frame(frame);
+                            }
+                        }
 
                         if replace_gesture_recognizer {
+                            first_frame();
                             event_tx
                                 .send(MainEvent::SetGestureRecognizer(Some(gesture_recognizer)))
                                 .unwrap();
+                            event_tx.send(MainEvent::SetFocusMap(focus)).unwrap();
                         }
                     }
+                    RenderEvent::WaitForRefresh(token, done_tx) => {
+                        let ctx = DrawContext {
+                            fb: framebuffer,
+                            rect: DISPLAY_RECT,
+                            gesture_recognizer: GestureRecognizer::default(),
+                            focus: FocusMap::default(),
+                            refresh,
+                            state: state.clone(),
+                        };
+                        let ctx = match token {
+                            Some(token) => wait_for_refresh(token)(ctx),
+                            None => wait_refreshes()(ctx),
+                        };
+                        framebuffer = ctx.fb;
+                        done_tx.send(()).ok();
+                    }
+                    RenderEvent::Tick => (),
                     RenderEvent::Exit => break,
                 },
                 Err(e) => panic!("{e:}"),
@@ -0,0 +1,60 @@
+//! Checking that continued drafts redraw
+//!
+//! Some apps don't repaint after SIGCONT, leaving whatever was last drawn on screen. A draft
+//! with a repaint command has the framebuffer compared a moment after it's continued, and the
+//! command run if nothing changed.
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    process::Command,
+    time::Duration,
+};
+
+use raft::Draft;
+
+use crate::{
+    channel::{channel, Sender},
+    display::DISPLAY_RECT,
+    render::RenderEvent,
+    ui::{dump_region, set_rect, ThenTrait},
+};
+
+/// Time a continued draft has to redraw before it's nudged
+pub const REPAINT_CHECK_DELAY: Duration = Duration::from_secs(1);
+
+/// Hash of everything on the framebuffer, None if the renderer has gone
+fn framebuffer_hash(render_tx: &Sender<RenderEvent>) -> Option<u64> {
+    let (hash_tx, hash_rx) = channel();
+    render_tx
+        .send(RenderEvent::execute(
+            set_rect(DISPLAY_RECT).then(dump_region(move |data| {
+                let mut hasher = DefaultHasher::new();
+                data.hash(&mut hasher);
+                hash_tx.send(hasher.finish()).ok();
+            })),
+            false,
+        ))
+        .ok()?;
+    hash_rx.recv().ok()
+}
+
+/// Run a just-continued draft's repaint command if the framebuffer doesn't change in time
+pub fn check_repaint(render_tx: &Sender<RenderEvent>, draft: &Draft) {
+    let Some(command) = &draft.repaint else {
+        return;
+    };
+
+    let before = framebuffer_hash(render_tx);
+    std::thread::sleep(REPAINT_CHECK_DELAY);
+    if before.is_none() || framebuffer_hash(render_tx) != before {
+        return;
+    }
+
+    println!(
+        "{:?} hasn't redrawn since being continued, running its repaint command",
+        draft.name
+    );
+    if let Err(e) = Command::new("sh").args(["-c", command]).spawn() {
+        println!("Failed to run repaint command for {:?}: {e:}", draft.name);
+    }
+}
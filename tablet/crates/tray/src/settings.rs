@@ -0,0 +1,246 @@
+//! Quick settings view
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use libremarkable::cgmath::Point2;
+use shared::{
+    frontlight::{brightness, frontlight_path},
+    locale::{tr, tr_args},
+    notification::{do_not_disturb, history, set_do_not_disturb},
+    usb::{set_usb_mode, usb_connected, usb_mode, UsbMode, UsbStorage},
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    panel_button, partial_refresh, text_button,
+    theme::{inverted, toggle_inverted},
+    ui::{
+        circle_stroke, focusable, margin, margin_left, offset_relative, overlay, recognize_gesture,
+        rect_border, rect_fill, set_height, set_rect, set_width, text, Draw, DrawContext, DrawFn,
+        ThenTrait,
+    },
+    user_profile::active_profile,
+    MainEvent, View,
+};
+
+/// Button in the top-right corner of the panel that opens quick settings
+pub fn settings_button(event_tx: Sender<MainEvent>) -> impl Draw {
+    panel_button(
+        1,
+        move || {
+            event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+        },
+        circle_stroke((layout().close_button_size / 4) as u32, Color::BLACK),
+    )
+}
+
+/// Brightness change applied per Enter press on a focused slider
+const BRIGHTNESS_STEP: u8 = 10;
+
+/// Horizontal bar filled to a percentage, a tap or drag ending inside it sets a new value
+fn slider(value: u8, on_change: impl Fn(u8) + Clone + Send + Sync + 'static) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let rect = ctx.rect;
+        let filled = rect.width * value.min(100) as u32 / 100;
+
+        rect_border(2, Color::WHITE, Color::BLACK)
+            .then(overlay(set_width(filled).then(rect_fill(Color::BLACK))))
+            .then(recognize_gesture(gesture::recognize_release({
+                let on_change = on_change.clone();
+                move |pos| {
+                    let x = (pos.x as u32).saturating_sub(rect.left).min(rect.width);
+                    on_change((x * 100 / rect.width.max(1)) as u8);
+                }
+            })))
+            .then(focusable({
+                let on_change = on_change.clone();
+                move || on_change((value + BRIGHTNESS_STEP) % (100 + BRIGHTNESS_STEP))
+            }))
+            .draw(ctx)
+    }
+}
+
+/// Whether a USB mode switch is in progress
+static USB_SWITCHING: AtomicBool = AtomicBool::new(false);
+
+/// Why the last USB mode switch failed, shown until the next one
+static USB_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// Switch to the next USB mode in the background, as unmounting and syncing can take a while
+fn cycle_usb_mode(event_tx: &Sender<MainEvent>, storage: &UsbStorage) {
+    if USB_SWITCHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    USB_ERROR.lock().unwrap().take();
+    event_tx.send(MainEvent::Redraw).ok();
+
+    let mode = usb_mode().map(UsbMode::next).unwrap_or(UsbMode::Ethernet);
+    let event_tx = event_tx.clone();
+    let storage = storage.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = set_usb_mode(mode, &storage) {
+            println!("Failed to switch USB to {mode:}: {e:}");
+            *USB_ERROR.lock().unwrap() = Some(e);
+        }
+        USB_SWITCHING.store(false, Ordering::SeqCst);
+        event_tx.send(MainEvent::Redraw).ok();
+    });
+}
+
+fn usb_label() -> String {
+    if USB_SWITCHING.load(Ordering::SeqCst) {
+        return tr("settings.usb_switching");
+    }
+    if let Some(error) = USB_ERROR.lock().unwrap().as_ref() {
+        return tr_args("settings.usb_failed", &[("error", error)]);
+    }
+
+    let mode = tr(match usb_mode() {
+        Some(UsbMode::Ethernet) => "usb.ethernet",
+        Some(UsbMode::MassStorage) => "usb.mass_storage",
+        Some(UsbMode::Mtp) => "usb.mtp",
+        None => "settings.off",
+    });
+    match usb_connected() {
+        true => tr_args("settings.usb_connected", &[("mode", &mode)]),
+        false => tr_args("settings.usb", &[("mode", &mode)]),
+    }
+}
+
+/// Full-panel list of toggles
+pub fn settings(event_tx: Sender<MainEvent>, usb_storage: UsbStorage) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let back_label = tr("settings.back");
+        let on_off = |on| match on {
+            true => tr("settings.on"),
+            false => tr("settings.off"),
+        };
+        let night_label = format!("{}: {}", tr("settings.night_mode"), on_off(inverted()));
+        let dnd_label = format!(
+            "{}: {}",
+            tr("settings.do_not_disturb"),
+            on_off(do_not_disturb())
+        );
+        let notifications_label = tr_args(
+            "settings.notifications",
+            &[("count", &history().len().to_string())],
+        );
+        let usb_label = usb_label();
+        let clock_label = tr("settings.clock");
+        let calibrate_label = tr("settings.calibrate");
+        let profile = active_profile().unwrap_or_else(|| tr("profiles.default"));
+        let profile_label = tr_args("settings.profile", &[("profile", &profile)]);
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        let header = ctx.rect;
+        ctx = overlay(
+            margin_left(header.width as i32 / 2).then(text_button(&clock_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Clock)).ok();
+                }
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height)).then(text_button(&night_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    toggle_inverted();
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 2)).then(text_button(&dnd_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    if let Err(e) = set_do_not_disturb(!do_not_disturb()) {
+                        println!("Failed to toggle do not disturb: {e:}");
+                    }
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 3)).then(text_button(&notifications_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Notifications)).ok();
+                }
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 4)).then(text_button(&usb_label, {
+                let event_tx = event_tx.clone();
+                let usb_storage = usb_storage.clone();
+                move || cycle_usb_mode(&event_tx, &usb_storage)
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 5)).then(text_button(&calibrate_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::Calibration)).ok();
+                }
+            })),
+        )(ctx);
+
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * 6)).then(text_button(&profile_label, {
+                let event_tx = event_tx.clone();
+                move || {
+                    event_tx.send(MainEvent::ShowView(View::UserProfiles)).ok();
+                }
+            })),
+        )(ctx);
+
+        // Devices without a frontlight get no slider
+        if frontlight_path().is_some() {
+            let value = brightness().unwrap_or_default();
+            let label = tr_args("settings.brightness", &[("percent", &value.to_string())]);
+
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * 7 + height / 4)).then(text(
+                    &label,
+                    layout().font_size,
+                    Color::BLACK,
+                )),
+            )(ctx);
+
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * 8))
+                    .then(set_height((height / 2) as u32))
+                    .then(slider(value, {
+                        let event_tx = event_tx.clone();
+                        move |brightness| {
+                            event_tx.send(MainEvent::SetBrightness(brightness)).ok();
+                            event_tx.send(MainEvent::Redraw).ok();
+                        }
+                    })),
+            )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
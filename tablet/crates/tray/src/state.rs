@@ -0,0 +1,72 @@
+//! Keyed widget state
+//!
+//! Immediate-mode widgets are rebuilt every frame, so anything they need to remember
+//! between frames lives here under a caller-chosen id. The store is owned by the main
+//! loop and shared with the render thread, which hands it to each draw via
+//! [`DrawContext`](crate::ui::DrawContext); gesture callbacks can keep a clone to update it.
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Default)]
+pub struct StateStore(Arc<Mutex<BTreeMap<String, Box<dyn Any + Send>>>>);
+
+impl StateStore {
+    /// Read a widget's state, or the default if it has none or holds a different type
+    pub fn get<T: Any + Clone + Default>(&self, id: &str) -> T {
+        self.0
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|state| state.downcast_ref::<T>())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set<T: Any + Send>(&self, id: &str, state: T) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), Box::new(state));
+    }
+
+    /// Modify a widget's state in place, starting from the default if it has none
+    pub fn update<T: Any + Send + Default, R>(&self, id: &str, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut states = self.0.lock().unwrap();
+        let state = states
+            .entry(id.to_string())
+            .or_insert_with(|| Box::new(T::default()));
+        if !state.is::<T>() {
+            *state = Box::new(T::default());
+        }
+        f(state.downcast_mut::<T>().unwrap())
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.0.lock().unwrap().remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_persists_by_id_and_type() {
+        let state = StateStore::default();
+        assert_eq!(state.get::<usize>("page"), 0);
+
+        state.update("page", |page: &mut usize| *page += 2);
+        state.clone().update("page", |page: &mut usize| *page += 1);
+        assert_eq!(state.get::<usize>("page"), 3);
+        assert_eq!(state.get::<String>("page"), "");
+
+        state.update("page", |name: &mut String| name.push('a'));
+        assert_eq!(state.get::<usize>("page"), 0);
+
+        state.remove("page");
+        assert_eq!(state.get::<String>("page"), "");
+    }
+}
@@ -0,0 +1,49 @@
+//! Free space readout for the home filesystem, with a warning when it runs low
+use libremarkable::cgmath::Point2;
+use shared::{
+    locale::tr_args,
+    storage::{disk_usage, format_bytes, HOME_PATH, LOW_STORAGE_THRESHOLD},
+};
+
+use crate::{
+    framebuffer::Color,
+    layout::layout,
+    partial_refresh,
+    ui::{offset_relative, text_aligned, Draw, DrawContext, DrawFn, ThenTrait},
+};
+
+/// Label at the left of the current rect showing free space, in black once it's low
+pub fn storage_indicator() -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let usage = match disk_usage(HOME_PATH) {
+            Some(usage) => usage,
+            None => return ctx,
+        };
+
+        let free = format_bytes(usage.available);
+        let low = usage.available < LOW_STORAGE_THRESHOLD;
+        let (label, color) = if low {
+            (
+                tr_args("tray.storage_low", &[("free", &free)]),
+                Color::BLACK,
+            )
+        } else {
+            (
+                tr_args("tray.storage_free", &[("free", &free)]),
+                Color::GRAY(96),
+            )
+        };
+
+        let layout = layout();
+        let ctx = offset_relative(Point2::new(0, layout.close_button_size / 4))
+            .then(text_aligned(
+                &label,
+                layout.font_size,
+                Point2::new(0.0, 0.0),
+                color,
+            ))
+            .then(partial_refresh())
+            .draw(ctx);
+        ctx
+    }
+}
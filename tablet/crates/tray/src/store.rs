@@ -0,0 +1,245 @@
+//! On-device app store, listing toltec app packages for install and removal
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, MutexGuard,
+};
+
+use libremarkable::cgmath::Point2;
+use shared::{
+    locale::{tr, tr_args},
+    opkg::{app_packages, run_package_action, Package, PackageAction},
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::{panel_height, panel_rect},
+    panel_button, partial_refresh,
+    refresh::low_power,
+    text_button,
+    ui::{
+        line, margin, margin_left, offset_relative, overlay, rect_border, set_rect, text_aligned,
+        Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the page shown by the package list
+pub const STORE_PAGE: &str = "store.page";
+
+#[derive(Debug, Default)]
+pub struct PackageStore {
+    packages: Mutex<Vec<Package>>,
+    status: Mutex<String>,
+    busy: AtomicBool,
+}
+
+impl PackageStore {
+    pub fn packages(&self) -> MutexGuard<'_, Vec<Package>> {
+        self.packages.lock().unwrap()
+    }
+
+    pub fn status(&self) -> String {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn set_status(&self, status: impl Into<String>, event_tx: &Sender<MainEvent>) {
+        *self.status.lock().unwrap() = status.into();
+        event_tx.send(MainEvent::Redraw).ok();
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.packages().len().div_ceil(rows_per_page()).max(1)
+    }
+
+    /// Reload the package list in the background
+    pub fn refresh(self: &Arc<Self>, event_tx: Sender<MainEvent>) {
+        let store = self.clone();
+        std::thread::spawn(move || {
+            store.set_status(tr("store.loading"), &event_tx);
+            store.reload(&event_tx);
+        });
+    }
+
+    fn reload(&self, event_tx: &Sender<MainEvent>) {
+        match app_packages() {
+            Ok(packages) => {
+                *self.packages() = packages;
+                self.set_status("", event_tx);
+            }
+            Err(e) => self.set_status(
+                tr_args("store.list_failed", &[("error", &e.to_string())]),
+                event_tx,
+            ),
+        }
+    }
+
+    /// Install or remove a package in the background, reporting opkg output as status
+    pub fn toggle(self: &Arc<Self>, package: Package, event_tx: Sender<MainEvent>) {
+        if self.busy.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let action = if package.installed {
+            PackageAction::Remove
+        } else {
+            PackageAction::Install
+        };
+
+        let (running, finished, failed) = match action {
+            PackageAction::Install => (
+                "store.installing",
+                "store.installed",
+                "store.install_failed",
+            ),
+            PackageAction::Remove => ("store.removing", "store.removed", "store.remove_failed"),
+        };
+
+        let store = self.clone();
+        std::thread::spawn(move || {
+            let args = [("package", package.name.as_str())];
+            store.set_status(tr_args(running, &args), &event_tx);
+
+            let result = run_package_action(action, &package.name, |line| {
+                println!("opkg: {line:}");
+
+                // Progress output is non-essential, so skip refreshing for it on low battery
+                if low_power() {
+                    *store.status.lock().unwrap() = line.to_string();
+                } else {
+                    store.set_status(line, &event_tx);
+                }
+            });
+
+            match result {
+                Ok(true) => {
+                    store.reload(&event_tx);
+                    store.set_status(tr_args(finished, &args), &event_tx);
+                }
+                Ok(false) => store.set_status(tr_args(failed, &args), &event_tx),
+                Err(e) => store.set_status(
+                    tr_args("store.opkg_failed", &[("error", &e.to_string())]),
+                    &event_tx,
+                ),
+            }
+
+            store.busy.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Package rows that fit between the header and status lines
+fn rows_per_page() -> usize {
+    (((panel_height() - layout().icon_spacing * 2) / layout().line_height) - 2).max(1) as usize
+}
+
+/// Button in the top-right corner of the panel that opens the store
+pub fn store_button(event_tx: Sender<MainEvent>, store: Arc<PackageStore>) -> impl Draw {
+    let size = layout().close_button_size;
+    panel_button(
+        0,
+        move || {
+            store.refresh(event_tx.clone());
+            event_tx.send(MainEvent::ShowView(View::PackageStore)).ok();
+        },
+        overlay(line(
+            Point2::new(-size / 3, 0),
+            Point2::new(size / 3, 0),
+            3,
+            Color::BLACK,
+        ))
+        .then(line(
+            Point2::new(0, -size / 3),
+            Point2::new(0, size / 3),
+            3,
+            Color::BLACK,
+        )),
+    )
+}
+
+/// Full-panel package list with paging and install / remove actions
+pub fn package_store(event_tx: Sender<MainEvent>, store: Arc<PackageStore>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        // Clamp in case the list shrank since the page was turned
+        let page_count = store.page_count();
+        let page = ctx.state.get::<usize>(STORE_PAGE).min(page_count - 1);
+        let status = store.status();
+
+        let rows = store
+            .packages()
+            .iter()
+            .skip(page * rows_per_page())
+            .take(rows_per_page())
+            .cloned()
+            .collect::<Vec<_>>();
+        let labels = rows
+            .iter()
+            .map(|package| {
+                format!(
+                    "{} {} {}",
+                    if package.installed { "[x]" } else { "[ ]" },
+                    package.name,
+                    package.version
+                )
+            })
+            .collect::<Vec<_>>();
+        let page_label = format!("{} / {}", page + 1, page_count);
+        let back_label = tr("store.back");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        // Header
+        let header = ctx.rect;
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+            }
+        }))(ctx);
+        ctx = overlay(
+            margin_left(header.width as i32 / 2).then(text_button(&page_label, {
+                let state = ctx.state.clone();
+                let event_tx = event_tx.clone();
+                move || {
+                    // Tapping the page indicator advances, wrapping back to the start
+                    state.set(STORE_PAGE, (page + 1) % page_count);
+                    event_tx.send(MainEvent::Redraw).ok();
+                }
+            })),
+        )(ctx);
+
+        // Package rows
+        for (i, (package, label)) in rows.iter().zip(labels.iter()).enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * (i as i32 + 1))).then(text_button(
+                    label,
+                    {
+                        let store = store.clone();
+                        let event_tx = event_tx.clone();
+                        let package = package.clone();
+                        move || store.toggle(package.clone(), event_tx.clone())
+                    },
+                )),
+            )(ctx);
+        }
+
+        // Status
+        ctx = overlay(
+            offset_relative(Point2::new(0, height * (rows_per_page() as i32 + 1))).then(
+                text_aligned(
+                    &status,
+                    layout().font_size,
+                    Point2::new(0.0, 0.0),
+                    Color::BLACK,
+                ),
+            ),
+        )(ctx);
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -0,0 +1,457 @@
+//! Remote framebuffer streaming over TCP
+//!
+//! Disabled unless `PARCHMENT_STREAM_ADDR` is set to a socket address to listen on,
+//! or just a port to listen on loopback. Clients are only accepted from the network,
+//! and may only inject touches, once `PARCHMENT_STREAM_TOKEN` is set to a shared token.
+//!
+//! Only frames the tray draws are streamed, not drafts' screens. The server runs in the
+//! tray process, so clients are disconnected when it exits, such as on switching to a draft.
+//!
+//! Protocol, all integers little-endian:
+//! * Client -> server, on connecting: `[len: u8]` followed by `len` bytes of token,
+//!   only when a token is configured
+//! * Server -> client: damaged tiles as `[left: u32][top: u32][width: u32][height: u32][len: u32]`
+//!   followed by `len` bytes of rgb565 pixel data, row-major
+//! * Client -> server: touch events as `[kind: u8][tracking_id: i32][x: u16][y: u16]`,
+//!   where kind is 0 for press, 1 for move and 2 for release
+use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::Duration,
+};
+
+use libremarkable::{
+    cgmath::Point2,
+    input::{
+        multitouch::{Finger, MultitouchEvent},
+        InputEvent,
+    },
+};
+
+use crate::{channel::Sender, display::DISPLAY_RECT, framebuffer::MxcfbRect, MainEvent};
+
+pub const STREAM_ADDR_VAR: &str = "PARCHMENT_STREAM_ADDR";
+pub const STREAM_TOKEN_VAR: &str = "PARCHMENT_STREAM_TOKEN";
+pub const TILE_SIZE: u32 = 64;
+
+/// How long a client may take to accept a frame before it's dropped for falling behind
+pub const STREAM_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const BYTES_PER_PIXEL: usize = 2;
+const TOUCH_PACKET_SIZE: usize = 9;
+const TILE_HEADER_SIZE: usize = 20;
+
+/// The most recent frame, numbered so each client can tell whether it's seen it
+#[derive(Default)]
+struct LatestFrame {
+    frame: Mutex<(u64, Option<Arc<Vec<u8>>>)>,
+    published: Condvar,
+}
+
+impl LatestFrame {
+    fn publish(&self, frame: Vec<u8>) {
+        let mut latest = self.frame.lock().unwrap();
+        *latest = (latest.0 + 1, Some(Arc::new(frame)));
+        self.published.notify_all();
+    }
+
+    /// Block until there's a frame newer than seen, skipping any published in between,
+    /// or None once the client has hung up
+    fn wait_newer(&self, seen: u64, hung_up: &AtomicBool) -> Option<(u64, Arc<Vec<u8>>)> {
+        let mut latest = self.frame.lock().unwrap();
+        loop {
+            if hung_up.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let (id, Some(frame)) = &*latest {
+                if *id > seen {
+                    return Some((*id, frame.clone()));
+                }
+            }
+            latest = self.published.wait(latest).unwrap();
+        }
+    }
+
+    /// Wake every waiting writer, so any whose client has hung up can stop
+    fn wake(&self) {
+        let _latest = self.frame.lock().unwrap();
+        self.published.notify_all();
+    }
+}
+
+/// Handle used by the render thread to publish frames to connected clients
+#[derive(Clone)]
+pub struct StreamHandle {
+    latest: Arc<LatestFrame>,
+    client_count: Arc<AtomicUsize>,
+}
+
+impl StreamHandle {
+    /// Whether any client is connected, used to skip dumping frames nobody will see
+    pub fn active(&self) -> bool {
+        self.client_count.load(Ordering::Relaxed) > 0
+    }
+
+    /// Publish a full-display rgb565 dump, replacing any frame clients haven't caught up to
+    pub fn send_frame(&self, frame: Vec<u8>) {
+        self.latest.publish(frame);
+    }
+}
+
+/// Address to listen on, a bare port meaning that port on loopback
+fn stream_addr(addr: &str) -> Option<SocketAddr> {
+    match addr.parse::<u16>() {
+        Ok(port) => Some(SocketAddr::from((Ipv4Addr::LOCALHOST, port))),
+        Err(_) => addr.parse().ok(),
+    }
+}
+
+/// Start the stream server if an address has been configured
+pub fn stream_init(event_tx: Sender<MainEvent>) -> Option<StreamHandle> {
+    let addr = std::env::var(STREAM_ADDR_VAR).ok()?;
+    let Some(addr) = stream_addr(&addr) else {
+        println!("Invalid stream address {addr:}");
+        return None;
+    };
+
+    let token = std::env::var(STREAM_TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty() && token.len() <= u8::MAX as usize);
+    if token.is_none() && !addr.ip().is_loopback() {
+        println!("Not streaming to {addr:} without {STREAM_TOKEN_VAR:} set");
+        return None;
+    }
+
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            println!("Failed to bind stream server to {addr:}: {e:}");
+            return None;
+        }
+    };
+
+    println!("Streaming framebuffer on {addr:}");
+    if token.is_none() {
+        println!("Stream touch input disabled, {STREAM_TOKEN_VAR:} isn't set");
+    }
+
+    let latest = Arc::new(LatestFrame::default());
+    let client_count = Arc::new(AtomicUsize::new(0));
+
+    {
+        let latest = latest.clone();
+        let client_count = client_count.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let token = token.clone();
+                let event_tx = event_tx.clone();
+                let latest = latest.clone();
+                let client_count = client_count.clone();
+                std::thread::spawn(move || {
+                    serve_client(stream, token.as_deref(), event_tx, latest, &client_count)
+                });
+            }
+        });
+    }
+
+    Some(StreamHandle {
+        latest,
+        client_count,
+    })
+}
+
+/// Check a new client's token, then stream frames to it and take touches from it
+fn serve_client(
+    mut stream: TcpStream,
+    token: Option<&str>,
+    event_tx: Sender<MainEvent>,
+    latest: Arc<LatestFrame>,
+    client_count: &AtomicUsize,
+) {
+    let peer = stream.peer_addr();
+    if let Some(token) = token {
+        stream.set_read_timeout(Some(STREAM_WRITE_TIMEOUT)).ok();
+        if !read_token(&mut stream).is_some_and(|sent| tokens_match(&sent, token)) {
+            println!("Stream client {peer:?} rejected, bad token");
+            return;
+        }
+        stream.set_read_timeout(None).ok();
+    }
+
+    // Reading is also how a hangup is noticed while the screen is idle and nothing's written
+    let hung_up = Arc::new(AtomicBool::new(false));
+    match stream.try_clone() {
+        Ok(reader) => {
+            let event_tx = token.map(|_| event_tx);
+            let hung_up = hung_up.clone();
+            let latest = latest.clone();
+            std::thread::spawn(move || {
+                touch_reader(reader, event_tx);
+                hung_up.store(true, Ordering::Relaxed);
+                latest.wake();
+            });
+        }
+        Err(e) => {
+            println!("Stream client {peer:?} dropped, failed to read from it: {e:}");
+            return;
+        }
+    }
+
+    println!("Stream client connected: {peer:?}");
+    stream.set_nodelay(true).ok();
+    stream.set_write_timeout(Some(STREAM_WRITE_TIMEOUT)).ok();
+
+    client_count.fetch_add(1, Ordering::Relaxed);
+    frame_writer(stream, &latest, &hung_up);
+    client_count.fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Read the length-prefixed token a client sends on connecting
+fn read_token(stream: &mut impl Read) -> Option<Vec<u8>> {
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len).ok()?;
+    let mut token = vec![0u8; len[0] as usize];
+    stream.read_exact(&mut token).ok()?;
+    Some(token)
+}
+
+/// Compare tokens without returning early on the first differing byte
+fn tokens_match(sent: &[u8], token: &str) -> bool {
+    sent.len() == token.len()
+        && sent
+            .iter()
+            .zip(token.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Send one client the tiles damaged since the last frame it got, until it hangs up
+/// or falls far enough behind for a write to time out. The first frame is sent whole.
+fn frame_writer(mut stream: TcpStream, latest: &LatestFrame, hung_up: &AtomicBool) {
+    let mut seen = 0;
+    let mut previous: Option<Arc<Vec<u8>>> = None;
+
+    loop {
+        let Some((id, frame)) = latest.wait_newer(seen, hung_up) else {
+            println!("Stream client hung up");
+            stream.shutdown(std::net::Shutdown::Both).ok();
+            return;
+        };
+        let damaged = damaged_tiles(previous.as_deref().map(Vec::as_slice), &frame);
+        if let Err(e) = write_tiles(&mut stream, &frame, damaged.into_iter()) {
+            println!("Stream client disconnected: {e:}");
+            stream.shutdown(std::net::Shutdown::Both).ok();
+            return;
+        }
+
+        seen = id;
+        previous = Some(frame);
+    }
+}
+
+/// Iterate over the tile rects covering the provided region
+fn tiles(rect: MxcfbRect) -> impl Iterator<Item = MxcfbRect> {
+    (rect.top..rect.top + rect.height)
+        .step_by(TILE_SIZE as usize)
+        .flat_map(move |top| {
+            (rect.left..rect.left + rect.width)
+                .step_by(TILE_SIZE as usize)
+                .map(move |left| MxcfbRect {
+                    top,
+                    left,
+                    width: TILE_SIZE.min(rect.left + rect.width - left),
+                    height: TILE_SIZE.min(rect.top + rect.height - top),
+                })
+        })
+}
+
+/// Byte range of a tile row within a full-display dump
+fn row_range(tile: &MxcfbRect, y: u32) -> std::ops::Range<usize> {
+    let start = (y * DISPLAY_RECT.width + tile.left) as usize * BYTES_PER_PIXEL;
+    start..start + tile.width as usize * BYTES_PER_PIXEL
+}
+
+fn damaged_tiles(previous: Option<&[u8]>, frame: &[u8]) -> Vec<MxcfbRect> {
+    tiles(DISPLAY_RECT)
+        .filter(|tile| match previous {
+            Some(previous) if previous.len() == frame.len() => (tile.top..tile.top + tile.height)
+                .any(|y| previous[row_range(tile, y)] != frame[row_range(tile, y)]),
+            _ => true,
+        })
+        .collect()
+}
+
+/// Header and pixels of one tile of a full-display dump
+fn tile_packet(frame: &[u8], tile: MxcfbRect) -> Vec<u8> {
+    let len = (tile.width * tile.height) as usize * BYTES_PER_PIXEL;
+    let mut packet = Vec::with_capacity(TILE_HEADER_SIZE + len);
+    packet.extend(tile.left.to_le_bytes());
+    packet.extend(tile.top.to_le_bytes());
+    packet.extend(tile.width.to_le_bytes());
+    packet.extend(tile.height.to_le_bytes());
+    packet.extend((len as u32).to_le_bytes());
+    for y in tile.top..tile.top + tile.height {
+        packet.extend_from_slice(&frame[row_range(&tile, y)]);
+    }
+    packet
+}
+
+fn write_tiles(
+    stream: &mut TcpStream,
+    frame: &[u8],
+    tiles: impl Iterator<Item = MxcfbRect>,
+) -> std::io::Result<()> {
+    for tile in tiles {
+        stream.write_all(&tile_packet(frame, tile))?;
+    }
+    stream.flush()
+}
+
+/// Decode a touch packet, None if its kind is unknown
+fn touch_event(packet: &[u8; TOUCH_PACKET_SIZE]) -> Option<MultitouchEvent> {
+    let tracking_id = i32::from_le_bytes([packet[1], packet[2], packet[3], packet[4]]);
+    let x = u16::from_le_bytes([packet[5], packet[6]]);
+    let y = u16::from_le_bytes([packet[7], packet[8]]);
+
+    let mut finger = Finger::default();
+    finger.tracking_id = tracking_id;
+    finger.pos = Point2::new(x, y);
+    finger.pressed = packet[0] != 2;
+
+    match packet[0] {
+        0 => Some(MultitouchEvent::Press { finger }),
+        1 => Some(MultitouchEvent::Move { finger }),
+        2 => Some(MultitouchEvent::Release { finger }),
+        _ => None,
+    }
+}
+
+/// Read injected touch events from a client until it hangs up, forwarding them to the
+/// main loop if it may inject touches and discarding them otherwise
+fn touch_reader(mut stream: TcpStream, event_tx: Option<Sender<MainEvent>>) {
+    let Some(event_tx) = event_tx else {
+        std::io::copy(&mut stream, &mut std::io::sink()).ok();
+        return;
+    };
+
+    let mut packet = [0u8; TOUCH_PACKET_SIZE];
+    while stream.read_exact(&mut packet).is_ok() {
+        let Some(event) = touch_event(&packet) else {
+            println!("Unknown stream touch packet kind {:}", packet[0]);
+            continue;
+        };
+
+        if event_tx
+            .send(MainEvent::Input(InputEvent::MultitouchEvent { event }))
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank_frame() -> Vec<u8> {
+        vec![0; (DISPLAY_RECT.width * DISPLAY_RECT.height) as usize * BYTES_PER_PIXEL]
+    }
+
+    #[test]
+    fn only_changed_tiles_are_damaged() {
+        let previous = blank_frame();
+        let mut frame = previous.clone();
+        assert!(damaged_tiles(Some(&previous), &frame).is_empty());
+        assert_eq!(
+            damaged_tiles(None, &frame).len(),
+            tiles(DISPLAY_RECT).count()
+        );
+
+        // One pixel in the second tile of the second row of tiles
+        let (x, y) = (TILE_SIZE + 3, TILE_SIZE + 5);
+        frame[(y * DISPLAY_RECT.width + x) as usize * BYTES_PER_PIXEL] = 0xff;
+        let damaged = damaged_tiles(Some(&previous), &frame);
+        assert_eq!(damaged.len(), 1);
+        assert_eq!((damaged[0].left, damaged[0].top), (TILE_SIZE, TILE_SIZE));
+    }
+
+    #[test]
+    fn encodes_tile_header_and_rows() {
+        let mut frame = blank_frame();
+        let row = DISPLAY_RECT.width as usize * BYTES_PER_PIXEL;
+        frame[2] = 1;
+        frame[row + 2] = 2;
+        let tile = MxcfbRect {
+            left: 1,
+            top: 0,
+            width: 2,
+            height: 2,
+        };
+
+        let packet = tile_packet(&frame, tile);
+        assert_eq!(&packet[..4], &1u32.to_le_bytes());
+        assert_eq!(&packet[4..8], &0u32.to_le_bytes());
+        assert_eq!(&packet[8..12], &2u32.to_le_bytes());
+        assert_eq!(&packet[12..16], &2u32.to_le_bytes());
+        assert_eq!(&packet[16..20], &8u32.to_le_bytes());
+        assert_eq!(&packet[20..], &[1, 0, 0, 0, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn decodes_touch_packets() {
+        let mut packet = [0u8; TOUCH_PACKET_SIZE];
+        packet[0] = 2;
+        packet[1..5].copy_from_slice(&7i32.to_le_bytes());
+        packet[5..7].copy_from_slice(&300u16.to_le_bytes());
+        packet[7..9].copy_from_slice(&400u16.to_le_bytes());
+
+        let Some(MultitouchEvent::Release { finger }) = touch_event(&packet) else {
+            panic!("Expected a release");
+        };
+        assert_eq!(finger.tracking_id, 7);
+        assert_eq!(finger.pos, Point2::new(300, 400));
+        assert!(!finger.pressed);
+
+        packet[0] = 3;
+        assert!(touch_event(&packet).is_none());
+    }
+
+    #[test]
+    fn bare_ports_bind_loopback_and_tokens_must_match() {
+        assert_eq!(stream_addr("5900"), "127.0.0.1:5900".parse().ok());
+        assert_eq!(stream_addr("0.0.0.0:5900"), "0.0.0.0:5900".parse().ok());
+        assert_eq!(stream_addr("nonsense"), None);
+
+        let mut handshake: &[u8] = &[6, b's', b'e', b'c', b'r', b'e', b't'];
+        let sent = read_token(&mut handshake).unwrap();
+        assert!(tokens_match(&sent, "secret"));
+        assert!(!tokens_match(&sent, "secre"));
+        assert!(!tokens_match(b"secrex", "secret"));
+    }
+
+    #[test]
+    fn idle_clients_that_hang_up_are_released() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let (event_tx, _event_rx) = crate::channel::channel();
+        let client_count = Arc::new(AtomicUsize::new(0));
+        let server = {
+            let client_count = client_count.clone();
+            std::thread::spawn(move || {
+                serve_client(stream, None, event_tx, Default::default(), &client_count)
+            })
+        };
+
+        // Nothing is ever published, so only the hangup can end the writer
+        drop(client);
+        server.join().unwrap();
+        assert_eq!(client_count.load(Ordering::Relaxed), 0);
+    }
+}
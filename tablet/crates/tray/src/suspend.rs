@@ -0,0 +1,50 @@
+//! Device suspend detection
+//!
+//! The boot clock keeps counting while the device is asleep but the monotonic clock
+//! stops, and neither follows changes to the wall clock. So the gap between them only
+//! grows across a suspend, and growth of it over one poll interval means we were asleep.
+use std::time::Duration;
+
+use nix::{
+    sys::time::TimeValLike,
+    time::{clock_gettime, ClockId},
+};
+
+use crate::{channel::Sender, MainEvent};
+
+pub const SUSPEND_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Growth in time asleep below this is put down to the two clocks being read apart
+pub const SUSPEND_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Total time spent suspended since boot
+fn time_asleep() -> Option<Duration> {
+    let clock = |clock_id| {
+        let time = clock_gettime(clock_id).ok()?;
+        Some(Duration::from_nanos(time.num_nanoseconds().max(0) as u64))
+    };
+    Some(clock(ClockId::CLOCK_BOOTTIME)?.saturating_sub(clock(ClockId::CLOCK_MONOTONIC)?))
+}
+
+pub fn suspend_monitor(event_tx: Sender<MainEvent>) {
+    let Some(mut last_asleep) = time_asleep() else {
+        println!("Failed to read the boot clock, suspend detection disabled");
+        return;
+    };
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(SUSPEND_POLL_INTERVAL);
+
+        let Some(asleep) = time_asleep() else {
+            continue;
+        };
+        let slept = asleep.saturating_sub(last_asleep);
+        last_asleep = asleep;
+
+        if slept > SUSPEND_THRESHOLD {
+            println!("Detected resume after {slept:?} asleep");
+            if event_tx.send(MainEvent::Resumed).is_err() {
+                break;
+            }
+        }
+    });
+}
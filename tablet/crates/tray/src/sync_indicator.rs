@@ -0,0 +1,79 @@
+//! Cloud sync indicator, so xochitl isn't closed while it's mid-sync
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use libremarkable::cgmath::Point2;
+use shared::{cloud_sync::xochitl_syncing, locale::tr, ProcessTree};
+
+use crate::{
+    animation::spinner,
+    channel::Sender,
+    framebuffer::{Color, MxcfbRect},
+    layout::layout,
+    partial_refresh,
+    ui::{offset_relative, overlay, set_rect, text_aligned, Draw, DrawContext, DrawFn, ThenTrait},
+    MainEvent,
+};
+
+pub const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+static SYNCING: AtomicBool = AtomicBool::new(false);
+
+/// Whether xochitl was syncing when last polled
+pub fn syncing() -> bool {
+    SYNCING.load(Ordering::Relaxed)
+}
+
+/// Poll xochitl's sync state in the background, redrawing when it changes
+pub fn sync_monitor(event_tx: Sender<MainEvent>) {
+    std::thread::spawn(move || loop {
+        let syncing = xochitl_syncing(&ProcessTree::scan());
+        if SYNCING.swap(syncing, Ordering::Relaxed) != syncing {
+            println!(
+                "xochitl sync {}",
+                if syncing { "started" } else { "finished" }
+            );
+            if event_tx.send(MainEvent::Redraw).is_err() {
+                break;
+            }
+        }
+
+        std::thread::sleep(SYNC_POLL_INTERVAL);
+    });
+}
+
+/// Label at the right of the current rect with a spinner before it, for while xochitl is syncing
+pub fn sync_indicator() -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let layout = layout();
+        let label = tr("tray.syncing");
+        let radius = (layout.font_size / 3.0) as i32;
+        let ctx = offset_relative(Point2::new(
+            ctx.rect.width as i32,
+            layout.close_button_size / 4,
+        ))
+        .then(text_aligned(
+            &label,
+            layout.font_size,
+            Point2::new(1.0, 0.0),
+            Color::GRAY(96),
+        ))
+        .draw(ctx);
+
+        let rect = ctx.rect;
+        let ctx = overlay(
+            offset_relative(Point2::new(-radius * 2, rect.height as i32 / 2))
+                .then(spinner("sync.spinner", radius)),
+        )(ctx);
+
+        set_rect(MxcfbRect {
+            left: rect.left.saturating_sub(radius as u32 * 3),
+            width: rect.width + radius as u32 * 3,
+            ..rect
+        })
+        .then(partial_refresh())
+        .draw(ctx)
+    }
+}
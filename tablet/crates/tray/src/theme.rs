@@ -0,0 +1,79 @@
+//! Display theme, applied to colors and images as they're drawn
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::{framebuffer::Color, user_profile::save_profile_setting};
+use libremarkable::image::{imageops, ImageBuffer, Rgb, RgbImage, RgbaImage};
+
+static INVERTED: AtomicBool = AtomicBool::new(false);
+
+static PANEL_BACKGROUND: AtomicU8 = AtomicU8::new(u8::MAX);
+
+pub fn inverted() -> bool {
+    INVERTED.load(Ordering::Relaxed)
+}
+
+pub fn set_inverted(inverted: bool) {
+    INVERTED.store(inverted, Ordering::Relaxed);
+}
+
+/// Flip night mode and persist it to the config file
+pub fn toggle_inverted() {
+    let inverted = !INVERTED.fetch_xor(true, Ordering::Relaxed);
+    println!("Night mode {}", if inverted { "on" } else { "off" });
+    save_profile_setting("invert", &inverted.to_string());
+}
+
+pub fn set_panel_background(level: u8) {
+    PANEL_BACKGROUND.store(level, Ordering::Relaxed);
+}
+
+/// Fill color of the tray panel, before the theme is applied
+pub fn panel_background() -> Color {
+    let level = PANEL_BACKGROUND.load(Ordering::Relaxed);
+    Color::RGB(level, level, level)
+}
+
+/// Map a color through the current theme
+pub fn themed(color: Color) -> Color {
+    if !inverted() {
+        return color;
+    }
+
+    match color {
+        Color::BLACK => Color::WHITE,
+        Color::WHITE => Color::BLACK,
+        Color::GRAY(level) => Color::GRAY(u8::MAX - level),
+        Color::RGB(r, g, b) => Color::RGB(u8::MAX - r, u8::MAX - g, u8::MAX - b),
+        color => color,
+    }
+}
+
+/// Copy an image with the current theme applied, if it needs changing
+pub fn themed_image(image: &RgbImage) -> Option<RgbImage> {
+    inverted().then(|| {
+        let mut image = image.clone();
+        imageops::invert(&mut image);
+        image
+    })
+}
+
+/// Composite an image with alpha over a solid background color
+///
+/// Blending happens before theming, which is linear, so inverting the result
+/// matches blending the inverted image over the inverted background.
+pub fn blend_image(image: &RgbaImage, background: Color) -> RgbImage {
+    let background = background.to_rgb8();
+    let data = image
+        .chunks(4)
+        .flat_map(|pixel| {
+            let alpha = pixel[3] as u32;
+            [0, 1, 2].map(|channel| {
+                ((pixel[channel] as u32 * alpha
+                    + background[channel] as u32 * (u8::MAX as u32 - alpha))
+                    / u8::MAX as u32) as u8
+            })
+        })
+        .collect::<Vec<_>>();
+
+    ImageBuffer::<Rgb<u8>, _>::from_raw(image.width(), image.height(), data).unwrap()
+}
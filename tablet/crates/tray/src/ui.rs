@@ -1,30 +1,56 @@
 use crate::{
+    display::{Display, MeasureOnly},
+    focus::{FocusCallback, FocusMap},
     framebuffer::{Color, DisplayTemp, DitherMode, MxcfbRect, WaveformMode},
+    profile::{timed, Metric},
     rect::{Empty, Position},
+    state::StateStore,
+    theme::{blend_image, inverted, themed, themed_image},
 };
-use gesture::{GestureCallback, GestureRecognizer};
+use gesture::{GestureCallback, GestureRecognizer, Pan, Zone};
+use launcher_core::capture::{CaptureFormat, CaptureWorker};
 use libremarkable::{
-    cgmath::Point2,
-    framebuffer::{
-        core::Framebuffer, refresh::PartialRefreshMode, FramebufferDraw, FramebufferIO,
-        FramebufferRefresh,
-    },
+    cgmath::{InnerSpace, Point2, Vector2},
+    framebuffer::refresh::PartialRefreshMode,
 };
+use shared::{
+    metrics::{count, Counter},
+    rm2fb,
+};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Subtrees whose pixels memo keeps at once
+pub const MEMO_CAPACITY: usize = 64;
+
+/// Pixels of memoized subtrees, by hash of their key, rect and theme
+static MEMO: Mutex<BTreeMap<u64, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Update marker handed out by the display for a refresh, used to wait on its completion
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RefreshToken(pub u32);
+
+impl RefreshToken {
+    /// Displays that don't track refreshes return a zero marker, which can't be waited on
+    pub fn from_marker(marker: u32) -> Option<Self> {
+        (marker != 0).then_some(RefreshToken(marker))
+    }
+}
 
 pub struct DrawContext {
-    pub fb: Framebuffer,
+    pub fb: Box<dyn Display>,
     pub rect: MxcfbRect,
     pub gesture_recognizer: GestureRecognizer,
-}
-
-impl Clone for DrawContext {
-    fn clone(&self) -> Self {
-        DrawContext {
-            fb: Framebuffer::default(),
-            rect: self.rect,
-            gesture_recognizer: GestureRecognizer::default(),
-        }
-    }
+    pub focus: FocusMap,
+    /// Most recently issued refresh, carried over between draws by the render thread
+    pub refresh: Option<RefreshToken>,
+    /// Widget state that persists across frames
+    pub state: StateStore,
 }
 
 pub trait DrawFn: Fn(DrawContext) -> DrawContext {}
@@ -52,20 +78,24 @@ pub fn partial_refresh(
     quant_bit: i32,
     force_full_refresh: bool,
 ) -> impl DrawFn {
-    move |ctx: DrawContext| {
-        ctx.fb.partial_refresh(
-            &ctx.rect,
-            match &refresh_mode {
-                PartialRefreshMode::DryRun => PartialRefreshMode::DryRun,
-                PartialRefreshMode::Async => PartialRefreshMode::Async,
-                PartialRefreshMode::Wait => PartialRefreshMode::Wait,
-            },
-            waveform_mode,
-            display_temp,
-            dither_mode,
-            quant_bit,
-            force_full_refresh,
-        );
+    move |mut ctx: DrawContext| {
+        let marker = timed(Metric::RefreshWait, || {
+            ctx.fb.partial_refresh(
+                &ctx.rect,
+                match &refresh_mode {
+                    PartialRefreshMode::DryRun => PartialRefreshMode::DryRun,
+                    PartialRefreshMode::Async => PartialRefreshMode::Async,
+                    PartialRefreshMode::Wait => PartialRefreshMode::Wait,
+                },
+                waveform_mode,
+                display_temp,
+                dither_mode,
+                quant_bit,
+                force_full_refresh,
+            )
+        });
+        ctx.refresh = RefreshToken::from_marker(marker).or(ctx.refresh);
+        count(Counter::Refreshes, 1);
         ctx
     }
 }
@@ -149,18 +179,42 @@ pub fn full_refresh(
     quant_bit: i32,
     wait_completion: bool,
 ) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let marker = timed(Metric::RefreshWait, || {
+            ctx.fb.full_refresh(
+                waveform_mode,
+                display_temp,
+                dither_mode,
+                quant_bit,
+                wait_completion,
+            )
+        });
+        ctx.refresh = RefreshToken::from_marker(marker).or(ctx.refresh);
+        count(Counter::Refreshes, 1);
+        ctx
+    }
+}
+
+/// Block until the given refresh has reached the panel
+pub fn wait_for_refresh(token: RefreshToken) -> impl DrawFn {
     move |ctx: DrawContext| {
-        ctx.fb.full_refresh(
-            waveform_mode,
-            display_temp,
-            dither_mode,
-            quant_bit,
-            wait_completion,
-        );
+        timed(Metric::RefreshWait, || {
+            ctx.fb.wait_refresh_complete(token.0)
+        });
         ctx
     }
 }
 
+/// Block until every refresh issued so far has reached the panel
+///
+/// Update markers are handed out in order, so waiting on the latest one fences all of them.
+pub fn wait_refreshes() -> impl DrawFn {
+    move |ctx: DrawContext| match ctx.refresh {
+        Some(token) => wait_for_refresh(token)(ctx),
+        None => ctx,
+    }
+}
+
 /// Restore a region of the framebuffer
 pub fn restore_region<T: std::borrow::Borrow<[u8]>>(data: T) -> impl DrawFn {
     move |mut ctx: DrawContext| {
@@ -170,17 +224,39 @@ pub fn restore_region<T: std::borrow::Borrow<[u8]>>(data: T) -> impl DrawFn {
 }
 
 /// Dump a region of the framebuffer using a callback function
+///
+/// Waits for rm2fb to apply any updates still queued by a stopped app first,
+/// so the dump matches what's on the panel.
 pub fn dump_region<F: Fn(Vec<u8>)>(f: F) -> impl DrawFn {
     move |ctx: DrawContext| {
+        rm2fb::wait_idle(rm2fb::IDLE_TIMEOUT);
         f(ctx.fb.dump_region(ctx.rect).unwrap());
         ctx
     }
 }
 
+/// Dump a region of the framebuffer, handing it to the capture worker to write out
+pub fn dump_screenshot(path: PathBuf, capture: CaptureWorker) -> impl DrawFn {
+    dump_region(move |data| capture.submit(path.clone(), data, CaptureFormat::Dump))
+}
+
+/// Dump a region of the framebuffer to a PNG, written out by the capture worker
+pub fn dump_png(path: PathBuf, capture: CaptureWorker) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let format = CaptureFormat::Png {
+            width: ctx.rect.width,
+            height: ctx.rect.height,
+        };
+        rm2fb::wait_idle(rm2fb::IDLE_TIMEOUT);
+        capture.submit(path.clone(), ctx.fb.dump_region(ctx.rect).unwrap(), format);
+        ctx
+    }
+}
+
 /// Draw a filled circle
 pub fn circle_stroke(rad: u32, color: Color) -> impl DrawFn {
     move |mut ctx: DrawContext| {
-        ctx.fb.draw_circle(ctx.rect.position(), rad, color);
+        ctx.fb.draw_circle(ctx.rect.position(), rad, themed(color));
         ctx
     }
 }
@@ -188,16 +264,11 @@ pub fn circle_stroke(rad: u32, color: Color) -> impl DrawFn {
 /// Draw an unfilled circle
 pub fn circle_fill(rad: u32, color: Color) -> impl DrawFn {
     move |mut ctx: DrawContext| {
-        ctx.fb.fill_circle(ctx.rect.position(), rad, color);
+        ctx.fb.fill_circle(ctx.rect.position(), rad, themed(color));
         ctx
     }
 }
 
-/// Draw a circle with distinct fill and stroke colors
-pub fn circle_border(rad: u32, fill_color: Color, stroke_color: Color) -> impl Draw {
-    circle_fill(rad, fill_color).then(circle_stroke(rad, stroke_color))
-}
-
 /// Draw a line of text
 pub fn text(text: &str, size: f32, color: Color) -> impl DrawFn + '_ {
     move |mut ctx: DrawContext| {
@@ -205,7 +276,7 @@ pub fn text(text: &str, size: f32, color: Color) -> impl DrawFn + '_ {
             ctx.rect.position().cast().unwrap(),
             text,
             size,
-            color,
+            themed(color),
             false,
         );
         DrawContext { rect, ..ctx }
@@ -242,11 +313,23 @@ pub fn text_aligned(
 /// Draw the provided RGB image, anchored at the top-left
 pub fn image(image: &libremarkable::image::RgbImage) -> impl DrawFn + '_ {
     move |mut ctx: DrawContext| {
-        let rect = ctx.fb.draw_image(image, ctx.rect.position());
+        let rect = match themed_image(image) {
+            Some(themed) => ctx.fb.draw_image(&themed, ctx.rect.position()),
+            None => ctx.fb.draw_image(image, ctx.rect.position()),
+        };
         DrawContext { rect, ..ctx }
     }
 }
 
+/// Draw the provided RGBA image, anchored at the top-left and blended over a background color
+pub fn image_alpha(image: &libremarkable::image::RgbaImage, background: Color) -> impl DrawFn + '_ {
+    move |ctx: DrawContext| {
+        let blended = blend_image(image, background);
+        let ctx = self::image(&blended)(ctx);
+        ctx
+    }
+}
+
 /// Run the provided draw command, ignoring any resulting changes to the rect
 pub fn overlay(f: impl Draw) -> impl DrawFn {
     move |mut ctx: DrawContext| {
@@ -331,7 +414,28 @@ pub fn margin(margin: i32) -> impl Draw {
 pub fn rect_fill(color: Color) -> impl DrawFn {
     move |mut ctx: DrawContext| {
         ctx.fb
-            .fill_rect(ctx.rect.position(), ctx.rect.size(), color);
+            .fill_rect(ctx.rect.position(), ctx.rect.size(), themed(color));
+        ctx
+    }
+}
+
+/// 4x4 ordered dither thresholds, so partial coverage spreads evenly over a region
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Fill a fraction of the rectangle's pixels in an ordered dither pattern
+///
+/// Stands in for translucency on a display that can't blend quickly, such as for fades.
+pub fn dither_fill(coverage: f32, color: Color) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let color = themed(color);
+        let level = (coverage.clamp(0.0, 1.0) * 16.0).round() as u8;
+        for y in ctx.rect.top..ctx.rect.top + ctx.rect.height {
+            for x in ctx.rect.left..ctx.rect.left + ctx.rect.width {
+                if BAYER[y as usize % 4][x as usize % 4] < level {
+                    ctx.fb.set_pixel(Point2::new(x as i32, y as i32), color);
+                }
+            }
+        }
         ctx
     }
 }
@@ -345,7 +449,7 @@ pub fn line(start: Point2<i32>, end: Point2<i32>, width: u32, color: Color) -> i
             ),
             Point2::new(ctx.rect.left as i32 + end.x, ctx.rect.top as i32 + end.y),
             width,
-            color,
+            themed(color),
         );
         ctx
     }
@@ -354,8 +458,12 @@ pub fn line(start: Point2<i32>, end: Point2<i32>, width: u32, color: Color) -> i
 /// Draw an unfilled rectangle
 pub fn rect_stroke(border_px: u32, color: Color) -> impl DrawFn {
     move |mut ctx: DrawContext| {
-        ctx.fb
-            .draw_rect(ctx.rect.position(), ctx.rect.size(), border_px, color);
+        ctx.fb.draw_rect(
+            ctx.rect.position(),
+            ctx.rect.size(),
+            border_px,
+            themed(color),
+        );
         ctx
     }
 }
@@ -365,6 +473,307 @@ pub fn rect_border(border_px: u32, fill_color: Color, stroke_color: Color) -> im
     rect_fill(fill_color).then(rect_stroke(border_px, stroke_color))
 }
 
+/// Mix two colors, with coverage 1.0 giving entirely the first
+fn mix(color: Color, background: Color, coverage: f32) -> Color {
+    let (color, background) = (color.to_rgb8(), background.to_rgb8());
+    let [r, g, b] = [0, 1, 2].map(|channel| {
+        (color[channel] as f32 * coverage + background[channel] as f32 * (1.0 - coverage)) as u8
+    });
+    Color::RGB(r, g, b)
+}
+
+/// Shade the pixels of a region covered by a shape, given its signed distance at each pixel center
+///
+/// With a blend color, edge pixels are mixed with it by coverage for anti-aliasing,
+/// otherwise each pixel is either drawn or left alone.
+fn shade(
+    fb: &mut dyn Display,
+    region: MxcfbRect,
+    color: Color,
+    blend: Option<Color>,
+    distance: impl Fn(Point2<f32>) -> f32,
+) {
+    let color = themed(color);
+    let blend = blend.map(themed);
+    for y in region.top as i32..(region.top + region.height) as i32 {
+        for x in region.left as i32..(region.left + region.width) as i32 {
+            let coverage =
+                (0.5 - distance(Point2::new(x as f32 + 0.5, y as f32 + 0.5))).clamp(0.0, 1.0);
+            match blend {
+                Some(_) if coverage >= 1.0 => fb.set_pixel(Point2::new(x, y), color),
+                Some(background) if coverage > 0.0 => {
+                    fb.set_pixel(Point2::new(x, y), mix(color, background, coverage))
+                }
+                None if coverage >= 0.5 => fb.set_pixel(Point2::new(x, y), color),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Signed distance from a rect with rounded corners, negative inside
+fn rounded_rect_distance(rect: MxcfbRect, radius: f32, p: Point2<f32>) -> f32 {
+    let half = Vector2::new(rect.width as f32, rect.height as f32) / 2.0;
+    let center = Point2::new(rect.left as f32 + half.x, rect.top as f32 + half.y);
+    let radius = radius.min(half.x).min(half.y);
+    let q = Vector2::new((p.x - center.x).abs(), (p.y - center.y).abs()) - half
+        + Vector2::new(radius, radius);
+    Vector2::new(q.x.max(0.0), q.y.max(0.0)).magnitude() + q.x.max(q.y).min(0.0) - radius
+}
+
+/// Square regions in each corner of a rect, where rounding happens
+fn corners(rect: MxcfbRect, radius: u32) -> [MxcfbRect; 4] {
+    let radius = radius.min(rect.width / 2).min(rect.height / 2);
+    let (right, bottom) = (
+        rect.left + rect.width - radius,
+        rect.top + rect.height - radius,
+    );
+    [
+        (rect.left, rect.top),
+        (right, rect.top),
+        (rect.left, bottom),
+        (right, bottom),
+    ]
+    .map(|(left, top)| MxcfbRect {
+        left,
+        top,
+        width: radius,
+        height: radius,
+    })
+}
+
+/// Draw a filled rectangle with rounded corners, anti-aliased against a blend color if provided
+///
+/// Straight runs are filled as rects, only the corners are shaded per pixel.
+pub fn rounded_rect_fill(radius: u32, color: Color, blend: Option<Color>) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let rect = ctx.rect;
+        let radius = radius.min(rect.width / 2).min(rect.height / 2);
+        ctx.fb.fill_rect(
+            Point2::new((rect.left + radius) as i32, rect.top as i32),
+            Vector2::new(rect.width - radius * 2, rect.height),
+            themed(color),
+        );
+        for left in [rect.left, rect.left + rect.width - radius] {
+            ctx.fb.fill_rect(
+                Point2::new(left as i32, (rect.top + radius) as i32),
+                Vector2::new(radius, rect.height - radius * 2),
+                themed(color),
+            );
+        }
+        for corner in corners(rect, radius) {
+            shade(&mut *ctx.fb, corner, color, blend, |p| {
+                rounded_rect_distance(rect, radius as f32, p)
+            });
+        }
+        ctx
+    }
+}
+
+/// Draw the outline of a rectangle with rounded corners, anti-aliased against a blend color if provided
+pub fn rounded_rect_stroke(
+    radius: u32,
+    border_px: u32,
+    color: Color,
+    blend: Option<Color>,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let rect = ctx.rect;
+        let radius = radius
+            .max(border_px)
+            .min(rect.width / 2)
+            .min(rect.height / 2);
+        let border = border_px as f32;
+        for (pos, size) in [
+            (
+                Point2::new(rect.left + radius, rect.top),
+                Vector2::new(rect.width - radius * 2, border_px),
+            ),
+            (
+                Point2::new(rect.left + radius, rect.top + rect.height - border_px),
+                Vector2::new(rect.width - radius * 2, border_px),
+            ),
+            (
+                Point2::new(rect.left, rect.top + radius),
+                Vector2::new(border_px, rect.height - radius * 2),
+            ),
+            (
+                Point2::new(rect.left + rect.width - border_px, rect.top + radius),
+                Vector2::new(border_px, rect.height - radius * 2),
+            ),
+        ] {
+            ctx.fb.fill_rect(pos.cast().unwrap(), size, themed(color));
+        }
+        for corner in corners(rect, radius) {
+            shade(&mut *ctx.fb, corner, color, blend, |p| {
+                let distance = rounded_rect_distance(rect, radius as f32, p);
+                (distance + border / 2.0).abs() - border / 2.0
+            });
+        }
+        ctx
+    }
+}
+
+/// Draw a rectangle with rounded corners and distinct fill and stroke colors
+pub fn rounded_rect_border(
+    radius: u32,
+    border_px: u32,
+    fill_color: Color,
+    stroke_color: Color,
+    blend: Option<Color>,
+) -> impl Draw {
+    rounded_rect_fill(radius, fill_color, blend).then(rounded_rect_stroke(
+        radius,
+        border_px,
+        stroke_color,
+        blend,
+    ))
+}
+
+/// Draw an anti-aliased line with round caps, relative to the rect's position
+pub fn line_smooth(
+    start: Point2<i32>,
+    end: Point2<i32>,
+    width: u32,
+    color: Color,
+    blend: Color,
+) -> impl DrawFn + Copy {
+    move |mut ctx: DrawContext| {
+        let origin = Vector2::new(ctx.rect.left as i32, ctx.rect.top as i32);
+        let (start, end) = (start + origin, end + origin);
+        let pad = width.div_ceil(2) as i32 + 1;
+        let (left, top) = (start.x.min(end.x) - pad, start.y.min(end.y) - pad);
+        let region = MxcfbRect {
+            left: left.max(0) as u32,
+            top: top.max(0) as u32,
+            width: ((start.x.max(end.x) + pad) - left.max(0)).max(0) as u32,
+            height: ((start.y.max(end.y) + pad) - top.max(0)).max(0) as u32,
+        };
+
+        let (a, b) = (start.cast::<f32>().unwrap(), end.cast::<f32>().unwrap());
+        let ab = b - a;
+        shade(&mut *ctx.fb, region, color, Some(blend), |p| {
+            let ap = p - a;
+            let t = if ab.magnitude2() > 0.0 {
+                (ap.dot(ab) / ab.magnitude2()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            (ap - ab * t).magnitude() - width as f32 / 2.0
+        });
+
+        ctx.rect = region;
+        ctx
+    }
+}
+
+/// Bounding region of a circle centered on the rect's position
+fn circle_region(ctx: &DrawContext, rad: u32) -> MxcfbRect {
+    let rad = rad as i32 + 1;
+    let (left, top) = (ctx.rect.left as i32 - rad, ctx.rect.top as i32 - rad);
+    MxcfbRect {
+        left: left.max(0) as u32,
+        top: top.max(0) as u32,
+        width: (left + rad * 2 + 1 - left.max(0)).max(0) as u32,
+        height: (top + rad * 2 + 1 - top.max(0)).max(0) as u32,
+    }
+}
+
+/// Draw an anti-aliased filled circle
+pub fn circle_smooth_fill(rad: u32, color: Color, blend: Color) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let center = Point2::new(ctx.rect.left as f32, ctx.rect.top as f32);
+        let region = circle_region(&ctx, rad);
+        shade(&mut *ctx.fb, region, color, Some(blend), |p| {
+            (p - center).magnitude() - rad as f32
+        });
+        ctx
+    }
+}
+
+/// Draw an anti-aliased circle outline, with the stroke inside its radius
+pub fn circle_smooth_stroke(rad: u32, width: u32, color: Color, blend: Color) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let center = Point2::new(ctx.rect.left as f32, ctx.rect.top as f32);
+        let region = circle_region(&ctx, rad);
+        let half = width as f32 / 2.0;
+        shade(&mut *ctx.fb, region, color, Some(blend), |p| {
+            ((p - center).magnitude() - rad as f32 + half).abs() - half
+        });
+        ctx
+    }
+}
+
+/// Disc of items around the rect's position, one per slice with the first straight up
+/// and the rest clockwise, matching [`gesture::slice_at`]. Each item is drawn in an
+/// item_size square, and the highlighted one is ringed.
+pub fn radial_menu<'a>(
+    radius: u32,
+    item_size: u32,
+    background: Color,
+    items: &'a [impl DrawFn],
+    highlighted: Option<usize>,
+) -> impl DrawFn + 'a {
+    move |mut ctx: DrawContext| {
+        let center = ctx.rect;
+        let region = circle_region(&ctx, radius);
+        ctx = overlay(
+            circle_smooth_fill(radius, background, Color::WHITE).then(circle_smooth_stroke(
+                radius,
+                2,
+                Color::BLACK,
+                Color::WHITE,
+            )),
+        )(ctx);
+
+        let width = std::f32::consts::TAU / items.len().max(1) as f32;
+        let at = |angle: f32, distance: f32| {
+            Point2::new(
+                (angle.sin() * distance) as i32,
+                (-angle.cos() * distance) as i32,
+            )
+        };
+        if items.len() > 1 {
+            for slice in 0..items.len() {
+                let angle = (slice as f32 + 0.5) * width;
+                ctx = overlay(line_smooth(
+                    at(angle, radius as f32 * 0.25),
+                    at(angle, radius as f32 - 2.0),
+                    2,
+                    Color::BLACK,
+                    background,
+                ))(ctx);
+            }
+        }
+
+        for (slice, item) in items.iter().enumerate() {
+            let offset = at(slice as f32 * width, radius as f32 * 0.6);
+            let (x, y) = (center.left as i32 + offset.x, center.top as i32 + offset.y);
+            ctx.rect = MxcfbRect {
+                left: (x - item_size as i32 / 2).max(0) as u32,
+                top: (y - item_size as i32 / 2).max(0) as u32,
+                width: item_size,
+                height: item_size,
+            };
+            ctx = item(ctx);
+
+            if highlighted == Some(slice) {
+                ctx = set_position(x.max(0) as u32, y.max(0) as u32)
+                    .then(circle_smooth_stroke(
+                        item_size * 2 / 3,
+                        3,
+                        Color::BLACK,
+                        background,
+                    ))
+                    .draw(ctx);
+            }
+        }
+
+        ctx.rect = region;
+        ctx
+    }
+}
+
 /// Arrange the provided draws horizontally
 pub fn horizontal<'a>(spacing: i32, draws: &'a [impl DrawFn]) -> impl DrawFn + 'a {
     move |mut ctx: DrawContext| {
@@ -427,6 +836,208 @@ pub fn vertical_fixed<'a>(element_height: i32, draws: &'a [impl DrawFn]) -> impl
     }
 }
 
+/// Rects for a grid of cells laid out within the provided rect, in row-major order
+///
+/// Cells share the available space evenly, with a partial last row centered.
+pub fn grid_cells(
+    rect: MxcfbRect,
+    columns: usize,
+    spacing: Vector2<i32>,
+    count: usize,
+) -> Vec<MxcfbRect> {
+    if columns == 0 || count == 0 {
+        return vec![];
+    }
+
+    let rows = count.div_ceil(columns);
+    let width = ((rect.width as i32 - spacing.x * (columns as i32 - 1)) / columns as i32).max(0);
+    let height = ((rect.height as i32 - spacing.y * (rows as i32 - 1)) / rows as i32).max(0);
+
+    (0..count)
+        .map(|i| {
+            let (row, column) = (i / columns, i % columns);
+            let in_row = (count - row * columns).min(columns);
+            let indent = (columns - in_row) as i32 * (width + spacing.x) / 2;
+            MxcfbRect {
+                left: (rect.left as i32 + indent + column as i32 * (width + spacing.x)) as u32,
+                top: (rect.top as i32 + row as i32 * (height + spacing.y)) as u32,
+                width: width as u32,
+                height: height as u32,
+            }
+        })
+        .collect()
+}
+
+/// Arrange the provided draws in a grid, each drawn with its cell as the current rect
+pub fn grid<'a>(
+    columns: usize,
+    spacing: Vector2<i32>,
+    cells: &'a [impl DrawFn],
+) -> impl DrawFn + 'a {
+    move |mut ctx: DrawContext| {
+        for (rect, cell) in grid_cells(ctx.rect, columns, spacing, cells.len())
+            .into_iter()
+            .zip(cells)
+        {
+            ctx = overlay(set_rect(rect).then(cell))(ctx);
+        }
+        ctx
+    }
+}
+
+/// Draw with a weighted share of a flex row's width
+pub struct Flexible<D: DrawFn> {
+    pub weight: u32,
+    pub draw: D,
+}
+
+/// Give a draw a share of a flex row's width proportional to its weight
+pub fn fill_width<D: DrawFn>(weight: u32, draw: D) -> Flexible<D> {
+    Flexible { weight, draw }
+}
+
+/// Give a draw an equal share of a flex row's width
+pub fn expand<D: DrawFn>(draw: D) -> Flexible<D> {
+    fill_width(1, draw)
+}
+
+/// Split a width between weighted items, with any rounding remainder going to the last
+pub fn flex_widths(width: u32, spacing: i32, weights: &[u32]) -> Vec<u32> {
+    let total = weights.iter().sum::<u32>();
+    if total == 0 {
+        return vec![0; weights.len()];
+    }
+
+    let available = (width as i32 - spacing * (weights.len() as i32 - 1)).max(0) as u32;
+    let mut widths = weights
+        .iter()
+        .map(|weight| available * weight / total)
+        .collect::<Vec<_>>();
+    let remainder = available - widths.iter().sum::<u32>();
+    if let Some(last) = widths.last_mut() {
+        *last += remainder;
+    }
+    widths
+}
+
+/// Arrange the provided draws horizontally, sharing out the current rect's width by weight
+pub fn flex_row<'a, D: DrawFn>(spacing: i32, items: &'a [Flexible<D>]) -> impl DrawFn + 'a {
+    move |mut ctx: DrawContext| {
+        let rect = ctx.rect;
+        let weights = items.iter().map(|item| item.weight).collect::<Vec<_>>();
+        let mut left = rect.left;
+        for (item, width) in items.iter().zip(flex_widths(rect.width, spacing, &weights)) {
+            ctx = overlay(
+                set_rect(MxcfbRect {
+                    left,
+                    width,
+                    ..rect
+                })
+                .then(&item.draw),
+            )(ctx);
+            left += width + spacing as u32;
+        }
+        ctx
+    }
+}
+
+/// Shrink the current rect to the given width / height ratio, keeping its top-left corner
+pub fn aspect_ratio(ratio: f32) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let (width, height) = (ctx.rect.width as f32, ctx.rect.height as f32);
+        if width > height * ratio {
+            ctx.rect.width = (height * ratio) as u32;
+        } else {
+            ctx.rect.height = (width / ratio) as u32;
+        }
+        ctx
+    }
+}
+
+/// Draw only if the condition holds, skipping its gestures and focus targets too
+pub fn when(condition: bool, draw: impl Draw) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        if condition {
+            draw.draw(ctx)
+        } else {
+            ctx
+        }
+    }
+}
+
+/// Cache the pixels a draw leaves in the current rect, blitting them back while its key is unchanged
+///
+/// On a hit the draw is replayed against a measure-only display, so gestures, focus targets
+/// and refreshes still happen. The draw must stay within the current rect, and its key must
+/// cover everything that changes how it looks.
+pub fn memo<K: Hash>(key: K, draw: impl Draw) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let rect = ctx.rect;
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (rect.left, rect.top, rect.width, rect.height, inverted()).hash(&mut hasher);
+        let id = hasher.finish();
+
+        let cached = MEMO.lock().unwrap().get(&id).cloned();
+        let pixels = match cached {
+            Some(pixels) => pixels,
+            None => {
+                ctx = draw.draw(ctx);
+                if let Ok(pixels) = ctx.fb.dump_region(rect) {
+                    let mut memo = MEMO.lock().unwrap();
+                    if memo.len() >= MEMO_CAPACITY {
+                        memo.pop_first();
+                    }
+                    memo.insert(id, pixels);
+                }
+                return ctx;
+            }
+        };
+
+        let DrawContext {
+            mut fb,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+            ..
+        } = ctx;
+        fb.restore_region(rect, &pixels).ok();
+
+        let display = Arc::new(Mutex::new(fb));
+        let ctx = draw.draw(DrawContext {
+            fb: Box::new(MeasureOnly(display.clone())),
+            rect,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        });
+        let DrawContext {
+            fb: measure_only,
+            rect,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        } = ctx;
+        drop(measure_only);
+
+        DrawContext {
+            fb: Arc::try_unwrap(display)
+                .ok()
+                .expect("Memoized draw kept hold of the display")
+                .into_inner()
+                .unwrap(),
+            rect,
+            gesture_recognizer,
+            focus,
+            refresh,
+            state,
+        }
+    }
+}
+
 /// Injects a gesture recognizer for the current rect
 pub fn recognize_gesture(g: impl GestureCallback + Clone + Send + Sync + 'static) -> impl DrawFn {
     move |mut ctx: DrawContext| {
@@ -441,6 +1052,60 @@ pub fn recognize_gesture(g: impl GestureCallback + Clone + Send + Sync + 'static
     }
 }
 
+/// Registers the current rect as a keyboard focus target
+pub fn focusable(f: impl FocusCallback + Clone + 'static) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        ctx.focus = ctx.focus.with_target(ctx.rect, f.clone());
+        ctx
+    }
+}
+
+/// Injects a callback for a tap made with several fingers, regardless of the current rect
+pub fn recognize_multi_tap(
+    fingers: usize,
+    hysteresis: f32,
+    f: impl Fn() + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        ctx.gesture_recognizer =
+            ctx.gesture_recognizer
+                .with_multi_tap(fingers, hysteresis, f.clone());
+        ctx
+    }
+}
+
+/// Injects a callback for fingers dragged together from within the current rect
+pub fn recognize_pan(
+    fingers: usize,
+    hysteresis: f32,
+    f: impl Fn(Pan) + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let zone = (
+            ctx.rect.position().cast().unwrap(),
+            ctx.rect.size().cast().unwrap(),
+        );
+        ctx.gesture_recognizer =
+            ctx.gesture_recognizer
+                .with_pan(zone, fingers, hysteresis, f.clone());
+        ctx
+    }
+}
+
+/// Injects a callback for fingers held in every zone at once, regardless of the current rect
+pub fn recognize_chord(
+    zones: Vec<Zone>,
+    duration: Duration,
+    f: impl Fn() + Clone + Send + Sync + 'static,
+) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        ctx.gesture_recognizer =
+            ctx.gesture_recognizer
+                .with_chord(zones.clone(), duration, f.clone());
+        ctx
+    }
+}
+
 /// Override the current rect x
 pub fn set_x(x: u32) -> impl DrawFn {
     move |mut ctx: DrawContext| {
@@ -490,3 +1155,79 @@ pub fn set_rect(rect: MxcfbRect) -> impl DrawFn {
         ctx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_centers_last_row() {
+        let rect = MxcfbRect {
+            left: 10,
+            top: 20,
+            width: 320,
+            height: 200,
+        };
+        let cells = grid_cells(rect, 3, Vector2::new(10, 0), 5);
+
+        assert_eq!(cells.len(), 5);
+        assert!(cells
+            .iter()
+            .all(|cell| cell.width == 100 && cell.height == 100));
+        assert_eq!(
+            cells.iter().map(|cell| cell.left).collect::<Vec<_>>(),
+            vec![10, 120, 230, 65, 175]
+        );
+        assert_eq!(
+            cells.iter().map(|cell| cell.top).collect::<Vec<_>>(),
+            vec![20, 20, 20, 120, 120]
+        );
+    }
+
+    #[test]
+    fn memo_restores_pixels_and_focus() {
+        let rect = MxcfbRect {
+            left: 2,
+            top: 2,
+            width: 4,
+            height: 4,
+        };
+        // Same key with a different fill, so a cache hit is visible in the pixels
+        let draw = |color| {
+            memo(
+                "memo_restores_pixels_and_focus",
+                rect_fill(color).then(focusable(|| ())),
+            )
+        };
+
+        let ctx = draw(Color::BLACK)(DrawContext {
+            fb: Box::new(crate::display::Simulator::new(8, 8)),
+            rect,
+            gesture_recognizer: GestureRecognizer::default(),
+            focus: FocusMap::default(),
+            refresh: None,
+            state: StateStore::default(),
+        });
+        let drawn = ctx.fb.dump_region(rect).unwrap();
+
+        let mut fb = ctx.fb;
+        fb.clear();
+        let ctx = draw(Color::WHITE)(DrawContext {
+            fb,
+            rect,
+            gesture_recognizer: GestureRecognizer::default(),
+            focus: FocusMap::default(),
+            refresh: None,
+            state: StateStore::default(),
+        });
+        assert_eq!(ctx.fb.dump_region(rect).unwrap(), drawn);
+        assert!(ctx.focus.get(0).is_some());
+    }
+
+    #[test]
+    fn flex_widths_share_by_weight() {
+        assert_eq!(flex_widths(110, 10, &[1, 1]), vec![50, 50]);
+        assert_eq!(flex_widths(100, 0, &[1, 2]), vec![33, 67]);
+        assert_eq!(flex_widths(100, 0, &[0, 0]), vec![0, 0]);
+    }
+}
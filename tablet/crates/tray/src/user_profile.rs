@@ -0,0 +1,131 @@
+//! Switching between user profiles, each with its own pins, theme and allowed drafts
+use std::sync::{Arc, Mutex};
+
+use launcher_core::drafts::DraftPrograms;
+use libremarkable::cgmath::Point2;
+use shared::{
+    config::{update_config, Config},
+    locale::tr,
+};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    panel::panel_rect,
+    partial_refresh, text_button,
+    theme::{set_inverted, set_panel_background},
+    ui::{
+        margin, offset_relative, overlay, rect_border, set_rect, Draw, DrawContext, DrawFn,
+        ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Config as of the last profile switch, for settings that follow the active profile
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+/// Apply the active profile's theme and remember the config for later switches
+pub fn user_profile_init(config: &Config) {
+    set_inverted(config.inverted());
+    set_panel_background(config.panel_level());
+    *CONFIG.lock().unwrap() = Some(config.clone());
+}
+
+fn with_config<R>(f: impl FnOnce(&Config) -> R) -> R {
+    let config = CONFIG.lock().unwrap();
+    f(config.as_ref().unwrap_or(&Config::default()))
+}
+
+pub fn active_profile() -> Option<String> {
+    with_config(|config| config.active_profile.clone())
+}
+
+/// Drafts pinned to the quick bar and pie menu under the active profile
+pub fn pinned_apps() -> Vec<String> {
+    with_config(|config| config.pinned_apps().to_vec())
+}
+
+/// Restrict drafts to those the active profile allows
+pub fn apply_allowed(drafts: &DraftPrograms) {
+    let allowed = with_config(|config| {
+        config
+            .profile()
+            .and_then(|profile| profile.allowed_apps.clone())
+    });
+    drafts.set_allowed(allowed);
+}
+
+/// Persist a setting under the active profile, or the top level if there isn't one
+pub fn save_profile_setting(key: &str, value: &str) {
+    let key = match active_profile() {
+        Some(name) => format!("profile.{name:}.{key:}"),
+        None => key.to_string(),
+    };
+    match update_config(&key, value) {
+        Ok(()) => *CONFIG.lock().unwrap() = Some(Config::load()),
+        Err(e) => println!("Failed to save {key:}: {e:}"),
+    }
+}
+
+/// Make a profile active, or go back to the top-level config with None
+pub fn switch_profile(name: Option<String>, drafts: &DraftPrograms) {
+    println!("Switching to user profile {name:?}");
+    if let Err(e) = update_config("activeProfile", name.as_deref().unwrap_or_default()) {
+        println!("Failed to save user profile: {e:}");
+    }
+
+    let mut config = Config::load();
+    config.active_profile = name;
+    user_profile_init(&config);
+    apply_allowed(drafts);
+}
+
+/// Full-panel list of user profiles, tapping one switches to it and returns to the tray
+pub fn user_profile_picker(event_tx: Sender<MainEvent>, drafts: Arc<DraftPrograms>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let back_label = tr("profiles.back");
+        let active = active_profile();
+        let names = with_config(|config| config.profiles.keys().cloned().collect::<Vec<_>>());
+        let choices = std::iter::once(None)
+            .chain(names.into_iter().map(Some))
+            .map(|name| {
+                let label = name.clone().unwrap_or_else(|| tr("profiles.default"));
+                let marker = if name == active { "> " } else { "  " };
+                (name, format!("{marker:}{label:}"))
+            })
+            .collect::<Vec<_>>();
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+
+        ctx = overlay(text_button(&back_label, {
+            let event_tx = event_tx.clone();
+            move || {
+                event_tx.send(MainEvent::ShowView(View::Settings)).ok();
+            }
+        }))(ctx);
+
+        for (i, (name, label)) in choices.iter().enumerate() {
+            ctx = overlay(
+                offset_relative(Point2::new(0, height * (i as i32 + 1))).then(text_button(
+                    label,
+                    {
+                        let event_tx = event_tx.clone();
+                        let drafts = drafts.clone();
+                        let name = name.clone();
+                        move || {
+                            switch_profile(name.clone(), &drafts);
+                            event_tx.send(MainEvent::ShowView(View::Tray)).ok();
+                        }
+                    },
+                )),
+            )(ctx);
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
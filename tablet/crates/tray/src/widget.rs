@@ -0,0 +1,188 @@
+//! Command-driven widgets declared in the config file
+//!
+//! Each widget's command is rerun on its own thread at the widget's interval. Output
+//! naming an existing PNG is drawn as an image scaled to the widget's rect, anything
+//! else as lines of text.
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use libremarkable::{
+    cgmath::Point2,
+    image::{imageops::FilterType, RgbImage},
+};
+use shared::config::WidgetConfig;
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    partial_refresh,
+    ui::{
+        focusable, image, offset_relative, overlay, recognize_gesture, rect_fill, set_rect,
+        text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent,
+};
+
+#[derive(Debug, Clone)]
+enum WidgetOutput {
+    Text(String),
+    Image(Arc<RgbImage>),
+}
+
+#[derive(Clone)]
+pub struct Widgets {
+    config: BTreeMap<String, WidgetConfig>,
+    outputs: Arc<Mutex<BTreeMap<String, WidgetOutput>>>,
+}
+
+/// Run a shell command, returning its trimmed stdout
+fn run_shell(command: &str) -> Option<String> {
+    let output = match Command::new("sh").arg("-c").arg(command).output() {
+        Ok(output) => output,
+        Err(e) => {
+            println!("Failed to run widget command {command:?}: {e:}");
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        println!("Widget command {command:?} exited with {}", output.status);
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn parse_output(stdout: String, config: &WidgetConfig) -> WidgetOutput {
+    if stdout.ends_with(".png") && Path::new(&stdout).is_file() {
+        match libremarkable::image::open(&stdout) {
+            Ok(image) => {
+                let image =
+                    image.resize(config.rect.width, config.rect.height, FilterType::Triangle);
+                return WidgetOutput::Image(Arc::new(image.to_rgb8()));
+            }
+            Err(e) => println!("Failed to load widget image {stdout:?}: {e:}"),
+        }
+    }
+
+    WidgetOutput::Text(stdout)
+}
+
+/// Start a thread per widget that reruns its command, redrawing whenever the output changes
+pub fn widgets_init(
+    config: BTreeMap<String, WidgetConfig>,
+    event_tx: Sender<MainEvent>,
+) -> Widgets {
+    let config = config
+        .into_iter()
+        .filter(|(name, widget)| {
+            let valid =
+                !widget.command.is_empty() && widget.rect.width > 0 && widget.rect.height > 0;
+            if !valid {
+                println!("Ignoring widget {name:?} without a command and rect");
+            }
+            valid
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let outputs = Arc::new(Mutex::new(BTreeMap::new()));
+
+    for (name, widget) in &config {
+        let name = name.clone();
+        let widget = widget.clone();
+        let outputs = outputs.clone();
+        let event_tx = event_tx.clone();
+        std::thread::spawn(move || {
+            let mut last = None;
+            loop {
+                let stdout = run_shell(&widget.command);
+                if let Some(stdout) = stdout.filter(|stdout| Some(stdout) != last.as_ref()) {
+                    let output = parse_output(stdout.clone(), &widget);
+                    outputs.lock().unwrap().insert(name.clone(), output);
+                    last = Some(stdout);
+
+                    if event_tx.send(MainEvent::Redraw).is_err() {
+                        break;
+                    }
+                }
+
+                std::thread::sleep(widget.interval);
+            }
+        });
+    }
+
+    Widgets { config, outputs }
+}
+
+/// Draw a widget's output at the top-left of the current rect
+fn widget_output(output: Option<WidgetOutput>) -> impl DrawFn {
+    move |ctx: DrawContext| match &output {
+        Some(WidgetOutput::Image(widget_image)) => overlay(image(widget_image))(ctx),
+        Some(WidgetOutput::Text(string)) => {
+            let layout = layout();
+            let lines = (ctx.rect.height as i32 / layout.line_height).max(1) as usize;
+            string
+                .lines()
+                .take(lines)
+                .enumerate()
+                .fold(ctx, |ctx, (i, line)| {
+                    overlay(
+                        offset_relative(Point2::new(
+                            0,
+                            layout.line_height * i as i32 + layout.line_height / 4,
+                        ))
+                        .then(text_aligned(
+                            line,
+                            layout.font_size,
+                            Point2::new(0.0, 0.0),
+                            Color::BLACK,
+                        )),
+                    )(ctx)
+                })
+        }
+        None => ctx,
+    }
+}
+
+/// Run a widget's tap command without waiting for it
+fn run_tap(command: String) {
+    std::thread::spawn(move || {
+        if let Err(e) = Command::new("sh").arg("-c").arg(&command).status() {
+            println!("Failed to run widget tap command {command:?}: {e:}");
+        }
+    });
+}
+
+/// Draw every widget's latest output in its rect
+pub fn widgets(widgets: Widgets) -> impl DrawFn {
+    move |mut ctx: DrawContext| {
+        let outputs = widgets.outputs.lock().unwrap().clone();
+        for (name, widget) in &widgets.config {
+            let tap = widget.tap.clone();
+            ctx = overlay(
+                set_rect(widget.rect)
+                    .then(rect_fill(Color::WHITE))
+                    .then(widget_output(outputs.get(name).cloned()))
+                    .then(move |ctx: DrawContext| match &tap {
+                        // Presses win over the tray's tap-to-close, since they're registered later
+                        Some(tap) => recognize_gesture(gesture::recognize_press({
+                            let tap = tap.clone();
+                            move |_| run_tap(tap.clone())
+                        }))
+                        .then(focusable({
+                            let tap = tap.clone();
+                            move || run_tap(tap.clone())
+                        }))
+                        .draw(ctx),
+                        None => ctx,
+                    })
+                    .then(partial_refresh()),
+            )(ctx);
+        }
+        ctx
+    }
+}
@@ -0,0 +1,408 @@
+//! Wi-Fi picker, for joining a network without going through xochitl
+//!
+//! Networks come from wpa_supplicant's last scan. Tapping an open or already saved one
+//! joins it straight away, while a new secured one asks for its passphrase on the
+//! on-screen keyboard first. Joining reports wpa_supplicant's progress as status.
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use libremarkable::cgmath::Point2;
+use net::wpa::{visible_networks, ScanResult, WpaControl, WIFI_INTERFACE, WPA_COMPLETED};
+use shared::locale::{tr, tr_args};
+
+use crate::{
+    channel::Sender,
+    framebuffer::Color,
+    layout::layout,
+    osk::{clear_keyboard, on_screen_keyboard},
+    panel::{panel_height, panel_rect},
+    partial_refresh,
+    state::StateStore,
+    text_button,
+    ui::{
+        margin, margin_bottom, margin_left, margin_top, offset_relative, overlay, rect_border,
+        set_rect, text_aligned, Draw, DrawContext, DrawFn, ThenTrait,
+    },
+    MainEvent, View,
+};
+
+/// Widget state id of the page shown by the network list
+pub const WIFI_PAGE: &str = "wifi.page";
+
+/// Widget state id of the network a passphrase is being entered for
+pub const WIFI_SELECTED: &str = "wifi.selected";
+
+/// Widget state id of the passphrase typed so far
+pub const WIFI_PASSPHRASE: &str = "wifi.passphrase";
+
+/// How long wpa_supplicant is given to scan before its results are read
+const SCAN_DURATION: Duration = Duration::from_secs(3);
+
+const CONNECT_POLL: Duration = Duration::from_millis(500);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Shortest passphrase WPA accepts
+const PASSPHRASE_MIN: usize = 8;
+
+#[derive(Debug, Default)]
+pub struct WifiPicker {
+    networks: Mutex<Vec<ScanResult>>,
+    known: Mutex<BTreeSet<String>>,
+    current: Mutex<Option<String>>,
+    status: Mutex<String>,
+    busy: AtomicBool,
+}
+
+impl WifiPicker {
+    pub fn networks(&self) -> MutexGuard<'_, Vec<ScanResult>> {
+        self.networks.lock().unwrap()
+    }
+
+    pub fn status(&self) -> String {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn set_status(&self, status: impl Into<String>, event_tx: &Sender<MainEvent>) {
+        *self.status.lock().unwrap() = status.into();
+        event_tx.send(MainEvent::Redraw).ok();
+    }
+
+    /// Whether wpa_supplicant already has a passphrase for the network
+    fn known(&self, ssid: &str) -> bool {
+        self.known.lock().unwrap().contains(ssid)
+    }
+
+    fn current(&self) -> Option<String> {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.networks().len().div_ceil(rows_per_page()).max(1)
+    }
+
+    /// Scan for networks in the background
+    pub fn scan(self: &Arc<Self>, event_tx: Sender<MainEvent>) {
+        if self.busy.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let picker = self.clone();
+        std::thread::spawn(move || {
+            picker.set_status(tr("wifi.scanning"), &event_tx);
+
+            let result = (|| -> std::io::Result<_> {
+                let control = WpaControl::open(WIFI_INTERFACE)?;
+                control.scan()?;
+                std::thread::sleep(SCAN_DURATION);
+                Ok((
+                    control.scan_results()?,
+                    control.known_networks()?,
+                    control.status()?,
+                ))
+            })();
+
+            match result {
+                Ok((results, known, status)) => {
+                    let networks = visible_networks(results);
+                    let empty = networks.is_empty();
+                    *picker.networks() = networks;
+                    *picker.known.lock().unwrap() = known;
+                    *picker.current.lock().unwrap() = status
+                        .get("ssid")
+                        .filter(|_| {
+                            status.get("wpa_state").map(String::as_str) == Some(WPA_COMPLETED)
+                        })
+                        .cloned();
+                    picker.set_status(
+                        if empty {
+                            tr("wifi.none")
+                        } else {
+                            String::new()
+                        },
+                        &event_tx,
+                    );
+                }
+                Err(e) => picker.set_status(
+                    tr_args("wifi.scan_failed", &[("error", &e.to_string())]),
+                    &event_tx,
+                ),
+            }
+
+            picker.busy.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Join a network in the background, reporting progress until it has an address
+    pub fn connect(
+        self: &Arc<Self>,
+        ssid: String,
+        passphrase: Option<String>,
+        event_tx: Sender<MainEvent>,
+    ) {
+        if self.busy.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let picker = self.clone();
+        std::thread::spawn(move || {
+            println!("Joining Wi-Fi network {ssid:?}");
+            picker.set_status(tr_args("wifi.connecting", &[("ssid", &ssid)]), &event_tx);
+
+            let result = (|| -> std::io::Result<Option<String>> {
+                let control = WpaControl::open(WIFI_INTERFACE)?;
+                control.connect(&ssid, passphrase.as_deref())?;
+
+                let start = Instant::now();
+                let mut last_state = String::new();
+                while start.elapsed() < CONNECT_TIMEOUT {
+                    std::thread::sleep(CONNECT_POLL);
+                    let status = control.status()?;
+                    let state = status.get("wpa_state").cloned().unwrap_or_default();
+                    let joined = state == WPA_COMPLETED && status.get("ssid") == Some(&ssid);
+                    if let Some(address) = status.get("ip_address").filter(|_| joined) {
+                        return Ok(Some(address.clone()));
+                    }
+
+                    if state != last_state {
+                        let progress = if joined {
+                            tr("wifi.addressing")
+                        } else {
+                            state.to_lowercase().replace('_', " ")
+                        };
+                        picker.set_status(
+                            tr_args("wifi.progress", &[("ssid", &ssid), ("state", &progress)]),
+                            &event_tx,
+                        );
+                        last_state = state;
+                    }
+                }
+                Ok(None)
+            })();
+
+            match result {
+                Ok(Some(address)) => {
+                    picker.known.lock().unwrap().insert(ssid.clone());
+                    *picker.current.lock().unwrap() = Some(ssid.clone());
+                    picker.set_status(
+                        tr_args("wifi.connected", &[("ssid", &ssid), ("address", &address)]),
+                        &event_tx,
+                    );
+                }
+                result => {
+                    // Ask for the passphrase again next time, in case it was wrong
+                    picker.known.lock().unwrap().remove(&ssid);
+                    let status = match result {
+                        Err(e) => tr_args(
+                            "wifi.connect_failed",
+                            &[("ssid", &ssid), ("error", &e.to_string())],
+                        ),
+                        _ => tr_args("wifi.timed_out", &[("ssid", &ssid)]),
+                    };
+                    picker.set_status(status, &event_tx);
+                }
+            }
+
+            picker.busy.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Forget the network list's page and any half-entered passphrase
+pub fn reset_wifi(state: &StateStore) {
+    state.remove(WIFI_PAGE);
+    state.remove(WIFI_SELECTED);
+    clear_keyboard(state, WIFI_PASSPHRASE);
+}
+
+/// Network rows that fit between the header and status lines
+fn rows_per_page() -> usize {
+    (((panel_height() - layout().icon_spacing * 2) / layout().line_height) - 2).max(1) as usize
+}
+
+/// Hide all but the last character typed, so typos can still be caught
+fn masked(passphrase: &str) -> String {
+    let count = passphrase.chars().count();
+    passphrase
+        .chars()
+        .enumerate()
+        .map(|(i, c)| if i + 1 == count { c } else { '*' })
+        .collect()
+}
+
+fn network_label(network: &ScanResult, current: bool) -> String {
+    format!(
+        "{}{} {}%{}",
+        if current { "> " } else { "" },
+        network.ssid,
+        network.signal_percent(),
+        if network.secured() { " *" } else { "" }
+    )
+}
+
+/// Full-panel network list, swapped for a passphrase entry keyboard once a new secured
+/// network is chosen
+pub fn wifi_picker(event_tx: Sender<MainEvent>, picker: Arc<WifiPicker>) -> impl DrawFn {
+    move |ctx: DrawContext| {
+        let height = layout().line_height;
+        let status = picker.status();
+        let back_label = tr("wifi.back");
+
+        let mut ctx = set_rect(panel_rect())
+            .then(rect_border(2, Color::WHITE, Color::BLACK))
+            .then(margin(layout().icon_spacing))
+            .draw(ctx);
+        let header = ctx.rect;
+
+        match ctx.state.get::<Option<String>>(WIFI_SELECTED) {
+            Some(ssid) => {
+                let passphrase = ctx.state.get::<String>(WIFI_PASSPHRASE);
+                let field = tr_args(
+                    "wifi.passphrase",
+                    &[("ssid", &ssid), ("passphrase", &masked(&passphrase))],
+                );
+
+                ctx = overlay(text_button(&back_label, {
+                    let state = ctx.state.clone();
+                    let event_tx = event_tx.clone();
+                    move || {
+                        state.remove(WIFI_SELECTED);
+                        clear_keyboard(&state, WIFI_PASSPHRASE);
+                        event_tx.send(MainEvent::Redraw).ok();
+                    }
+                }))(ctx);
+                ctx = overlay(
+                    margin_left(header.width as i32 / 4)
+                        .then(offset_relative(Point2::new(0, height / 4)))
+                        .then(text_aligned(
+                            &field,
+                            layout().font_size,
+                            Point2::new(0.0, 0.0),
+                            Color::BLACK,
+                        )),
+                )(ctx);
+
+                ctx = overlay(margin_top(height).then(margin_bottom(height)).then(
+                    on_screen_keyboard(WIFI_PASSPHRASE, event_tx.clone(), {
+                        let state = ctx.state.clone();
+                        let picker = picker.clone();
+                        let event_tx = event_tx.clone();
+                        move |passphrase: String| {
+                            if passphrase.chars().count() < PASSPHRASE_MIN {
+                                picker.set_status(tr("wifi.too_short"), &event_tx);
+                                return;
+                            }
+                            state.remove(WIFI_SELECTED);
+                            clear_keyboard(&state, WIFI_PASSPHRASE);
+                            picker.connect(ssid.clone(), Some(passphrase), event_tx.clone());
+                        }
+                    }),
+                ))(ctx);
+
+                ctx = overlay(
+                    offset_relative(Point2::new(0, header.height as i32 - height)).then(
+                        text_aligned(
+                            &status,
+                            layout().font_size,
+                            Point2::new(0.0, 0.0),
+                            Color::BLACK,
+                        ),
+                    ),
+                )(ctx);
+            }
+            None => {
+                let page_count = picker.page_count();
+                let page = ctx.state.get::<usize>(WIFI_PAGE).min(page_count - 1);
+                let current = picker.current();
+                let rows = picker
+                    .networks()
+                    .iter()
+                    .skip(page * rows_per_page())
+                    .take(rows_per_page())
+                    .cloned()
+                    .collect::<Vec<_>>();
+                let labels = rows
+                    .iter()
+                    .map(|network| network_label(network, current.as_ref() == Some(&network.ssid)))
+                    .collect::<Vec<_>>();
+                let rescan_label = tr("wifi.rescan");
+                let page_label = format!("{} / {}", page + 1, page_count);
+
+                // Header
+                ctx = overlay(text_button(&back_label, {
+                    let event_tx = event_tx.clone();
+                    move || {
+                        event_tx.send(MainEvent::ShowView(View::Network)).ok();
+                    }
+                }))(ctx);
+                ctx = overlay(margin_left(header.width as i32 / 3).then(text_button(
+                    &rescan_label,
+                    {
+                        let picker = picker.clone();
+                        let event_tx = event_tx.clone();
+                        move || picker.scan(event_tx.clone())
+                    },
+                )))(ctx);
+                ctx = overlay(margin_left(header.width as i32 * 2 / 3).then(text_button(
+                    &page_label,
+                    {
+                        let state = ctx.state.clone();
+                        let event_tx = event_tx.clone();
+                        move || {
+                            // Tapping the page indicator advances, wrapping back to the start
+                            state.set(WIFI_PAGE, (page + 1) % page_count);
+                            event_tx.send(MainEvent::Redraw).ok();
+                        }
+                    },
+                )))(ctx);
+
+                // Network rows
+                for (i, (network, label)) in rows.iter().zip(labels.iter()).enumerate() {
+                    ctx = overlay(
+                        offset_relative(Point2::new(0, height * (i as i32 + 1))).then(text_button(
+                            label,
+                            {
+                                let state = ctx.state.clone();
+                                let picker = picker.clone();
+                                let event_tx = event_tx.clone();
+                                let network = network.clone();
+                                move || {
+                                    if network.secured() && !picker.known(&network.ssid) {
+                                        state.set(WIFI_SELECTED, Some(network.ssid.clone()));
+                                        clear_keyboard(&state, WIFI_PASSPHRASE);
+                                        event_tx.send(MainEvent::Redraw).ok();
+                                    } else {
+                                        picker.connect(
+                                            network.ssid.clone(),
+                                            None,
+                                            event_tx.clone(),
+                                        );
+                                    }
+                                }
+                            },
+                        )),
+                    )(ctx);
+                }
+
+                // Status
+                ctx = overlay(
+                    offset_relative(Point2::new(0, height * (rows_per_page() as i32 + 1))).then(
+                        text_aligned(
+                            &status,
+                            layout().font_size,
+                            Point2::new(0.0, 0.0),
+                            Color::BLACK,
+                        ),
+                    ),
+                )(ctx);
+            }
+        }
+
+        set_rect(panel_rect()).then(partial_refresh()).draw(ctx)
+    }
+}
@@ -3,14 +3,410 @@ use libremarkable::{
     input::{ev::EvDevContext, multitouch::MultitouchEvent, InputDevice, InputEvent},
 };
 
-use shared::TAP_HYSTERESIS;
+use shared::{
+    action::{Action, ACTION_ARG},
+    config::Config,
+    draft_running,
+    handoff::{hand_off, STANDBY_ARG},
+    health::{run_health_check, set_hung, tree_usage, HangDetector, HEALTH_INTERVAL},
+    hooks::{run_hooks, HookEvent},
+    instance::single_instance,
+    notification::{do_not_disturb, listen, record, Urgency, NOTIFICATION_ARG},
+    oom::protect_launcher,
+    path_temp_pids, path_temp_session,
+    pidfile::read_pids,
+    session::Session,
+    ProcessTree, PALM_CONTACT_SIZE, PALM_PRESSURE, TAP_HYSTERESIS,
+};
+
+use proc::{Pid, ProcFsRoot, Stat, State};
+use raft::{Draft, Drafts};
+
+use gesture::{
+    recognize_drag, recognize_drag_release, Clock, EventType, GestureRecognizer, PalmRejection,
+    SystemClock, TouchFilter, Zone,
+};
+
+use std::{
+    path::PathBuf,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often to check for idleness while no input arrives
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often to check whether the foreground draft is still running
+const APP_EXIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const TRAY_PATH: &str = "/home/root/tray";
+
+/// Held while a tray process is drawing, so banners and the tray take turns
+static DISPLAY: Mutex<()> = Mutex::new(());
+
+/// Input of the tray showing a banner, closed to dismiss it early
+static BANNER: Mutex<Option<ChildStdin>> = Mutex::new(None);
+
+/// Tray waiting in standby for the next gesture
+static STANDBY: Mutex<Option<Child>> = Mutex::new(None);
+
+/// Start a tray to wait in standby, to be handed the next gesture
+fn spawn_standby(standby: &mut Option<Child>) {
+    println!("Spawning standby tray process");
+    match Command::new(TRAY_PATH).arg(STANDBY_ARG).spawn() {
+        Ok(child) => *standby = Some(child),
+        Err(e) => println!("Failed to spawn standby tray: {e:}"),
+    }
+}
+
+/// Hand an action to the standby tray and wait for it to exit, replacing it after.
+/// Returns false if there's no standby tray able to take it.
+fn hand_off_to_standby(action: Action) -> bool {
+    let mut standby = STANDBY.lock().unwrap();
+    let child = match standby.as_mut() {
+        Some(child) => child,
+        None => return false,
+    };
+
+    let handed_off = match hand_off(action) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("Failed to hand off to standby tray: {e:}");
+            false
+        }
+    };
+
+    // Reap the standby if it's gone, whether it just finished or died before being reached
+    if handed_off {
+        child.wait().ok();
+    }
+    if handed_off || !matches!(child.try_wait(), Ok(None)) {
+        spawn_standby(&mut standby);
+    }
+    handed_off
+}
+
+/// Hand off to the tray until it exits, releasing the touchscreen meanwhile.
+/// The standby tray is used if it's ready, otherwise a fresh one is spawned.
+fn run_tray(multitouch: &mut EvDevContext, action: Action) {
+    BANNER.lock().unwrap().take();
+    let _display = DISPLAY.lock().unwrap();
+
+    multitouch.stop();
+    println!("Handing {action:} to standby tray");
+    if !hand_off_to_standby(action) {
+        println!("Spawning tray process for {action:}");
+        Command::new(TRAY_PATH)
+            .args([ACTION_ARG, &action.to_string()])
+            .spawn()
+            .unwrap()
+            .wait()
+            .unwrap();
+    }
+    multitouch.start();
+}
 
-use gesture::{recognize_drag, GestureRecognizer};
+/// Show each notification posted by a draft as a banner, one at a time, and keep it in the history.
+/// Do not disturb holds back all but critical banners.
+fn show_notifications() {
+    let listener = listen(|notification| {
+        if let Err(e) = record(&notification) {
+            println!("Failed to record notification: {e:}");
+        }
+        if do_not_disturb() && notification.urgency < Urgency::Critical {
+            println!("Do not disturb, holding back notification {notification:?}");
+            return;
+        }
 
-use std::sync::mpsc::channel;
+        let _display = DISPLAY.lock().unwrap();
+        println!("Spawning tray process for notification {notification:?}");
+        let child = Command::new(TRAY_PATH)
+            .args([
+                ACTION_ARG,
+                &Action::Notify.to_string(),
+                NOTIFICATION_ARG,
+                &notification.to_string(),
+            ])
+            .stdin(Stdio::piped())
+            .spawn();
+        match child {
+            Ok(mut child) => {
+                *BANNER.lock().unwrap() = child.stdin.take();
+                child.wait().ok();
+                BANNER.lock().unwrap().take();
+            }
+            Err(e) => println!("Failed to spawn tray for notification: {e:}"),
+        }
+    });
+    if let Err(e) = listener {
+        println!("Failed to listen for notifications: {e:}");
+    }
+}
+
+/// Set on touch input, and cleared by each health check
+static INPUT_SEEN: AtomicBool = AtomicBool::new(false);
+
+/// The foreground draft and the pid it was launched with, looked up again only when the
+/// session or pid directory changes rather than on every poll
+#[derive(Default)]
+struct ForegroundCache {
+    loaded: bool,
+    session_modified: Option<SystemTime>,
+    pids_modified: Option<SystemTime>,
+    foreground: Option<(Draft, Pid)>,
+}
+
+impl ForegroundCache {
+    fn refresh(&mut self) -> Option<&(Draft, Pid)> {
+        let modified = |path: PathBuf| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        let session_modified = modified(path_temp_session());
+        let pids_modified = modified(path_temp_pids());
+        if !self.loaded
+            || session_modified != self.session_modified
+            || pids_modified != self.pids_modified
+        {
+            self.session_modified = session_modified;
+            self.pids_modified = pids_modified;
+            self.foreground = Self::load();
+            self.loaded = true;
+        }
+        self.foreground.as_ref()
+    }
+
+    fn load() -> Option<(Draft, Pid)> {
+        let foreground = Session::load()?.foreground?;
+        let draft = Drafts::new()
+            .ok()?
+            .take()
+            .into_iter()
+            .find(|draft| draft.id() == foreground)?;
+        let pid = read_pids()
+            .into_iter()
+            .find(|pidfile| pidfile.id == foreground)?
+            .pid;
+        Some((draft, pid))
+    }
+}
+
+/// Shared by the monitors and gesture masking, so they don't each reread every draft
+static FOREGROUND: Mutex<Option<ForegroundCache>> = Mutex::new(None);
+
+/// The foreground draft and its process' status, if it's been launched and is still around
+fn foreground_draft() -> Option<(Draft, Stat)> {
+    let mut cache = FOREGROUND.lock().unwrap();
+    let (draft, pid) = cache
+        .get_or_insert_with(ForegroundCache::default)
+        .refresh()?;
+    let stat = ProcFsRoot::system().stat(*pid).ok()?;
+    Some((draft.clone(), stat))
+}
+
+/// Periodically check the foreground draft, flagging it for the tray if it has hung
+fn health_monitor() {
+    std::thread::spawn(|| {
+        let mut detector = HangDetector::default();
+        let mut watched = None;
+        loop {
+            std::thread::sleep(HEALTH_INTERVAL);
+            let input = INPUT_SEEN.swap(false, Ordering::Relaxed);
+
+            let (draft, stat) = match foreground_draft() {
+                Some(foreground) => foreground,
+                None => {
+                    detector.reset();
+                    continue;
+                }
+            };
+            // Stopped behind the tray, so neither busy nor able to answer a check
+            if stat.state == State::Traced {
+                detector.reset();
+                continue;
+            }
+
+            let id = draft.id();
+            if watched.as_ref() != Some(&id) {
+                detector.reset();
+                watched = Some(id.clone());
+            }
+
+            let hung = match run_health_check(&draft) {
+                Some(healthy) => !healthy,
+                None => {
+                    // Only drafts without a check of their own need the whole tree scanned
+                    let procs = ProcessTree::scan();
+                    let Some(proc) = procs.find(stat.process_id) else {
+                        continue;
+                    };
+                    let (cpu_time, running) = tree_usage(&procs, proc);
+                    detector.sample(Instant::now(), cpu_time, running, input)
+                }
+            };
+            set_hung(&id, hung);
+        }
+    });
+}
+
+/// Watch for the foreground draft exiting on its own, which leaves its last frame frozen
+/// on screen, and wake the event loop to run the action for it
+fn app_exit_monitor(exited: Arc<Mutex<Option<Action>>>, action: Action, wake: Sender<InputEvent>) {
+    std::thread::spawn(move || {
+        let mut watched = None;
+        loop {
+            std::thread::sleep(APP_EXIT_POLL_INTERVAL);
+
+            // The tray decides what's in the foreground while it's up, and may close it
+            let Ok(_display) = DISPLAY.try_lock() else {
+                watched = None;
+                continue;
+            };
+
+            let foreground = foreground_draft().filter(|(_, stat)| stat.state != State::Zombie);
+            match (foreground, watched.take()) {
+                (Some((draft, _)), _) => watched = Some(draft.name),
+                (None, Some(name)) => {
+                    println!("Foreground draft {name:?} exited, running {action:}");
+                    *exited.lock().unwrap() = Some(action);
+                    wake.send(InputEvent::Unknown {}).ok();
+                }
+                (None, None) => (),
+            }
+        }
+    });
+}
+
+/// Gestures the foreground draft asks the launcher not to claim
+fn active_gesture_mask() -> Vec<String> {
+    foreground_draft()
+        .map(|(draft, _)| draft.gesture_mask)
+        .unwrap_or_default()
+}
+
+/// Bottom edge strip a swipe up starts in, scaled along with the tray's touch targets
+fn swipe_zone(config: &Config) -> Zone {
+    let zone_height = (128.0 * config.ui_scale) as u16;
+    (
+        cgmath::Point2::new(0, libremarkable::dimensions::DISPLAYHEIGHT - zone_height),
+        cgmath::Vector2::new(libremarkable::dimensions::DISPLAYWIDTH, zone_height),
+    )
+}
+
+/// Recognize the bottom edge swipes and multi-finger taps bound in the config, skipping
+/// masked gestures. Each sets the pending action, to be run once the gesture completes.
+///
+/// A swipe up runs the swipe action once it travels swipeDistance. One released before
+/// that runs the swipeShort action instead.
+fn build_recognizer(
+    config: &Config,
+    clock: &Arc<dyn Clock>,
+    mask: &[String],
+    pending_action: Arc<Mutex<Option<Action>>>,
+) -> GestureRecognizer {
+    let hysteresis = TAP_HYSTERESIS * config.ui_scale;
+
+    if !mask.is_empty() {
+        println!("Masking gestures {mask:?}");
+    }
+
+    let mut gesture_recognizer = GestureRecognizer::default()
+        .with_clock(clock.clone())
+        .with_palm_rejection(PalmRejection {
+            max_pressure: Some(PALM_PRESSURE),
+            max_size: Some(PALM_CONTACT_SIZE),
+        });
+    let zone = swipe_zone(config);
+    let full_swipe = config.swipe_distance * config.ui_scale;
+
+    for (gesture, action) in &config.gestures {
+        // Masking the swipe masks both lengths of it
+        let masked_as = match gesture.as_str() {
+            "swipeShort" => "swipe",
+            gesture => gesture,
+        };
+        if mask.iter().any(|masked| masked == masked_as) {
+            continue;
+        }
+        if gesture.contains('.') {
+            // Made in the tray, which binds them itself
+            continue;
+        }
+        if action.in_tray() {
+            println!("Ignoring {gesture:?}, {action:} can only be bound in the tray");
+            continue;
+        }
+
+        let action = *action;
+        let pending_action = pending_action.clone();
+        if gesture == "swipe" {
+            println!("Binding swipe to {action:}");
+            gesture_recognizer =
+                gesture_recognizer.with_callback(gesture::recognize_starting_zone(
+                    zone.0,
+                    zone.1,
+                    recognize_drag(move |delta| {
+                        let full = delta.y > full_swipe;
+                        if full {
+                            *pending_action.lock().unwrap() = Some(action);
+                        }
+                        full
+                    }),
+                ));
+            continue;
+        }
+        if gesture == "swipeShort" {
+            if full_swipe <= hysteresis {
+                println!("Warning: swipeShort can't be made unless swipeDistance is over {TAP_HYSTERESIS:}");
+            }
+            println!("Binding short swipe to {action:}");
+            gesture_recognizer =
+                gesture_recognizer.with_callback(gesture::recognize_starting_zone(
+                    zone.0,
+                    zone.1,
+                    recognize_drag_release(move |delta| {
+                        let partial = delta.y > hysteresis;
+                        if partial {
+                            *pending_action.lock().unwrap() = Some(action);
+                        }
+                        partial
+                    }),
+                ));
+            continue;
+        }
+
+        let fingers = match gesture
+            .strip_prefix("tap")
+            .and_then(|fingers| fingers.parse().ok())
+        {
+            Some(fingers) => fingers,
+            None => {
+                println!("Ignoring unknown gesture {gesture:?}");
+                continue;
+            }
+        };
+
+        println!("Binding {fingers:}-finger tap to {action:}");
+        gesture_recognizer = gesture_recognizer.with_multi_tap(fingers, hysteresis, move || {
+            *pending_action.lock().unwrap() = Some(action);
+        });
+    }
+
+    gesture_recognizer
+}
 
 fn main() -> ! {
     println!("wave startup");
+    let _instance = single_instance("wave");
+
+    let config = Config::load();
+    protect_launcher(config.launcher_oom_score_adj);
 
     // Create an MPSC channel to receive input events
     let (input_tx, input_rx) = channel::<InputEvent>();
@@ -18,51 +414,110 @@ fn main() -> ! {
     // Start event channels
     println!("Starting event channel...");
 
-    let mut multitouch = EvDevContext::new(InputDevice::Multitouch, input_tx);
+    let mut multitouch = EvDevContext::new(InputDevice::Multitouch, input_tx.clone());
 
     multitouch.start();
 
-    let mut gesture_recognizer =
-        GestureRecognizer::default().with_callback(gesture::recognize_starting_zone(
-            cgmath::Point2::new(0, libremarkable::dimensions::DISPLAYHEIGHT - 128),
-            cgmath::Vector2::new(libremarkable::dimensions::DISPLAYWIDTH, 128),
-            recognize_drag(move |delta| {
-                if delta.y > TAP_HYSTERESIS {
-                    true
-                } else {
-                    false
-                }
-            }),
-        ));
+    show_notifications();
+    spawn_standby(&mut STANDBY.lock().unwrap());
+    health_monitor();
+
+    let pending_action = Arc::new(Mutex::new(None));
+    let app_exited = Arc::new(Mutex::new(None));
+    match config.app_exit_action {
+        Some(action) if action.in_tray() => {
+            println!("Ignoring appExitAction, {action:} can only be bound in the tray")
+        }
+        Some(action) => app_exit_monitor(app_exited.clone(), action, input_tx),
+        None => (),
+    }
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock::default());
+    let mut touch_filter = TouchFilter::new((
+        cgmath::Point2::new(0, 0),
+        cgmath::Vector2::new(
+            libremarkable::dimensions::DISPLAYWIDTH,
+            libremarkable::dimensions::DISPLAYHEIGHT,
+        ),
+    ))
+    .with_clock(clock.clone())
+    .with_edge_margin(config.touch_edge_margin)
+    .with_edge_zone(swipe_zone(&config))
+    .with_min_contact(config.touch_min_contact);
+    let mut gesture_recognizer = build_recognizer(
+        &config,
+        &clock,
+        &active_gesture_mask(),
+        pending_action.clone(),
+    );
 
     // Enter event loop
     println!("Entering event loop...");
-    while let Ok(event) = input_rx.recv() {
+    let mut last_input = Instant::now();
+    loop {
+        let event = match input_rx.recv_timeout(IDLE_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                // Only cover the screen when no draft is using it
+                if let Some(idle_timeout) = config.idle_timeout {
+                    if last_input.elapsed() >= idle_timeout && !draft_running(&ProcessTree::scan())
+                    {
+                        println!("Idle for {:?}", last_input.elapsed());
+                        run_tray(&mut multitouch, Action::Idle);
+                        touch_filter.reset();
+                        gesture_recognizer = build_recognizer(
+                            &config,
+                            &clock,
+                            &active_gesture_mask(),
+                            pending_action.clone(),
+                        );
+                        last_input = Instant::now();
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        last_input = Instant::now();
+
         match event {
             InputEvent::MultitouchEvent { event } => {
                 println!("{event:?}");
-                let res = match event {
-                    MultitouchEvent::Press { finger } => gesture_recognizer.finger_press(finger),
-                    MultitouchEvent::Release { finger } => {
-                        gesture_recognizer.finger_release(finger)
-                    }
-                    MultitouchEvent::Move { finger } => gesture_recognizer.finger_move(finger),
-                    _ => vec![],
+                INPUT_SEEN.store(true, Ordering::Relaxed);
+                let (event_type, finger) = match event {
+                    MultitouchEvent::Press { finger } => (EventType::Press, finger),
+                    MultitouchEvent::Release { finger } => (EventType::Release, finger),
+                    MultitouchEvent::Move { finger } => (EventType::Move, finger),
+                    _ => continue,
                 };
-
-                if res.len() > 0 {
-                    multitouch.stop();
-                    println!("Gesture triggered, spawning tray process");
-                    std::process::Command::new("/home/root/tray")
-                        .spawn()
-                        .unwrap()
-                        .wait()
-                        .unwrap();
-                    multitouch.start();
+                let touch = config.touch_transform.apply(finger.into());
+                for (event_type, touch) in touch_filter.filter(event_type, touch) {
+                    gesture_recognizer.finger_event(event_type, touch);
                 }
             }
             _ => (),
         }
+
+        let gesture = pending_action.lock().unwrap().take();
+        if let Some(action) = gesture {
+            println!("Gesture triggered");
+            run_hooks(HookEvent::Gesture {
+                action: action.to_string(),
+            });
+        }
+        let exited = app_exited.lock().unwrap().take();
+        if let Some(action) = gesture.or(exited) {
+            run_tray(&mut multitouch, action);
+
+            // The tray may have switched drafts, pick up the new foreground's mask
+            touch_filter.reset();
+            gesture_recognizer = build_recognizer(
+                &config,
+                &clock,
+                &active_gesture_mask(),
+                pending_action.clone(),
+            );
+            last_input = Instant::now();
+        }
     }
 
     panic!("Event loops closed unexpectedly");
@@ -0,0 +1,9 @@
+//! Draft file parsing, which reads files anyone can drop into the draft directory
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use raft::Draft;
+
+fuzz_target!(|input: &str| {
+    let _ = Draft::new(input);
+});
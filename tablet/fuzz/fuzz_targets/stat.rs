@@ -0,0 +1,9 @@
+//! /proc/<pid>/stat parsing, where process names are chosen by the process itself
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proc::Stat;
+
+fuzz_target!(|input: &str| {
+    let _ = input.parse::<Stat>();
+});